@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+
+use crate::error::Error;
+
+/// Chart versions published by a Helm repository, from its `index.yaml`
+#[derive(Debug, Default, Deserialize)]
+pub struct ChartIndex {
+    #[serde(default)]
+    entries: HashMap<String, Vec<ChartEntry>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChartEntry {
+    version: String,
+}
+
+impl ChartIndex {
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        serde_yaml::from_str(content)
+            .map_err(|e| Error::SerializationError(format!("Invalid chart index: {}", e)))
+    }
+    
+    /// Index of `chart` published at `versions`, as listed by an OCI registry
+    pub fn from_versions(chart: &str, versions: Vec<String>) -> Self {
+        let entries = versions.into_iter().map(|version| ChartEntry { version }).collect();
+        Self { entries: HashMap::from([(chart.to_string(), entries)]) }
+    }
+    
+    /// Fetches the index of the Helm repository at `repository`
+    pub async fn fetch(repository: &str) -> Result<Self, Error> {
+        let url = format!("{}/index.yaml", repository.trim_end_matches('/'));
+        let fetch_error = |e: reqwest::Error| Error::DependencyError(format!("Failed to fetch {}: {}", url, e));
+        
+        let content = reqwest::get(&url).await
+            .and_then(|response| response.error_for_status())
+            .map_err(fetch_error)?
+            .text().await
+            .map_err(fetch_error)?;
+        
+        Self::parse(&content)
+    }
+    
+    /// Published versions of `chart` that are valid semver, as written in the index
+    fn versions<'a>(&'a self, chart: &str) -> impl Iterator<Item = (Version, &'a str)> {
+        self.entries
+            .get(chart)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| parse_version(&entry.version).map(|version| (version, entry.version.as_str())))
+    }
+    
+    /// Newest version of `chart` satisfying `constraint`
+    pub fn resolve(&self, chart: &str, constraint: &Constraint) -> Option<&str> {
+        self.versions(chart)
+            .filter(|(version, _)| constraint.matches(version))
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, written)| written)
+    }
+    
+    /// Newest version of `chart` that is not a pre-release
+    pub fn latest(&self, chart: &str) -> Option<&str> {
+        self.versions(chart)
+            .filter(|(version, _)| version.pre.is_empty())
+            .max_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(_, written)| written)
+    }
+}
+
+/// Helm-style version constraint: alternatives separated by `||`, each a list
+/// of comparisons separated by spaces or commas, such as `~1.14`,
+/// `>=0.9 <1.0` or `1.2 - 1.4`. A bare version matches itself, with missing
+/// parts matching anything.
+#[derive(Debug, Clone)]
+pub struct Constraint(Vec<VersionReq>);
+
+impl Constraint {
+    pub fn parse(constraint: &str) -> Result<Self, Error> {
+        let invalid = |reason: String| Error::DependencyError(format!("Invalid version constraint {}: {}", constraint, reason));
+        
+        let alternatives = constraint
+            .split("||")
+            .map(|alternative| {
+                let comparators = comparators(alternative).map_err(&invalid)?;
+                VersionReq::parse(&comparators.join(", ")).map_err(|e| invalid(e.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        
+        Ok(Self(alternatives))
+    }
+    
+    pub fn matches(&self, version: &Version) -> bool {
+        self.0.iter().any(|requirement| requirement.matches(version))
+    }
+}
+
+/// Comparators of one alternative in the syntax of the `semver` crate
+fn comparators(alternative: &str) -> Result<Vec<String>, String> {
+    let tokens: Vec<&str> = alternative
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|token| !token.is_empty())
+        .collect();
+    
+    let mut comparators = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        // Hyphen range: `1.2 - 1.4`
+        if tokens.get(i + 1) == Some(&"-") {
+            let upper = tokens.get(i + 2).ok_or("range without an upper bound")?;
+            comparators.push(format!(">={}", tokens[i].trim_start_matches('v')));
+            comparators.push(format!("<={}", upper.trim_start_matches('v')));
+            i += 3;
+            continue;
+        }
+        
+        // An operator may be separated from its version: `>= 1.0`
+        let mut token = tokens[i].to_string();
+        if token.chars().all(|c| "<>=~^".contains(c)) {
+            i += 1;
+            token.push_str(tokens.get(i).ok_or("operator without a version")?);
+        }
+        i += 1;
+        
+        let split = token.find(|c: char| !"<>=~^".contains(c)).unwrap_or(token.len());
+        let (operator, version) = token.split_at(split);
+        let version = version.trim_start_matches('v');
+        let wildcard = version.split('.').any(|part| matches!(part, "x" | "X" | "*"));
+        
+        comparators.push(match operator {
+            "" if !wildcard => format!("={}", version),
+            _ => format!("{}{}", operator, version),
+        });
+    }
+    
+    if comparators.is_empty() {
+        return Err("empty constraint".to_string());
+    }
+    Ok(comparators)
+}
+
+/// Parses a chart version, which may carry a `v` prefix
+pub fn parse_version(version: &str) -> Option<Version> {
+    Version::parse(version.trim_start_matches('v')).ok()
+}
+
+/// Whether `version` names one chart version rather than a constraint
+pub fn is_exact(version: &str) -> bool {
+    parse_version(version).is_some()
+}
+
+/// Whether chart version `candidate` is newer than `current`
+pub fn is_newer(candidate: &str, current: &str) -> bool {
+    match (parse_version(candidate), parse_version(current)) {
+        (Some(candidate), Some(current)) => candidate > current,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn matches(constraint: &str, version: &str) -> bool {
+        Constraint::parse(constraint).unwrap().matches(&parse_version(version).unwrap())
+    }
+    
+    #[test]
+    fn constraints_follow_helm_syntax() {
+        assert!(matches("~1.14", "1.14.9"));
+        assert!(!matches("~1.14", "1.15.0"));
+        assert!(matches(">=0.9 <1.0", "0.9.4"));
+        assert!(!matches(">=0.9 <1.0", "1.0.0"));
+        assert!(matches(">= 0.9, < 1.0", "0.9.0"));
+        assert!(matches("1.2 - 1.4", "1.4.0"));
+        assert!(!matches("1.2 - 1.4", "1.5.0"));
+        assert!(matches("^2.0 || ~1.14", "1.14.2"));
+        assert!(matches("1.14", "1.14.3"));
+        assert!(matches("1.14.x", "1.14.3"));
+        assert!(matches("v1.2.3", "1.2.3"));
+        assert!(!matches("~1.14", "1.14.5-rc.1"));
+        assert!(Constraint::parse(">=").is_err());
+        assert!(Constraint::parse("latest").is_err());
+    }
+    
+    #[test]
+    fn resolves_newest_matching_version() {
+        let index = ChartIndex::parse(&std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/charts/index.yaml")).unwrap()).unwrap();
+        
+        assert_eq!(index.resolve("loki-stack", &Constraint::parse("~2.9").unwrap()), Some("2.9.11"));
+        assert_eq!(index.resolve("loki-stack", &Constraint::parse(">=2.8 <2.9").unwrap()), Some("v2.8.9"));
+        assert_eq!(index.resolve("loki-stack", &Constraint::parse("~3.0").unwrap()), None);
+        assert_eq!(index.latest("loki-stack"), Some("2.10.2"));
+        assert_eq!(index.resolve("promtail", &Constraint::parse("*").unwrap()), None);
+        assert!(is_newer("2.10.2", "v2.9.11"));
+    }
+}
@@ -0,0 +1,578 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::core::v1::{
+    EnvVar, PersistentVolumeClaim, ResourceRequirements, Toleration, Volume, VolumeMount,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::ResourceExt;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::crd::{self, ConditionOperator, GitProvider, StepCondition};
+use crate::error::Error;
+
+use super::references::{self, Reference};
+use super::{execution, object, order, to_value, trigger, workspace, Object};
+
+/// API version of the Argo Workflows kinds the operator generates
+pub const API_VERSION: &str = "argoproj.io/v1alpha1";
+
+/// DAG template running the pipeline's steps
+const ENTRYPOINT: &str = "main";
+
+/// Exit handler DAG running the pipeline's `finally` steps
+const EXIT_HANDLER: &str = "finally";
+
+/// Directory steps write their results to, read back as output parameters
+const RESULTS_DIR: &str = "/tmp/zerg/results";
+
+pub type WorkflowTemplate = Object<WorkflowSpec>;
+pub type CronWorkflow = Object<CronWorkflowSpec>;
+pub type Workflow = Object<WorkflowRunSpec>;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkflowSpec {
+    pub workflow_metadata: WorkflowMetadata,
+    pub entrypoint: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_exit: Option<String>,
+    pub arguments: Arguments,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub volume_claim_templates: Vec<PersistentVolumeClaim>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<Volume>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_deadline_seconds: Option<i64>,
+    #[serde(flatten)]
+    pub pod: PodSettings,
+    pub templates: Vec<Template>,
+}
+
+/// ServiceAccount and scheduling of the pods of a workflow or template
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PodSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_account_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_selector: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tolerations: Vec<Toleration>,
+}
+
+/// Metadata Argo applies to every Workflow submitted from the template
+#[derive(Serialize, Debug, Clone)]
+pub struct WorkflowMetadata {
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Arguments {
+    pub parameters: Vec<Parameter>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Parameter {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Template {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inputs: Option<Inputs>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outputs: Option<Outputs>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dag: Option<Dag>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<Container>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_strategy: Option<RetryStrategy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_deadline_seconds: Option<i64>,
+    #[serde(flatten)]
+    pub pod: PodSettings,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RetryStrategy {
+    pub limit: u32,
+}
+
+/// Parameters and artifacts a template takes, or a DAG task passes it
+#[derive(Serialize, Debug, Clone)]
+pub struct Inputs {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<Parameter>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<Artifact>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Outputs {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<OutputParameter>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<Artifact>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputParameter {
+    pub name: String,
+    pub value_from: ValueFrom,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ValueFrom {
+    pub path: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Artifact {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Dag {
+    pub tasks: Vec<DagTask>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DagTask {
+    pub name: String,
+    pub template: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arguments: Option<Inputs>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Container {
+    pub image: String,
+    pub command: Vec<String>,
+    pub args: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<EnvVar>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub volume_mounts: Vec<VolumeMount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceRequirements>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CronWorkflowSpec {
+    pub schedule: String,
+    pub workflow_spec: CronWorkflowTemplate,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CronWorkflowTemplate {
+    pub workflow_template_ref: TemplateRef,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkflowRunSpec {
+    pub workflow_template_ref: TemplateRef,
+    pub arguments: Arguments,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TemplateRef {
+    pub name: String,
+}
+
+/// Name of the container template running `step`
+fn template_name(step: &crd::PipelineStep) -> String {
+    format!("step-{}", step.name)
+}
+
+/// Quoted govaluate string literal
+fn literal(step: &crd::PipelineStep, value: &str) -> Result<String, Error> {
+    if value.contains(['\'', '{', '}']) {
+        return Err(Error::CiCdError(format!(
+            "Condition value {:?} of step {} cannot be expressed for Argo Workflows",
+            value, step.name
+        )));
+    }
+    
+    Ok(format!("'{}'", value))
+}
+
+/// `when` expression requiring every condition of `step` to hold
+fn when(step: &crd::PipelineStep, conditions: &[StepCondition]) -> Result<Option<String>, Error> {
+    let mut expressions = Vec::new();
+    
+    for condition in conditions {
+        let (comparison, join) = match condition.operator {
+            ConditionOperator::In => ("==", " || "),
+            ConditionOperator::NotIn => ("!=", " && "),
+        };
+        let input = format!("'{{{{workflow.parameters.{}}}}}'", condition.param);
+        let comparisons = condition
+            .values
+            .iter()
+            .map(|value| Ok(format!("{} {} {}", input, comparison, literal(step, value)?)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        
+        expressions.push(format!("({})", comparisons.join(join)));
+    }
+    
+    Ok((!expressions.is_empty()).then(|| expressions.join(" && ")))
+}
+
+/// Name of the input artifact of a template receiving artifact `name` of `step`
+fn input_artifact_name(input: &crd::InputArtifact) -> String {
+    format!("{}-{}", input.step, input.name)
+}
+
+/// Inputs passing `step` the results and artifacts of earlier steps it uses
+fn step_inputs(
+    step: &crd::PipelineStep,
+    parameter: impl Fn(&str, &str) -> Parameter,
+    artifact: impl Fn(&crd::InputArtifact) -> Artifact,
+) -> Option<Inputs> {
+    let inputs = Inputs {
+        parameters: references::result_inputs(step)
+            .iter()
+            .map(|(producer, name)| parameter(producer, name))
+            .collect(),
+        artifacts: step.input_artifacts.iter().flatten().map(artifact).collect(),
+    };
+    
+    (!inputs.parameters.is_empty() || !inputs.artifacts.is_empty()).then_some(inputs)
+}
+
+fn dag_task(step: &crd::PipelineStep, dependencies: &[&str]) -> Result<DagTask, Error> {
+    Ok(DagTask {
+        name: step.name.clone(),
+        template: template_name(step),
+        dependencies: dependencies.iter().map(|name| name.to_string()).collect(),
+        when: when(step, step.when.as_deref().unwrap_or_default())?,
+        arguments: step_inputs(
+            step,
+            |producer, name| Parameter {
+                name: references::result_param(producer, name),
+                value: Some(format!("{{{{tasks.{}.outputs.parameters.{}}}}}", producer, name)),
+                description: None,
+            },
+            |input| Artifact {
+                name: input_artifact_name(input),
+                path: None,
+                from: Some(format!("{{{{tasks.{}.outputs.artifacts.{}}}}}", input.step, input.name)),
+            },
+        ),
+    })
+}
+
+/// ServiceAccount and scheduling of pods with `settings`
+fn pod_settings(settings: &crd::ExecutionSettings) -> PodSettings {
+    PodSettings {
+        service_account_name: settings.service_account.clone(),
+        node_selector: execution::node_selector(settings),
+        tolerations: execution::tolerations(settings),
+    }
+}
+
+/// Container template running `step.commands` through `sh -c`, in the first
+/// workspace it mounts unless it sets a working directory. Results are
+/// collected from `RESULTS_DIR` as output parameters. Pod settings the step
+/// leaves unset come from the workflow.
+fn container_template(pipeline: &crd::Pipeline, step: &crd::PipelineStep) -> Template {
+    let volume_mounts: Vec<VolumeMount> = workspace::mounts(pipeline, step)
+        .iter()
+        .map(|mount| VolumeMount {
+            name: mount.workspace.clone(),
+            mount_path: workspace::mount_path(mount),
+            read_only: mount.read_only,
+            ..Default::default()
+        })
+        .collect();
+    let settings = execution::step_settings(pipeline, step);
+    
+    let mut resolved = references::rewrite_step(step, |reference| match reference {
+        Reference::Param(name) => Some(format!("{{{{workflow.parameters.{}}}}}", name)),
+        Reference::Result { step, name } => {
+            Some(format!("{{{{inputs.parameters.{}}}}}", references::result_param(step, name)))
+        }
+    });
+    let results = step.results.clone().unwrap_or_default();
+    if !results.is_empty() {
+        resolved.env.get_or_insert_with(Default::default).insert(references::RESULTS_VARIABLE.to_string(), RESULTS_DIR.to_string());
+        resolved.commands.insert(0, format!("mkdir -p \"${}\"", references::RESULTS_VARIABLE));
+    }
+    
+    let outputs = Outputs {
+        parameters: results
+            .iter()
+            .map(|result| OutputParameter {
+                name: result.name.clone(),
+                value_from: ValueFrom { path: format!("{}/{}", RESULTS_DIR, result.name) },
+            })
+            .collect(),
+        artifacts: step
+            .artifacts
+            .iter()
+            .flatten()
+            .map(|artifact| Artifact { name: artifact.name.clone(), path: Some(artifact.path.clone()), from: None })
+            .collect(),
+    };
+    
+    Template {
+        name: template_name(step),
+        inputs: step_inputs(
+            step,
+            |producer, name| Parameter { name: references::result_param(producer, name), value: None, description: None },
+            |input| Artifact { name: input_artifact_name(input), path: Some(input.path.clone()), from: None },
+        ),
+        outputs: (!outputs.parameters.is_empty() || !outputs.artifacts.is_empty()).then_some(outputs),
+        container: Some(Container {
+            image: step.image.clone(),
+            command: vec!["sh".to_string(), "-c".to_string()],
+            args: vec![super::script(&resolved)],
+            working_dir: step
+                .working_dir
+                .clone()
+                .or_else(|| volume_mounts.first().map(|mount| mount.mount_path.clone())),
+            env: super::env(&resolved),
+            volume_mounts,
+            resources: execution::resources(&settings),
+        }),
+        retry_strategy: settings.retries.map(|limit| RetryStrategy { limit }),
+        active_deadline_seconds: settings.timeout.as_deref().and_then(execution::seconds),
+        pod: pod_settings(&step.execution),
+        ..Default::default()
+    }
+}
+
+/// WorkflowTemplate running the steps as a DAG in dependency order, with the
+/// `finally` steps as exit handler, bounded by the pipeline's timeout
+pub fn workflow_template(pipeline: &crd::Pipeline, owner: &crd::DependencyManager) -> Result<WorkflowTemplate, Error> {
+    let run_after = order::run_after(pipeline)?;
+    let finally: Vec<&crd::PipelineStep> = pipeline.finally.iter().flatten().collect();
+    
+    let mut templates = vec![Template {
+        name: ENTRYPOINT.to_string(),
+        dag: Some(Dag {
+            tasks: pipeline
+                .steps
+                .iter()
+                .zip(&run_after)
+                .map(|(step, after)| dag_task(step, after))
+                .collect::<Result<_, _>>()?,
+        }),
+        ..Default::default()
+    }];
+    if !finally.is_empty() {
+        templates.push(Template {
+            name: EXIT_HANDLER.to_string(),
+            dag: Some(Dag {
+                tasks: finally.iter().map(|step| dag_task(step, &[])).collect::<Result<_, _>>()?,
+            }),
+            ..Default::default()
+        });
+    }
+    templates.extend(
+        pipeline
+            .steps
+            .iter()
+            .chain(finally.iter().copied())
+            .map(|step| container_template(pipeline, step)),
+    );
+    
+    // Volume claims are claimed afresh for every Workflow, the rest are plain volumes
+    let workspaces = workspace::workspaces(pipeline);
+    
+    let spec = WorkflowSpec {
+        workflow_metadata: WorkflowMetadata { labels: super::run_labels(owner, pipeline) },
+        entrypoint: ENTRYPOINT.to_string(),
+        on_exit: (!finally.is_empty()).then(|| EXIT_HANDLER.to_string()),
+        arguments: Arguments {
+            parameters: references::params(pipeline)
+                .into_iter()
+                .map(|param| Parameter { name: param.name, value: param.default, description: param.description })
+                .collect(),
+        },
+        volume_claim_templates: workspaces
+            .iter()
+            .filter(|workspace| workspace.type_ == crd::WorkspaceType::VolumeClaim)
+            .map(|workspace| PersistentVolumeClaim {
+                metadata: ObjectMeta { name: Some(workspace.name.clone()), ..Default::default() },
+                spec: Some(workspace::claim_spec(workspace)),
+                ..Default::default()
+            })
+            .collect(),
+        volumes: workspaces.iter().filter_map(|workspace| workspace::volume(owner, pipeline, workspace)).collect(),
+        active_deadline_seconds: pipeline.execution.timeout.as_deref().and_then(execution::seconds),
+        pod: pod_settings(&pipeline.execution),
+        templates,
+    };
+    
+    Ok(object(API_VERSION, "WorkflowTemplate", pipeline.name.clone(), &owner.namespace().unwrap_or_default(), spec))
+}
+
+/// CronWorkflow submitting the WorkflowTemplate on `schedule`
+pub fn cron_workflow(pipeline: &crd::Pipeline, schedule: &str, namespace: &str) -> CronWorkflow {
+    let spec = CronWorkflowSpec {
+        schedule: schedule.to_string(),
+        workflow_spec: CronWorkflowTemplate {
+            workflow_template_ref: TemplateRef { name: pipeline.name.clone() },
+        },
+    };
+    
+    object(API_VERSION, "CronWorkflow", format!("{}-cron", pipeline.name), namespace, spec)
+}
+
+/// Workflow submitting the WorkflowTemplate of `pipeline` once with `parameters`
+pub fn workflow(pipeline: &crd::Pipeline, owner: &crd::DependencyManager, parameters: Vec<Parameter>) -> Workflow {
+    Object {
+        api_version: API_VERSION,
+        kind: "Workflow",
+        metadata: ObjectMeta {
+            generate_name: Some(format!("{}-", pipeline.name)),
+            namespace: owner.namespace(),
+            labels: Some(super::run_labels(owner, pipeline)),
+            ..Default::default()
+        },
+        spec: WorkflowRunSpec {
+            workflow_template_ref: TemplateRef { name: pipeline.name.clone() },
+            arguments: Arguments { parameters },
+        },
+    }
+}
+
+/// The operator's webhook receiver is reachable by anyone who can reach the
+/// operator, so git triggers must name a secret the deliveries are signed with
+fn validate_trigger(pipeline: &crd::Pipeline) -> Result<(), Error> {
+    let Some(git) = &pipeline.trigger.git else {
+        return Ok(());
+    };
+    
+    if git.secret_ref.is_none() {
+        return Err(Error::CiCdError(format!(
+            "Pipeline {}: git triggers of Argo Workflows pipelines need a secret_ref", pipeline.name
+        )));
+    }
+    if trigger::provider(git) == GitProvider::Generic {
+        return Err(Error::CiCdError(format!(
+            "Pipeline {}: webhooks of generic git hosts cannot be verified", pipeline.name
+        )));
+    }
+    
+    Ok(())
+}
+
+/// Every object generated for `pipeline` of `owner`: its cache claims and
+/// WorkflowTemplate, plus a CronWorkflow when it has a schedule trigger
+pub fn manifests(pipeline: &crd::Pipeline, owner: &crd::DependencyManager) -> Result<Vec<Value>, Error> {
+    workspace::validate(pipeline)?;
+    references::validate(pipeline)?;
+    execution::validate(pipeline)?;
+    validate_trigger(pipeline)?;
+    
+    let namespace = &owner.namespace().unwrap_or_default();
+    let mut manifests = Vec::new();
+    for claim in workspace::cache_claims(pipeline, owner) {
+        manifests.push(to_value(&claim)?);
+    }
+    manifests.push(to_value(&workflow_template(pipeline, owner)?)?);
+    
+    // Workflows it submits carry the template's `workflowMetadata` labels
+    if let Some(schedule) = &pipeline.trigger.schedule {
+        manifests.push(to_value(&cron_workflow(pipeline, schedule, namespace))?);
+    }
+    
+    Ok(manifests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crd::tests::owner;
+    use crate::cicd::tests::{assert_golden, release_pipeline, to_yaml, workspace_pipeline};
+    
+    #[test]
+    fn renders_dag_conditions_and_exit_handler() {
+        let mut pipeline = release_pipeline();
+        pipeline.trigger.schedule = Some("0 3 * * *".to_string());
+        
+        let yaml = to_yaml(&manifests(&pipeline, &owner("apps", "platform")).unwrap()).unwrap();
+        assert_golden("argo/release.yaml", &yaml);
+    }
+    
+    #[test]
+    fn requires_verifiable_git_triggers() {
+        let mut pipeline = release_pipeline();
+        let git = pipeline.trigger.git.as_mut().unwrap();
+        git.secret_ref = None;
+        let error = manifests(&pipeline, &owner("apps", "platform")).unwrap_err().to_string();
+        assert!(error.contains("need a secret_ref"), "{}", error);
+        
+        let git = pipeline.trigger.git.as_mut().unwrap();
+        git.secret_ref = Some("webhook".to_string());
+        git.provider = Some(GitProvider::Generic);
+        assert!(manifests(&pipeline, &owner("apps", "platform")).is_err());
+    }
+    
+    #[test]
+    fn mounts_workspaces_and_secret_env() {
+        let yaml = to_yaml(&manifests(&workspace_pipeline(), &owner("apps", "platform")).unwrap()).unwrap();
+        assert_golden("argo/workspaces.yaml", &yaml);
+    }
+    
+    #[test]
+    fn passes_results_and_artifacts() {
+        let yaml = to_yaml(&manifests(&crate::cicd::tests::results_pipeline(), &owner("apps", "platform")).unwrap()).unwrap();
+        assert_golden("argo/results.yaml", &yaml);
+    }
+    
+    #[test]
+    fn applies_resources_retries_and_pod_settings() {
+        let template = to_value(&workflow_template(&crate::cicd::tests::tuned_pipeline(), &owner("apps", "platform")).unwrap()).unwrap();
+        let spec = &template["spec"];
+        assert_eq!(spec["activeDeadlineSeconds"], 5400);
+        assert_eq!(spec["serviceAccountName"], "builder");
+        assert_eq!(spec["nodeSelector"]["accelerator"], "gpu");
+        assert_eq!(spec["tolerations"][0]["effect"], "NoSchedule");
+        
+        let build = &spec["templates"][2];
+        assert_eq!(build["name"], "step-build");
+        assert_eq!(build["container"]["resources"]["limits"]["memory"], "8Gi");
+        assert_eq!(build["retryStrategy"]["limit"], 2);
+        assert_eq!(build["activeDeadlineSeconds"], 2700);
+        assert!(build.get("serviceAccountName").is_none());
+        assert_eq!(spec["templates"][3]["serviceAccountName"], "notifier");
+    }
+    
+    #[test]
+    fn rejects_values_argo_cannot_quote() {
+        let mut pipeline = release_pipeline();
+        pipeline.steps[0].when = Some(vec![StepCondition {
+            param: "git-revision".to_string(),
+            operator: ConditionOperator::In,
+            values: vec!["it's".to_string()],
+        }]);
+        
+        assert!(workflow_template(&pipeline, &owner("apps", "platform")).is_err());
+    }
+}
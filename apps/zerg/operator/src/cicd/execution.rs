@@ -0,0 +1,237 @@
+use std::collections::{BTreeMap, HashMap};
+
+use k8s_openapi::api::core::v1::{ResourceRequirements, Toleration};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+
+use crate::config::CiCdTemplate;
+use crate::crd::{ExecutionSettings, Pipeline, PipelineStep, StepResources};
+use crate::error::Error;
+
+const TOLERATION_OPERATORS: &[&str] = &["Equal", "Exists"];
+
+const TAINT_EFFECTS: &[&str] = &["NoSchedule", "PreferNoSchedule", "NoExecute"];
+
+/// `settings` with the fields it leaves unset taken from `fallback`; requests
+/// and limits are taken separately
+fn merge(settings: &ExecutionSettings, fallback: &ExecutionSettings) -> ExecutionSettings {
+    let resources = match (&settings.resources, &fallback.resources) {
+        (Some(resources), Some(fallback)) => Some(StepResources {
+            requests: resources.requests.clone().or_else(|| fallback.requests.clone()),
+            limits: resources.limits.clone().or_else(|| fallback.limits.clone()),
+        }),
+        (resources, fallback) => resources.clone().or_else(|| fallback.clone()),
+    };
+    
+    ExecutionSettings {
+        resources,
+        retries: settings.retries.or(fallback.retries),
+        timeout: settings.timeout.clone().or_else(|| fallback.timeout.clone()),
+        node_selector: settings.node_selector.clone().or_else(|| fallback.node_selector.clone()),
+        tolerations: settings.tolerations.clone().or_else(|| fallback.tolerations.clone()),
+        service_account: settings.service_account.clone().or_else(|| fallback.service_account.clone()),
+    }
+}
+
+/// `pipeline` with the settings it leaves unset taken from the `defaults` of
+/// the provider's `cicd_templates` entry
+pub fn with_defaults(pipeline: &Pipeline, template: Option<&CiCdTemplate>) -> Pipeline {
+    let Some(template) = template else {
+        return pipeline.clone();
+    };
+    
+    Pipeline {
+        execution: merge(&pipeline.execution, &template.defaults),
+        ..pipeline.clone()
+    }
+}
+
+/// Settings `step` runs with: its own, then those of the pipeline except for
+/// the timeout, which bounds the whole run
+pub fn step_settings(pipeline: &Pipeline, step: &PipelineStep) -> ExecutionSettings {
+    let inherited = ExecutionSettings { timeout: None, ..pipeline.execution.clone() };
+    merge(&step.execution, &inherited)
+}
+
+/// Whether `step` sets any of the settings of the pod it runs in, rather than
+/// inheriting those of the run
+pub fn sets_pod_settings(step: &PipelineStep) -> bool {
+    let settings = &step.execution;
+    settings.node_selector.is_some() || settings.tolerations.is_some() || settings.service_account.is_some()
+}
+
+/// Seconds in a duration such as `90s`, `30m` or `1h30m`, or `None` if it is
+/// not one or is zero
+pub fn seconds(duration: &str) -> Option<i64> {
+    let mut total: i64 = 0;
+    let mut digits = String::new();
+    
+    for c in duration.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        let value: i64 = digits.parse().ok()?;
+        total = total.checked_add(value.checked_mul(unit)?)?;
+        digits.clear();
+    }
+    
+    (digits.is_empty() && total > 0).then_some(total)
+}
+
+/// Resource requirements of a step with `settings`
+pub fn resources(settings: &ExecutionSettings) -> Option<ResourceRequirements> {
+    let quantities = |values: &Option<HashMap<String, String>>| {
+        values
+            .as_ref()
+            .map(|values| values.iter().map(|(name, value)| (name.clone(), Quantity(value.clone()))).collect())
+    };
+    
+    settings.resources.as_ref().map(|resources| ResourceRequirements {
+        requests: quantities(&resources.requests),
+        limits: quantities(&resources.limits),
+        ..Default::default()
+    })
+}
+
+/// Node selector of `settings`, sorted
+pub fn node_selector(settings: &ExecutionSettings) -> Option<BTreeMap<String, String>> {
+    settings.node_selector.as_ref().map(|selector| selector.clone().into_iter().collect())
+}
+
+/// Tolerations of `settings`
+pub fn tolerations(settings: &ExecutionSettings) -> Vec<Toleration> {
+    settings
+        .tolerations
+        .iter()
+        .flatten()
+        .map(|toleration| Toleration {
+            key: toleration.key.clone(),
+            operator: toleration.operator.clone(),
+            value: toleration.value.clone(),
+            effect: toleration.effect.clone(),
+            toleration_seconds: toleration.toleration_seconds,
+        })
+        .collect()
+}
+
+fn validate_settings<E>(settings: &ExecutionSettings, invalid: impl Fn(String) -> E) -> Result<(), E> {
+    if let Some(timeout) = settings.timeout.as_ref().filter(|timeout| seconds(timeout).is_none()) {
+        return Err(invalid(format!("has timeout {}, which is not a duration like 30m or 1h30m", timeout)));
+    }
+    
+    for toleration in settings.tolerations.iter().flatten() {
+        if let Some(operator) = toleration.operator.as_deref().filter(|op| !TOLERATION_OPERATORS.contains(op)) {
+            return Err(invalid(format!("tolerates with unknown operator {}", operator)));
+        }
+        if let Some(effect) = toleration.effect.as_deref().filter(|effect| !TAINT_EFFECTS.contains(effect)) {
+            return Err(invalid(format!("tolerates unknown taint effect {}", effect)));
+        }
+        if toleration.operator.as_deref() == Some("Exists") && toleration.value.is_some() {
+            return Err(invalid("tolerates with operator Exists and a value".to_string()));
+        }
+    }
+    
+    Ok(())
+}
+
+/// Checks the timeouts and tolerations of `pipeline` and its steps
+pub fn validate(pipeline: &Pipeline) -> Result<(), Error> {
+    validate_settings(&pipeline.execution, |message| {
+        Error::CiCdError(format!("Pipeline {}: {}", pipeline.name, message))
+    })?;
+    
+    for step in pipeline.steps.iter().chain(pipeline.finally.iter().flatten()) {
+        validate_settings(&step.execution, |message| {
+            Error::CiCdError(format!("Pipeline {}: step {} {}", pipeline.name, step.name, message))
+        })?;
+    }
+    
+    Ok(())
+}
+
+/// Checks the timeout and tolerations of CI/CD template defaults, describing
+/// the first problem
+pub fn validate_defaults(defaults: &ExecutionSettings) -> Result<(), String> {
+    validate_settings(defaults, |message| message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cicd::tests::{pipeline, step};
+    use crate::crd::StepToleration;
+    
+    #[test]
+    fn steps_inherit_pipeline_settings_and_template_defaults() {
+        let mut build = step("build");
+        build.execution.resources = Some(StepResources {
+            requests: None,
+            limits: Some(HashMap::from([("memory".to_string(), "4Gi".to_string())])),
+        });
+        build.execution.retries = Some(0);
+        let mut release = pipeline("release", vec![build, step("notify")]);
+        release.execution.timeout = Some("1h".to_string());
+        release.execution.retries = Some(2);
+        
+        let template = CiCdTemplate {
+            name: "tekton".to_string(),
+            provider: "tekton".to_string(),
+            config: HashMap::new(),
+            steps: HashMap::new(),
+            defaults: ExecutionSettings {
+                resources: Some(StepResources {
+                    requests: Some(HashMap::from([("cpu".to_string(), "500m".to_string())])),
+                    limits: Some(HashMap::from([("memory".to_string(), "1Gi".to_string())])),
+                }),
+                timeout: Some("2h".to_string()),
+                service_account: Some("builder".to_string()),
+                ..Default::default()
+            },
+        };
+        let release = with_defaults(&release, Some(&template));
+        assert_eq!(release.execution.timeout.as_deref(), Some("1h"));
+        
+        let build = step_settings(&release, &release.steps[0]);
+        let resources = build.resources.unwrap();
+        assert_eq!(resources.requests.unwrap()["cpu"], "500m");
+        assert_eq!(resources.limits.unwrap()["memory"], "4Gi");
+        assert_eq!(build.retries, Some(0));
+        assert_eq!(build.service_account.as_deref(), Some("builder"));
+        // The pipeline timeout bounds the run rather than each step
+        assert_eq!(build.timeout, None);
+        assert_eq!(step_settings(&release, &release.steps[1]).retries, Some(2));
+    }
+    
+    #[test]
+    fn parses_durations_and_rejects_invalid_settings() {
+        assert_eq!(seconds("90s"), Some(90));
+        assert_eq!(seconds("1h30m"), Some(5400));
+        assert_eq!(seconds("0s"), None);
+        assert_eq!(seconds("30"), None);
+        assert_eq!(seconds("ms"), None);
+        assert_eq!(seconds("1d"), None);
+        
+        let mut build = step("build");
+        build.execution.timeout = Some("ten minutes".to_string());
+        assert!(validate(&pipeline("build", vec![build.clone()])).is_err());
+        
+        build.execution.timeout = Some("10m".to_string());
+        build.execution.tolerations = Some(vec![StepToleration {
+            key: Some("gpu".to_string()),
+            operator: Some("Exists".to_string()),
+            value: Some("true".to_string()),
+            effect: None,
+            toleration_seconds: None,
+        }]);
+        assert!(validate(&pipeline("build", vec![build.clone()])).is_err());
+        
+        build.execution.tolerations.as_mut().unwrap()[0].value = None;
+        assert!(validate(&pipeline("build", vec![build])).is_ok());
+    }
+}
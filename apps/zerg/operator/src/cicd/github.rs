@@ -0,0 +1,421 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use serde::Serialize;
+
+use crate::config::CiCdTemplate;
+use crate::crd::{self, ConditionOperator, ParamType, StepCondition};
+use crate::error::Error;
+
+use super::hosted::{self, Ordered};
+use super::references::{self, Reference};
+use super::{execution, order, trigger};
+
+/// Runner jobs run on unless the `github-actions` template sets `runs_on`
+const DEFAULT_RUNNER: &str = "ubuntu-latest";
+
+/// Id of the workflow step running the commands, whose outputs carry the results
+const RUN_STEP: &str = "run";
+
+/// Checkout root, which artifacts are staged under for upload
+const ARTIFACT_ROOT: &str = "$GITHUB_WORKSPACE";
+
+/// Action checking out the repository, which GitLab CI does for every job
+/// and GitHub Actions leaves to the workflow
+const CHECKOUT: &str = "actions/checkout@v4";
+const DOWNLOAD_ARTIFACT: &str = "actions/download-artifact@v4";
+const UPLOAD_ARTIFACT: &str = "actions/upload-artifact@v4";
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Workflow {
+    pub name: String,
+    pub on: Triggers,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    pub jobs: Ordered<Job>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Triggers {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub push: Option<Filter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pull_request: Option<Filter>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub schedule: Vec<Schedule>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workflow_dispatch: Option<Dispatch>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Filter {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub branches: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Schedule {
+    pub cron: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Dispatch {
+    #[serde(skip_serializing_if = "Ordered::is_empty")]
+    pub inputs: Ordered<Input>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Input {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "type")]
+    pub type_: &'static str,
+    pub required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct Job {
+    pub runs_on: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub needs: Vec<String>,
+    #[serde(rename = "if", skip_serializing_if = "Option::is_none")]
+    pub if_: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_minutes: Option<i64>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub outputs: BTreeMap<String, String>,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct Step {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uses: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub with: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_directory: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run: Option<String>,
+}
+
+/// Name of the workflow file of `pipeline` under `.github/workflows`
+pub fn file_name(pipeline: &crd::Pipeline) -> String {
+    format!("zerg-{}.yml", pipeline.name)
+}
+
+/// Quoted expression string literal
+fn literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// Expression evaluating to param `name` of `pipeline`: the trigger params
+/// from the `github` context, the others from the dispatch inputs when runs
+/// can be dispatched, falling back to their defaults
+fn param_expression(pipeline: &crd::Pipeline, name: &str) -> String {
+    match name {
+        "git-repo-url" => "format('{0}/{1}', github.server_url, github.repository)".to_string(),
+        "git-revision" => "github.sha".to_string(),
+        "git-branch" => "(github.base_ref || github.ref_name)".to_string(),
+        _ => {
+            let default = pipeline
+                .params
+                .iter()
+                .flatten()
+                .find(|param| param.name == name)
+                .and_then(|param| param.default.as_deref());
+            match (pipeline.trigger.manual, default) {
+                (true, Some(default)) => format!("(inputs.{} || {})", name, literal(default)),
+                (true, None) => format!("inputs.{}", name),
+                (false, default) => literal(default.unwrap_or_default()),
+            }
+        }
+    }
+}
+
+/// Expression requiring every condition to hold
+fn conditions(pipeline: &crd::Pipeline, conditions: &[StepCondition]) -> Vec<String> {
+    conditions
+        .iter()
+        .map(|condition| {
+            let (comparison, join) = match condition.operator {
+                ConditionOperator::In => ("==", " || "),
+                ConditionOperator::NotIn => ("!=", " && "),
+            };
+            let param = param_expression(pipeline, &condition.param);
+            let comparisons: Vec<String> = condition
+                .values
+                .iter()
+                .map(|value| format!("{} {} {}", param, comparison, literal(value)))
+                .collect();
+            format!("({})", comparisons.join(join))
+        })
+        .collect()
+}
+
+/// `on` of the workflow of `pipeline`, reporting git events GitHub Actions
+/// cannot be triggered by
+fn triggers(pipeline: &crd::Pipeline, report: &mut Vec<String>) -> Triggers {
+    let mut triggers = Triggers::default();
+    
+    if let Some(git) = &pipeline.trigger.git {
+        // Branch filters only match across `/` with `**`
+        let branches: Vec<String> = git.branches.iter().map(|branch| branch.replace("**", "*").replace('*', "**")).collect();
+        for event in trigger::events(git) {
+            match event {
+                "push" => {
+                    let push = triggers.push.get_or_insert_with(Filter::default);
+                    push.branches = if branches.is_empty() { vec!["**".to_string()] } else { branches.clone() };
+                }
+                "tag_push" => triggers.push.get_or_insert_with(Filter::default).tags = vec!["**".to_string()],
+                "pull_request" => triggers.pull_request = Some(Filter { branches: branches.clone(), tags: Vec::new() }),
+                event => report.push(format!("event {} is not supported by GitHub Actions and is ignored", event)),
+            }
+        }
+    }
+    
+    if let Some(cron) = &pipeline.trigger.schedule {
+        triggers.schedule.push(Schedule { cron: cron.clone() });
+    }
+    
+    if pipeline.trigger.manual {
+        let inputs = pipeline
+            .params
+            .iter()
+            .flatten()
+            .map(|param| {
+                let input = Input {
+                    description: param.description.clone(),
+                    type_: if param.type_ == ParamType::Boolean { "boolean" } else { "string" },
+                    required: param.default.is_none(),
+                    default: param.default.clone(),
+                };
+                (param.name.clone(), input)
+            })
+            .collect();
+        triggers.workflow_dispatch = Some(Dispatch { inputs: Ordered(inputs) });
+    }
+    
+    triggers
+}
+
+/// Job running `step` after `needs` in a container of its image, gated by
+/// `status` and its conditions. The repository is checked out and artifacts
+/// are downloaded before the commands, artifacts are uploaded after them, and
+/// results are passed on as job outputs.
+fn job(pipeline: &crd::Pipeline, step: &crd::PipelineStep, needs: Vec<String>, status: Option<&str>, runs_on: &str) -> Job {
+    let settings = execution::step_settings(pipeline, step);
+    let variable = |reference: Reference| match reference {
+        Reference::Param(name) => hosted::variable(name),
+        Reference::Result { step, name } => hosted::variable(&references::result_param(step, name)),
+    };
+    
+    let mut env: BTreeMap<String, String> = step
+        .env
+        .iter()
+        .flatten()
+        .map(|(name, value)| {
+            let value = references::rewrite(value, &|reference| match reference {
+                Reference::Param(_) => Some(format!("${{{{ env.{} }}}}", variable(reference))),
+                Reference::Result { step, name } => Some(format!("${{{{ needs.{}.outputs.{} }}}}", step, name)),
+            });
+            (name.clone(), value)
+        })
+        .collect();
+    for secret in step.secret_env.iter().flatten() {
+        let value = format!("${{{{ secrets.{} }}}}", hosted::secret_variable(&secret.secret, &secret.key));
+        env.insert(secret.name.clone(), value);
+    }
+    for (producer, name) in references::result_inputs(step) {
+        let value = format!("${{{{ needs.{}.outputs.{} }}}}", producer, name);
+        env.insert(hosted::variable(&references::result_param(&producer, &name)), value);
+    }
+    
+    let results: Vec<String> = step.results.iter().flatten().map(|result| result.name.clone()).collect();
+    if !results.is_empty() {
+        env.insert(references::RESULTS_VARIABLE.to_string(), hosted::RESULTS_DIR.to_string());
+    }
+    
+    let mut resolved = crd::PipelineStep {
+        commands: step
+            .commands
+            .iter()
+            .map(|command| references::rewrite(command, &|reference| Some(format!("${{{}}}", variable(reference)))))
+            .collect(),
+        ..step.clone()
+    };
+    if !results.is_empty() {
+        resolved.commands.insert(0, format!("mkdir -p \"${}\"", references::RESULTS_VARIABLE));
+        resolved.commands.extend(results.iter().map(|name| {
+            format!(
+                "printf '{name}<<ZERG_EOF\\n%s\\nZERG_EOF\\n' \"$(cat \"${dir}/{name}\")\" >> \"$GITHUB_OUTPUT\"",
+                name = name,
+                dir = references::RESULTS_VARIABLE,
+            )
+        }));
+    }
+    let resolved = references::copy_artifacts(resolved, ARTIFACT_ROOT);
+    
+    let mut steps = vec![Step { uses: Some(CHECKOUT.to_string()), ..Default::default() }];
+    let producers: BTreeSet<&str> = step.input_artifacts.iter().flatten().map(|input| input.step.as_str()).collect();
+    for producer in producers {
+        steps.push(Step {
+            uses: Some(DOWNLOAD_ARTIFACT.to_string()),
+            with: BTreeMap::from([
+                ("name".to_string(), producer.to_string()),
+                ("path".to_string(), format!("{}/{}", references::ARTIFACTS_DIR, producer)),
+            ]),
+            ..Default::default()
+        });
+    }
+    steps.push(Step {
+        id: Some(RUN_STEP.to_string()),
+        shell: Some("sh".to_string()),
+        working_directory: step.working_dir.clone(),
+        env,
+        run: Some(super::script(&resolved)),
+        ..Default::default()
+    });
+    if step.artifacts.iter().flatten().next().is_some() {
+        steps.push(Step {
+            uses: Some(UPLOAD_ARTIFACT.to_string()),
+            with: BTreeMap::from([
+                ("name".to_string(), step.name.clone()),
+                ("path".to_string(), format!("{}/{}", references::ARTIFACTS_DIR, step.name)),
+                ("include-hidden-files".to_string(), "true".to_string()),
+            ]),
+            ..Default::default()
+        });
+    }
+    
+    let mut gates: Vec<String> = status.into_iter().map(str::to_string).collect();
+    gates.extend(conditions(pipeline, step.when.as_deref().unwrap_or_default()));
+    
+    Job {
+        runs_on: runs_on.to_string(),
+        needs,
+        if_: (!gates.is_empty()).then(|| format!("${{{{ {} }}}}", gates.join(" && "))),
+        container: (!step.image.is_empty()).then(|| step.image.clone()),
+        timeout_minutes: settings.timeout.as_deref().and_then(execution::seconds).map(|seconds| (seconds + 59) / 60),
+        outputs: results
+            .iter()
+            .map(|name| (name.clone(), format!("${{{{ steps.{}.outputs.{} }}}}", RUN_STEP, name)))
+            .collect(),
+        steps,
+    }
+}
+
+/// Workflow running the steps of `pipeline` as jobs in dependency order, with
+/// the `finally` steps as jobs that always run once the others finish, along
+/// with the compatibility report of the pipeline
+pub fn workflow(pipeline: &crd::Pipeline, template: Option<&CiCdTemplate>) -> Result<(Workflow, Vec<String>), Error> {
+    let run_after = order::run_after(pipeline)?;
+    let runs_on = hosted::template_value(template, "runs_on")
+        .and_then(|value| value.as_str())
+        .unwrap_or(DEFAULT_RUNNER);
+    let mut report = hosted::report(pipeline, "repository secret");
+    let on = triggers(pipeline, &mut report);
+    
+    let retried = std::iter::once(("", &pipeline.execution))
+        .chain(pipeline.steps.iter().chain(pipeline.finally.iter().flatten()).map(|s| (s.name.as_str(), &s.execution)));
+    for (step, _) in retried.filter(|(_, settings)| settings.retries.is_some_and(|retries| retries > 0)) {
+        let subject = if step.is_empty() { String::new() } else { format!("step {}: ", step) };
+        report.push(format!("{}retries are ignored, GitHub Actions does not retry jobs", subject));
+    }
+    
+    // Jobs after a conditional one run when it is skipped, like the other providers' steps
+    let conditional: HashSet<&str> = pipeline
+        .steps
+        .iter()
+        .filter(|step| step.when.is_some())
+        .map(|step| step.name.as_str())
+        .collect();
+    
+    let mut jobs = Vec::new();
+    for (step, after) in pipeline.steps.iter().zip(&run_after) {
+        let needs = hosted::needs(step, after);
+        let status = (step.when.is_some() || needs.iter().any(|need| conditional.contains(need.as_str())))
+            .then_some("!failure() && !cancelled()");
+        jobs.push((step.name.clone(), job(pipeline, step, needs, status, runs_on)));
+    }
+    let everything: Vec<String> = pipeline.steps.iter().map(|step| step.name.clone()).collect();
+    for step in pipeline.finally.iter().flatten() {
+        jobs.push((step.name.clone(), job(pipeline, step, everything.clone(), Some("always()"), runs_on)));
+    }
+    
+    let env = references::params(pipeline)
+        .iter()
+        .map(|param| (hosted::variable(&param.name), format!("${{{{ {} }}}}", param_expression(pipeline, &param.name))))
+        .collect();
+    
+    let workflow = Workflow { name: pipeline.name.clone(), on, env, jobs: Ordered(jobs) };
+    Ok((workflow, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cicd::tests::{assert_golden, release_pipeline, results_pipeline, tuned_pipeline, workspace_pipeline};
+    
+    fn render(pipeline: &crd::Pipeline) -> String {
+        let (workflow, _) = workflow(pipeline, None).unwrap();
+        serde_yaml::to_string(&workflow).unwrap()
+    }
+    
+    #[test]
+    fn renders_jobs_conditions_and_finally() {
+        let mut pipeline = release_pipeline();
+        pipeline.trigger.schedule = Some("0 3 * * *".to_string());
+        pipeline.trigger.git.as_mut().unwrap().branches.push("release/*".to_string());
+        
+        assert_golden("github/release.yaml", &render(&pipeline));
+    }
+    
+    #[test]
+    fn passes_results_and_artifacts() {
+        assert_golden("github/results.yaml", &render(&results_pipeline()));
+    }
+    
+    #[test]
+    fn reports_what_github_actions_cannot_express() {
+        let (workflow, report) = super::workflow(&tuned_pipeline(), None).unwrap();
+        let build = &workflow.jobs.0[0].1;
+        assert_eq!(build.timeout_minutes, Some(45));
+        assert_eq!(
+            report,
+            [
+                "run timeout 1h30m cannot be set in the workflow and is ignored",
+                "node selector and tolerations are ignored, runners are picked by label",
+                "service account builder is ignored",
+                "step build: resources are ignored, runners have fixed sizes",
+                "step notify: service account notifier is ignored",
+                "workspace shared-data is not shared, steps run as separate jobs and pass files on as artifacts",
+                "step build: retries are ignored, GitHub Actions does not retry jobs",
+            ]
+        );
+        
+        let (_, report) = super::workflow(&workspace_pipeline(), None).unwrap();
+        assert_eq!(
+            report,
+            [
+                "workspace source is not shared, steps run as separate jobs and pass files on as artifacts",
+                "cache workspace cargo is not kept between runs",
+                "workspace settings cannot be mounted from the cluster and is ignored",
+                "step publish: REGISTRY_TOKEN is read from the repository secret REGISTRY_TOKEN instead of key token of Secret registry",
+            ]
+        );
+    }
+}
@@ -0,0 +1,355 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::config::CiCdTemplate;
+use crate::crd::{self, ConditionOperator, StepCondition};
+use crate::error::Error;
+
+use super::hosted::{self, Ordered};
+use super::references::{self, Reference};
+use super::{execution, order, trigger, PARAMS};
+
+/// Variable selecting the pipeline of manual and scheduled runs, as every
+/// pipeline of the repository shares `.gitlab-ci.yml`
+pub const PIPELINE_VARIABLE: &str = "ZERG_PIPELINE";
+
+/// Checkout root, which artifacts are staged under for upload
+const ARTIFACT_ROOT: &str = "$CI_PROJECT_DIR";
+
+/// Directory under the checkout holding the dotenv reports results are passed on in
+const DOTENV_DIR: &str = ".results";
+
+/// Most retries GitLab allows a job
+const MAX_RETRIES: u32 = 2;
+
+/// Values of the trigger params in GitLab's predefined variables; a merge
+/// request pipeline has a target branch instead of a branch
+const TRIGGER_VALUES: &[(&str, &str)] = &[
+    ("git-repo-url", "$CI_PROJECT_URL"),
+    ("git-revision", "$CI_COMMIT_SHA"),
+    ("git-branch", "$CI_COMMIT_BRANCH$CI_MERGE_REQUEST_TARGET_BRANCH_NAME"),
+];
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CiConfig {
+    pub variables: Ordered<Variable>,
+    #[serde(flatten)]
+    pub jobs: Ordered<Job>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Variable {
+    pub value: String,
+    pub description: String,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Job {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    pub needs: Vec<String>,
+    pub rules: Vec<Rule>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+    pub script: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifacts: Option<Artifacts>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Rule {
+    #[serde(rename = "if", skip_serializing_if = "Option::is_none")]
+    pub if_: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub when: Option<&'static str>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Artifacts {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reports: Option<Reports>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Reports {
+    pub dotenv: String,
+}
+
+/// Name of the job running `step` of `pipeline`
+fn job_name(pipeline: &crd::Pipeline, step: &str) -> String {
+    format!("{}:{}", pipeline.name, step)
+}
+
+/// Single-quoted shell word
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Script line ending the job successfully unless every condition holds
+fn guard(step: &crd::PipelineStep, conditions: &[StepCondition]) -> Option<String> {
+    let tests: Vec<String> = conditions
+        .iter()
+        .map(|condition| {
+            let (comparison, join) = match condition.operator {
+                ConditionOperator::In => ("=", " || "),
+                ConditionOperator::NotIn => ("!=", " && "),
+            };
+            let variable = hosted::variable(&condition.param);
+            let comparisons: Vec<String> = condition
+                .values
+                .iter()
+                .map(|value| format!("[ \"${}\" {} {} ]", variable, comparison, quote(value)))
+                .collect();
+            format!("{{ {}; }}", comparisons.join(join))
+        })
+        .collect();
+    
+    (!tests.is_empty()).then(|| {
+        format!("{} || {{ echo \"Skipping {}, its conditions do not hold\"; exit 0; }}", tests.join(" && "), step.name)
+    })
+}
+
+/// `rules:if` expressions matching the pipelines `pipeline` is triggered by,
+/// reporting triggers `.gitlab-ci.yml` cannot express
+fn triggers(pipeline: &crd::Pipeline, report: &mut Vec<String>) -> Vec<String> {
+    let mut expressions = Vec::new();
+    
+    if let Some(git) = &pipeline.trigger.git {
+        let branches = trigger::branch_regex(&git.branches, "").map(|regex| regex.replace('/', "\\/"));
+        let on_branch = |variable: &str| {
+            branches
+                .as_ref()
+                .map(|regex| format!(" && ${} =~ /{}/", variable, regex))
+                .unwrap_or_default()
+        };
+        for event in trigger::events(git) {
+            match event {
+                "push" => expressions.push(format!("$CI_PIPELINE_SOURCE == \"push\" && $CI_COMMIT_BRANCH{}", on_branch("CI_COMMIT_BRANCH"))),
+                "tag_push" => expressions.push("$CI_COMMIT_TAG".to_string()),
+                "pull_request" | "merge_request" => expressions.push(format!(
+                    "$CI_PIPELINE_SOURCE == \"merge_request_event\"{}",
+                    on_branch("CI_MERGE_REQUEST_TARGET_BRANCH_NAME")
+                )),
+                event => report.push(format!("event {} is not supported by GitLab CI and is ignored", event)),
+            }
+        }
+    }
+    
+    if let Some(cron) = &pipeline.trigger.schedule {
+        report.push(format!(
+            "schedule {} needs a pipeline schedule in the GitLab project setting {} to {}",
+            cron, PIPELINE_VARIABLE, pipeline.name
+        ));
+    }
+    if pipeline.trigger.manual || pipeline.trigger.schedule.is_some() {
+        expressions.push(format!("${} == \"{}\"", PIPELINE_VARIABLE, pipeline.name));
+    }
+    
+    expressions
+}
+
+/// Job running `step` after `needs` in a container of its image, on the
+/// pipeline's triggers, in `when` state. Artifacts of the steps it needs are
+/// copied in before its commands, its own are copied out for upload after
+/// them, and results are passed on as a dotenv report.
+fn job(
+    pipeline: &crd::Pipeline,
+    step: &crd::PipelineStep,
+    needs: Vec<String>,
+    triggers: &[String],
+    when: Option<&'static str>,
+    tags: &[String],
+    report: &mut Vec<String>,
+) -> Job {
+    let settings = execution::step_settings(pipeline, step);
+    let resolved = references::rewrite_step(step, |reference| {
+        let name = match reference {
+            Reference::Param(name) => hosted::variable(name),
+            Reference::Result { step, name } => hosted::variable(&references::result_param(step, name)),
+        };
+        Some(format!("${{{}}}", name))
+    });
+    
+    let mut variables: BTreeMap<String, String> = pipeline
+        .params
+        .iter()
+        .flatten()
+        .map(|param| (hosted::variable(&param.name), param.default.clone().unwrap_or_default()))
+        .collect();
+    variables.extend(resolved.env.clone().unwrap_or_default());
+    for secret in step.secret_env.iter().flatten() {
+        let source = hosted::secret_variable(&secret.secret, &secret.key);
+        if source != secret.name {
+            variables.insert(secret.name.clone(), format!("${}", source));
+        }
+    }
+    
+    let results: Vec<String> = step.results.iter().flatten().map(|result| result.name.clone()).collect();
+    let dotenv = format!("{}/{}.env", DOTENV_DIR, step.name);
+    let mut commands = Vec::new();
+    if !results.is_empty() {
+        variables.insert(references::RESULTS_VARIABLE.to_string(), hosted::RESULTS_DIR.to_string());
+        commands.push(format!("mkdir -p \"${}\" \"{}/{}\"", references::RESULTS_VARIABLE, ARTIFACT_ROOT, DOTENV_DIR));
+    }
+    commands.extend(resolved.commands.iter().cloned());
+    commands.extend(results.iter().map(|name| {
+        format!(
+            "printf '{variable}=%s\\n' \"$(cat \"${dir}/{name}\")\" >> \"{root}/{dotenv}\"",
+            variable = hosted::variable(&references::result_param(&step.name, name)),
+            dir = references::RESULTS_VARIABLE,
+            name = name,
+            root = ARTIFACT_ROOT,
+            dotenv = dotenv,
+        )
+    }));
+    let resolved = references::copy_artifacts(crd::PipelineStep { commands, ..resolved }, ARTIFACT_ROOT);
+    
+    let mut script: Vec<String> = guard(step, step.when.as_deref().unwrap_or_default()).into_iter().collect();
+    if step.when.is_some() {
+        report.push(format!("step {}: conditions are checked by the job script, which passes when they do not hold", step.name));
+    }
+    if let Some(working_dir) = &step.working_dir {
+        script.push(format!("cd {}", quote(working_dir)));
+    }
+    script.extend(resolved.commands);
+    
+    let retry = settings.retries.filter(|retries| *retries > 0).map(|retries| {
+        if retries > MAX_RETRIES {
+            report.push(format!("step {}: {} retries are capped at {} by GitLab CI", step.name, retries, MAX_RETRIES));
+        }
+        retries.min(MAX_RETRIES)
+    });
+    
+    let artifacts = Artifacts {
+        paths: step
+            .artifacts
+            .iter()
+            .flatten()
+            .next()
+            .map(|_| format!("{}/{}/", references::ARTIFACTS_DIR, step.name))
+            .into_iter()
+            .collect(),
+        reports: (!results.is_empty()).then_some(Reports { dotenv }),
+    };
+    
+    let rules = if triggers.is_empty() {
+        vec![Rule { if_: None, when: Some("never") }]
+    } else {
+        triggers.iter().map(|expression| Rule { if_: Some(expression.clone()), when }).collect()
+    };
+    
+    Job {
+        image: (!step.image.is_empty()).then(|| step.image.clone()),
+        tags: tags.to_vec(),
+        needs: needs.iter().map(|need| job_name(pipeline, need)).collect(),
+        rules,
+        variables,
+        script,
+        timeout: settings.timeout.as_deref().and_then(execution::seconds).map(|seconds| format!("{}m", (seconds + 59) / 60)),
+        retry,
+        artifacts: (!artifacts.paths.is_empty() || artifacts.reports.is_some()).then_some(artifacts),
+    }
+}
+
+/// `.gitlab-ci.yml` running the steps of `pipelines` as jobs in dependency
+/// order on the triggers of their pipeline, with the `finally` steps as jobs
+/// that always run once the others finish, along with the compatibility
+/// report of each pipeline
+pub fn ci_config(
+    pipelines: &[crd::Pipeline],
+    template: Option<&CiCdTemplate>,
+) -> Result<(CiConfig, BTreeMap<String, Vec<String>>), Error> {
+    let tags: Vec<String> = hosted::template_value(template, "tags")
+        .and_then(|value| value.as_array())
+        .map(|tags| tags.iter().filter_map(|tag| tag.as_str().map(str::to_string)).collect())
+        .unwrap_or_default();
+    
+    let mut jobs = Vec::new();
+    let mut reports = BTreeMap::new();
+    for pipeline in pipelines {
+        let run_after = order::run_after(pipeline)?;
+        let mut report = hosted::report(pipeline, "CI/CD variable");
+        let triggers = triggers(pipeline, &mut report);
+        
+        for (step, after) in pipeline.steps.iter().zip(&run_after) {
+            let job = job(pipeline, step, hosted::needs(step, after), &triggers, None, &tags, &mut report);
+            jobs.push((job_name(pipeline, &step.name), job));
+        }
+        let everything: Vec<String> = pipeline.steps.iter().map(|step| step.name.clone()).collect();
+        for step in pipeline.finally.iter().flatten() {
+            let job = job(pipeline, step, everything.clone(), &triggers, Some("always"), &tags, &mut report);
+            jobs.push((job_name(pipeline, &step.name), job));
+        }
+        
+        reports.insert(pipeline.name.clone(), report);
+    }
+    
+    let mut variables: Vec<(String, Variable)> = PARAMS
+        .iter()
+        .zip(TRIGGER_VALUES)
+        .map(|((name, description), (_, value))| {
+            (hosted::variable(name), Variable { value: value.to_string(), description: description.to_string() })
+        })
+        .collect();
+    if pipelines.iter().any(|pipeline| pipeline.trigger.manual || pipeline.trigger.schedule.is_some()) {
+        let selector = Variable {
+            value: String::new(),
+            description: "Pipeline to run, for manual and scheduled runs".to_string(),
+        };
+        variables.push((PIPELINE_VARIABLE.to_string(), selector));
+    }
+    
+    Ok((CiConfig { variables: Ordered(variables), jobs: Ordered(jobs) }, reports))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cicd::tests::{assert_golden, release_pipeline, results_pipeline, tuned_pipeline};
+    
+    #[test]
+    fn renders_pipelines_into_one_file() {
+        let mut release = release_pipeline();
+        release.trigger.git.as_mut().unwrap().repository = "https://gitlab.com/acme/app".to_string();
+        release.trigger.git.as_mut().unwrap().events.push("merge_request".to_string());
+        release.trigger.git.as_mut().unwrap().branches.push("release/*".to_string());
+        
+        let deploy = crd::Pipeline { name: "deploy".to_string(), ..results_pipeline() };
+        
+        let (config, reports) = ci_config(&[release, deploy], None).unwrap();
+        assert_golden("gitlab/ci.yaml", &serde_yaml::to_string(&config).unwrap());
+        assert_eq!(
+            reports["release"],
+            [
+                "workspace shared-data is not shared, steps run as separate jobs and pass files on as artifacts",
+                "step publish: conditions are checked by the job script, which passes when they do not hold",
+            ]
+        );
+    }
+    
+    #[test]
+    fn caps_retries_and_reports_schedules() {
+        let mut pipeline = tuned_pipeline();
+        pipeline.steps[0].execution.retries = Some(5);
+        pipeline.trigger.schedule = Some("0 3 * * *".to_string());
+        
+        let (config, reports) = ci_config(&[pipeline], None).unwrap();
+        let build = &config.jobs.0[0].1;
+        assert_eq!(build.retry, Some(2));
+        assert_eq!(build.timeout.as_deref(), Some("45m"));
+        assert_eq!(build.rules.len(), 2);
+        assert!(reports["release"].contains(&"step build: 5 retries are capped at 2 by GitLab CI".to_string()));
+        assert!(reports["release"].contains(
+            &"schedule 0 3 * * * needs a pipeline schedule in the GitLab project setting ZERG_PIPELINE to release".to_string()
+        ));
+    }
+}
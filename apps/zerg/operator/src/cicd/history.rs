@@ -0,0 +1,406 @@
+use std::cmp::Reverse;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::DateTime;
+use futures_util::StreamExt;
+use kube::{
+    api::{Api, DeleteParams, DynamicObject, ListParams, ResourceExt},
+    runtime::{watcher, WatchStreamExt},
+    Client,
+};
+use serde_json::Value;
+use tracing::{debug, info, instrument, warn};
+
+use crate::config::SharedConfig;
+use crate::crd::{CiCdProvider, DependencyManager, PipelineRunRecord, RunPhase};
+use crate::error::Error;
+use crate::reload;
+use crate::resources::{self, ARGO_WORKFLOW, TEKTON_PIPELINE_RUN, TEKTON_TASK_RUN};
+
+use super::PIPELINE_LABEL;
+
+/// Runs kept per pipeline when `retention.history_limit` is unset
+const DEFAULT_HISTORY_LIMIT: u32 = 5;
+
+/// Delay between discovery attempts for run kinds whose CRDs are not installed yet
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Label Tekton puts on TaskRuns, naming the pipeline task they run
+const TEKTON_PIPELINE_TASK_LABEL: &str = "tekton.dev/pipelineTask";
+
+/// Label Tekton puts on TaskRuns, naming their PipelineRun
+const TEKTON_PIPELINE_RUN_LABEL: &str = "tekton.dev/pipelineRun";
+
+impl RunPhase {
+    /// Whether the run has completed
+    pub fn is_finished(self) -> bool {
+        matches!(self, RunPhase::Succeeded | RunPhase::Failed | RunPhase::Cancelled)
+    }
+    
+    /// Name of the phase, as serialized
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RunPhase::Pending => "Pending",
+            RunPhase::Running => "Running",
+            RunPhase::Succeeded => "Succeeded",
+            RunPhase::Failed => "Failed",
+            RunPhase::Cancelled => "Cancelled",
+        }
+    }
+}
+
+fn string(value: &Value) -> Option<String> {
+    value.as_str().filter(|s| !s.is_empty()).map(str::to_string)
+}
+
+/// Value of the `{name, value}` entry called `name` in `list`
+fn named_value(list: &Value, name: &str) -> Option<String> {
+    list.as_array()?
+        .iter()
+        .find(|entry| entry["name"] == name)
+        .and_then(|entry| string(&entry["value"]))
+}
+
+/// Whole seconds between two RFC 3339 timestamps
+fn duration(started_at: Option<&str>, finished_at: Option<&str>) -> Option<i64> {
+    let started = DateTime::parse_from_rfc3339(started_at?).ok()?;
+    let finished = DateTime::parse_from_rfc3339(finished_at?).ok()?;
+    
+    Some((finished - started).num_seconds())
+}
+
+/// The `Succeeded` condition of a Tekton run, as `(status, reason)`
+fn succeeded_condition(status: &Value) -> Option<(&str, &str)> {
+    let condition = status["conditions"]
+        .as_array()?
+        .iter()
+        .find(|condition| condition["type"] == "Succeeded")?;
+    
+    Some((condition["status"].as_str()?, condition["reason"].as_str().unwrap_or_default()))
+}
+
+/// Record of a Tekton PipelineRun; its failed step comes from its TaskRuns,
+/// see `failed_task`
+pub fn tekton_record(run: &DynamicObject) -> PipelineRunRecord {
+    let status = &run.data["status"];
+    let phase = match succeeded_condition(status) {
+        Some(("True", _)) => RunPhase::Succeeded,
+        Some(("False", reason)) if reason.contains("Cancelled") => RunPhase::Cancelled,
+        Some(("False", _)) => RunPhase::Failed,
+        Some((_, reason)) if reason.contains("Pending") => RunPhase::Pending,
+        Some(_) => RunPhase::Running,
+        None => RunPhase::Pending,
+    };
+    let started_at = string(&status["startTime"]);
+    let finished_at = string(&status["completionTime"]);
+    
+    PipelineRunRecord {
+        name: run.name_any(),
+        phase,
+        duration_seconds: duration(started_at.as_deref(), finished_at.as_deref()),
+        started_at,
+        finished_at,
+        revision: named_value(&run.data["spec"]["params"], "git-revision"),
+        failed_step: None,
+    }
+}
+
+/// Pipeline task of the first TaskRun in `task_runs` that failed
+pub fn failed_task(task_runs: &[DynamicObject]) -> Option<String> {
+    task_runs
+        .iter()
+        .filter(|task_run| matches!(succeeded_condition(&task_run.data["status"]), Some(("False", _))))
+        .min_by_key(|task_run| task_run.data["status"]["completionTime"].as_str().map(str::to_string))
+        .and_then(|task_run| task_run.labels().get(TEKTON_PIPELINE_TASK_LABEL).cloned())
+}
+
+/// Record of an Argo Workflow, with its first failed DAG task as failed step
+pub fn argo_record(run: &DynamicObject) -> PipelineRunRecord {
+    let status = &run.data["status"];
+    let phase = match status["phase"].as_str() {
+        Some("Succeeded") => RunPhase::Succeeded,
+        Some("Failed" | "Error") if !run.data["spec"]["shutdown"].is_null() => RunPhase::Cancelled,
+        Some("Failed" | "Error") => RunPhase::Failed,
+        Some("Running") => RunPhase::Running,
+        _ => RunPhase::Pending,
+    };
+    let started_at = string(&status["startedAt"]);
+    let finished_at = string(&status["finishedAt"]);
+    
+    let failed_step = status["nodes"].as_object().and_then(|nodes| {
+        nodes
+            .values()
+            .filter(|node| node["type"] == "Pod" && matches!(node["phase"].as_str(), Some("Failed" | "Error")))
+            .min_by_key(|node| node["finishedAt"].as_str().map(str::to_string))
+            .and_then(|node| string(&node["displayName"]))
+    });
+    
+    PipelineRunRecord {
+        name: run.name_any(),
+        phase,
+        duration_seconds: duration(started_at.as_deref(), finished_at.as_deref()),
+        started_at,
+        finished_at,
+        revision: named_value(&run.data["spec"]["arguments"]["parameters"], "git-revision"),
+        failed_step: if phase == RunPhase::Failed { failed_step } else { None },
+    }
+}
+
+/// Sorts `runs` newest first
+fn newest_first(runs: &mut [DynamicObject]) {
+    runs.sort_by_key(|run| (Reverse(run.metadata.creation_timestamp.clone()), Reverse(run.name_any())));
+}
+
+/// Keeps the run history of pipelines in `CiCdStatus`, and prunes runs beyond it
+pub struct RunTracker {
+    client: Client,
+    config: SharedConfig,
+}
+
+impl RunTracker {
+    pub fn new(client: Client, config: SharedConfig) -> Self {
+        Self { client, config }
+    }
+    
+    /// Tracks PipelineRuns and Workflows of every pipeline until the process exits
+    #[instrument(skip(self))]
+    pub async fn run(self) -> anyhow::Result<()> {
+        let tracker = Arc::new(self);
+        let tasks: Vec<_> = [TEKTON_PIPELINE_RUN, ARGO_WORKFLOW]
+            .into_iter()
+            .map(|kind| tokio::spawn(tracker.clone().track(kind)))
+            .collect();
+        
+        for task in tasks {
+            task.await?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Watches runs of `kind` labelled with their pipeline, waiting for its CRD
+    /// to be installed first; the operator may install Tekton or Argo itself.
+    /// The watches follow changes of `operator.watch_namespaces`.
+    async fn track(self: Arc<Self>, kind: (&'static str, &'static str, &'static str)) {
+        let resource = loop {
+            match resources::resolve_kind(&self.client, kind).await {
+                Ok((resource, _)) => break resource,
+                Err(e) => {
+                    debug!("Not tracking {} yet: {}", kind.2, e);
+                    tokio::time::sleep(DISCOVERY_INTERVAL).await;
+                }
+            }
+        };
+        
+        info!("Tracking {} history", resource.kind);
+        let watcher_config = watcher::Config::default().labels(PIPELINE_LABEL);
+        let mut config = self.config.clone();
+        loop {
+            let namespaces = config.borrow_and_update().operator.watch_namespaces.clone();
+            let apis: Vec<Api<DynamicObject>> = if namespaces.is_empty() {
+                vec![Api::all_with(self.client.clone(), &resource)]
+            } else {
+                namespaces.iter().map(|ns| Api::namespaced_with(self.client.clone(), ns, &resource)).collect()
+            };
+            
+            let streams = apis
+                .into_iter()
+                .map(|api| watcher(api, watcher_config.clone()).default_backoff().applied_objects().boxed());
+            
+            let events = futures_util::stream::select_all(streams).for_each(|event| {
+                let tracker = self.clone();
+                async move {
+                    match event {
+                        Ok(run) => {
+                            if let Err(e) = tracker.refresh(kind, &run).await {
+                                warn!("Failed to update history of run {}: {}", run.name_any(), e);
+                            }
+                        }
+                        Err(e) => warn!("Watch of {} failed: {}", kind.2, e),
+                    }
+                }
+            });
+            
+            tokio::select! {
+                _ = events => return,
+                _ = reload::namespaces_changed(&mut config, &namespaces) => {
+                    info!("Watched namespaces changed, tracking {} history again", resource.kind);
+                }
+            }
+        }
+    }
+    
+    /// Rebuilds the history of the pipeline `run` belongs to
+    async fn refresh(&self, kind: (&str, &str, &str), run: &DynamicObject) -> Result<(), Error> {
+        let (Some(namespace), Some(pipeline)) = (run.namespace(), run.labels().get(PIPELINE_LABEL)) else {
+            return Ok(());
+        };
+        let Some(owner_ref) = resources::owner_from_labels(&run.metadata) else {
+            return Ok(());
+        };
+        let tekton = kind == TEKTON_PIPELINE_RUN;
+        let provider = if tekton { CiCdProvider::Tekton } else { CiCdProvider::ArgoWorkflows };
+        
+        let owners: Api<DependencyManager> =
+            Api::namespaced(self.client.clone(), owner_ref.namespace.as_deref().unwrap_or(&namespace));
+        let Some(owner) = owners.get_opt(&owner_ref.name).await?.filter(|dm| {
+            dm.spec.cicd.as_ref().is_some_and(|cicd| {
+                cicd.provider == provider && cicd.pipelines.iter().any(|p| &p.name == pipeline)
+            })
+        }) else {
+            return Ok(());
+        };
+        let retention = owner.spec.cicd.as_ref().and_then(|cicd| cicd.retention.clone()).unwrap_or_default();
+        let limit = retention.history_limit.unwrap_or(DEFAULT_HISTORY_LIMIT) as usize;
+        
+        let (resource, _) = resources::resolve_kind(&self.client, kind).await?;
+        let api: Api<DynamicObject> = Api::namespaced_with(self.client.clone(), &namespace, &resource);
+        let selector = format!("{},{}={}", resources::owner_selector(&owner), PIPELINE_LABEL, pipeline);
+        let mut runs = api.list(&ListParams::default().labels(&selector)).await?.items;
+        newest_first(&mut runs);
+        
+        // Failed steps of recorded Tekton runs are kept rather than looked up again
+        let recorded: Vec<PipelineRunRecord> = owner
+            .status
+            .as_ref()
+            .and_then(|status| status.cicd_status.as_ref())
+            .and_then(|status| status.pipelines.iter().find(|p| &p.name == pipeline))
+            .and_then(|status| status.runs.clone())
+            .unwrap_or_default();
+        
+        let mut records = Vec::new();
+        for run in runs.iter().take(limit) {
+            let record = if tekton {
+                let mut record = tekton_record(run);
+                if record.phase == RunPhase::Failed {
+                    record.failed_step = match recorded.iter().find(|r| r.name == record.name && r.failed_step.is_some()) {
+                        Some(r) => r.failed_step.clone(),
+                        None => self.tekton_failed_task(&namespace, &record.name).await?,
+                    };
+                }
+                record
+            } else {
+                argo_record(run)
+            };
+            records.push(record);
+        }
+        
+        if retention.prune.unwrap_or(true) {
+            for run in runs.iter().skip(limit) {
+                let finished = if tekton { tekton_record(run).phase } else { argo_record(run).phase };
+                if finished.is_finished() {
+                    info!("Pruning {} {} of pipeline {}", resource.kind, run.name_any(), pipeline);
+                    match api.delete(&run.name_any(), &DeleteParams::default()).await {
+                        Ok(_) => {}
+                        Err(kube::Error::Api(response)) if response.code == 404 => {}
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+        }
+        
+        super::update_pipeline_status(&self.client, &owner, pipeline, None, |status| {
+            if let Some(latest) = records.first() {
+                status.status = latest.phase.as_str().to_string();
+                status.last_run = latest.started_at.clone().or(status.last_run.take());
+                status.last_run_name = Some(latest.name.clone());
+                status.last_revision = latest.revision.clone();
+            }
+            status.runs = Some(records.clone());
+        })
+        .await
+    }
+    
+    /// Pipeline task that failed the Tekton PipelineRun `run`
+    async fn tekton_failed_task(&self, namespace: &str, run: &str) -> Result<Option<String>, Error> {
+        let (resource, _) = resources::resolve_kind(&self.client, TEKTON_TASK_RUN).await?;
+        let api: Api<DynamicObject> = Api::namespaced_with(self.client.clone(), namespace, &resource);
+        let selector = format!("{}={}", TEKTON_PIPELINE_RUN_LABEL, run);
+        
+        Ok(failed_task(&api.list(&ListParams::default().labels(&selector)).await?.items))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    fn object(value: Value) -> DynamicObject {
+        serde_json::from_value(value).unwrap()
+    }
+    
+    #[test]
+    fn reads_tekton_runs() {
+        let run = object(json!({
+            "apiVersion": "tekton.dev/v1",
+            "kind": "PipelineRun",
+            "metadata": { "name": "build-run-abcde" },
+            "spec": { "params": [{ "name": "git-revision", "value": "abc123" }] },
+            "status": {
+                "startTime": "2026-10-18T10:00:00Z",
+                "completionTime": "2026-10-18T10:02:30Z",
+                "conditions": [{ "type": "Succeeded", "status": "False", "reason": "Failed" }],
+            },
+        }));
+        let record = tekton_record(&run);
+        
+        assert_eq!(record.phase, RunPhase::Failed);
+        assert_eq!(record.duration_seconds, Some(150));
+        assert_eq!(record.revision.as_deref(), Some("abc123"));
+        
+        let task_run = |task: &str, status: &str| {
+            object(json!({
+                "apiVersion": "tekton.dev/v1",
+                "kind": "TaskRun",
+                "metadata": { "name": format!("build-run-abcde-{}", task), "labels": { TEKTON_PIPELINE_TASK_LABEL: task } },
+                "status": { "conditions": [{ "type": "Succeeded", "status": status }] },
+            }))
+        };
+        assert_eq!(failed_task(&[task_run("checkout", "True"), task_run("test", "False")]).as_deref(), Some("test"));
+        
+        let cancelled = object(json!({
+            "apiVersion": "tekton.dev/v1",
+            "kind": "PipelineRun",
+            "metadata": { "name": "build-run-fghij" },
+            "status": { "conditions": [{ "type": "Succeeded", "status": "False", "reason": "Cancelled" }] },
+        }));
+        assert_eq!(tekton_record(&cancelled).phase, RunPhase::Cancelled);
+    }
+    
+    #[test]
+    fn reads_argo_workflows() {
+        let run = object(json!({
+            "apiVersion": "argoproj.io/v1alpha1",
+            "kind": "Workflow",
+            "metadata": { "name": "release-x7k2p" },
+            "spec": { "arguments": { "parameters": [{ "name": "git-revision", "value": "def456" }] } },
+            "status": {
+                "phase": "Failed",
+                "startedAt": "2026-10-18T10:00:00Z",
+                "finishedAt": "2026-10-18T10:01:00Z",
+                "nodes": {
+                    "release-x7k2p": { "type": "DAG", "phase": "Failed", "displayName": "release-x7k2p" },
+                    "release-x7k2p-1": { "type": "Pod", "phase": "Succeeded", "displayName": "checkout" },
+                    "release-x7k2p-2": { "type": "Pod", "phase": "Failed", "displayName": "lint", "finishedAt": "2026-10-18T10:00:40Z" },
+                },
+            },
+        }));
+        let record = argo_record(&run);
+        
+        assert_eq!(record.phase, RunPhase::Failed);
+        assert_eq!(record.duration_seconds, Some(60));
+        assert_eq!(record.revision.as_deref(), Some("def456"));
+        assert_eq!(record.failed_step.as_deref(), Some("lint"));
+        
+        let running = object(json!({
+            "apiVersion": "argoproj.io/v1alpha1",
+            "kind": "Workflow",
+            "metadata": { "name": "release-q9w8e" },
+            "status": { "phase": "Running", "startedAt": "2026-10-18T10:00:00Z" },
+        }));
+        let record = argo_record(&running);
+        assert_eq!((record.phase, record.duration_seconds), (RunPhase::Running, None));
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::config::CiCdTemplate;
+use crate::crd::{CiCdGitWrite, CiCdProvider, GitProvider, Pipeline, PipelineStep, WorkspaceType};
+use crate::error::Error;
+
+use super::{execution, github, gitlab, references, trigger, workspace};
+
+/// Directory GitHub Actions reads workflows from
+pub const GITHUB_WORKFLOWS: &str = ".github/workflows";
+
+/// File GitLab CI reads its pipeline from, at the repository root
+pub const GITLAB_CI: &str = ".gitlab-ci.yml";
+
+/// Directory steps write their results to, read back once their commands succeed
+pub const RESULTS_DIR: &str = "/tmp/zerg/results";
+
+/// Mapping serialized in the order of its entries, as CI services show jobs in
+/// file order
+#[derive(Debug, Clone)]
+pub struct Ordered<T>(pub Vec<(String, T)>);
+
+impl<T> Ordered<T> {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<T: Serialize> Serialize for Ordered<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, value) in &self.0 {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+/// Files rendered for the pipelines of one trigger repository
+#[derive(Debug)]
+pub struct Rendering {
+    /// Repository of the pipelines' git trigger, if they have one
+    pub repository: Option<String>,
+    
+    /// Directory of `files` within the repository
+    pub path: &'static str,
+    
+    /// File contents by name
+    pub files: BTreeMap<String, String>,
+    
+    /// Compatibility report of each pipeline
+    pub reports: BTreeMap<String, Vec<String>>,
+}
+
+/// Name of the CI service of a provider rendering workflow files
+pub fn service(provider: &CiCdProvider) -> &'static str {
+    match provider {
+        CiCdProvider::GithubActions => "GitHub Actions",
+        CiCdProvider::GitlabCi => "GitLab CI",
+        CiCdProvider::Tekton => "Tekton",
+        CiCdProvider::ArgoWorkflows => "Argo Workflows",
+    }
+}
+
+/// Whether `provider` renders workflow files for a CI service rather than
+/// objects in the cluster
+pub fn is_hosted(provider: &CiCdProvider) -> bool {
+    matches!(provider, CiCdProvider::GithubActions | CiCdProvider::GitlabCi)
+}
+
+/// First line of the files rendered for `owner`, telling them apart from
+/// files written by hand or for other DependencyManagers
+pub fn header(owner: &str) -> String {
+    format!("# Generated by zerg-operator for {}, do not edit\n", owner)
+}
+
+/// Environment variable carrying param, result or secret `name`
+pub fn variable(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect()
+}
+
+/// Variable the CI service holds the value of `secret_env` entry `key` of
+/// Secret `secret` in
+pub fn secret_variable(secret: &str, key: &str) -> String {
+    variable(&format!("{}_{}", secret, key))
+}
+
+/// Value of `config` key `key` of the provider's `cicd_templates` entry
+pub fn template_value<'a>(template: Option<&'a CiCdTemplate>, key: &str) -> Option<&'a serde_json::Value> {
+    template.and_then(|template| template.config.get(key))
+}
+
+/// Steps `step` needs to have finished: those it runs after, and those whose
+/// results or artifacts it takes, which must be listed for the CI service to
+/// pass them on
+pub fn needs(step: &PipelineStep, run_after: &[&str]) -> Vec<String> {
+    let mut needs: Vec<String> = run_after.iter().map(|name| name.to_string()).collect();
+    let inputs = references::result_inputs(step)
+        .into_iter()
+        .map(|(producer, _)| producer)
+        .chain(step.input_artifacts.iter().flatten().map(|input| input.step.clone()));
+    for producer in inputs {
+        if !needs.contains(&producer) {
+            needs.push(producer);
+        }
+    }
+    needs
+}
+
+/// Checks `pipeline` like the cluster providers do, and that its git trigger
+/// points at a host running `provider`
+fn validate(provider: &CiCdProvider, pipeline: &Pipeline) -> Result<(), Error> {
+    workspace::validate(pipeline)?;
+    references::validate(pipeline)?;
+    execution::validate(pipeline)?;
+    
+    if let Some(git) = &pipeline.trigger.git {
+        let foreign = match provider {
+            CiCdProvider::GithubActions => trigger::provider(git) == GitProvider::GitLab,
+            _ => matches!(trigger::provider(git), GitProvider::GitHub | GitProvider::Gitea),
+        };
+        if foreign {
+            return Err(Error::CiCdError(format!(
+                "Pipeline {}: repository {} does not run {}",
+                pipeline.name,
+                git.repository,
+                service(provider)
+            )));
+        }
+    }
+    
+    Ok(())
+}
+
+/// Features of `pipeline` neither CI service can express: settings for the
+/// pods steps run in, the run timeout, and workspaces, as jobs run on
+/// separate runners. Secrets are read from `secrets` of the CI service.
+pub fn report(pipeline: &Pipeline, secrets: &str) -> Vec<String> {
+    let mut report = Vec::new();
+    
+    if let Some(timeout) = &pipeline.execution.timeout {
+        report.push(format!("run timeout {} cannot be set in the workflow and is ignored", timeout));
+    }
+    
+    let steps = pipeline.steps.iter().chain(pipeline.finally.iter().flatten());
+    let settings = std::iter::once((String::new(), &pipeline.execution))
+        .chain(steps.clone().map(|step| (format!("step {}: ", step.name), &step.execution)));
+    for (subject, settings) in settings {
+        if settings.resources.is_some() {
+            report.push(format!("{}resources are ignored, runners have fixed sizes", subject));
+        }
+        if settings.node_selector.is_some() || settings.tolerations.is_some() {
+            report.push(format!("{}node selector and tolerations are ignored, runners are picked by label", subject));
+        }
+        if let Some(service_account) = &settings.service_account {
+            report.push(format!("{}service account {} is ignored", subject, service_account));
+        }
+    }
+    
+    let mut mounted: HashMap<String, usize> = HashMap::new();
+    for step in steps.clone() {
+        for mount in workspace::mounts(pipeline, step) {
+            *mounted.entry(mount.workspace).or_default() += 1;
+        }
+    }
+    for workspace in workspace::workspaces(pipeline) {
+        let name = &workspace.name;
+        match workspace.type_ {
+            WorkspaceType::VolumeClaim if mounted.get(name).is_some_and(|count| *count > 1) => report.push(format!(
+                "workspace {} is not shared, steps run as separate jobs and pass files on as artifacts",
+                name
+            )),
+            WorkspaceType::Cache => report.push(format!("cache workspace {} is not kept between runs", name)),
+            WorkspaceType::ConfigMap | WorkspaceType::Secret => {
+                report.push(format!("workspace {} cannot be mounted from the cluster and is ignored", name))
+            }
+            _ => {}
+        }
+    }
+    
+    for step in steps {
+        for variable in step.secret_env.iter().flatten() {
+            report.push(format!(
+                "step {}: {} is read from the {} {} instead of key {} of Secret {}",
+                step.name,
+                variable.name,
+                secrets,
+                secret_variable(&variable.secret, &variable.key),
+                variable.key,
+                variable.secret
+            ));
+        }
+    }
+    
+    report
+}
+
+/// `document` as YAML under the header for `owner`
+fn file<T: serde::Serialize>(owner: &str, document: &T) -> Result<String, Error> {
+    let yaml = serde_yaml::to_string(document)
+        .map_err(|e| Error::SerializationError(format!("Failed to serialize workflow: {}", e)))?;
+    Ok(format!("{}{}", header(owner), yaml))
+}
+
+/// Renders `pipelines` for `provider`, one `Rendering` per trigger
+/// repository, with files starting with the header for `owner`
+pub fn render(
+    provider: &CiCdProvider,
+    pipelines: &[Pipeline],
+    template: Option<&CiCdTemplate>,
+    owner: &str,
+) -> Result<Vec<Rendering>, Error> {
+    let mut repositories: Vec<(Option<String>, Vec<&Pipeline>)> = Vec::new();
+    for pipeline in pipelines {
+        validate(provider, pipeline)?;
+        let repository = pipeline.trigger.git.as_ref().map(|git| git.repository.clone());
+        match repositories.iter_mut().find(|(r, _)| *r == repository) {
+            Some((_, pipelines)) => pipelines.push(pipeline),
+            None => repositories.push((repository, vec![pipeline])),
+        }
+    }
+    
+    repositories
+        .into_iter()
+        .map(|(repository, pipelines)| {
+            let mut files = BTreeMap::new();
+            let mut reports = BTreeMap::new();
+            let path = match provider {
+                CiCdProvider::GithubActions => {
+                    for pipeline in pipelines {
+                        let (workflow, report) = github::workflow(pipeline, template)?;
+                        files.insert(github::file_name(pipeline), file(owner, &workflow)?);
+                        reports.insert(pipeline.name.clone(), report);
+                    }
+                    GITHUB_WORKFLOWS
+                }
+                _ => {
+                    let pipelines: Vec<Pipeline> = pipelines.into_iter().cloned().collect();
+                    let (config, pipeline_reports) = gitlab::ci_config(&pipelines, template)?;
+                    files.insert(GITLAB_CI.to_string(), file(owner, &config)?);
+                    reports = pipeline_reports;
+                    "."
+                }
+            };
+            Ok(Rendering { repository, path, files, reports })
+        })
+        .collect()
+}
+
+/// Branch the workflows of `pipelines` are committed to: the one `git_write`
+/// names, or the first trigger branch without wildcards
+pub fn commit_branch(git_write: &CiCdGitWrite, pipelines: &[&Pipeline]) -> Result<String, Error> {
+    if let Some(branch) = &git_write.branch {
+        return Ok(branch.clone());
+    }
+    
+    pipelines
+        .iter()
+        .flat_map(|pipeline| pipeline.trigger.git.iter().flat_map(|git| git.branches.iter()))
+        .find(|branch| !branch.contains(['*', '?']))
+        .cloned()
+        .ok_or_else(|| {
+            Error::CiCdError("git_write needs a branch, the trigger branches all have wildcards".to_string())
+        })
+}
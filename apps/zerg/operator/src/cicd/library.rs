@@ -0,0 +1,250 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::config::{CiCdTemplate, StepTemplate, TemplateParam};
+use crate::crd::{Pipeline, PipelineStep};
+use crate::error::Error;
+
+/// Step template shipped with the operator
+struct Builtin {
+    name: &'static str,
+    description: &'static str,
+    image: &'static str,
+    /// Params as (name, default) pairs
+    params: &'static [(&'static str, Option<&'static str>)],
+    commands: &'static [&'static str],
+}
+
+const BUILTIN: &[Builtin] = &[
+    Builtin {
+        name: "git-clone",
+        description: "Clones the repository into the working directory and checks out the revision, if any",
+        image: "alpine/git:2.45.2",
+        params: &[("url", Some("$(params.git-repo-url)")), ("revision", Some("$(params.git-revision)"))],
+        commands: &[
+            "git clone \"$URL\" .",
+            "if [ -n \"$REVISION\" ]; then git checkout \"$REVISION\"; fi",
+        ],
+    },
+    Builtin {
+        name: "cargo-build",
+        description: "Builds the Cargo workspace",
+        image: "rust:1.79",
+        params: &[("profile", Some("release")), ("args", Some(""))],
+        commands: &["cargo build --locked --profile \"$PROFILE\" $ARGS"],
+    },
+    Builtin {
+        name: "cargo-test",
+        description: "Tests the Cargo workspace",
+        image: "rust:1.79",
+        params: &[("args", Some(""))],
+        commands: &["cargo test --locked $ARGS"],
+    },
+    Builtin {
+        name: "kaniko",
+        description: "Builds and pushes an image with kaniko, reading registry credentials from /kaniko/.docker",
+        image: "gcr.io/kaniko-project/executor:v1.23.2-debug",
+        params: &[("image", None), ("context", Some(".")), ("dockerfile", Some("Dockerfile"))],
+        commands: &["/kaniko/executor --context \"$CONTEXT\" --dockerfile \"$DOCKERFILE\" --destination \"$IMAGE\""],
+    },
+    Builtin {
+        name: "buildah",
+        description: "Builds and pushes an image with buildah, reading registry credentials from $REGISTRY_AUTH_FILE",
+        image: "quay.io/buildah/stable:v1.37",
+        params: &[("image", None), ("context", Some(".")), ("dockerfile", Some("Dockerfile"))],
+        commands: &[
+            "buildah bud --storage-driver=vfs -f \"$DOCKERFILE\" -t \"$IMAGE\" \"$CONTEXT\"",
+            "buildah push --storage-driver=vfs \"$IMAGE\"",
+        ],
+    },
+    Builtin {
+        name: "helm-push",
+        description: "Packages a chart and pushes it to an OCI registry",
+        image: "alpine/helm:3.15.4",
+        params: &[("chart", Some(".")), ("registry", None)],
+        commands: &[
+            "helm package \"$CHART\" --destination /tmp/charts",
+            "helm push /tmp/charts/*.tgz \"$REGISTRY\"",
+        ],
+    },
+    Builtin {
+        name: "image-scan",
+        description: "Fails when the image has vulnerabilities of the given severities",
+        image: "aquasec/trivy:0.54.1",
+        params: &[("image", None), ("severity", Some("HIGH,CRITICAL"))],
+        commands: &["trivy image --exit-code 1 --severity \"$SEVERITY\" \"$IMAGE\""],
+    },
+];
+
+/// Built-in step templates by name
+fn builtin() -> BTreeMap<String, StepTemplate> {
+    BUILTIN
+        .iter()
+        .map(|builtin| {
+            let template = StepTemplate {
+                description: builtin.description.to_string(),
+                image: builtin.image.to_string(),
+                commands: builtin.commands.iter().map(|command| command.to_string()).collect(),
+                params: builtin
+                    .params
+                    .iter()
+                    .map(|(name, default)| TemplateParam {
+                        name: name.to_string(),
+                        description: String::new(),
+                        default: default.map(str::to_string),
+                    })
+                    .collect(),
+                env: HashMap::new(),
+            };
+            (builtin.name.to_string(), template)
+        })
+        .collect()
+}
+
+/// Step templates available to pipelines: the built-in ones, overridden and
+/// extended by the `steps` of the provider's `cicd_templates` entry
+pub fn library(template: Option<&CiCdTemplate>) -> BTreeMap<String, StepTemplate> {
+    let mut library = builtin();
+    library.extend(template.into_iter().flat_map(|template| template.steps.clone()));
+    library
+}
+
+/// Environment variable exposing `param` to the template's commands
+fn param_variable(param: &str) -> String {
+    param.to_uppercase().replace('-', "_")
+}
+
+/// `step` with its template applied: the template's image unless the step
+/// sets one, its commands, and its env followed by the params and the
+/// step's own env
+fn expand_step(
+    pipeline: &Pipeline,
+    step: &PipelineStep,
+    library: &BTreeMap<String, StepTemplate>,
+) -> Result<PipelineStep, Error> {
+    let invalid = |message: String| Err(Error::CiCdError(format!("Pipeline {}: step {} {}", pipeline.name, step.name, message)));
+    
+    let Some(name) = &step.template else {
+        if step.image.is_empty() {
+            return invalid("needs an image or a template".to_string());
+        }
+        if step.params.is_some() {
+            return invalid("sets params without a template".to_string());
+        }
+        return Ok(step.clone());
+    };
+    let Some(template) = library.get(name) else {
+        return invalid(format!("uses unknown template {}", name));
+    };
+    if !step.commands.is_empty() {
+        return invalid("sets both commands and a template".to_string());
+    }
+    
+    let values = step.params.clone().unwrap_or_default();
+    if let Some(unknown) = values.keys().find(|name| !template.params.iter().any(|param| &param.name == *name)) {
+        return invalid(format!("passes unknown param {} to template {}", unknown, name));
+    }
+    
+    let mut env: BTreeMap<String, String> = template.env.clone().into_iter().collect();
+    for param in &template.params {
+        let Some(value) = values.get(&param.name).or(param.default.as_ref()) else {
+            return invalid(format!("needs param {} of template {}", param.name, name));
+        };
+        env.insert(param_variable(&param.name), value.clone());
+    }
+    env.extend(step.env.clone().into_iter().flatten());
+    
+    Ok(PipelineStep {
+        image: if step.image.is_empty() { template.image.clone() } else { step.image.clone() },
+        commands: template.commands.clone(),
+        template: None,
+        params: None,
+        env: Some(env.into_iter().collect()),
+        ..step.clone()
+    })
+}
+
+/// `pipeline` with every templated step replaced by the step it expands to
+pub fn expand(pipeline: &Pipeline, library: &BTreeMap<String, StepTemplate>) -> Result<Pipeline, Error> {
+    let expand_all = |steps: &[PipelineStep]| {
+        steps
+            .iter()
+            .map(|step| expand_step(pipeline, step, library))
+            .collect::<Result<Vec<_>, _>>()
+    };
+    
+    Ok(Pipeline {
+        steps: expand_all(&pipeline.steps)?,
+        finally: pipeline.finally.as_deref().map(expand_all).transpose()?,
+        ..pipeline.clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cicd::tests::{pipeline, step};
+    
+    fn templated(name: &str, template: &str, params: &[(&str, &str)]) -> PipelineStep {
+        PipelineStep {
+            image: String::new(),
+            commands: Vec::new(),
+            template: Some(template.to_string()),
+            params: Some(params.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()),
+            ..step(name)
+        }
+    }
+    
+    #[test]
+    fn expands_templates_with_params_and_defaults() {
+        let mut build = templated("image", "kaniko", &[("image", "ghcr.io/acme/app:$(params.git-revision)")]);
+        build.env = Some(HashMap::from([("CONTEXT".to_string(), "app".to_string())]));
+        let release = pipeline("release", vec![templated("clone", "git-clone", &[]), build]);
+        
+        let expanded = expand(&release, &library(None)).unwrap();
+        let env = |step: &PipelineStep, name: &str| step.env.as_ref().unwrap()[name].clone();
+        
+        assert_eq!(expanded.steps[0].image, "alpine/git:2.45.2");
+        assert_eq!(env(&expanded.steps[0], "URL"), "$(params.git-repo-url)");
+        assert_eq!(env(&expanded.steps[1], "IMAGE"), "ghcr.io/acme/app:$(params.git-revision)");
+        assert_eq!(env(&expanded.steps[1], "DOCKERFILE"), "Dockerfile");
+        // The step's own env wins over params
+        assert_eq!(env(&expanded.steps[1], "CONTEXT"), "app");
+        assert!(expanded.steps[1].template.is_none());
+    }
+    
+    #[test]
+    fn provider_templates_override_builtin_ones() {
+        let template = CiCdTemplate {
+            name: "tekton".to_string(),
+            provider: "tekton".to_string(),
+            config: HashMap::new(),
+            steps: HashMap::from([(
+                "cargo-test".to_string(),
+                StepTemplate {
+                    description: String::new(),
+                    image: "rust:1.80".to_string(),
+                    commands: vec!["cargo nextest run".to_string()],
+                    params: Vec::new(),
+                    env: HashMap::new(),
+                },
+            )]),
+            defaults: Default::default(),
+        };
+        let library = library(Some(&template));
+        
+        assert_eq!(library["cargo-test"].image, "rust:1.80");
+        assert!(library.contains_key("helm-push"));
+    }
+    
+    #[test]
+    fn rejects_unresolvable_steps() {
+        let library = library(None);
+        let expand_one = |step: PipelineStep| expand(&pipeline("build", vec![step]), &library);
+        
+        assert!(expand_one(templated("scan", "image-scan", &[])).is_err());
+        assert!(expand_one(templated("scan", "image-scan", &[("image", "app"), ("format", "json")])).is_err());
+        assert!(expand_one(templated("scan", "unknown", &[])).is_err());
+        assert!(expand_one(PipelineStep { image: String::new(), ..step("test") }).is_err());
+        assert!(expand_one(templated("scan", "image-scan", &[("image", "app")])).is_ok());
+    }
+}
@@ -0,0 +1,545 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use kube::ResourceExt;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::crd::{ConditionOperator, DependencyManager, Pipeline, PipelineStep};
+use crate::error::Error;
+use crate::resources;
+
+use super::references::{self, Reference};
+use super::{execution, library, order};
+
+/// Where the steps of a local run execute
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Runtime {
+    /// Docker or Podman when either is available, local processes otherwise
+    Auto,
+    Docker,
+    Podman,
+    /// Local processes, ignoring step images
+    Process,
+}
+
+/// Container runtimes tried, in order, for `Runtime::Auto`
+const ENGINES: &[&str] = &["docker", "podman"];
+
+/// Path the working directory is mounted at in containers
+const CONTAINER_WORKDIR: &str = "/workspace";
+
+/// Whether `engine` is installed and answering
+fn available(engine: &str) -> bool {
+    Command::new(engine)
+        .arg("version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Container runtime program for `runtime`, or `None` for local processes
+pub fn engine(runtime: Runtime) -> Result<Option<&'static str>, Error> {
+    let required = |engine: &'static str| {
+        if available(engine) {
+            Ok(Some(engine))
+        } else {
+            Err(Error::CommandError(format!("{} is not available", engine)))
+        }
+    };
+    
+    match runtime {
+        Runtime::Auto => Ok(ENGINES.iter().copied().find(|engine| available(engine))),
+        Runtime::Docker => required("docker"),
+        Runtime::Podman => required("podman"),
+        Runtime::Process => Ok(None),
+    }
+}
+
+/// DependencyManager in `manifests`, picked by `manager` when there are several
+pub fn load_manager(manifests: &str, manager: Option<&str>) -> Result<DependencyManager, Error> {
+    let mut managers = resources::parse_yaml(manifests)?
+        .into_iter()
+        .filter(|manifest| manifest["kind"] == "DependencyManager")
+        .map(|manifest| {
+            serde_json::from_value::<DependencyManager>(manifest)
+                .map_err(|e| Error::SerializationError(format!("Invalid DependencyManager: {}", e)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    
+    let index = match manager {
+        Some(manager) => managers.iter().position(|dm| dm.name_any() == manager),
+        None if managers.len() > 1 => {
+            return Err(Error::CiCdError("The manifests hold several DependencyManagers, pick one by name".to_string()));
+        }
+        None => (!managers.is_empty()).then_some(0),
+    }
+    .ok_or_else(|| Error::CiCdError(format!("No DependencyManager named {}", manager.unwrap_or_default())))?;
+    
+    Ok(managers.swap_remove(index))
+}
+
+/// Pipeline `name` of the DependencyManager in `manifests`, picked by
+/// `manager` when there are several, with its templated steps expanded and
+/// its settings defaulted like the operator does
+pub fn load_pipeline(manifests: &str, manager: Option<&str>, name: &str, config: &Config) -> Result<Pipeline, Error> {
+    let dm = load_manager(manifests, manager)?;
+    
+    let cicd = dm
+        .spec
+        .cicd
+        .as_ref()
+        .ok_or_else(|| Error::CiCdError(format!("{} has no CI/CD configuration", dm.name_any())))?;
+    let pipeline = cicd
+        .pipelines
+        .iter()
+        .find(|pipeline| pipeline.name == name)
+        .ok_or_else(|| Error::CiCdError(format!("No pipeline named {}", name)))?;
+    
+    let template = config.cicd_templates.get(cicd.provider.template_name());
+    let pipeline = execution::with_defaults(&library::expand(pipeline, &library::library(template))?, template);
+    references::validate(&pipeline)?;
+    execution::validate(&pipeline)?;
+    
+    Ok(pipeline)
+}
+
+/// Output of `git` in `workdir`, if it succeeds
+fn git(workdir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).current_dir(workdir).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Params of a local run of `pipeline`: `overrides`, then the commit and
+/// branch checked out in `workdir`, then the defaults
+pub fn params(
+    pipeline: &Pipeline,
+    workdir: &Path,
+    overrides: BTreeMap<String, String>,
+) -> Result<BTreeMap<String, String>, Error> {
+    let checkout: [(&str, &[&str]); 2] = [
+        ("git-revision", &["rev-parse", "HEAD"]),
+        ("git-branch", &["rev-parse", "--abbrev-ref", "HEAD"]),
+    ];
+    let mut params: BTreeMap<String, String> = checkout
+        .into_iter()
+        .filter_map(|(name, args)| Some((name.to_string(), git(workdir, args)?)))
+        .collect();
+    params.extend(overrides);
+    
+    super::run_params(pipeline, params)
+}
+
+/// Params given as `name=value` pairs, each possibly comma-separated
+pub fn parse_params(pairs: &[String]) -> Result<BTreeMap<String, String>, Error> {
+    let mut params = BTreeMap::new();
+    for pair in pairs {
+        params.extend(super::parse_params(pair)?);
+    }
+    Ok(params)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepStatus {
+    Succeeded,
+    /// Exit code, unless the step could not be started or was killed
+    Failed(Option<i32>),
+    /// Conditions did not hold
+    Skipped,
+    /// Step it runs after did not succeed
+    NotRun(String),
+}
+
+impl fmt::Display for StepStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepStatus::Succeeded => write!(f, "Succeeded"),
+            StepStatus::Failed(Some(code)) => write!(f, "Failed (exit code {})", code),
+            StepStatus::Failed(None) => write!(f, "Failed"),
+            StepStatus::Skipped => write!(f, "Skipped (conditions not met)"),
+            StepStatus::NotRun(step) => write!(f, "Not run ({} did not succeed)", step),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StepOutcome {
+    pub name: String,
+    pub status: StepStatus,
+    /// Time spent over all attempts
+    pub duration: Duration,
+    pub attempts: u32,
+}
+
+impl StepOutcome {
+    fn not_run(step: &PipelineStep, status: StepStatus) -> Self {
+        Self { name: step.name.clone(), status, duration: Duration::ZERO, attempts: 0 }
+    }
+}
+
+/// Outcome of a local run, steps in the order they ran
+#[derive(Debug, Clone)]
+pub struct RunSummary {
+    pub pipeline: String,
+    /// Container runtime, or `local` for local processes
+    pub runtime: String,
+    pub steps: Vec<StepOutcome>,
+}
+
+impl RunSummary {
+    pub fn succeeded(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|step| matches!(step.status, StepStatus::Succeeded | StepStatus::Skipped))
+    }
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let result = if self.succeeded() { "Succeeded" } else { "Failed" };
+        writeln!(f, "Pipeline {} ({}): {}", self.pipeline, self.runtime, result)?;
+        
+        let statuses: Vec<String> = self.steps.iter().map(|step| step.status.to_string()).collect();
+        let name_width = self.steps.iter().map(|step| step.name.len()).max().unwrap_or_default();
+        let status_width = statuses.iter().map(String::len).max().unwrap_or_default();
+        
+        for (step, status) in self.steps.iter().zip(&statuses) {
+            let mut line = format!("  {:name_width$}  {:status_width$}", step.name, status);
+            if step.attempts > 0 {
+                line.push_str(&format!("  {:.1}s", step.duration.as_secs_f64()));
+            }
+            if step.attempts > 1 {
+                line.push_str(&format!(" ({} attempts)", step.attempts));
+            }
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        
+        Ok(())
+    }
+}
+
+/// Scratch directory of a run holding results and artifacts, removed on drop
+struct RunDir(PathBuf);
+
+impl Drop for RunDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Whether every condition of `step` holds for `params`
+fn conditions_hold(step: &PipelineStep, params: &BTreeMap<String, String>) -> bool {
+    step.when.iter().flatten().all(|condition| {
+        let value = params.get(&condition.param).map(String::as_str).unwrap_or_default();
+        let listed = condition.values.iter().any(|candidate| candidate == value);
+        match condition.operator {
+            ConditionOperator::In => listed,
+            ConditionOperator::NotIn => !listed,
+        }
+    })
+}
+
+struct Runner<'a> {
+    pipeline: &'a Pipeline,
+    params: &'a BTreeMap<String, String>,
+    engine: Option<&'static str>,
+    workdir: PathBuf,
+    run_dir: RunDir,
+    /// Results written so far, by step and name
+    results: HashMap<(String, String), String>,
+}
+
+impl Runner<'_> {
+    /// Command running `step`, whose references are resolved already, in
+    /// `working_dir` relative to the run's working directory, or to its mount
+    /// in the container
+    fn command(&self, step: &PipelineStep) -> Command {
+        let script = super::script(step);
+        let env: BTreeMap<String, String> = step.env.clone().unwrap_or_default().into_iter().collect();
+        let working_dir = step.working_dir.as_deref().unwrap_or(".");
+        
+        let Some(engine) = self.engine else {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(script).current_dir(self.workdir.join(working_dir)).envs(env);
+            return command;
+        };
+        
+        let run_dir = self.run_dir.0.display();
+        let mut command = Command::new(engine);
+        command
+            .args(["run", "--rm", "--volume"])
+            .arg(format!("{}:{}", self.workdir.display(), CONTAINER_WORKDIR))
+            .arg("--volume")
+            .arg(format!("{}:{}", run_dir, run_dir))
+            .arg("--workdir")
+            .arg(Path::new(CONTAINER_WORKDIR).join(working_dir));
+        for (name, value) in env {
+            command.arg("--env").arg(format!("{}={}", name, value));
+        }
+        // Secret values are passed on from the environment of the run
+        for variable in step.secret_env.iter().flatten() {
+            command.arg("--env").arg(&variable.name);
+        }
+        command.args(["--entrypoint", "sh"]).arg(&step.image).arg("-c").arg(script);
+        
+        command
+    }
+    
+    /// Runs `step` with its params, the results it references and its
+    /// artifacts in place, retrying failures as often as it allows
+    fn run_step(&mut self, step: &PipelineStep) -> Result<StepOutcome, Error> {
+        let results_dir = self.run_dir.0.join("results").join(&step.name);
+        std::fs::create_dir_all(&results_dir)
+            .map_err(|e| Error::IoError(format!("Failed to create {}: {}", results_dir.display(), e)))?;
+        
+        let mut resolved = references::rewrite_step(step, |reference| match reference {
+            Reference::Param(name) => self.params.get(name).cloned(),
+            Reference::Result { step, name } => {
+                Some(self.results.get(&(step.to_string(), name.to_string())).cloned().unwrap_or_default())
+            }
+        });
+        if step.results.is_some() {
+            resolved
+                .env
+                .get_or_insert_with(Default::default)
+                .insert(references::RESULTS_VARIABLE.to_string(), results_dir.display().to_string());
+        }
+        let resolved = references::copy_artifacts(resolved, &self.run_dir.0.display().to_string());
+        
+        for variable in step.secret_env.iter().flatten() {
+            if std::env::var_os(&variable.name).is_none() {
+                warn!(
+                    "Step {} reads {} from Secret {}, which is not set in the environment",
+                    step.name, variable.name, variable.secret
+                );
+            }
+        }
+        
+        let attempts = execution::step_settings(self.pipeline, step).retries.unwrap_or_default() + 1;
+        let started = Instant::now();
+        let mut outcome = StepOutcome::not_run(step, StepStatus::Failed(None));
+        
+        while outcome.attempts < attempts {
+            outcome.attempts += 1;
+            let retry = if outcome.attempts > 1 { format!(", attempt {}", outcome.attempts) } else { String::new() };
+            println!("==> {} ({}{})", step.name, self.engine.map_or("local", |_| step.image.as_str()), retry);
+            
+            outcome.status = match self.command(&resolved).status() {
+                Ok(status) if status.success() => StepStatus::Succeeded,
+                Ok(status) => StepStatus::Failed(status.code()),
+                Err(e) => {
+                    eprintln!("Failed to start step {}: {}", step.name, e);
+                    StepStatus::Failed(None)
+                }
+            };
+            if outcome.status == StepStatus::Succeeded {
+                break;
+            }
+        }
+        outcome.duration = started.elapsed();
+        
+        if outcome.status == StepStatus::Succeeded {
+            for result in step.results.iter().flatten() {
+                match std::fs::read_to_string(results_dir.join(&result.name)) {
+                    Ok(value) => {
+                        let value = value.trim_end_matches('\n').to_string();
+                        self.results.insert((step.name.clone(), result.name.clone()), value);
+                    }
+                    Err(_) => warn!("Step {} did not write result {}", step.name, result.name),
+                }
+            }
+        }
+        
+        Ok(outcome)
+    }
+}
+
+/// Runs `pipeline` with `params` in `workdir`, in containers of `engine` or
+/// as local processes. Steps run one at a time in dependency order; steps
+/// after a failed one are not run, and the `finally` steps always run.
+pub fn run(
+    pipeline: &Pipeline,
+    params: &BTreeMap<String, String>,
+    engine: Option<&'static str>,
+    workdir: &Path,
+) -> Result<RunSummary, Error> {
+    let run_after = order::run_after(pipeline)?;
+    
+    let workdir = workdir
+        .canonicalize()
+        .map_err(|e| Error::IoError(format!("Failed to resolve {}: {}", workdir.display(), e)))?;
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or_default();
+    let run_dir = RunDir(std::env::temp_dir().join(format!("zerg-run-{}-{}-{}", pipeline.name, std::process::id(), nanos)));
+    
+    let mut runner = Runner { pipeline, params, engine, workdir, run_dir, results: HashMap::new() };
+    let mut statuses: HashMap<&str, StepStatus> = HashMap::new();
+    let mut steps = Vec::new();
+    
+    let mut pending: Vec<usize> = (0..pipeline.steps.len()).collect();
+    while let Some(position) = pending
+        .iter()
+        .position(|&i| run_after[i].iter().all(|name| statuses.contains_key(name)))
+    {
+        let i = pending.remove(position);
+        let step = &pipeline.steps[i];
+        let blocker = run_after[i]
+            .iter()
+            .find(|name| matches!(statuses[**name], StepStatus::Failed(_) | StepStatus::NotRun(_)));
+        
+        let outcome = match blocker {
+            Some(blocker) => StepOutcome::not_run(step, StepStatus::NotRun(blocker.to_string())),
+            None if !conditions_hold(step, params) => StepOutcome::not_run(step, StepStatus::Skipped),
+            None => runner.run_step(step)?,
+        };
+        statuses.insert(&step.name, outcome.status.clone());
+        steps.push(outcome);
+    }
+    
+    for step in pipeline.finally.iter().flatten() {
+        let outcome = if conditions_hold(step, params) {
+            runner.run_step(step)?
+        } else {
+            StepOutcome::not_run(step, StepStatus::Skipped)
+        };
+        steps.push(outcome);
+    }
+    
+    Ok(RunSummary {
+        pipeline: pipeline.name.clone(),
+        runtime: engine.unwrap_or("local").to_string(),
+        steps,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cicd::tests::{pipeline, step};
+    use crate::crd::{StepCondition, StepResult};
+    
+    /// Empty working directory unique to the calling test
+    fn workdir(name: &str) -> RunDir {
+        let dir = RunDir(std::env::temp_dir().join(format!("zerg-local-test-{}-{}", name, std::process::id())));
+        let _ = std::fs::remove_dir_all(&dir.0);
+        std::fs::create_dir_all(dir.0.join("app")).unwrap();
+        dir
+    }
+    
+    fn statuses(summary: &RunSummary) -> Vec<(&str, &StepStatus)> {
+        summary.steps.iter().map(|step| (step.name.as_str(), &step.status)).collect()
+    }
+    
+    #[test]
+    fn runs_steps_in_order_with_env_working_dir_and_results() {
+        let dir = workdir("order");
+        
+        let mut greet = step("greet");
+        greet.commands = vec!["echo \"$GREETING\" > greeting.txt".to_string()];
+        greet.env = Some([("GREETING".to_string(), "hello $(params.git-branch)".to_string())].into_iter().collect());
+        greet.working_dir = Some("app".to_string());
+        greet.depends_on = Some(Vec::new());
+        let mut read = step("read");
+        read.commands = vec!["cat app/greeting.txt > \"$RESULTS_DIR/greeting\"".to_string()];
+        read.results = Some(vec![StepResult { name: "greeting".to_string(), description: None }]);
+        let mut check = step("check");
+        check.commands = vec!["test \"$(steps.read.results.greeting)\" = \"hello main\"".to_string()];
+        // Declared first, but runs after the step it depends on
+        check.depends_on = Some(vec!["read".to_string()]);
+        read.depends_on = Some(vec!["greet".to_string()]);
+        
+        let pipeline = pipeline("build", vec![check, greet, read]);
+        let params = BTreeMap::from([("git-branch".to_string(), "main".to_string())]);
+        let summary = run(&pipeline, &params, None, &dir.0).unwrap();
+        
+        assert_eq!(
+            statuses(&summary),
+            [("greet", &StepStatus::Succeeded), ("read", &StepStatus::Succeeded), ("check", &StepStatus::Succeeded)]
+        );
+        assert!(summary.succeeded());
+        assert!(summary.to_string().starts_with("Pipeline build (local): Succeeded\n  greet  Succeeded  "));
+    }
+    
+    #[test]
+    fn stops_after_failures_and_runs_finally_steps() {
+        let dir = workdir("failure");
+        
+        let mut flaky = step("flaky");
+        flaky.commands = vec!["echo x >> attempts".to_string(), "exit 3".to_string()];
+        flaky.execution.retries = Some(1);
+        let mut deploy = step("deploy");
+        deploy.depends_on = Some(vec!["flaky".to_string()]);
+        let mut lint = step("lint");
+        lint.depends_on = Some(Vec::new());
+        lint.when = Some(vec![StepCondition {
+            param: "git-branch".to_string(),
+            operator: ConditionOperator::In,
+            values: vec!["main".to_string()],
+        }]);
+        let mut notify = step("notify");
+        notify.commands = vec!["touch notified".to_string()];
+        
+        let mut pipeline = pipeline("release", vec![flaky, deploy, lint]);
+        pipeline.finally = Some(vec![notify]);
+        let summary = run(&pipeline, &BTreeMap::new(), None, &dir.0).unwrap();
+        
+        assert_eq!(
+            statuses(&summary),
+            [
+                ("flaky", &StepStatus::Failed(Some(3))),
+                ("deploy", &StepStatus::NotRun("flaky".to_string())),
+                ("lint", &StepStatus::Skipped),
+                ("notify", &StepStatus::Succeeded),
+            ]
+        );
+        assert_eq!(summary.steps[0].attempts, 2);
+        assert_eq!(std::fs::read_to_string(dir.0.join("attempts")).unwrap(), "x\nx\n");
+        assert!(dir.0.join("notified").exists());
+        assert!(!summary.succeeded());
+    }
+    
+    #[test]
+    fn fails_steps_at_the_first_failing_command() {
+        let dir = workdir("errexit");
+        
+        let mut test = step("test");
+        test.commands = vec!["false".to_string(), "touch passed".to_string()];
+        let summary = run(&pipeline("build", vec![test]), &BTreeMap::new(), None, &dir.0).unwrap();
+        
+        assert_eq!(statuses(&summary), [("test", &StepStatus::Failed(Some(1)))]);
+        assert!(!dir.0.join("passed").exists());
+    }
+    
+    #[test]
+    fn loads_expanded_pipelines_from_manifests() {
+        let manifests = r#"
+apiVersion: v1
+kind: Namespace
+metadata:
+  name: apps
+---
+apiVersion: zerg.io/v1
+kind: DependencyManager
+metadata:
+  name: app
+spec:
+  dependencies: []
+  cicd:
+    provider: tekton
+    pipelines:
+      - name: build
+        trigger: { manual: true }
+        steps:
+          - { name: test, template: cargo-test }
+"#;
+        let config = Config::default();
+        
+        let pipeline = load_pipeline(manifests, None, "build", &config).unwrap();
+        assert_eq!(pipeline.steps[0].image, "rust:1.79");
+        assert!(load_pipeline(manifests, None, "deploy", &config).is_err());
+        assert!(load_pipeline(manifests, Some("other"), "build", &config).is_err());
+    }
+}
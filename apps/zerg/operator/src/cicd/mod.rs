@@ -0,0 +1,1011 @@
+use anyhow::Result;
+use k8s_openapi::api::core::v1::{EnvVar, EnvVarSource, SecretKeySelector};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::{Api, Patch, PatchParams};
+use kube::{Client, ResourceExt};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::process::Command;
+use tracing::{info, instrument, warn};
+
+use crate::crd::{
+    CiCdConfig, CiCdGitWrite, CiCdProvider, CiCdStatus, DependencyManager, Pipeline, PipelineStatus, PipelineStep,
+    WorkspaceType,
+};
+use crate::config::CiCdTemplate;
+use crate::error::Error;
+use crate::gitops::{self, git};
+use crate::impersonation::{self, Impersonation};
+use crate::resources;
+
+mod argo;
+pub mod execution;
+mod github;
+mod gitlab;
+mod history;
+pub mod hosted;
+mod library;
+pub mod local;
+mod order;
+mod references;
+mod tekton;
+mod trigger;
+pub mod webhook;
+mod workspace;
+
+pub use history::RunTracker;
+pub use trigger::SECRET_KEY as WEBHOOK_SECRET_KEY;
+
+/// Parameters every generated pipeline accepts, filled from webhooks by the triggers
+pub const PARAMS: &[(&str, &str)] = &[
+    ("git-repo-url", "URL of the repository that triggered the run"),
+    ("git-revision", "Commit that triggered the run"),
+    ("git-branch", "Branch pushed to, or targeted by the pull request, that triggered the run"),
+];
+
+/// Label on PipelineRuns and Workflows naming the pipeline they run, however
+/// they were started
+pub const PIPELINE_LABEL: &str = "zerg.io/pipeline";
+
+/// Labels of the runs of `pipeline`, tying them to `owner` even when an
+/// EventListener or CronWorkflow starts them
+fn run_labels(owner: &DependencyManager, pipeline: &Pipeline) -> BTreeMap<String, String> {
+    let mut labels = resources::owner_labels(owner);
+    labels.insert(PIPELINE_LABEL.to_string(), pipeline.name.clone());
+    labels
+}
+
+/// Attempts at recording a run before giving up on concurrent status updates
+const RECORD_ATTEMPTS: usize = 3;
+
+/// Status reported for runs the operator started
+const STARTED: &str = "Started";
+
+/// Status reported for run requests that cannot be started as given
+const REJECTED: &str = "Rejected";
+
+/// Annotation requesting a manual run as `<pipeline>@<nonce>`; setting a new
+/// nonce requests another run
+pub const RUN_PIPELINE_ANNOTATION: &str = "zerg.io/run-pipeline";
+
+/// Annotation overriding params of the requested run, as comma-separated
+/// `name=value` pairs
+pub const RUN_PARAMS_ANNOTATION: &str = "zerg.io/run-pipeline-params";
+
+/// Tekton or Argo object with a typed spec
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Object<S> {
+    pub api_version: &'static str,
+    pub kind: &'static str,
+    pub metadata: ObjectMeta,
+    pub spec: S,
+}
+
+fn object<S>(api_version: &'static str, kind: &'static str, name: String, namespace: &str, spec: S) -> Object<S> {
+    Object {
+        api_version,
+        kind,
+        metadata: ObjectMeta {
+            name: Some(name),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        },
+        spec,
+    }
+}
+
+fn to_value<T: Serialize>(object: &T) -> Result<Value, Error> {
+    serde_json::to_value(object)
+        .map_err(|e| Error::SerializationError(format!("Failed to serialize CI/CD object: {}", e)))
+}
+
+/// Shell script running the commands of `step` in order, stopping at the
+/// first one that fails
+fn script(step: &PipelineStep) -> String {
+    std::iter::once("set -e\n".to_string())
+        .chain(step.commands.iter().map(|command| format!("{}\n", command)))
+        .collect()
+}
+
+/// Environment of `step`, including the variables read from Secrets, sorted so
+/// that regenerating an unchanged step yields identical objects
+fn env(step: &PipelineStep) -> Vec<EnvVar> {
+    let mut env: BTreeMap<_, _> = step
+        .env
+        .iter()
+        .flatten()
+        .map(|(name, value)| (name.clone(), EnvVar { name: name.clone(), value: Some(value.clone()), ..Default::default() }))
+        .collect();
+    
+    for variable in step.secret_env.iter().flatten() {
+        let source = EnvVarSource {
+            secret_key_ref: Some(SecretKeySelector {
+                name: variable.secret.clone(),
+                key: variable.key.clone(),
+                optional: None,
+            }),
+            ..Default::default()
+        };
+        env.insert(
+            variable.name.clone(),
+            EnvVar { name: variable.name.clone(), value_from: Some(source), ..Default::default() },
+        );
+    }
+    
+    env.into_values().collect()
+}
+
+/// Names of the cache claims of `config` for `owner`, which outlive the
+/// objects rendered from previous keys
+fn cache_claim_names(config: &CiCdConfig, owner: &DependencyManager) -> Vec<String> {
+    config
+        .pipelines
+        .iter()
+        .flat_map(|pipeline| {
+            workspace::workspaces(pipeline)
+                .into_iter()
+                .filter(|workspace| workspace.type_ == WorkspaceType::Cache)
+                .map(|workspace| workspace::cache_claim_name(owner, pipeline, &workspace))
+        })
+        .collect()
+}
+
+impl CiCdProvider {
+    /// Key of the provider's entry in `Config::cicd_templates`
+    pub fn template_name(&self) -> &'static str {
+        match self {
+            CiCdProvider::Tekton => "tekton",
+            CiCdProvider::ArgoWorkflows => "argo-workflows",
+            CiCdProvider::GithubActions => "github-actions",
+            CiCdProvider::GitlabCi => "gitlab-ci",
+        }
+    }
+}
+
+pub struct CiCdManager {
+    client: Client,
+    impersonation: Option<Impersonation>,
+}
+
+impl CiCdManager {
+    pub fn new(client: Client, impersonation: Option<Impersonation>) -> Self {
+        Self { client, impersonation }
+    }
+    
+    /// Cluster CLI command run as the DependencyManager's ServiceAccount, if any
+    fn command(&self, program: &str) -> Command {
+        impersonation::command(program, self.impersonation.as_ref())
+    }
+    
+    /// Installs the provider and creates the pipelines of `config`, with their
+    /// templated steps expanded from the built-in library and `template`, and
+    /// the settings they leave unset taken from its defaults. GitHub Actions
+    /// and GitLab CI pipelines are rendered into workflow files instead, and
+    /// what those cannot express is recorded through `status_client`.
+    #[instrument(skip(self, status_client, template, owner))]
+    pub async fn setup_cicd(
+        &self,
+        status_client: &Client,
+        config: &CiCdConfig,
+        template: Option<&CiCdTemplate>,
+        owner: &DependencyManager,
+    ) -> Result<(), Error> {
+        let namespace = owner.namespace().unwrap_or_default();
+        let config = prepare(config, template)?;
+        
+        if config.git_write.is_some() && !hosted::is_hosted(&config.provider) {
+            return Err(Error::CiCdError(format!("git_write is not supported by {}", hosted::service(&config.provider))));
+        }
+        
+        match config.provider {
+            CiCdProvider::Tekton => self.setup_tekton(&config, &namespace, owner).await?,
+            CiCdProvider::ArgoWorkflows => self.setup_argo_workflows(&config, &namespace, owner).await?,
+            CiCdProvider::GithubActions | CiCdProvider::GitlabCi => {
+                self.setup_hosted(status_client, &config, template, owner).await?
+            }
+        }
+        
+        // Caches whose key, pipeline or provider changed are dropped along with their contents
+        let caches = if hosted::is_hosted(&config.provider) { Vec::new() } else { cache_claim_names(&config, owner) };
+        resources::prune_labelled(&self.client, resources::PERSISTENT_VOLUME_CLAIM, &namespace, owner, &caches).await
+    }
+    
+    /// Renders the pipelines of `config` into workflow files, commits them to
+    /// their trigger repositories in git-write mode, and records the
+    /// compatibility report and commit of every pipeline in its status
+    #[instrument(skip(self, status_client, config, template, owner))]
+    async fn setup_hosted(
+        &self,
+        status_client: &Client,
+        config: &CiCdConfig,
+        template: Option<&CiCdTemplate>,
+        owner: &DependencyManager,
+    ) -> Result<(), Error> {
+        info!("Rendering {} pipelines", hosted::service(&config.provider));
+        
+        let source = format!("{}/{}", owner.namespace().unwrap_or_default(), owner.name_any());
+        for rendering in hosted::render(&config.provider, &config.pipelines, template, &source)? {
+            let commit = match &config.git_write {
+                Some(git_write) => Some(self.commit_workflows(config, git_write, &rendering, &source, owner).await?),
+                None => None,
+            };
+            
+            for (name, report) in &rendering.reports {
+                for message in report {
+                    info!("Pipeline {}: {}", name, message);
+                }
+                update_pipeline_status(status_client, owner, name, None, |status| {
+                    status.compatibility = (!report.is_empty()).then(|| report.clone());
+                    status.last_commit = commit.clone();
+                })
+                .await?;
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Commits the files of `rendering` to its trigger repository, replacing
+    /// those rendered before for `source`, and returns the commit
+    async fn commit_workflows(
+        &self,
+        config: &CiCdConfig,
+        git_write: &CiCdGitWrite,
+        rendering: &hosted::Rendering,
+        source: &str,
+        owner: &DependencyManager,
+    ) -> Result<String, Error> {
+        let pipelines: Vec<&Pipeline> = config
+            .pipelines
+            .iter()
+            .filter(|pipeline| rendering.reports.contains_key(&pipeline.name))
+            .collect();
+        let names: Vec<&str> = pipelines.iter().map(|pipeline| pipeline.name.as_str()).collect();
+        let Some(repository) = &rendering.repository else {
+            return Err(Error::CiCdError(format!(
+                "Pipelines {} have no git trigger naming the repository to commit them to",
+                names.join(", ")
+            )));
+        };
+        
+        let branch = hosted::commit_branch(git_write, &pipelines)?;
+        let namespace = owner.namespace().unwrap_or_default();
+        let commit = &git_write.commit;
+        let credentials = gitops::git_credentials(&self.client, &namespace, commit.secret_ref.as_deref()).await?;
+        
+        let mut message = format!("Update {} pipelines of {}\n\n", hosted::service(&config.provider), source);
+        for name in &names {
+            message.push_str(&format!("- {}\n", name));
+        }
+        let header = hosted::header(source);
+        
+        let write = git::GitWrite {
+            repository,
+            branch: &branch,
+            target_branch: commit.pull_request_branch.as_deref().unwrap_or(&branch),
+            path: rendering.path,
+            marker: &header,
+            message: &message,
+            author_name: commit.author_name.as_deref().unwrap_or(git::DEFAULT_AUTHOR_NAME),
+            author_email: commit.author_email.as_deref().unwrap_or(git::DEFAULT_AUTHOR_EMAIL),
+        };
+        
+        let commit = git::GitWriter::new(credentials).write(&write, &rendering.files)?;
+        info!("Committed pipelines {} to {} at {}", names.join(", "), repository, commit);
+        
+        Ok(commit)
+    }
+    
+    #[instrument(skip(self, owner))]
+    async fn setup_tekton(
+        &self,
+        config: &CiCdConfig,
+        namespace: &str,
+        owner: &DependencyManager,
+    ) -> Result<(), Error> {
+        info!("Setting up Tekton CI/CD");
+        
+        // Install Tekton if not present
+        self.install_tekton().await?;
+        
+        // EventListeners run as a ServiceAccount of the DependencyManager
+        let service_account = tekton::listener_service_account(&owner.name_any());
+        let triggered = config.pipelines.iter().any(|p| p.trigger.git.is_some());
+        if triggered {
+            for manifest in tekton::listener_access(&service_account, namespace)? {
+                resources::apply_manifest(&self.client, manifest, owner).await?;
+            }
+        } else {
+            // Not garbage-collected through ownerReferences, being cluster-scoped
+            resources::prune_labelled(&self.client, resources::CLUSTER_ROLE_BINDING, namespace, owner, &[]).await?;
+        }
+        
+        // Create pipelines
+        for pipeline in &config.pipelines {
+            self.create_tekton_pipeline(pipeline, namespace, &service_account, owner).await?;
+        }
+        
+        Ok(())
+    }
+    
+    #[instrument(skip(self))]
+    async fn install_tekton(&self) -> Result<(), Error> {
+        info!("Installing Tekton Pipelines");
+        
+        // Check if Tekton is already installed
+        let check_cmd = self.command("kubectl")
+            .args(["get", "namespace", "tekton-pipelines"])
+            .output();
+        
+        if check_cmd.is_ok() && check_cmd.unwrap().status.success() {
+            info!("Tekton already installed");
+            return Ok(());
+        }
+        
+        // Install Tekton Pipelines
+        let install_cmd = self.command("kubectl")
+            .args([
+                "apply", "-f",
+                "https://storage.googleapis.com/tekton-releases/pipeline/latest/release.yaml"
+            ])
+            .output()
+            .map_err(|e| Error::CommandError(format!("Failed to install Tekton: {}", e)))?;
+        
+        if !install_cmd.status.success() {
+            let stderr = String::from_utf8_lossy(&install_cmd.stderr);
+            return Err(Error::CommandError(format!("Tekton install failed: {}", stderr)));
+        }
+        
+        // Install Tekton Dashboard (optional)
+        let dashboard_cmd = self.command("kubectl")
+            .args([
+                "apply", "-f",
+                "https://storage.googleapis.com/tekton-releases/dashboard/latest/release.yaml"
+            ])
+            .output();
+        
+        if let Ok(output) = dashboard_cmd {
+            if output.status.success() {
+                info!("Tekton Dashboard installed");
+            } else {
+                warn!("Failed to install Tekton Dashboard (optional)");
+            }
+        }
+        
+        Ok(())
+    }
+    
+    #[instrument(skip(self, owner))]
+    async fn create_tekton_pipeline(
+        &self,
+        pipeline: &Pipeline,
+        namespace: &str,
+        service_account: &str,
+        owner: &DependencyManager,
+    ) -> Result<(), Error> {
+        info!("Creating Tekton pipeline: {}", pipeline.name);
+        
+        // Cache claims, Tasks and Pipeline, plus trigger objects if a git trigger is configured
+        for manifest in tekton::manifests(pipeline, owner, service_account)? {
+            resources::apply_manifest(&self.client, manifest, owner).await?;
+        }
+        
+        Ok(())
+    }
+    
+    #[instrument(skip(self, owner))]
+    async fn setup_argo_workflows(
+        &self,
+        config: &CiCdConfig,
+        namespace: &str,
+        owner: &DependencyManager,
+    ) -> Result<(), Error> {
+        info!("Setting up Argo Workflows CI/CD");
+        
+        // Install Argo Workflows if not present
+        self.install_argo_workflows().await?;
+        
+        // Create workflows
+        for pipeline in &config.pipelines {
+            self.create_argo_workflow(pipeline, namespace, owner).await?;
+        }
+        
+        Ok(())
+    }
+    
+    #[instrument(skip(self))]
+    async fn install_argo_workflows(&self) -> Result<(), Error> {
+        info!("Installing Argo Workflows");
+        
+        // Check if Argo Workflows is already installed
+        let check_cmd = self.command("kubectl")
+            .args(["get", "namespace", "argo"])
+            .output();
+        
+        if check_cmd.is_ok() && check_cmd.unwrap().status.success() {
+            info!("Argo Workflows already installed");
+            return Ok(());
+        }
+        
+        // Create namespace
+        let create_ns_cmd = self.command("kubectl")
+            .args(["create", "namespace", "argo"])
+            .output()
+            .map_err(|e| Error::CommandError(format!("Failed to create argo namespace: {}", e)))?;
+        
+        if !create_ns_cmd.status.success() {
+            let stderr = String::from_utf8_lossy(&create_ns_cmd.stderr);
+            // Ignore error if namespace already exists
+            if !stderr.contains("already exists") {
+                return Err(Error::CommandError(format!("Failed to create argo namespace: {}", stderr)));
+            }
+        }
+        
+        // Install Argo Workflows
+        let install_cmd = self.command("kubectl")
+            .args([
+                "apply", "-n", "argo", "-f",
+                "https://github.com/argoproj/argo-workflows/releases/latest/download/install.yaml"
+            ])
+            .output()
+            .map_err(|e| Error::CommandError(format!("Failed to install Argo Workflows: {}", e)))?;
+        
+        if !install_cmd.status.success() {
+            let stderr = String::from_utf8_lossy(&install_cmd.stderr);
+            return Err(Error::CommandError(format!("Argo Workflows install failed: {}", stderr)));
+        }
+        
+        Ok(())
+    }
+    
+    #[instrument(skip(self, owner))]
+    async fn create_argo_workflow(
+        &self,
+        pipeline: &Pipeline,
+        namespace: &str,
+        owner: &DependencyManager,
+    ) -> Result<(), Error> {
+        info!("Creating Argo Workflow: {}", pipeline.name);
+        
+        // Cache claims and WorkflowTemplate, plus a CronWorkflow if a schedule trigger is configured
+        for manifest in argo::manifests(pipeline, owner)? {
+            resources::apply_manifest(&self.client, manifest, owner).await?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Starts a run of `pipeline` with `params`, as a PipelineRun or a Workflow
+    /// from its WorkflowTemplate, returning its name. Settings the pipeline
+    /// leaves unset come from the defaults of `template`.
+    #[instrument(skip(self, template, owner))]
+    pub async fn submit_run(
+        &self,
+        pipeline: &Pipeline,
+        params: &BTreeMap<String, String>,
+        template: Option<&CiCdTemplate>,
+        owner: &DependencyManager,
+    ) -> Result<String, Error> {
+        let provider = owner.spec.cicd.as_ref().map(|cicd| &cicd.provider);
+        let pipeline = &execution::with_defaults(pipeline, template);
+        
+        let run = match provider {
+            Some(CiCdProvider::Tekton) => {
+                let params = params
+                    .iter()
+                    .map(|(name, value)| tekton::Param { name: name.clone(), value: value.clone() })
+                    .collect();
+                to_value(&tekton::pipeline_run(pipeline, owner, params))?
+            }
+            Some(CiCdProvider::ArgoWorkflows) => {
+                let parameters = params
+                    .iter()
+                    .map(|(name, value)| argo::Parameter {
+                        name: name.clone(),
+                        value: Some(value.clone()),
+                        description: None,
+                    })
+                    .collect();
+                to_value(&argo::workflow(pipeline, owner, parameters))?
+            }
+            Some(provider) => {
+                return Err(Error::CiCdError(format!(
+                    "Pipeline {}: runs are started by {}, not the operator",
+                    pipeline.name,
+                    hosted::service(provider)
+                )));
+            }
+            None => return Err(Error::CiCdError(format!("{} has no CI/CD configuration", owner.name_any()))),
+        };
+        
+        let name = resources::create_manifest(&self.client, run, owner).await?;
+        info!("Started run {} of pipeline {}", name, pipeline.name);
+        
+        Ok(name)
+    }
+    
+    /// Starts the run requested through `RUN_PIPELINE_ANNOTATION` unless it
+    /// was started already, recording it through `status_client`.
+    ///
+    /// Requests naming an unknown pipeline or params are recorded as rejected
+    /// with the reason in the pipeline's status, rather than retried.
+    #[instrument(skip(self, status_client, template, owner))]
+    pub async fn run_requested(
+        &self,
+        status_client: &Client,
+        template: Option<&CiCdTemplate>,
+        owner: &DependencyManager,
+    ) -> Result<Option<String>, Error> {
+        let (Some(cicd), Some(request)) = (&owner.spec.cicd, owner.annotations().get(RUN_PIPELINE_ANNOTATION)) else {
+            return Ok(None);
+        };
+        
+        // Read the status afresh so that a cached copy cannot start the same run twice
+        let api: Api<DependencyManager> = Api::namespaced(status_client.clone(), &owner.namespace().unwrap_or_default());
+        let current = api.get_status(&owner.name_any()).await?;
+        let handled = current.status.and_then(|status| status.cicd_status).and_then(|status| status.run_request);
+        if handled.as_ref() == Some(request) {
+            return Ok(None);
+        }
+        
+        let (pipeline, params) = match requested_run(cicd, request, owner.annotations().get(RUN_PARAMS_ANNOTATION)) {
+            Ok(run) => run,
+            Err(e) => {
+                warn!("Rejected run request {} of {}: {}", request, owner.name_any(), e);
+                let name = request.split_once('@').map_or(request.as_str(), |(name, _)| name);
+                update_pipeline_status(status_client, owner, name, Some(request.clone()), |status| {
+                    status.status = REJECTED.to_string();
+                    status.error = Some(e.to_string());
+                })
+                .await?;
+                return Ok(None);
+            }
+        };
+        
+        let run_name = self.submit_run(pipeline, &params, template, owner).await?;
+        record_run(status_client, owner, pipeline, &run_name, &params, Some(request.clone())).await?;
+        
+        Ok(Some(run_name))
+    }
+}
+
+/// Pipeline and params of run request `request`, with the params in
+/// `overrides` as given in `RUN_PARAMS_ANNOTATION`
+fn requested_run<'a>(
+    config: &'a CiCdConfig,
+    request: &str,
+    overrides: Option<&String>,
+) -> Result<(&'a Pipeline, BTreeMap<String, String>), Error> {
+    let name = request.split_once('@').map_or(request, |(name, _)| name);
+    let pipeline = manual_pipeline(config, name)?;
+    let overrides = overrides.map(|params| parse_params(params)).transpose()?.unwrap_or_default();
+    
+    Ok((pipeline, run_params(pipeline, overrides)?))
+}
+
+/// `config` with the templated steps of its pipelines expanded from the
+/// built-in library and `template`, and the settings they leave unset taken
+/// from its defaults
+pub fn prepare(config: &CiCdConfig, template: Option<&CiCdTemplate>) -> Result<CiCdConfig, Error> {
+    let library = library::library(template);
+    
+    Ok(CiCdConfig {
+        pipelines: config
+            .pipelines
+            .iter()
+            .map(|pipeline| {
+                let expanded = library::expand(pipeline, &library)?;
+                Ok::<_, Error>(execution::with_defaults(&expanded, template))
+            })
+            .collect::<Result<_, _>>()?,
+        ..config.clone()
+    })
+}
+
+/// Pipeline `name` of `config`, provided it allows manual runs
+pub fn manual_pipeline<'a>(config: &'a CiCdConfig, name: &str) -> Result<&'a Pipeline, Error> {
+    let pipeline = config
+        .pipelines
+        .iter()
+        .find(|pipeline| pipeline.name == name)
+        .ok_or_else(|| Error::CiCdError(format!("No pipeline named {}", name)))?;
+    
+    if !pipeline.trigger.manual {
+        return Err(Error::CiCdError(format!("Pipeline {}: manual runs are not enabled", name)));
+    }
+    
+    Ok(pipeline)
+}
+
+/// Params given as comma-separated `name=value` pairs
+fn parse_params(params: &str) -> Result<BTreeMap<String, String>, Error> {
+    params
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value)) => Ok((name.trim().to_string(), value.trim().to_string())),
+            None => Err(Error::CiCdError(format!("Param {} is not of the form name=value", pair))),
+        })
+        .collect()
+}
+
+/// Params of a manual run of `pipeline`: `overrides` on top of the defaults,
+/// with the repository of its git trigger as `git-repo-url`. Fails on unknown
+/// params, values of the wrong type and params left without a value.
+pub fn run_params(pipeline: &Pipeline, overrides: BTreeMap<String, String>) -> Result<BTreeMap<String, String>, Error> {
+    let declared = references::params(pipeline);
+    if let Some(name) = overrides.keys().find(|name| !declared.iter().any(|param| &param.name == *name)) {
+        return Err(Error::CiCdError(format!("Pipeline {}: unknown param {}", pipeline.name, name)));
+    }
+    
+    let repository = pipeline.trigger.git.as_ref().map(|git| git.repository.clone()).unwrap_or_default();
+    let mut params = BTreeMap::new();
+    for param in &declared {
+        let value = match overrides.get(&param.name).or(param.default.as_ref()) {
+            Some(value) if param.name == "git-repo-url" && value.is_empty() => repository.clone(),
+            Some(value) => value.clone(),
+            None => return Err(Error::CiCdError(format!("Pipeline {}: param {} needs a value", pipeline.name, param.name))),
+        };
+        references::check_value(pipeline, param, &value)?;
+        params.insert(param.name.clone(), value);
+    }
+    
+    Ok(params)
+}
+
+/// Records `run_name`, just started with `params`, as the latest run of
+/// `pipeline` in the CI/CD status of `owner`, along with the `run_request` it
+/// answers
+pub async fn record_run(
+    client: &Client,
+    owner: &DependencyManager,
+    pipeline: &Pipeline,
+    run_name: &str,
+    params: &BTreeMap<String, String>,
+    run_request: Option<String>,
+) -> Result<(), Error> {
+    let started = chrono::Utc::now().to_rfc3339();
+    let revision = params.get("git-revision").filter(|revision| !revision.is_empty());
+    
+    update_pipeline_status(client, owner, &pipeline.name, run_request, |status| {
+        status.status = STARTED.to_string();
+        status.last_run = Some(started.clone());
+        status.last_run_name = Some(run_name.to_string());
+        status.last_revision = revision.cloned();
+        status.error = None;
+    })
+    .await
+}
+
+/// Applies `update` to the status of pipeline `name` in the CI/CD status of
+/// `owner`, and records `run_request` when set, retrying when the status
+/// changes concurrently
+pub async fn update_pipeline_status(
+    client: &Client,
+    owner: &DependencyManager,
+    name: &str,
+    run_request: Option<String>,
+    update: impl Fn(&mut PipelineStatus),
+) -> Result<(), Error> {
+    let Some(cicd) = &owner.spec.cicd else {
+        return Ok(());
+    };
+    let api: Api<DependencyManager> = Api::namespaced(client.clone(), &owner.namespace().unwrap_or_default());
+    
+    for _ in 0..RECORD_ATTEMPTS {
+        let current = api.get_status(&owner.name_any()).await?;
+        let mut status = current
+            .status
+            .and_then(|status| status.cicd_status)
+            .unwrap_or_else(|| CiCdStatus { provider: cicd.provider.clone(), pipelines: Vec::new(), run_request: None });
+        status.provider = cicd.provider.clone();
+        
+        let index = match status.pipelines.iter().position(|pipeline| pipeline.name == name) {
+            Some(index) => index,
+            None => {
+                status.pipelines.push(PipelineStatus {
+                    name: name.to_string(),
+                    status: String::new(),
+                    last_run: None,
+                    last_run_name: None,
+                    last_revision: None,
+                    runs: None,
+                    compatibility: None,
+                    last_commit: None,
+                    error: None,
+                });
+                status.pipelines.len() - 1
+            }
+        };
+        update(&mut status.pipelines[index]);
+        status.pipelines.sort_by(|a, b| a.name.cmp(&b.name));
+        if run_request.is_some() {
+            status.run_request = run_request.clone();
+        }
+        
+        // The resourceVersion turns a concurrent update into a conflict instead of a lost run
+        let patch = serde_json::json!({
+            "metadata": { "resourceVersion": current.metadata.resource_version },
+            "status": { "cicd_status": status },
+        });
+        
+        match api.patch_status(&owner.name_any(), &PatchParams::default(), &Patch::Merge(&patch)).await {
+            Err(kube::Error::Api(response)) if response.code == 409 => continue,
+            result => {
+                result?;
+                return Ok(());
+            }
+        }
+    }
+    
+    Err(Error::CiCdError(format!(
+        "Status of {} kept changing while updating pipeline {}",
+        owner.name_any(),
+        name
+    )))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::Path;
+    
+    use serde_json::Value;
+    
+    use crate::crd::{
+        ConditionOperator, ExecutionSettings, GitTrigger, InputArtifact, ParamType, Pipeline, PipelineParam,
+        PipelineStep, PipelineTrigger, PipelineWorkspace, SecretEnvVar, StepArtifact, StepCondition, StepMount,
+        StepResources, StepResult, StepToleration, WorkspaceType,
+    };
+    use crate::error::Error;
+    
+    pub fn step(name: &str) -> PipelineStep {
+        PipelineStep {
+            name: name.to_string(),
+            image: "alpine:3.20".to_string(),
+            commands: vec![format!("echo {}", name)],
+            template: None,
+            params: None,
+            env: None,
+            working_dir: None,
+            depends_on: None,
+            when: None,
+            mounts: None,
+            secret_env: None,
+            results: None,
+            artifacts: None,
+            input_artifacts: None,
+            execution: Default::default(),
+        }
+    }
+    
+    /// Pipeline triggered by pushes to `main`
+    pub fn pipeline(name: &str, steps: Vec<PipelineStep>) -> Pipeline {
+        Pipeline {
+            name: name.to_string(),
+            trigger: PipelineTrigger {
+                git: Some(GitTrigger {
+                    repository: "https://github.com/acme/app".to_string(),
+                    branches: vec!["main".to_string()],
+                    events: vec!["push".to_string()],
+                    provider: None,
+                    secret_ref: Some("webhook".to_string()),
+                }),
+                schedule: None,
+                manual: false,
+            },
+            steps,
+            finally: None,
+            workspaces: None,
+            params: None,
+            execution: Default::default(),
+        }
+    }
+    
+    /// Checkout fanning out to test and lint, joined by a conditional publish,
+    /// with a notification that always runs
+    pub fn release_pipeline() -> Pipeline {
+        let mut test = step("test");
+        test.depends_on = Some(vec!["checkout".to_string()]);
+        let mut lint = step("lint");
+        lint.depends_on = Some(vec!["checkout".to_string()]);
+        let mut publish = step("publish");
+        publish.depends_on = Some(vec!["test".to_string(), "lint".to_string()]);
+        publish.when = Some(vec![StepCondition {
+            param: "git-repo-url".to_string(),
+            operator: ConditionOperator::NotIn,
+            values: vec![String::new()],
+        }]);
+        
+        Pipeline {
+            finally: Some(vec![step("notify")]),
+            ..pipeline("release", vec![step("checkout"), test, lint, publish])
+        }
+    }
+    
+    fn workspace(name: &str, type_: WorkspaceType) -> PipelineWorkspace {
+        PipelineWorkspace { name: name.to_string(), type_, size: None, storage_class: None, source: None, key: None }
+    }
+    
+    fn mount(workspace: &str, path: Option<&str>, read_only: bool) -> StepMount {
+        StepMount {
+            workspace: workspace.to_string(),
+            path: path.map(str::to_string),
+            read_only: read_only.then_some(true),
+        }
+    }
+    
+    /// Build sharing its sources, caching cargo downloads, reading settings
+    /// from a ConfigMap and publishing with a token from a Secret
+    pub fn workspace_pipeline() -> Pipeline {
+        let mut build = step("build");
+        build.mounts = Some(vec![
+            mount("source", None, false),
+            mount("cargo", Some("/usr/local/cargo/registry"), false),
+            mount("settings", Some("/etc/build"), true),
+        ]);
+        let mut publish = step("publish");
+        publish.depends_on = Some(vec!["build".to_string()]);
+        publish.mounts = Some(vec![mount("source", None, false), mount("scratch", None, false)]);
+        publish.secret_env = Some(vec![SecretEnvVar {
+            name: "REGISTRY_TOKEN".to_string(),
+            secret: "registry".to_string(),
+            key: "token".to_string(),
+        }]);
+        publish.env = Some([("REGISTRY".to_string(), "ghcr.io".to_string())].into_iter().collect());
+        
+        Pipeline {
+            workspaces: Some(vec![
+                PipelineWorkspace { size: Some("2Gi".to_string()), ..workspace("source", WorkspaceType::VolumeClaim) },
+                PipelineWorkspace {
+                    size: Some("10Gi".to_string()),
+                    storage_class: Some("fast".to_string()),
+                    key: Some("Cargo.lock-v1".to_string()),
+                    ..workspace("cargo", WorkspaceType::Cache)
+                },
+                PipelineWorkspace { source: Some("build-settings".to_string()), ..workspace("settings", WorkspaceType::ConfigMap) },
+                workspace("scratch", WorkspaceType::EmptyDir),
+            ]),
+            ..pipeline("package", vec![build, publish])
+        }
+    }
+    
+    /// Manual release passing the digest and binary of its build on to the
+    /// deploy step, which targets a configurable environment
+    pub fn results_pipeline() -> Pipeline {
+        let mut build = step("build");
+        build.commands = vec![
+            "cargo build --release".to_string(),
+            "sha256sum target/release/app | cut -d' ' -f1 > \"$RESULTS_DIR/digest\"".to_string(),
+        ];
+        build.results = Some(vec![StepResult { name: "digest".to_string(), description: Some("Binary digest".to_string()) }]);
+        build.artifacts = Some(vec![StepArtifact { name: "binary".to_string(), path: "target/release/app".to_string() }]);
+        let mut deploy = step("deploy");
+        deploy.commands = vec!["deploy --env $(params.environment) --digest $(steps.build.results.digest) /opt/app".to_string()];
+        deploy.input_artifacts = Some(vec![InputArtifact {
+            step: "build".to_string(),
+            name: "binary".to_string(),
+            path: "/opt/app".to_string(),
+        }]);
+        
+        let mut release = pipeline("release", vec![build, deploy]);
+        release.trigger = PipelineTrigger { git: None, schedule: None, manual: true };
+        release.params = Some(vec![PipelineParam {
+            name: "environment".to_string(),
+            type_: ParamType::String,
+            description: Some("Environment to deploy to".to_string()),
+            default: Some("staging".to_string()),
+        }]);
+        release
+    }
+    
+    /// Build on GPU nodes with generous limits and retries, under a run
+    /// timeout, whose notification runs as its own ServiceAccount
+    pub fn tuned_pipeline() -> Pipeline {
+        let mut build = step("build");
+        build.execution = ExecutionSettings {
+            resources: Some(StepResources {
+                requests: Some([("cpu".to_string(), "2".to_string())].into_iter().collect()),
+                limits: Some([("memory".to_string(), "8Gi".to_string())].into_iter().collect()),
+            }),
+            retries: Some(2),
+            timeout: Some("45m".to_string()),
+            ..Default::default()
+        };
+        let mut notify = step("notify");
+        notify.execution.service_account = Some("notifier".to_string());
+        
+        let mut release = pipeline("release", vec![build]);
+        release.finally = Some(vec![notify]);
+        release.execution = ExecutionSettings {
+            timeout: Some("1h30m".to_string()),
+            node_selector: Some([("accelerator".to_string(), "gpu".to_string())].into_iter().collect()),
+            tolerations: Some(vec![StepToleration {
+                key: Some("gpu".to_string()),
+                operator: Some("Exists".to_string()),
+                value: None,
+                effect: Some("NoSchedule".to_string()),
+                toleration_seconds: None,
+            }]),
+            service_account: Some("builder".to_string()),
+            ..Default::default()
+        };
+        release
+    }
+    
+    /// Manifests as a multi-document YAML string
+    pub fn to_yaml(manifests: &[Value]) -> Result<String, Error> {
+        let documents = manifests
+            .iter()
+            .map(serde_yaml::to_string)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::SerializationError(e.to_string()))?;
+        Ok(documents.join("---\n"))
+    }
+    
+    /// Compares `actual` with `testdata/<name>`, rewriting the file instead
+    /// when `UPDATE_GOLDEN` is set
+    pub fn assert_golden(name: &str, actual: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata").join(name);
+        
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, actual).unwrap();
+        }
+        
+        let expected = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("{}: {} (run with UPDATE_GOLDEN=1 to create it)", path.display(), e));
+        assert!(
+            actual == expected,
+            "{} is out of date (run with UPDATE_GOLDEN=1 to update it)\n--- expected\n{}\n--- actual\n{}",
+            path.display(),
+            expected,
+            actual
+        );
+    }
+    
+    #[test]
+    fn manual_runs_override_default_params() {
+        let mut build = pipeline("build", vec![step("test")]);
+        let mut config = crate::crd::CiCdConfig {
+            provider: crate::crd::CiCdProvider::Tekton,
+            pipelines: vec![build.clone()],
+            retention: None,
+            git_write: None,
+        };
+        assert!(super::manual_pipeline(&config, "build").is_err());
+        assert!(super::manual_pipeline(&config, "deploy").is_err());
+        
+        build.trigger.manual = true;
+        build.params = Some(vec![crate::crd::PipelineParam {
+            name: "replicas".to_string(),
+            type_: crate::crd::ParamType::Integer,
+            description: None,
+            default: Some("1".to_string()),
+        }]);
+        config.pipelines = vec![build];
+        let build = super::manual_pipeline(&config, "build").unwrap();
+        
+        let overrides = super::parse_params("git-revision=abc123, replicas=3").unwrap();
+        let params = super::run_params(build, overrides).unwrap();
+        assert_eq!(params["git-repo-url"], "https://github.com/acme/app");
+        assert_eq!(params["git-revision"], "abc123");
+        assert_eq!(params["git-branch"], "");
+        assert_eq!(params["replicas"], "3");
+        
+        assert!(super::parse_params("git-revision").is_err());
+        let unknown = super::parse_params("branch=main").unwrap();
+        assert!(super::run_params(build, unknown).is_err());
+        let mistyped = super::parse_params("replicas=three").unwrap();
+        assert!(super::run_params(build, mistyped).is_err());
+        
+        // Annotated requests resolve the same way, with the nonce after `@`
+        let (requested, params) = super::requested_run(&config, "build@1", Some(&"replicas=2".to_string())).unwrap();
+        assert_eq!(requested.name, "build");
+        assert_eq!(params["replicas"], "2");
+        assert!(super::requested_run(&config, "deploy@1", None).is_err());
+        assert!(super::requested_run(&config, "build@2", Some(&"replicas".to_string())).is_err());
+    }
+}
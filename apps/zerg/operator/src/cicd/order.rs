@@ -0,0 +1,146 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::crd::Pipeline;
+use crate::error::Error;
+
+use super::references;
+
+/// Names of the steps each of `pipeline.steps` runs after: its `depends_on`,
+/// or the previous step when unset, so that steps run sequentially by default.
+///
+/// Fails unless step names are unique, dependencies name other steps without
+/// forming a cycle, `finally` steps declare no dependencies and conditions
+/// only test known params.
+pub fn run_after(pipeline: &Pipeline) -> Result<Vec<Vec<&str>>, Error> {
+    let invalid = |message: String| Err(Error::CiCdError(format!("Pipeline {}: {}", pipeline.name, message)));
+    
+    if pipeline.steps.is_empty() {
+        return invalid("no steps".to_string());
+    }
+    
+    let params = references::params(pipeline);
+    let finally = pipeline.finally.iter().flatten();
+    let mut names = HashSet::new();
+    for step in pipeline.steps.iter().chain(finally.clone()) {
+        if !names.insert(step.name.as_str()) {
+            return invalid(format!("more than one step named {}", step.name));
+        }
+        for condition in step.when.iter().flatten() {
+            if !params.iter().any(|param| param.name == condition.param) {
+                return invalid(format!("step {} tests unknown param {}", step.name, condition.param));
+            }
+            if condition.values.is_empty() {
+                return invalid(format!("condition on {} of step {} has no values", condition.param, step.name));
+            }
+        }
+    }
+    if let Some(step) = finally.clone().find(|s| s.depends_on.is_some()) {
+        return invalid(format!("finally step {} cannot declare depends_on", step.name));
+    }
+    
+    let index: HashMap<&str, usize> = pipeline.steps.iter().enumerate().map(|(i, s)| (s.name.as_str(), i)).collect();
+    let mut run_after = Vec::new();
+    
+    for (i, step) in pipeline.steps.iter().enumerate() {
+        let after: Vec<&str> = match &step.depends_on {
+            Some(names) => names.iter().map(String::as_str).collect(),
+            None => i.checked_sub(1).map(|p| pipeline.steps[p].name.as_str()).into_iter().collect(),
+        };
+        
+        for name in &after {
+            if *name == step.name || !index.contains_key(name) {
+                return invalid(format!("step {} depends on unknown step {}", step.name, name));
+            }
+        }
+        
+        run_after.push(after);
+    }
+    
+    let mut done = HashSet::new();
+    for i in 0..pipeline.steps.len() {
+        if let Some(cycle) = find_cycle(i, &run_after, &index, &mut Vec::new(), &mut done) {
+            return invalid(format!("dependency cycle through step {}", pipeline.steps[cycle].name));
+        }
+    }
+    
+    Ok(run_after)
+}
+
+/// Depth-first search from step `i`, returning a step on a cycle if there is
+/// one; `visiting` holds the current path
+fn find_cycle(
+    i: usize,
+    run_after: &[Vec<&str>],
+    index: &HashMap<&str, usize>,
+    visiting: &mut Vec<usize>,
+    done: &mut HashSet<usize>,
+) -> Option<usize> {
+    if done.contains(&i) {
+        return None;
+    }
+    if visiting.contains(&i) {
+        return Some(i);
+    }
+    
+    visiting.push(i);
+    for name in &run_after[i] {
+        if let Some(cycle) = find_cycle(index[name], run_after, index, visiting, done) {
+            return Some(cycle);
+        }
+    }
+    visiting.pop();
+    done.insert(i);
+    
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cicd::tests::{pipeline, step};
+    use crate::crd::{ConditionOperator, StepCondition};
+    
+    #[test]
+    fn steps_are_sequential_unless_they_declare_dependencies() {
+        let mut pipeline = pipeline("build", vec![step("checkout"), step("test"), step("lint"), step("image")]);
+        pipeline.steps[1].depends_on = Some(vec!["checkout".to_string()]);
+        pipeline.steps[2].depends_on = Some(vec!["checkout".to_string()]);
+        pipeline.steps[3].depends_on = Some(vec!["test".to_string(), "lint".to_string()]);
+        
+        assert_eq!(
+            run_after(&pipeline).unwrap(),
+            vec![vec![], vec!["checkout"], vec!["checkout"], vec!["test", "lint"]]
+        );
+        
+        pipeline.steps[3].depends_on = None;
+        assert_eq!(run_after(&pipeline).unwrap()[3], vec!["lint"]);
+    }
+    
+    #[test]
+    fn rejects_invalid_graphs() {
+        let mut cyclic = pipeline("build", vec![step("a"), step("b")]);
+        cyclic.steps[0].depends_on = Some(vec!["b".to_string()]);
+        assert!(run_after(&cyclic).unwrap_err().to_string().contains("cycle"));
+        
+        let mut unknown = pipeline("build", vec![step("a")]);
+        unknown.steps[0].depends_on = Some(vec!["missing".to_string()]);
+        assert!(run_after(&unknown).is_err());
+        
+        let mut finally = pipeline("build", vec![step("a")]);
+        let mut notify = step("notify");
+        notify.depends_on = Some(vec!["a".to_string()]);
+        finally.finally = Some(vec![notify]);
+        assert!(run_after(&finally).is_err());
+        
+        let mut condition = pipeline("build", vec![step("a")]);
+        condition.steps[0].when = Some(vec![StepCondition {
+            param: "branch".to_string(),
+            operator: ConditionOperator::In,
+            values: vec!["main".to_string()],
+        }]);
+        assert!(run_after(&condition).is_err());
+        
+        assert!(run_after(&pipeline("build", vec![step("a"), step("a")])).is_err());
+        assert!(run_after(&pipeline("build", vec![])).is_err());
+    }
+}
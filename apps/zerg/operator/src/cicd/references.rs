@@ -0,0 +1,377 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::crd::{ParamType, Pipeline, PipelineParam, PipelineStep, WorkspaceType};
+use crate::error::Error;
+
+use super::{order, workspace, PARAMS};
+
+/// Variable holding the directory steps write their results to
+pub const RESULTS_VARIABLE: &str = "RESULTS_DIR";
+
+/// Directory under the artifact workspace holding artifacts passed between
+/// Tekton tasks, which have no artifact repository; under the checkout for
+/// GitHub Actions and GitLab CI, which upload it
+pub const ARTIFACTS_DIR: &str = ".artifacts";
+
+/// `$(params.<name>)` or `$(steps.<step>.results.<name>)` in a command or env value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reference<'a> {
+    Param(&'a str),
+    Result { step: &'a str, name: &'a str },
+}
+
+impl<'a> Reference<'a> {
+    fn parse(expression: &'a str) -> Option<Self> {
+        if let Some(name) = expression.strip_prefix("params.") {
+            return Some(Reference::Param(name));
+        }
+        let (step, name) = expression.strip_prefix("steps.")?.split_once(".results.")?;
+        Some(Reference::Result { step, name })
+    }
+}
+
+/// References in `text`, in order
+pub fn references(text: &str) -> Vec<Reference<'_>> {
+    let mut references = Vec::new();
+    let mut rest = text;
+    
+    while let Some(start) = rest.find("$(") {
+        let Some(end) = rest[start..].find(')') else {
+            break;
+        };
+        references.extend(Reference::parse(&rest[start + 2..start + end]));
+        rest = &rest[start + end + 1..];
+    }
+    
+    references
+}
+
+/// `text` with every reference `replace` returns a value for replaced by it
+pub fn rewrite(text: &str, replace: &impl Fn(Reference) -> Option<String>) -> String {
+    let mut rewritten = String::new();
+    let mut rest = text;
+    
+    while let Some(start) = rest.find("$(") {
+        let Some(end) = rest[start..].find(')') else {
+            break;
+        };
+        let expression = &rest[start..start + end + 1];
+        rewritten.push_str(&rest[..start]);
+        match Reference::parse(&expression[2..expression.len() - 1]).and_then(replace) {
+            Some(value) => rewritten.push_str(&value),
+            None => rewritten.push_str(expression),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    rewritten.push_str(rest);
+    
+    rewritten
+}
+
+/// `step` with the references in its commands and env rewritten by `replace`
+pub fn rewrite_step(step: &PipelineStep, replace: impl Fn(Reference) -> Option<String>) -> PipelineStep {
+    PipelineStep {
+        commands: step.commands.iter().map(|command| rewrite(command, &replace)).collect(),
+        env: step.env.as_ref().map(|env| {
+            env.iter().map(|(name, value)| (name.clone(), rewrite(value, &replace))).collect()
+        }),
+        ..step.clone()
+    }
+}
+
+/// Every reference in the commands and env of `step`
+fn step_references(step: &PipelineStep) -> Vec<Reference<'_>> {
+    step.commands
+        .iter()
+        .chain(step.env.iter().flat_map(|env| env.values()))
+        .flat_map(|text| references(text))
+        .collect()
+}
+
+/// Results of earlier steps `step` references, as (step, result) pairs
+pub fn result_inputs(step: &PipelineStep) -> Vec<(String, String)> {
+    let inputs: BTreeSet<(String, String)> = step_references(step)
+        .into_iter()
+        .filter_map(|reference| match reference {
+            Reference::Result { step, name } => Some((step.to_string(), name.to_string())),
+            Reference::Param(_) => None,
+        })
+        .collect();
+    
+    inputs.into_iter().collect()
+}
+
+/// Name of the param carrying result `name` of `step` into a later step
+pub fn result_param(step: &str, name: &str) -> String {
+    format!("{}-results-{}", step, name)
+}
+
+/// Params triggers fill, which default to empty
+pub fn trigger_params() -> Vec<PipelineParam> {
+    PARAMS
+        .iter()
+        .map(|(name, description)| PipelineParam {
+            name: name.to_string(),
+            type_: ParamType::String,
+            description: Some(description.to_string()),
+            default: Some(String::new()),
+        })
+        .collect()
+}
+
+/// Params of `pipeline`: the trigger params followed by its own
+pub fn params(pipeline: &Pipeline) -> Vec<PipelineParam> {
+    let mut params = trigger_params();
+    params.extend(pipeline.params.iter().flatten().cloned());
+    params
+}
+
+/// Whether `value` is a valid value of `param`
+pub fn check_value(pipeline: &Pipeline, param: &PipelineParam, value: &str) -> Result<(), Error> {
+    let valid = match param.type_ {
+        ParamType::String => true,
+        ParamType::Integer => value.parse::<i64>().is_ok(),
+        ParamType::Boolean => value == "true" || value == "false",
+    };
+    
+    if valid {
+        Ok(())
+    } else {
+        let type_ = match param.type_ {
+            ParamType::String => "string",
+            ParamType::Integer => "integer",
+            ParamType::Boolean => "boolean",
+        };
+        Err(Error::CiCdError(format!("Pipeline {}: param {} is not a {}: {:?}", pipeline.name, param.name, type_, value)))
+    }
+}
+
+/// Workspace Tekton passes artifacts through: the first volume claim, which
+/// producing and consuming steps must mount
+pub fn artifact_workspace(pipeline: &Pipeline) -> Option<String> {
+    workspace::workspaces(pipeline)
+        .into_iter()
+        .find(|workspace| workspace.type_ == WorkspaceType::VolumeClaim)
+        .map(|workspace| workspace.name)
+}
+
+/// Location of artifact `name` of `step` under the artifact workspace at `root`
+pub fn artifact_path(root: &str, step: &str, name: &str) -> String {
+    format!("{}/{}/{}/{}", root, ARTIFACTS_DIR, step, name)
+}
+
+/// `step` copying its input artifacts in from the artifact store at `root`,
+/// and its artifacts out to it once its commands succeed
+pub fn copy_artifacts(mut step: PipelineStep, root: &str) -> PipelineStep {
+    let outputs = step.artifacts.clone().unwrap_or_default();
+    
+    let mut commands: Vec<String> = step
+        .input_artifacts
+        .iter()
+        .flatten()
+        .map(|input| {
+            format!(
+                "mkdir -p \"$(dirname \"{path}\")\" && cp -R \"{from}\" \"{path}\"",
+                path = input.path,
+                from = artifact_path(root, &input.step, &input.name),
+            )
+        })
+        .collect();
+    commands.append(&mut step.commands);
+    if !outputs.is_empty() {
+        commands.push("status=$?; [ \"$status\" -eq 0 ] || exit \"$status\"".to_string());
+        commands.push(format!("mkdir -p \"{}\"", artifact_path(root, &step.name, "").trim_end_matches('/')));
+        commands.extend(outputs.iter().map(|output| {
+            format!("cp -R \"{}\" \"{}\"", output.path, artifact_path(root, &step.name, &output.name))
+        }));
+    }
+    step.commands = commands;
+    
+    step
+}
+
+/// Name of a result, param or artifact, which ends up in generated param and
+/// file names
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+/// Steps each step of `pipeline.steps` runs after, directly or not
+fn ancestors(pipeline: &Pipeline) -> Result<HashMap<&str, HashSet<&str>>, Error> {
+    let run_after = order::run_after(pipeline)?;
+    let direct: HashMap<&str, &Vec<&str>> = pipeline.steps.iter().map(|s| s.name.as_str()).zip(&run_after).collect();
+    
+    let mut ancestors = HashMap::new();
+    for step in &pipeline.steps {
+        let mut seen = HashSet::new();
+        let mut pending: Vec<&str> = direct[step.name.as_str()].clone();
+        while let Some(name) = pending.pop() {
+            if seen.insert(name) {
+                pending.extend(direct[name].iter().copied());
+            }
+        }
+        ancestors.insert(step.name.as_str(), seen);
+    }
+    
+    Ok(ancestors)
+}
+
+/// Checks that params are well-formed with defaults of their type, and that
+/// every param, result and artifact a step references is declared, by an
+/// earlier step for results and artifacts
+pub fn validate(pipeline: &Pipeline) -> Result<(), Error> {
+    let invalid = |message: String| Err(Error::CiCdError(format!("Pipeline {}: {}", pipeline.name, message)));
+    
+    let params = params(pipeline);
+    let mut names = HashSet::new();
+    for param in &params {
+        if !valid_name(&param.name) {
+            return invalid(format!("invalid param name {}", param.name));
+        }
+        if !names.insert(param.name.as_str()) {
+            return invalid(format!("more than one param named {}", param.name));
+        }
+        match &param.default {
+            Some(default) => check_value(pipeline, param, default)?,
+            None if pipeline.trigger.git.is_some() || pipeline.trigger.schedule.is_some() => {
+                return invalid(format!("param {} needs a default, as the pipeline is triggered", param.name));
+            }
+            None => {}
+        }
+    }
+    
+    let ancestors = ancestors(pipeline)?;
+    let steps: HashMap<&str, &PipelineStep> = pipeline.steps.iter().map(|step| (step.name.as_str(), step)).collect();
+    
+    for step in pipeline.steps.iter().chain(pipeline.finally.iter().flatten()) {
+        let earlier = ancestors.get(step.name.as_str());
+        
+        let mut declared = HashSet::new();
+        for name in step.results.iter().flatten().map(|r| &r.name).chain(step.artifacts.iter().flatten().map(|a| &a.name)) {
+            if !valid_name(name) {
+                return invalid(format!("step {} has invalid result or artifact name {}", step.name, name));
+            }
+            if !declared.insert(name) {
+                return invalid(format!("step {} declares {} more than once", step.name, name));
+            }
+        }
+        
+        for reference in step_references(step) {
+            match reference {
+                Reference::Param(name) if !names.contains(name) => {
+                    return invalid(format!("step {} references unknown param {}", step.name, name));
+                }
+                Reference::Param(_) => {}
+                Reference::Result { step: producer, name } => {
+                    let declared = earlier.filter(|earlier| earlier.contains(producer)).is_some_and(|_| {
+                        steps[producer].results.iter().flatten().any(|result| result.name == name)
+                    });
+                    if !declared {
+                        return invalid(format!(
+                            "step {} references result {} of {}, which is not an earlier step declaring it",
+                            step.name, name, producer
+                        ));
+                    }
+                }
+            }
+        }
+        
+        for input in step.input_artifacts.iter().flatten() {
+            let declared = earlier.filter(|earlier| earlier.contains(input.step.as_str())).is_some_and(|_| {
+                steps[input.step.as_str()].artifacts.iter().flatten().any(|artifact| artifact.name == input.name)
+            });
+            if !declared {
+                return invalid(format!(
+                    "step {} takes artifact {} of {}, which is not an earlier step declaring it",
+                    step.name, input.name, input.step
+                ));
+            }
+        }
+        
+        let paths = step.artifacts.iter().flatten().map(|a| &a.path).chain(step.input_artifacts.iter().flatten().map(|a| &a.path));
+        for path in paths {
+            if path.is_empty() || path.contains(['"', '$', '`', '\\']) {
+                return invalid(format!("step {} has invalid artifact path {:?}", step.name, path));
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cicd::tests::{pipeline, step};
+    use crate::crd::{InputArtifact, StepArtifact, StepResult};
+    
+    fn result(name: &str) -> StepResult {
+        StepResult { name: name.to_string(), description: None }
+    }
+    
+    #[test]
+    fn rewrites_params_and_results() {
+        let text = "echo $(params.git-revision) $(steps.build.results.digest) $(date) $(params.";
+        
+        assert_eq!(
+            references(text),
+            vec![Reference::Param("git-revision"), Reference::Result { step: "build", name: "digest" }]
+        );
+        assert_eq!(
+            rewrite(text, &|reference| match reference {
+                Reference::Param(name) => Some(format!("{{{{workflow.parameters.{}}}}}", name)),
+                Reference::Result { .. } => None,
+            }),
+            "echo {{workflow.parameters.git-revision}} $(steps.build.results.digest) $(date) $(params."
+        );
+    }
+    
+    #[test]
+    fn resolves_references_to_earlier_steps() {
+        let mut build = step("build");
+        build.results = Some(vec![result("digest")]);
+        build.artifacts = Some(vec![StepArtifact { name: "binary".to_string(), path: "target/app".to_string() }]);
+        let mut deploy = step("deploy");
+        deploy.commands = vec!["deploy $(steps.build.results.digest) $(params.git-branch)".to_string()];
+        deploy.input_artifacts = Some(vec![InputArtifact {
+            step: "build".to_string(),
+            name: "binary".to_string(),
+            path: "/bin/app".to_string(),
+        }]);
+        
+        let release = pipeline("release", vec![build.clone(), deploy.clone()]);
+        assert!(validate(&release).is_ok());
+        assert_eq!(result_inputs(&release.steps[1]), vec![("build".to_string(), "digest".to_string())]);
+        
+        // Steps running in parallel cannot see each other's results
+        deploy.depends_on = Some(Vec::new());
+        assert!(validate(&pipeline("release", vec![build.clone(), deploy.clone()])).is_err());
+        
+        deploy.depends_on = None;
+        deploy.commands = vec!["deploy $(steps.build.results.tag)".to_string()];
+        assert!(validate(&pipeline("release", vec![build.clone(), deploy.clone()])).is_err());
+        
+        deploy.commands = vec!["deploy $(params.environment)".to_string()];
+        assert!(validate(&pipeline("release", vec![build, deploy])).is_err());
+    }
+    
+    #[test]
+    fn checks_param_types() {
+        let mut release = pipeline("release", vec![step("build")]);
+        release.params = Some(vec![PipelineParam {
+            name: "replicas".to_string(),
+            type_: ParamType::Integer,
+            description: None,
+            default: Some("three".to_string()),
+        }]);
+        assert!(validate(&release).is_err());
+        
+        release.params.as_mut().unwrap()[0].default = Some("3".to_string());
+        assert!(validate(&release).is_ok());
+        
+        // Triggered runs only set the trigger params
+        release.params.as_mut().unwrap()[0].default = None;
+        assert!(validate(&release).is_err());
+    }
+}
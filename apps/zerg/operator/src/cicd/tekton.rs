@@ -0,0 +1,907 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::core::v1::{
+    ConfigMapVolumeSource, EmptyDirVolumeSource, EnvVar, PersistentVolumeClaimSpec,
+    PersistentVolumeClaimVolumeSource, ResourceRequirements, SecretVolumeSource, ServiceAccount, Toleration,
+};
+use k8s_openapi::api::rbac::v1::{ClusterRoleBinding, RoleBinding, RoleRef, Subject};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::ResourceExt;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::crd::{self, ConditionOperator, GitProvider, GitTrigger, StepCondition};
+use crate::error::Error;
+
+use super::references::{self, Reference};
+use super::{execution, object, order, to_value, trigger, workspace, Object, PARAMS};
+
+/// API version of the Tekton Pipelines kinds the operator generates
+pub const API_VERSION: &str = "tekton.dev/v1";
+
+/// API version of the Tekton Triggers kinds, which have no v1 yet
+pub const TRIGGERS_API_VERSION: &str = "triggers.tekton.dev/v1beta1";
+
+/// ClusterRole shipped with Tekton Triggers granting EventListeners access to
+/// trigger objects in their namespace
+const LISTENER_ROLE: &str = "tekton-triggers-eventlistener-roles";
+
+/// ClusterRole shipped with Tekton Triggers granting EventListeners access to
+/// ClusterInterceptors and other cluster-scoped trigger objects
+const LISTENER_CLUSTER_ROLE: &str = "tekton-triggers-eventlistener-clusterroles";
+
+pub type Pipeline = Object<PipelineSpec>;
+pub type Task = Object<TaskSpec>;
+pub type PipelineRun = Object<PipelineRunSpec>;
+pub type TriggerBinding = Object<TriggerBindingSpec>;
+pub type TriggerTemplate = Object<TriggerTemplateSpec>;
+pub type EventListener = Object<EventListenerSpec>;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PipelineSpec {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<ParamSpec>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub workspaces: Vec<WorkspaceDeclaration>,
+    pub tasks: Vec<PipelineTask>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub finally: Vec<PipelineTask>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineTask {
+    pub name: String,
+    pub task_ref: TaskRef,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub run_after: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub when: Vec<WhenExpression>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<Param>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub workspaces: Vec<WorkspacePipelineTaskBinding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct WhenExpression {
+    pub input: String,
+    pub operator: &'static str,
+    pub values: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TaskRef {
+    pub name: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TaskSpec {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<ParamSpec>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub workspaces: Vec<WorkspaceDeclaration>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub results: Vec<TaskResult>,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TaskResult {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Step {
+    pub name: String,
+    pub image: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+    pub script: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<EnvVar>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compute_resources: Option<ResourceRequirements>,
+}
+
+/// Declared parameter
+#[derive(Serialize, Debug, Clone)]
+pub struct ParamSpec {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+}
+
+/// Parameter value passed to a task, run or template
+#[derive(Serialize, Debug, Clone)]
+pub struct Param {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceDeclaration {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mount_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_only: Option<bool>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct WorkspacePipelineTaskBinding {
+    pub name: String,
+    pub workspace: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineRunSpec {
+    pub pipeline_ref: PipelineRef,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<Param>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub workspaces: Vec<WorkspaceBinding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeouts: Option<Timeouts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_run_template: Option<TaskRunTemplate>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub task_run_specs: Vec<PipelineTaskRunSpec>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PipelineRef {
+    pub name: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Timeouts {
+    pub pipeline: String,
+}
+
+/// ServiceAccount and pod settings of the TaskRuns of a PipelineRun
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskRunTemplate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_account_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod_template: Option<PodTemplate>,
+}
+
+/// Settings of the TaskRun of one pipeline task, overriding the template
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelineTaskRunSpec {
+    pub pipeline_task_name: String,
+    #[serde(flatten)]
+    pub template: TaskRunTemplate,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PodTemplate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_selector: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tolerations: Vec<Toleration>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceBinding {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume_claim_template: Option<VolumeClaimTemplate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub empty_dir: Option<EmptyDirVolumeSource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_map: Option<ConfigMapVolumeSource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<SecretVolumeSource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub persistent_volume_claim: Option<PersistentVolumeClaimVolumeSource>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct VolumeClaimTemplate {
+    pub spec: PersistentVolumeClaimSpec,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TriggerBindingSpec {
+    pub params: Vec<Param>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TriggerTemplateSpec {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<ParamSpec>,
+    pub resourcetemplates: Vec<PipelineRun>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EventListenerSpec {
+    pub service_account_name: String,
+    pub triggers: Vec<EventListenerTrigger>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct EventListenerTrigger {
+    pub name: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub interceptors: Vec<Interceptor>,
+    pub bindings: Vec<TriggerRef>,
+    pub template: TriggerRef,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Interceptor {
+    #[serde(rename = "ref")]
+    pub reference: InterceptorRef,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<InterceptorParam>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct InterceptorRef {
+    pub name: &'static str,
+    pub kind: &'static str,
+}
+
+/// Interceptor parameter, whose value may be any JSON
+#[derive(Serialize, Debug, Clone)]
+pub struct InterceptorParam {
+    pub name: &'static str,
+    pub value: Value,
+}
+
+/// Reference to a TriggerBinding or TriggerTemplate
+#[derive(Serialize, Debug, Clone)]
+pub struct TriggerRef {
+    #[serde(rename = "ref")]
+    pub name: String,
+}
+
+/// Params of `PARAMS` set to `value(name)`
+fn params(value: impl Fn(&str) -> String) -> Vec<Param> {
+    PARAMS
+        .iter()
+        .map(|(name, _)| Param { name: name.to_string(), value: value(name) })
+        .collect()
+}
+
+/// Declarations of `params`, with their defaults when `defaults` is set
+fn param_specs(params: Vec<crd::PipelineParam>, defaults: bool) -> Vec<ParamSpec> {
+    params
+        .into_iter()
+        .map(|param| ParamSpec {
+            name: param.name,
+            description: param.description,
+            default: param.default.filter(|_| defaults),
+        })
+        .collect()
+}
+
+/// Directory Tekton collects task results from
+const RESULTS_DIR: &str = "/tekton/results";
+
+/// `step` reading the results it references from params, and copying its
+/// input artifacts in from and its artifacts out to the artifact workspace.
+/// Copying out only happens when the commands succeed.
+fn resolved_step(pipeline: &crd::Pipeline, step: &crd::PipelineStep) -> Result<crd::PipelineStep, Error> {
+    let mut step = references::rewrite_step(step, |reference| match reference {
+        Reference::Result { step, name } => Some(format!("$(params.{})", references::result_param(step, name))),
+        Reference::Param(_) => None,
+    });
+    
+    if step.results.is_some() {
+        step.env.get_or_insert_with(Default::default).insert(references::RESULTS_VARIABLE.to_string(), RESULTS_DIR.to_string());
+    }
+    
+    if step.input_artifacts.iter().flatten().next().is_none() && step.artifacts.iter().flatten().next().is_none() {
+        return Ok(step);
+    }
+    
+    let store = references::artifact_workspace(pipeline)
+        .filter(|store| workspace::mounts(pipeline, &step).iter().any(|mount| &mount.workspace == store))
+        .ok_or_else(|| {
+            Error::CiCdError(format!(
+                "Pipeline {}: step {} passes artifacts, which Tekton needs the first volume-claim workspace mounted for",
+                pipeline.name, step.name
+            ))
+        })?;
+    
+    Ok(references::copy_artifacts(step, &format!("$(workspaces.{}.path)", store)))
+}
+
+/// Name of the Task generated for `step`
+fn task_name(pipeline: &crd::Pipeline, step: &crd::PipelineStep) -> String {
+    format!("{}-{}", pipeline.name, step.name)
+}
+
+/// Task running `step` as a single script step, in the first workspace it
+/// mounts unless it sets a working directory, taking the pipeline params and
+/// the results of earlier steps it references as params
+pub fn task(pipeline: &crd::Pipeline, step: &crd::PipelineStep, namespace: &str) -> Result<Task, Error> {
+    let mounts = workspace::mounts(pipeline, step);
+    let result_params = references::result_inputs(step).into_iter().map(|(producer, name)| crd::PipelineParam {
+        name: references::result_param(&producer, &name),
+        type_: crd::ParamType::String,
+        description: Some(format!("Result {} of step {}", name, producer)),
+        default: None,
+    });
+    let resolved = resolved_step(pipeline, step)?;
+    let settings = execution::step_settings(pipeline, step);
+    
+    let spec = TaskSpec {
+        params: param_specs(references::params(pipeline).into_iter().chain(result_params).collect(), false),
+        workspaces: mounts
+            .iter()
+            .map(|mount| WorkspaceDeclaration {
+                name: mount.workspace.clone(),
+                mount_path: mount.path.clone(),
+                read_only: mount.read_only,
+            })
+            .collect(),
+        results: step
+            .results
+            .iter()
+            .flatten()
+            .map(|result| TaskResult { name: result.name.clone(), description: result.description.clone() })
+            .collect(),
+        steps: vec![Step {
+            name: step.name.clone(),
+            image: step.image.clone(),
+            working_dir: step
+                .working_dir
+                .clone()
+                .or_else(|| mounts.first().map(|mount| format!("$(workspaces.{}.path)", mount.workspace))),
+            script: format!("#!/bin/sh\n{}", super::script(&resolved)),
+            env: super::env(&resolved),
+            compute_resources: execution::resources(&settings),
+        }],
+    };
+    
+    Ok(object(API_VERSION, "Task", task_name(pipeline, step), namespace, spec))
+}
+
+/// When expressions for `conditions`
+fn when(conditions: Option<&Vec<StepCondition>>) -> Vec<WhenExpression> {
+    conditions
+        .into_iter()
+        .flatten()
+        .map(|condition| WhenExpression {
+            input: format!("$(params.{})", condition.param),
+            operator: match condition.operator {
+                ConditionOperator::In => "in",
+                ConditionOperator::NotIn => "notin",
+            },
+            values: condition.values.clone(),
+        })
+        .collect()
+}
+
+/// Pipeline task referencing the Task of `step`, with its retries and timeout
+fn pipeline_task(pipeline: &crd::Pipeline, step: &crd::PipelineStep, run_after: &[&str]) -> PipelineTask {
+    let settings = execution::step_settings(pipeline, step);
+    
+    PipelineTask {
+        name: step.name.clone(),
+        task_ref: TaskRef { name: task_name(pipeline, step) },
+        run_after: run_after.iter().map(|name| name.to_string()).collect(),
+        when: when(step.when.as_ref()),
+        params: references::params(pipeline)
+            .into_iter()
+            .map(|param| Param { value: format!("$(params.{})", param.name), name: param.name })
+            .chain(references::result_inputs(step).into_iter().map(|(producer, name)| Param {
+                name: references::result_param(&producer, &name),
+                value: format!("$(tasks.{}.results.{})", producer, name),
+            }))
+            .collect(),
+        workspaces: workspace::mounts(pipeline, step)
+            .into_iter()
+            .map(|mount| WorkspacePipelineTaskBinding { name: mount.workspace.clone(), workspace: mount.workspace })
+            .collect(),
+        retries: settings.retries,
+        timeout: settings.timeout,
+    }
+}
+
+/// Pipeline running one Task per step in dependency order, each bound to the
+/// workspaces it mounts, followed by the `finally` steps
+pub fn pipeline(pipeline: &crd::Pipeline, namespace: &str) -> Result<Pipeline, Error> {
+    let run_after = order::run_after(pipeline)?;
+    
+    let spec = PipelineSpec {
+        params: param_specs(references::params(pipeline), true),
+        workspaces: workspace::workspaces(pipeline)
+            .into_iter()
+            .map(|workspace| WorkspaceDeclaration { name: workspace.name, mount_path: None, read_only: None })
+            .collect(),
+        tasks: pipeline
+            .steps
+            .iter()
+            .zip(&run_after)
+            .map(|(step, after)| pipeline_task(pipeline, step, after))
+            .collect(),
+        finally: pipeline
+            .finally
+            .iter()
+            .flatten()
+            .map(|step| pipeline_task(pipeline, step, &[]))
+            .collect(),
+    };
+    
+    Ok(object(API_VERSION, "Pipeline", pipeline.name.clone(), namespace, spec))
+}
+
+/// Binding of `workspace` in runs of `pipeline` of `owner`; volume claims get
+/// a fresh volume for every run while caches reuse their claim
+fn workspace_binding(
+    owner: &crd::DependencyManager,
+    pipeline: &crd::Pipeline,
+    workspace: &crd::PipelineWorkspace,
+) -> WorkspaceBinding {
+    let volume = workspace::volume(owner, pipeline, workspace).unwrap_or_default();
+    
+    WorkspaceBinding {
+        name: workspace.name.clone(),
+        volume_claim_template: (workspace.type_ == crd::WorkspaceType::VolumeClaim)
+            .then(|| VolumeClaimTemplate { spec: workspace::claim_spec(workspace) }),
+        empty_dir: volume.empty_dir,
+        config_map: volume.config_map,
+        secret: volume.secret,
+        persistent_volume_claim: volume.persistent_volume_claim,
+    }
+}
+
+/// ServiceAccount and pod settings of TaskRuns with `settings`, if any
+fn task_run_template(settings: &crd::ExecutionSettings) -> Option<TaskRunTemplate> {
+    let pod_template = PodTemplate {
+        node_selector: execution::node_selector(settings),
+        tolerations: execution::tolerations(settings),
+    };
+    let pod_template = (pod_template.node_selector.is_some() || !pod_template.tolerations.is_empty()).then_some(pod_template);
+    
+    (settings.service_account.is_some() || pod_template.is_some()).then(|| TaskRunTemplate {
+        service_account_name: settings.service_account.clone(),
+        pod_template,
+    })
+}
+
+/// PipelineRun of `pipeline` with `params` and bindings for its workspaces,
+/// bounded by the pipeline's timeout. The pipeline's pod settings apply to
+/// every TaskRun, and steps setting their own get a TaskRun spec of their own.
+pub fn pipeline_run(pipeline: &crd::Pipeline, owner: &crd::DependencyManager, params: Vec<Param>) -> PipelineRun {
+    Object {
+        api_version: API_VERSION,
+        kind: "PipelineRun",
+        metadata: ObjectMeta {
+            generate_name: Some(format!("{}-run-", pipeline.name)),
+            labels: Some(super::run_labels(owner, pipeline)),
+            ..Default::default()
+        },
+        spec: PipelineRunSpec {
+            pipeline_ref: PipelineRef { name: pipeline.name.clone() },
+            params,
+            workspaces: workspace::workspaces(pipeline)
+                .iter()
+                .map(|workspace| workspace_binding(owner, pipeline, workspace))
+                .collect(),
+            timeouts: pipeline.execution.timeout.clone().map(|timeout| Timeouts { pipeline: timeout }),
+            task_run_template: task_run_template(&pipeline.execution),
+            task_run_specs: pipeline
+                .steps
+                .iter()
+                .chain(pipeline.finally.iter().flatten())
+                .filter(|step| execution::sets_pod_settings(step))
+                .filter_map(|step| {
+                    task_run_template(&execution::step_settings(pipeline, step)).map(|template| PipelineTaskRunSpec {
+                        pipeline_task_name: step.name.clone(),
+                        template,
+                    })
+                })
+                .collect(),
+        },
+    }
+}
+
+/// TriggerBinding taking the repository, commit and branch from the
+/// extensions the CEL interceptor adds
+pub fn trigger_binding(pipeline: &crd::Pipeline, namespace: &str) -> TriggerBinding {
+    let spec = TriggerBindingSpec {
+        params: params(|name| match name {
+            "git-repo-url" => "$(extensions.repo_url)",
+            "git-revision" => "$(extensions.revision)",
+            _ => "$(extensions.branch)",
+        }
+        .to_string()),
+    };
+    
+    object(TRIGGERS_API_VERSION, "TriggerBinding", format!("{}-binding", pipeline.name), namespace, spec)
+}
+
+/// TriggerTemplate starting a PipelineRun with the bound params, leaving the
+/// pipeline's own params at their defaults
+pub fn trigger_template(pipeline: &crd::Pipeline, owner: &crd::DependencyManager) -> TriggerTemplate {
+    let spec = TriggerTemplateSpec {
+        params: param_specs(references::trigger_params(), false),
+        resourcetemplates: vec![pipeline_run(pipeline, owner, params(|name| format!("$(tt.params.{})", name)))],
+    };
+    let namespace = owner.namespace().unwrap_or_default();
+    
+    object(TRIGGERS_API_VERSION, "TriggerTemplate", format!("{}-template", pipeline.name), &namespace, spec)
+}
+
+/// Single-quoted CEL string literal
+fn cel_string(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn interceptor(name: &'static str, params: Vec<InterceptorParam>) -> Interceptor {
+    Interceptor {
+        reference: InterceptorRef { name, kind: "ClusterInterceptor" },
+        params,
+    }
+}
+
+/// CEL filter matching events of `git` from its repository to its branches,
+/// and the overlays extracting the repository URL, commit and branch for the
+/// binding
+fn cel_filter(git: &GitTrigger) -> (String, Vec<(&'static str, &'static str)>) {
+    let branches = |field: &str, prefix: &str| {
+        trigger::branch_regex(&git.branches, prefix)
+            .map(|regex| format!(" && {}.matches({})", field, cel_string(&regex)))
+            .unwrap_or_default()
+    };
+    let header = |name: &str, value: &str| format!("header.match({}, {})", cel_string(name), cel_string(value));
+    let events = trigger::events(git);
+    let path = trigger::repository_path(&git.repository);
+    
+    let (repository, matches, overlays): (Option<String>, Vec<String>, _) = match trigger::provider(git) {
+        provider @ (GitProvider::GitHub | GitProvider::Gitea) => {
+            let event_header = if provider == GitProvider::Gitea { "X-Gitea-Event" } else { "X-GitHub-Event" };
+            (
+                Some(format!("body.repository.full_name == {}", cel_string(path))),
+                events
+                    .iter()
+                    .map(|event| match *event {
+                        "push" => format!("{}{}", header(event_header, event), branches("body.ref", "refs/heads/")),
+                        "pull_request" => format!(
+                            "{}{}",
+                            header(event_header, event),
+                            branches("body.pull_request.base.ref", "")
+                        ),
+                        _ => header(event_header, event),
+                    })
+                    .collect(),
+                vec![
+                    ("repo_url", "body.repository.clone_url"),
+                    ("revision", "has(body.pull_request) ? body.pull_request.head.sha : body.after"),
+                    ("branch", "has(body.pull_request) ? body.pull_request.base.ref : body.ref.replace('refs/heads/', '')"),
+                ],
+            )
+        }
+        GitProvider::GitLab => (
+            Some(format!("body.project.path_with_namespace == {}", cel_string(path))),
+            events
+                .iter()
+                .filter_map(|event| trigger::gitlab_event(event))
+                .map(|event| match event {
+                    "Push Hook" => format!("{}{}", header("X-Gitlab-Event", event), branches("body.ref", "refs/heads/")),
+                    "Merge Request Hook" => format!(
+                        "{}{}",
+                        header("X-Gitlab-Event", event),
+                        branches("body.object_attributes.target_branch", "")
+                    ),
+                    _ => header("X-Gitlab-Event", event),
+                })
+                .collect(),
+            vec![
+                ("repo_url", "body.project.git_http_url"),
+                ("revision", "has(body.object_attributes) ? body.object_attributes.last_commit.id : body.checkout_sha"),
+                (
+                    "branch",
+                    "has(body.object_attributes) ? body.object_attributes.target_branch : body.ref.replace('refs/heads/', '')",
+                ),
+            ],
+        ),
+        GitProvider::Generic => (
+            None,
+            vec![format!("true{}", branches("body.ref", "refs/heads/"))],
+            vec![
+                ("repo_url", "body.repository.url"),
+                ("revision", "body.head_commit.id"),
+                ("branch", "body.ref.replace('refs/heads/', '')"),
+            ],
+        ),
+    };
+    
+    let filter = match repository {
+        Some(repository) => format!("{} && ({})", repository, matches.join(" || ")),
+        None => matches.join(" || "),
+    };
+    
+    (filter, overlays)
+}
+
+/// Interceptors validating webhooks of `git` and filtering them down to its
+/// repository, branches and events
+pub fn interceptors(git: &GitTrigger) -> Vec<Interceptor> {
+    let mut interceptors = Vec::new();
+    let secret = git.secret_ref.as_ref().map(|name| InterceptorParam {
+        name: "secretRef",
+        value: json!({ "secretName": name, "secretKey": trigger::SECRET_KEY }),
+    });
+    
+    match trigger::provider(git) {
+        GitProvider::GitHub => {
+            let event_types = InterceptorParam { name: "eventTypes", value: json!(trigger::events(git)) };
+            interceptors.push(interceptor("github", secret.into_iter().chain([event_types]).collect()));
+        }
+        GitProvider::GitLab => {
+            let events: Vec<&str> = trigger::events(git).into_iter().filter_map(trigger::gitlab_event).collect();
+            let event_types = InterceptorParam { name: "eventTypes", value: json!(events) };
+            interceptors.push(interceptor("gitlab", secret.into_iter().chain([event_types]).collect()));
+        }
+        GitProvider::Gitea | GitProvider::Generic => {}
+    }
+    
+    let (filter, overlays) = cel_filter(git);
+    let overlays: Vec<Value> = overlays
+        .into_iter()
+        .map(|(key, expression)| json!({ "key": key, "expression": expression }))
+        .collect();
+    let mut params = vec![InterceptorParam { name: "overlays", value: json!(overlays) }];
+    if filter != "true" {
+        params.insert(0, InterceptorParam { name: "filter", value: json!(filter) });
+    }
+    interceptors.push(interceptor("cel", params));
+    
+    interceptors
+}
+
+/// EventListener running as `service_account`, wiring webhooks accepted by
+/// the interceptors to the binding and template
+pub fn event_listener(
+    pipeline: &crd::Pipeline,
+    git: &GitTrigger,
+    namespace: &str,
+    service_account: &str,
+) -> EventListener {
+    let spec = EventListenerSpec {
+        service_account_name: service_account.to_string(),
+        triggers: vec![EventListenerTrigger {
+            name: format!("{}-trigger", pipeline.name),
+            interceptors: interceptors(git),
+            bindings: vec![TriggerRef { name: format!("{}-binding", pipeline.name) }],
+            template: TriggerRef { name: format!("{}-template", pipeline.name) },
+        }],
+    };
+    
+    object(TRIGGERS_API_VERSION, "EventListener", format!("{}-listener", pipeline.name), namespace, spec)
+}
+
+/// ServiceAccount EventListeners of `owner` run as
+pub fn listener_service_account(owner_name: &str) -> String {
+    format!("{}-triggers", owner_name)
+}
+
+/// Name of the ClusterRoleBinding of `service_account`, unique across namespaces
+pub fn listener_cluster_role_binding(service_account: &str, namespace: &str) -> String {
+    format!("{}-{}", namespace, service_account)
+}
+
+/// ServiceAccount for EventListeners bound to the roles Tekton Triggers ships
+/// for them
+pub fn listener_access(service_account: &str, namespace: &str) -> Result<Vec<Value>, Error> {
+    let meta = |name: String, namespace: Option<&str>| ObjectMeta {
+        name: Some(name),
+        namespace: namespace.map(str::to_string),
+        ..Default::default()
+    };
+    let role = |name: &str| RoleRef {
+        api_group: "rbac.authorization.k8s.io".to_string(),
+        kind: "ClusterRole".to_string(),
+        name: name.to_string(),
+    };
+    let subjects = Some(vec![Subject {
+        kind: "ServiceAccount".to_string(),
+        name: service_account.to_string(),
+        namespace: Some(namespace.to_string()),
+        ..Default::default()
+    }]);
+    
+    Ok(vec![
+        to_value(&ServiceAccount {
+            metadata: meta(service_account.to_string(), Some(namespace)),
+            ..Default::default()
+        })?,
+        to_value(&RoleBinding {
+            metadata: meta(service_account.to_string(), Some(namespace)),
+            role_ref: role(LISTENER_ROLE),
+            subjects: subjects.clone(),
+        })?,
+        to_value(&ClusterRoleBinding {
+            metadata: meta(listener_cluster_role_binding(service_account, namespace), None),
+            role_ref: role(LISTENER_CLUSTER_ROLE),
+            subjects,
+        })?,
+    ])
+}
+
+/// Every object generated for `pipeline` of `owner`: its cache claims, Tasks
+/// and Pipeline, followed by the trigger objects when it has a git trigger,
+/// whose EventListener runs as `service_account`
+pub fn manifests(pipeline: &crd::Pipeline, owner: &crd::DependencyManager, service_account: &str) -> Result<Vec<Value>, Error> {
+    workspace::validate(pipeline)?;
+    references::validate(pipeline)?;
+    execution::validate(pipeline)?;
+    
+    let namespace = &owner.namespace().unwrap_or_default();
+    let mut manifests = Vec::new();
+    
+    for claim in workspace::cache_claims(pipeline, owner) {
+        manifests.push(to_value(&claim)?);
+    }
+    for step in pipeline.steps.iter().chain(pipeline.finally.iter().flatten()) {
+        manifests.push(to_value(&task(pipeline, step, namespace)?)?);
+    }
+    manifests.push(to_value(&self::pipeline(pipeline, namespace)?)?);
+    
+    if let Some(git) = &pipeline.trigger.git {
+        trigger::validate(&pipeline.name, git)?;
+        if trigger::provider(git) == GitProvider::Gitea && git.secret_ref.is_some() {
+            return Err(Error::CiCdError(format!(
+                "Pipeline {}: Tekton Triggers cannot validate Gitea webhooks with secret_ref",
+                pipeline.name
+            )));
+        }
+        
+        manifests.push(to_value(&trigger_binding(pipeline, namespace))?);
+        manifests.push(to_value(&trigger_template(pipeline, owner))?);
+        manifests.push(to_value(&event_listener(pipeline, git, namespace, service_account))?);
+    }
+    
+    Ok(manifests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crd::tests::owner;
+    use crate::cicd::tests::{assert_golden, release_pipeline, step, to_yaml};
+    
+    #[test]
+    fn renders_tricky_steps_and_triggers() {
+        let mut test = step("test");
+        test.commands = vec![
+            "echo \"building: $(params.git-revision)\"".to_string(),
+            "cargo test -- --test-threads=1".to_string(),
+            "echo 'single: quoted' # comment".to_string(),
+            "- not a list item".to_string(),
+        ];
+        test.env = Some(
+            [("RUSTFLAGS", "-D warnings"), ("GREETING", "say \"hi\": now"), ("EMPTY", "")]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        );
+        let pipeline = crate::cicd::tests::pipeline("build", vec![test, step("lint")]);
+        
+        let yaml = to_yaml(&manifests(&pipeline, &owner("apps", "platform"), "platform-triggers").unwrap()).unwrap();
+        assert_golden("tekton/build.yaml", &yaml);
+        
+        // What Tekton reads back is exactly what was configured
+        let parsed = crate::resources::parse_yaml(&yaml).unwrap();
+        assert_eq!(
+            parsed[0]["spec"]["steps"][0]["script"],
+            "#!/bin/sh\nset -e\necho \"building: $(params.git-revision)\"\ncargo test -- --test-threads=1\necho 'single: quoted' # comment\n- not a list item\n"
+        );
+        assert_eq!(parsed[0]["spec"]["steps"][0]["env"][1]["value"], "say \"hi\": now");
+        assert!(parsed[1]["spec"]["steps"][0].get("env").is_none());
+    }
+    
+    #[test]
+    fn renders_fan_out_conditions_and_finally() {
+        let pipeline = self::pipeline(&release_pipeline(), "apps").unwrap();
+        let yaml = to_yaml(&[to_value(&pipeline).unwrap()]).unwrap();
+        
+        assert_golden("tekton/release.yaml", &yaml);
+    }
+    
+    #[test]
+    fn binds_workspaces_and_secret_env() {
+        let pipeline = crate::cicd::tests::workspace_pipeline();
+        let mut manifests = manifests(&pipeline, &owner("apps", "platform"), "platform-triggers").unwrap();
+        manifests.truncate(4);
+        manifests.push(to_value(&pipeline_run(&pipeline, &owner("apps", "platform"), params(|_| String::new()))).unwrap());
+        
+        assert_golden("tekton/workspaces.yaml", &to_yaml(&manifests).unwrap());
+    }
+    
+    #[test]
+    fn passes_results_and_artifacts() {
+        let yaml = to_yaml(&manifests(&crate::cicd::tests::results_pipeline(), &owner("apps", "platform"), "platform-triggers").unwrap()).unwrap();
+        assert_golden("tekton/results.yaml", &yaml);
+    }
+    
+    #[test]
+    fn applies_resources_retries_and_pod_settings() {
+        let pipeline = crate::cicd::tests::tuned_pipeline();
+        let task = to_value(&task(&pipeline, &pipeline.steps[0], "apps").unwrap()).unwrap();
+        assert_eq!(
+            task["spec"]["steps"][0]["computeResources"],
+            json!({ "requests": { "cpu": "2" }, "limits": { "memory": "8Gi" } })
+        );
+        
+        let spec = to_value(&self::pipeline(&pipeline, "apps").unwrap()).unwrap()["spec"].clone();
+        assert_eq!((&spec["tasks"][0]["retries"], &spec["tasks"][0]["timeout"]), (&json!(2), &json!("45m")));
+        assert!(spec["finally"][0].get("retries").is_none());
+        
+        let run = to_value(&pipeline_run(&pipeline, &owner("apps", "platform"), Vec::new())).unwrap()["spec"].clone();
+        assert_eq!(run["timeouts"]["pipeline"], "1h30m");
+        assert_eq!(run["taskRunTemplate"]["serviceAccountName"], "builder");
+        assert_eq!(run["taskRunTemplate"]["podTemplate"]["nodeSelector"]["accelerator"], "gpu");
+        assert_eq!(run["taskRunTemplate"]["podTemplate"]["tolerations"][0]["operator"], "Exists");
+        assert_eq!(run["taskRunSpecs"].as_array().unwrap().len(), 1);
+        assert_eq!(run["taskRunSpecs"][0]["pipelineTaskName"], "notify");
+        assert_eq!(run["taskRunSpecs"][0]["serviceAccountName"], "notifier");
+        assert_eq!(run["taskRunSpecs"][0]["podTemplate"]["nodeSelector"]["accelerator"], "gpu");
+    }
+    
+    #[test]
+    fn filters_gitlab_merge_requests_by_target_branch() {
+        let git = GitTrigger {
+            repository: "git@gitlab.com:acme/platform/app.git".to_string(),
+            branches: vec!["main".to_string(), "release/*".to_string()],
+            events: vec!["push".to_string(), "merge_request".to_string()],
+            provider: None,
+            secret_ref: None,
+        };
+        
+        let interceptors = interceptors(&git);
+        assert_eq!(interceptors[0].reference.name, "gitlab");
+        assert_eq!(interceptors[0].params[0].value, json!(["Push Hook", "Merge Request Hook"]));
+        assert_eq!(
+            interceptors[1].params[0].value,
+            "body.project.path_with_namespace == 'acme/platform/app' && (\
+             header.match('X-Gitlab-Event', 'Push Hook') && body.ref.matches('^refs/heads/(?:main|release/.*)$') || \
+             header.match('X-Gitlab-Event', 'Merge Request Hook') && \
+             body.object_attributes.target_branch.matches('^(?:main|release/.*)$'))"
+        );
+        
+        // Generic webhooks only get the CEL interceptor, without a filter when any branch matches
+        let generic = GitTrigger {
+            repository: "https://git.acme.io/acme/app".to_string(),
+            branches: vec!["*".to_string()],
+            ..git
+        };
+        let interceptors = self::interceptors(&generic);
+        assert_eq!(interceptors.len(), 1);
+        assert_eq!(interceptors[0].params[0].name, "overlays");
+    }
+    
+    #[test]
+    fn binds_listener_service_account_to_trigger_roles() {
+        let manifests = listener_access("platform-triggers", "apps").unwrap();
+        
+        assert_eq!(manifests[0]["kind"], "ServiceAccount");
+        assert_eq!(manifests[1]["roleRef"]["name"], LISTENER_ROLE);
+        assert_eq!(manifests[2]["metadata"]["name"], "apps-platform-triggers");
+        assert_eq!(manifests[2]["subjects"][0]["namespace"], "apps");
+    }
+}
@@ -0,0 +1,160 @@
+use crate::crd::{GitProvider, GitTrigger};
+use crate::error::Error;
+
+/// Key of the webhook secret in the Secret named by `secret_ref`
+pub const SECRET_KEY: &str = "token";
+
+/// Event matched when a trigger lists none
+const DEFAULT_EVENT: &str = "push";
+
+/// Host and path of a repository URL, for `https://`, `ssh://` and scp-like
+/// `git@host:path` forms
+fn host_and_path(url: &str) -> (&str, &str) {
+    let (rest, scp) = match url.split_once("://") {
+        Some((_, rest)) => (rest, false),
+        None => (url, true),
+    };
+    let rest = rest.split_once('@').map_or(rest, |(_, rest)| rest);
+    let separator = if scp { ':' } else { '/' };
+    let (host, path) = rest.split_once(separator).unwrap_or((rest, ""));
+    
+    (host.split(':').next().unwrap_or(host), path)
+}
+
+/// Provider of `trigger`, inferred from the repository host when unset
+pub fn provider(trigger: &GitTrigger) -> GitProvider {
+    trigger.provider.unwrap_or_else(|| {
+        let (host, _) = host_and_path(&trigger.repository);
+        if host.contains("github") {
+            GitProvider::GitHub
+        } else if host.contains("gitlab") {
+            GitProvider::GitLab
+        } else if host.contains("gitea") {
+            GitProvider::Gitea
+        } else {
+            GitProvider::Generic
+        }
+    })
+}
+
+/// `owner/name` path of a repository URL, e.g. `acme/app` for both
+/// `https://github.com/acme/app.git` and `git@github.com:acme/app`
+pub fn repository_path(url: &str) -> &str {
+    let (_, path) = host_and_path(url);
+    let path = path.trim_matches('/');
+    path.strip_suffix(".git").unwrap_or(path)
+}
+
+/// Events of `trigger`, `push` when it lists none
+pub fn events(trigger: &GitTrigger) -> Vec<&str> {
+    if trigger.events.is_empty() {
+        vec![DEFAULT_EVENT]
+    } else {
+        trigger.events.iter().map(String::as_str).collect()
+    }
+}
+
+/// Value of GitLab's `X-Gitlab-Event` header for `event`
+pub fn gitlab_event(event: &str) -> Option<&'static str> {
+    match event {
+        "push" => Some("Push Hook"),
+        "pull_request" | "merge_request" => Some("Merge Request Hook"),
+        "tag_push" => Some("Tag Push Hook"),
+        _ => None,
+    }
+}
+
+/// Anchored regular expression matching any of `branches` after `prefix`,
+/// `None` when every branch matches
+pub fn branch_regex(branches: &[String], prefix: &str) -> Option<String> {
+    if branches.is_empty() || branches.iter().any(|b| b == "*") {
+        return None;
+    }
+    
+    let alternatives: Vec<String> = branches
+        .iter()
+        .map(|branch| {
+            branch
+                .chars()
+                .map(|c| match c {
+                    '*' => ".*".to_string(),
+                    '?' => ".".to_string(),
+                    c if "\\.+()[]{}^$|".contains(c) => format!("\\{}", c),
+                    c => c.to_string(),
+                })
+                .collect()
+        })
+        .collect();
+    
+    Some(format!("^{}(?:{})$", prefix, alternatives.join("|")))
+}
+
+/// Checks that `trigger` can be expressed for its provider
+pub fn validate(pipeline: &str, trigger: &GitTrigger) -> Result<(), Error> {
+    let invalid = |message: String| Err(Error::CiCdError(format!("Pipeline {}: {}", pipeline, message)));
+    
+    match provider(trigger) {
+        GitProvider::GitHub | GitProvider::Gitea => Ok(()),
+        GitProvider::GitLab => match events(trigger).into_iter().find(|e| gitlab_event(e).is_none()) {
+            Some(event) => invalid(format!("unsupported GitLab event {}", event)),
+            None => Ok(()),
+        },
+        GitProvider::Generic => {
+            if trigger.secret_ref.is_some() {
+                return invalid("generic webhooks cannot be validated with secret_ref".to_string());
+            }
+            match events(trigger).into_iter().find(|e| *e != DEFAULT_EVENT) {
+                Some(event) => invalid(format!("generic webhooks only support push events, not {}", event)),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn trigger(repository: &str) -> GitTrigger {
+        GitTrigger {
+            repository: repository.to_string(),
+            branches: vec![],
+            events: vec![],
+            provider: None,
+            secret_ref: None,
+        }
+    }
+    
+    #[test]
+    fn parses_repository_urls() {
+        assert_eq!(repository_path("https://github.com/acme/app.git"), "acme/app");
+        assert_eq!(repository_path("git@gitlab.com:group/sub/app.git"), "group/sub/app");
+        assert_eq!(repository_path("ssh://git@git.acme.io:2222/acme/app"), "acme/app");
+        
+        assert_eq!(provider(&trigger("git@github.com:acme/app")), GitProvider::GitHub);
+        assert_eq!(provider(&trigger("https://gitlab.acme.io/acme/app")), GitProvider::GitLab);
+        assert_eq!(provider(&trigger("https://gitea.acme.io/acme/app")), GitProvider::Gitea);
+        assert_eq!(provider(&trigger("https://git.acme.io/acme/app")), GitProvider::Generic);
+    }
+    
+    #[test]
+    fn translates_branch_globs() {
+        let branches = vec!["main".to_string(), "release/*".to_string(), "v1.?".to_string()];
+        assert_eq!(
+            branch_regex(&branches, "refs/heads/").unwrap(),
+            "^refs/heads/(?:main|release/.*|v1\\..)$"
+        );
+        assert_eq!(branch_regex(&["*".to_string()], ""), None);
+    }
+    
+    #[test]
+    fn rejects_events_the_provider_cannot_send() {
+        let mut gitlab = trigger("https://gitlab.com/acme/app");
+        gitlab.events = vec!["push".to_string(), "release".to_string()];
+        assert!(validate("build", &gitlab).is_err());
+        
+        let mut generic = trigger("https://git.acme.io/acme/app");
+        generic.secret_ref = Some("webhook".to_string());
+        assert!(validate("build", &generic).is_err());
+    }
+}
@@ -0,0 +1,258 @@
+use axum::http::HeaderMap;
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::Sha256;
+
+use crate::crd::{GitProvider, GitTrigger};
+use crate::error::Error;
+use crate::glob;
+
+use super::trigger;
+
+/// A webhook delivery, normalized across git hosts
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub provider: GitProvider,
+    /// `push`, `pull_request`, `tag_push` or the host's own event name
+    pub kind: String,
+    /// `owner/name` path of the repository
+    pub repository: Option<String>,
+    /// Pushed branch, or target branch of a pull request
+    pub branch: Option<String>,
+    pub repo_url: Option<String>,
+    pub revision: Option<String>,
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+fn string(value: &Value) -> Option<String> {
+    value.as_str().filter(|s| !s.is_empty()).map(str::to_string)
+}
+
+/// Parses a delivery, telling hosts apart by their event header. Gitea also
+/// sends GitHub's headers, so it is checked first.
+pub fn parse(headers: &HeaderMap, body: &[u8]) -> Result<Event, Error> {
+    let payload: Value = serde_json::from_slice(body)
+        .map_err(|e| Error::SerializationError(format!("Invalid webhook payload: {}", e)))?;
+    
+    let (provider, kind) = if let Some(kind) = header(headers, "X-Gitea-Event") {
+        (GitProvider::Gitea, kind.to_string())
+    } else if let Some(kind) = header(headers, "X-GitHub-Event") {
+        (GitProvider::GitHub, kind.to_string())
+    } else if let Some(kind) = header(headers, "X-Gitlab-Event") {
+        let kind = match kind {
+            "Push Hook" => "push",
+            "Merge Request Hook" => "pull_request",
+            "Tag Push Hook" => "tag_push",
+            other => other,
+        };
+        (GitProvider::GitLab, kind.to_string())
+    } else {
+        (GitProvider::Generic, "push".to_string())
+    };
+    
+    let pushed_branch = string(&payload["ref"]).and_then(|r| r.strip_prefix("refs/heads/").map(str::to_string));
+    
+    let event = match provider {
+        GitProvider::GitHub | GitProvider::Gitea => {
+            let pull_request = &payload["pull_request"];
+            Event {
+                repository: string(&payload["repository"]["full_name"]),
+                branch: string(&pull_request["base"]["ref"]).or(pushed_branch),
+                repo_url: string(&payload["repository"]["clone_url"]),
+                revision: string(&pull_request["head"]["sha"]).or_else(|| string(&payload["after"])),
+                provider,
+                kind,
+            }
+        }
+        GitProvider::GitLab => {
+            let attributes = &payload["object_attributes"];
+            Event {
+                repository: string(&payload["project"]["path_with_namespace"]),
+                branch: string(&attributes["target_branch"]).or(pushed_branch),
+                repo_url: string(&payload["project"]["git_http_url"]),
+                revision: string(&attributes["last_commit"]["id"]).or_else(|| string(&payload["checkout_sha"])),
+                provider,
+                kind,
+            }
+        }
+        GitProvider::Generic => {
+            let repo_url = string(&payload["repository"]["url"]);
+            Event {
+                repository: repo_url.as_deref().map(|url| trigger::repository_path(url).to_string()),
+                branch: pushed_branch,
+                repo_url,
+                revision: string(&payload["head_commit"]["id"]).or_else(|| string(&payload["after"])),
+                provider,
+                kind,
+            }
+        }
+    };
+    
+    Ok(event)
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Whether `hex` is the HMAC-SHA256 of `body` keyed with `secret`
+fn verify_hmac(secret: &[u8], body: &[u8], hex: &str) -> bool {
+    let Some(signature) = decode_hex(hex) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Compares without short-circuiting, so timing does not reveal the secret
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Checks the signature (GitHub, Gitea) or token (GitLab) of a delivery from
+/// `provider` against the webhook secret
+pub fn verify(provider: GitProvider, headers: &HeaderMap, body: &[u8], secret: &[u8]) -> bool {
+    match provider {
+        GitProvider::GitHub => header(headers, "X-Hub-Signature-256")
+            .and_then(|signature| signature.strip_prefix("sha256="))
+            .is_some_and(|hex| verify_hmac(secret, body, hex)),
+        GitProvider::Gitea => header(headers, "X-Gitea-Signature")
+            .is_some_and(|hex| verify_hmac(secret, body, hex)),
+        GitProvider::GitLab => header(headers, "X-Gitlab-Token")
+            .is_some_and(|token| constant_time_eq(token.as_bytes(), secret)),
+        GitProvider::Generic => false,
+    }
+}
+
+/// Whether `event` comes from the repository of `trigger` and has one of its
+/// events and branches; tag pushes are not filtered by branch
+pub fn matches(trigger: &GitTrigger, event: &Event) -> bool {
+    let provider = trigger::provider(trigger);
+    if provider != GitProvider::Generic && provider != event.provider {
+        return false;
+    }
+    
+    let repository = trigger::repository_path(&trigger.repository);
+    if event.repository.as_deref().is_some_and(|r| r != repository) {
+        return false;
+    }
+    
+    let kind = match event.kind.as_str() {
+        "pull_request" if provider == GitProvider::GitLab => "merge_request",
+        kind => kind,
+    };
+    let events = trigger::events(trigger);
+    if !events.contains(&event.kind.as_str()) && !events.contains(&kind) {
+        return false;
+    }
+    
+    event.kind == "tag_push"
+        || trigger.branches.is_empty()
+        || event.branch.as_deref().is_some_and(|branch| glob::matches_any(&trigger.branches, branch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+            .collect()
+    }
+    
+    fn sign(secret: &[u8], body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(body);
+        mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+    
+    fn github_trigger() -> GitTrigger {
+        GitTrigger {
+            repository: "https://github.com/acme/app.git".to_string(),
+            branches: vec!["main".to_string(), "release/*".to_string()],
+            events: vec!["push".to_string(), "pull_request".to_string()],
+            provider: None,
+            secret_ref: Some("webhook".to_string()),
+        }
+    }
+    
+    #[test]
+    fn parses_and_matches_github_deliveries() {
+        let push = json!({
+            "ref": "refs/heads/release/1.2",
+            "after": "abc123",
+            "repository": { "full_name": "acme/app", "clone_url": "https://github.com/acme/app.git" },
+        })
+        .to_string();
+        let event = parse(&headers(&[("X-GitHub-Event", "push")]), push.as_bytes()).unwrap();
+        
+        assert_eq!(event.branch.as_deref(), Some("release/1.2"));
+        assert_eq!(event.revision.as_deref(), Some("abc123"));
+        assert!(matches(&github_trigger(), &event));
+        
+        let pull_request = json!({
+            "pull_request": { "base": { "ref": "feature" }, "head": { "sha": "def456" } },
+            "repository": { "full_name": "acme/app" },
+        })
+        .to_string();
+        let event = parse(&headers(&[("X-GitHub-Event", "pull_request")]), pull_request.as_bytes()).unwrap();
+        assert_eq!(event.revision.as_deref(), Some("def456"));
+        assert!(!matches(&github_trigger(), &event));
+        
+        let other = Event { repository: Some("acme/other".to_string()), ..event };
+        assert!(!matches(&github_trigger(), &other));
+    }
+    
+    #[test]
+    fn matches_gitlab_merge_requests() {
+        let body = json!({
+            "project": { "path_with_namespace": "acme/app", "git_http_url": "https://gitlab.com/acme/app.git" },
+            "object_attributes": { "target_branch": "main", "last_commit": { "id": "abc123" } },
+        })
+        .to_string();
+        let event = parse(&headers(&[("X-Gitlab-Event", "Merge Request Hook")]), body.as_bytes()).unwrap();
+        
+        let trigger = GitTrigger {
+            repository: "https://gitlab.com/acme/app".to_string(),
+            events: vec!["merge_request".to_string()],
+            ..github_trigger()
+        };
+        assert_eq!(event.provider, GitProvider::GitLab);
+        assert!(matches(&trigger, &event));
+    }
+    
+    #[test]
+    fn verifies_signatures() {
+        let body = br#"{"ref":"refs/heads/main"}"#;
+        let signature = sign(b"s3cret", body);
+        
+        let github = headers(&[("X-Hub-Signature-256", &format!("sha256={}", signature))]);
+        assert!(verify(GitProvider::GitHub, &github, body, b"s3cret"));
+        assert!(!verify(GitProvider::GitHub, &github, body, b"other"));
+        assert!(!verify(GitProvider::GitHub, &github, b"{}", b"s3cret"));
+        
+        let gitea = headers(&[("X-Gitea-Signature", &signature)]);
+        assert!(verify(GitProvider::Gitea, &gitea, body, b"s3cret"));
+        
+        let gitlab = headers(&[("X-Gitlab-Token", "s3cret")]);
+        assert!(verify(GitProvider::GitLab, &gitlab, body, b"s3cret"));
+        assert!(!verify(GitProvider::GitLab, &gitlab, body, b"s3cre"));
+        
+        assert!(!verify(GitProvider::GitHub, &HeaderMap::new(), body, b"s3cret"));
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::{BTreeMap, HashSet};
+
+use k8s_openapi::api::core::v1::{
+    ConfigMapVolumeSource, EmptyDirVolumeSource, PersistentVolumeClaim, PersistentVolumeClaimSpec,
+    PersistentVolumeClaimVolumeSource, SecretVolumeSource, Volume, VolumeResourceRequirements,
+};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::ResourceExt;
+use sha2::{Digest, Sha256};
+
+use crate::crd::{DependencyManager, Pipeline, PipelineStep, PipelineWorkspace, StepMount, WorkspaceType};
+use crate::error::Error;
+
+/// Workspace every step mounts when a pipeline declares none
+pub const SHARED_WORKSPACE: &str = "shared-data";
+
+/// Storage requested by volume claims that do not set a size
+const DEFAULT_SIZE: &str = "1Gi";
+
+/// Directory workspaces are mounted under when a mount sets no path
+const MOUNT_ROOT: &str = "/workspace";
+
+/// Hex digits of the key hash in cache claim names
+const CACHE_HASH_LENGTH: usize = 10;
+
+/// Workspaces of `pipeline`, or the shared volume claim when it declares none
+pub fn workspaces(pipeline: &Pipeline) -> Vec<PipelineWorkspace> {
+    pipeline.workspaces.clone().unwrap_or_else(|| {
+        vec![PipelineWorkspace {
+            name: SHARED_WORKSPACE.to_string(),
+            type_: WorkspaceType::VolumeClaim,
+            size: None,
+            storage_class: None,
+            source: None,
+            key: None,
+        }]
+    })
+}
+
+/// Mounts of `step`, or every workspace of `pipeline` when it lists none
+pub fn mounts(pipeline: &Pipeline, step: &PipelineStep) -> Vec<StepMount> {
+    step.mounts.clone().unwrap_or_else(|| {
+        workspaces(pipeline)
+            .into_iter()
+            .map(|workspace| StepMount { workspace: workspace.name, path: None, read_only: None })
+            .collect()
+    })
+}
+
+/// Path `mount` is mounted at
+pub fn mount_path(mount: &StepMount) -> String {
+    mount.path.clone().unwrap_or_else(|| format!("{}/{}", MOUNT_ROOT, mount.workspace))
+}
+
+/// Checks that workspaces have unique names and the settings their type
+/// needs, that mounts name declared workspaces and that step variables are
+/// not set twice
+pub fn validate(pipeline: &Pipeline) -> Result<(), Error> {
+    let invalid = |message: String| Err(Error::CiCdError(format!("Pipeline {}: {}", pipeline.name, message)));
+    
+    let workspaces = workspaces(pipeline);
+    let mut names = HashSet::new();
+    for workspace in &workspaces {
+        if !names.insert(workspace.name.as_str()) {
+            return invalid(format!("more than one workspace named {}", workspace.name));
+        }
+        match workspace.type_ {
+            WorkspaceType::ConfigMap | WorkspaceType::Secret if workspace.source.is_none() => {
+                return invalid(format!("workspace {} needs a source", workspace.name));
+            }
+            WorkspaceType::Cache if workspace.key.is_none() => {
+                return invalid(format!("cache workspace {} needs a key", workspace.name));
+            }
+            _ => {}
+        }
+    }
+    
+    for step in pipeline.steps.iter().chain(pipeline.finally.iter().flatten()) {
+        for mount in step.mounts.iter().flatten() {
+            if !names.contains(mount.workspace.as_str()) {
+                return invalid(format!("step {} mounts unknown workspace {}", step.name, mount.workspace));
+            }
+        }
+        
+        let mut variables: HashSet<&str> = step.env.iter().flatten().map(|(name, _)| name.as_str()).collect();
+        for variable in step.secret_env.iter().flatten() {
+            if !variables.insert(variable.name.as_str()) {
+                return invalid(format!("step {} sets {} more than once", step.name, variable.name));
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// Name of the claim backing cache `workspace` of `owner`, which changes with its key
+pub fn cache_claim_name(owner: &DependencyManager, pipeline: &Pipeline, workspace: &PipelineWorkspace) -> String {
+    let digest = Sha256::digest(workspace.key.as_deref().unwrap_or_default().as_bytes());
+    let hash: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    
+    format!("{}-{}-{}-{}", owner.name_any(), pipeline.name, workspace.name, &hash[..CACHE_HASH_LENGTH])
+}
+
+/// Claim spec for a `volume-claim` or `cache` workspace
+pub fn claim_spec(workspace: &PipelineWorkspace) -> PersistentVolumeClaimSpec {
+    let size = workspace.size.clone().unwrap_or_else(|| DEFAULT_SIZE.to_string());
+    
+    PersistentVolumeClaimSpec {
+        access_modes: Some(vec!["ReadWriteOnce".to_string()]),
+        storage_class_name: workspace.storage_class.clone(),
+        resources: Some(VolumeResourceRequirements {
+            requests: Some(BTreeMap::from([("storage".to_string(), Quantity(size))])),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Claims backing the cache workspaces of `pipeline`, which outlive its runs
+pub fn cache_claims(pipeline: &Pipeline, owner: &DependencyManager) -> Vec<PersistentVolumeClaim> {
+    workspaces(pipeline)
+        .iter()
+        .filter(|workspace| workspace.type_ == WorkspaceType::Cache)
+        .map(|workspace| PersistentVolumeClaim {
+            metadata: ObjectMeta {
+                name: Some(cache_claim_name(owner, pipeline, workspace)),
+                namespace: owner.namespace(),
+                labels: Some(super::run_labels(owner, pipeline)),
+                ..Default::default()
+            },
+            spec: Some(claim_spec(workspace)),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Volume backing `workspace` in runs of `owner`, `None` for volume claims,
+/// which are claimed from a template for each run
+pub fn volume(owner: &DependencyManager, pipeline: &Pipeline, workspace: &PipelineWorkspace) -> Option<Volume> {
+    let source = workspace.source.clone().unwrap_or_default();
+    let mut volume = Volume { name: workspace.name.clone(), ..Default::default() };
+    
+    match workspace.type_ {
+        WorkspaceType::VolumeClaim => return None,
+        WorkspaceType::EmptyDir => volume.empty_dir = Some(EmptyDirVolumeSource::default()),
+        WorkspaceType::ConfigMap => {
+            volume.config_map = Some(ConfigMapVolumeSource { name: source, ..Default::default() });
+        }
+        WorkspaceType::Secret => {
+            volume.secret = Some(SecretVolumeSource { secret_name: Some(source), ..Default::default() });
+        }
+        WorkspaceType::Cache => {
+            volume.persistent_volume_claim = Some(PersistentVolumeClaimVolumeSource {
+                claim_name: cache_claim_name(owner, pipeline, workspace),
+                read_only: None,
+            });
+        }
+    }
+    
+    Some(volume)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cicd::tests::{pipeline, step};
+    use crate::crd::tests::owner;
+    
+    fn cache(key: &str) -> PipelineWorkspace {
+        PipelineWorkspace {
+            name: "cargo".to_string(),
+            type_: WorkspaceType::Cache,
+            size: Some("5Gi".to_string()),
+            storage_class: None,
+            source: None,
+            key: Some(key.to_string()),
+        }
+    }
+    
+    #[test]
+    fn cache_claims_follow_their_key() {
+        let build = pipeline("build", vec![step("test")]);
+        let platform = owner("apps", "platform");
+        let v1 = cache_claim_name(&platform, &build, &cache("cargo-v1"));
+        
+        assert!(v1.starts_with("platform-build-cargo-"));
+        assert_eq!(v1.len(), "platform-build-cargo-".len() + CACHE_HASH_LENGTH);
+        assert_eq!(v1, cache_claim_name(&platform, &build, &cache("cargo-v1")));
+        assert_ne!(v1, cache_claim_name(&platform, &build, &cache("cargo-v2")));
+        assert_ne!(v1, cache_claim_name(&owner("apps", "web"), &build, &cache("cargo-v1")));
+    }
+    
+    #[test]
+    fn rejects_invalid_workspaces() {
+        let mut build = pipeline("build", vec![step("test")]);
+        assert!(validate(&build).is_ok());
+        
+        build.steps[0].mounts = Some(vec![StepMount { workspace: "missing".to_string(), path: None, read_only: None }]);
+        assert!(validate(&build).is_err());
+        
+        build.steps[0].mounts = None;
+        build.workspaces = Some(vec![PipelineWorkspace { key: None, ..cache("cargo-v1") }]);
+        assert!(validate(&build).is_err());
+    }
+}
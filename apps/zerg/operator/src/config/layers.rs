@@ -0,0 +1,371 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use serde_json::{Map, Value};
+use tracing::{debug, warn};
+
+use super::Config;
+use crate::error::Error;
+
+/// Prefix of environment variables setting configuration values
+pub const ENV_PREFIX: &str = "ZERG_";
+
+/// Separator of the keys in environment variable names, e.g.
+/// `ZERG_OPERATOR__WEBHOOK_PORT` for `operator.webhook_port`
+const ENV_SEPARATOR: &str = "__";
+
+/// Source of the built-in defaults
+pub const DEFAULTS: &str = "defaults";
+
+/// Source of values given on the command line
+pub const COMMAND_LINE: &str = "command line";
+
+/// Configuration assembled from layers, each overriding the values of the
+/// ones before it, remembering where every value came from
+#[derive(Debug, Clone)]
+pub struct Layers {
+    value: Value,
+    
+    /// Source of each value set, by path; values below a path without an
+    /// entry of their own come from the source of that path
+    sources: BTreeMap<String, String>,
+}
+
+/// A value of the effective configuration and where it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setting {
+    pub path: String,
+    pub value: Value,
+    pub source: String,
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}  # {}", self.path, self.value, self.source)
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn within(path: &str, ancestor: &str) -> bool {
+    ancestor.is_empty()
+        || path == ancestor
+        || path.strip_prefix(ancestor).is_some_and(|rest| rest.starts_with('.'))
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Layers {
+    /// The built-in defaults
+    pub fn new() -> Self {
+        let mut layers = Self { value: Value::Object(Map::new()), sources: BTreeMap::new() };
+        let defaults = serde_json::to_value(Config::default()).expect("default configuration serializes");
+        layers.add(DEFAULTS, defaults);
+        layers
+    }
+    
+    /// Overrides the values set in `layer`. Mappings are merged key by key,
+    /// anything else replaces the current value.
+    pub fn add(&mut self, source: &str, layer: Value) {
+        merge(&mut self.value, layer, "", source, &mut self.sources);
+    }
+    
+    /// Adds the YAML document `content`, which must be a mapping
+    pub fn add_yaml(&mut self, source: &str, content: &str) -> Result<(), Error> {
+        let layer: Value = serde_yaml::from_str(content)
+            .map_err(|e| Error::ConfigError(format!("Failed to parse {}: {}", source, e)))?;
+        match layer {
+            Value::Null => Ok(()),
+            Value::Object(_) => {
+                self.add(source, layer);
+                Ok(())
+            }
+            _ => Err(Error::ConfigError(format!("{} is not a mapping of settings", source))),
+        }
+    }
+    
+    /// Adds the `ZERG_*` variables of `vars`, whose names are the path of the
+    /// setting in upper case with `__` between keys. Keys match existing ones
+    /// regardless of `-` and `_`, and values are read as YAML, except for
+    /// settings that are strings, which take the value as it is.
+    ///
+    /// Variables without `__` or outside the known sections are skipped, such
+    /// as the service links Kubernetes adds for the `zerg-operator-*` Services.
+    pub fn add_env(&mut self, vars: impl IntoIterator<Item = (String, String)>) -> Result<(), Error> {
+        let mut vars: Vec<(String, String)> = vars.into_iter().filter(|(name, _)| name.starts_with(ENV_PREFIX)).collect();
+        vars.sort();
+        
+        for (name, raw) in vars {
+            let keys: Vec<String> = name[ENV_PREFIX.len()..]
+                .split(ENV_SEPARATOR)
+                .map(str::to_lowercase)
+                .collect();
+            if keys.iter().any(String::is_empty) {
+                return Err(Error::ConfigError(format!("Environment variable {} names no setting", name)));
+            }
+            
+            let keys = self.resolve_keys(&keys);
+            if keys.len() < 2 || self.value.get(&keys[0]).is_none() {
+                debug!("Skipping environment variable {}, which names no setting", name);
+                continue;
+            }
+            let mut layer = match self.current(&keys) {
+                Some(Value::String(_)) => Value::String(raw),
+                _ => serde_yaml::from_str(&raw)
+                    .map_err(|e| Error::ConfigError(format!("Environment variable {}: {}", name, e)))?,
+            };
+            for key in keys.into_iter().rev() {
+                layer = Value::Object(Map::from_iter([(key, layer)]));
+            }
+            self.add(&format!("env {}", name), layer);
+        }
+        
+        Ok(())
+    }
+    
+    /// `keys` with each replaced by an existing key at its level that only
+    /// differs in case or in `-` versus `_`
+    fn resolve_keys(&self, keys: &[String]) -> Vec<String> {
+        let normalize = |key: &str| key.to_lowercase().replace('-', "_");
+        let mut current = Some(&self.value);
+        
+        keys.iter()
+            .map(|key| {
+                let existing = current
+                    .and_then(Value::as_object)
+                    .and_then(|map| map.keys().find(|candidate| normalize(candidate) == normalize(key)))
+                    .cloned();
+                let key = existing.unwrap_or_else(|| key.clone());
+                current = current.and_then(|value| value.get(&key));
+                key
+            })
+            .collect()
+    }
+    
+    /// Current value at `keys`, if any
+    fn current(&self, keys: &[String]) -> Option<&Value> {
+        keys.iter().try_fold(&self.value, |value, key| value.get(key))
+    }
+    
+    /// Source of the value at `path`, or the sources of the values set below
+    /// it, which are the likelier culprits when it is invalid
+    pub fn source(&self, path: &str) -> String {
+        let below: BTreeSet<&str> = self
+            .sources
+            .iter()
+            .filter(|(set, _)| *set != path && within(set, path))
+            .map(|(_, source)| source.as_str())
+            .collect();
+        if !below.is_empty() {
+            return below.into_iter().collect::<Vec<_>>().join(", ");
+        }
+        
+        let mut ancestor = path;
+        loop {
+            if let Some(source) = self.sources.get(ancestor) {
+                return source.clone();
+            }
+            match ancestor.rfind('.') {
+                Some(end) => ancestor = &ancestor[..end],
+                None if !ancestor.is_empty() => ancestor = "",
+                None => return DEFAULTS.to_string(),
+            }
+        }
+    }
+    
+    /// Every value of the configuration, with its source, by path
+    pub fn settings(&self) -> Vec<Setting> {
+        let mut settings = Vec::new();
+        flatten(&self.value, "", &mut |path, value| {
+            settings.push(Setting { path: path.to_string(), value: value.clone(), source: self.source(path) });
+        });
+        settings
+    }
+    
+    /// The effective configuration, validated. Errors name the path and the
+    /// source of the offending value. Settings the operator does not know are
+    /// reported, and rejected when `strict`.
+    pub fn config(&self, strict: bool) -> Result<Config, Error> {
+        let config: Config = serde_path_to_error::deserialize(&self.value).map_err(|e| {
+            let path = e.path().to_string();
+            Error::ConfigError(format!("{}: {} (from {})", path, e.inner(), self.source(&path)))
+        })?;
+        
+        let known = serde_json::to_value(&config)
+            .map_err(|e| Error::ConfigError(format!("Failed to serialize configuration: {}", e)))?;
+        let mut unknown = Vec::new();
+        unknown_paths(&self.value, &known, "", &mut unknown);
+        let unknown: Vec<String> = unknown
+            .iter()
+            .map(|path| format!("{}: unknown setting (from {})", path, self.source(path)))
+            .collect();
+        if strict && !unknown.is_empty() {
+            return Err(Error::ConfigError(unknown.join("; ")));
+        }
+        for problem in &unknown {
+            warn!("Ignoring {}", problem);
+        }
+        
+        let problems: Vec<String> = config
+            .problems()
+            .into_iter()
+            .map(|(path, message)| format!("{}: {} (from {})", path, message, self.source(&path)))
+            .collect();
+        if !problems.is_empty() {
+            return Err(Error::ConfigError(problems.join("; ")));
+        }
+        
+        Ok(config)
+    }
+}
+
+fn merge(base: &mut Value, layer: Value, path: &str, source: &str, sources: &mut BTreeMap<String, String>) {
+    match (base, layer) {
+        (Value::Object(base), Value::Object(layer)) => {
+            for (key, value) in layer {
+                let path = join(path, &key);
+                merge(base.entry(key).or_insert(Value::Null), value, &path, source, sources);
+            }
+        }
+        (base, layer) => {
+            sources.retain(|set, _| !within(set, path));
+            sources.insert(path.to_string(), source.to_string());
+            *base = layer;
+        }
+    }
+}
+
+/// Calls `visit` with every value that is not a non-empty mapping
+fn flatten(value: &Value, path: &str, visit: &mut impl FnMut(&str, &Value)) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                flatten(value, &join(path, key), visit);
+            }
+        }
+        _ => visit(path, value),
+    }
+}
+
+/// Paths in `value` that deserializing into `Config` dropped, as found in
+/// its serialization `known`
+fn unknown_paths(value: &Value, known: &Value, path: &str, unknown: &mut Vec<String>) {
+    if let (Value::Object(map), Value::Object(known)) = (value, known) {
+        for (key, value) in map {
+            let path = join(path, key);
+            match known.get(key) {
+                Some(known) => unknown_paths(value, known, &path, unknown),
+                None => unknown.push(path),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    #[test]
+    fn later_layers_override_earlier_ones() {
+        let mut layers = Layers::new();
+        layers
+            .add_yaml("file config.yaml", "operator:\n  webhook_port: 9000\n  metrics_port: 9100\n")
+            .unwrap();
+        layers.add_yaml("ConfigMap zerg-system/zerg", "operator:\n  metrics_port: 9200\n").unwrap();
+        layers
+            .add_env([
+                ("ZERG_OPERATOR__WATCH_NAMESPACES".to_string(), "[team-a, team-b]".to_string()),
+                ("ZERG_CICD_TEMPLATES__ARGO_WORKFLOWS__DEFAULTS__RETRIES".to_string(), "2".to_string()),
+                ("ZERG_OPERATOR__DEFAULT_NAMESPACE".to_string(), "2024".to_string()),
+                ("ZERG_OPERATOR__LOG_LEVEL".to_string(), "zerg_operator=debug,kube=info".to_string()),
+                ("HOME".to_string(), "/root".to_string()),
+            ])
+            .unwrap();
+        layers.add(COMMAND_LINE, json!({ "operator": { "webhook_port": 9300 } }));
+        
+        let config = layers.config(true).unwrap();
+        assert_eq!(config.operator.webhook_port, 9300);
+        assert_eq!(config.operator.metrics_port, 9200);
+        assert_eq!(config.operator.watch_namespaces, ["team-a", "team-b"]);
+        assert_eq!(config.cicd_templates["argo-workflows"].defaults.retries, Some(2));
+        assert_eq!(config.operator.default_namespace, "2024");
+        assert_eq!(config.operator.log_level, "zerg_operator=debug,kube=info");
+        assert_eq!(config.operator.reconciliation_interval, 300);
+        
+        assert_eq!(layers.source("operator.webhook_port"), COMMAND_LINE);
+        assert_eq!(layers.source("operator.metrics_port"), "ConfigMap zerg-system/zerg");
+        assert_eq!(layers.source("operator.watch_namespaces"), "env ZERG_OPERATOR__WATCH_NAMESPACES");
+        assert_eq!(layers.source("operator.reconciliation_interval"), DEFAULTS);
+        assert!(layers.settings().contains(&Setting {
+            path: "cicd_templates.argo-workflows.defaults.retries".to_string(),
+            value: json!(2),
+            source: "env ZERG_CICD_TEMPLATES__ARGO_WORKFLOWS__DEFAULTS__RETRIES".to_string(),
+        }));
+    }
+    
+    #[test]
+    fn errors_name_the_path_and_source() {
+        let mut layers = Layers::new();
+        layers.add_yaml("file config.yaml", "operator:\n  webhook_port: many\n").unwrap();
+        let error = layers.config(false).unwrap_err().to_string();
+        assert!(error.contains("operator.webhook_port: invalid type"), "{}", error);
+        assert!(error.ends_with("(from file config.yaml)"), "{}", error);
+        
+        let mut layers = Layers::new();
+        layers
+            .add_yaml(
+                "file config.yaml",
+                "cicd_templates:\n  tekton:\n    provider: jenkins\n    defaults: { timeout: soon }\n",
+            )
+            .unwrap();
+        layers.add_env([("ZERG_OPERATOR__MAX_CONCURRENT_RECONCILES".to_string(), "0".to_string())]).unwrap();
+        assert_eq!(
+            layers.config(false).unwrap_err().to_string(),
+            "Configuration error: \
+             cicd_templates.tekton.defaults: has timeout soon, which is not a duration like 30m or 1h30m (from file config.yaml); \
+             cicd_templates.tekton.provider: unknown provider jenkins (from file config.yaml); \
+             operator.max_concurrent_reconciles: must be positive (from env ZERG_OPERATOR__MAX_CONCURRENT_RECONCILES)"
+        );
+    }
+    
+    #[test]
+    fn rejects_unknown_settings_when_strict() {
+        let mut layers = Layers::new();
+        layers.add_yaml("file config.yaml", "operator:\n  webhook_prot: 9000\n").unwrap();
+        
+        assert!(layers.config(false).is_ok());
+        assert_eq!(
+            layers.config(true).unwrap_err().to_string(),
+            "Configuration error: operator.webhook_prot: unknown setting (from file config.yaml)"
+        );
+    }
+    
+    #[test]
+    fn skips_service_links() {
+        let mut layers = Layers::new();
+        layers
+            .add_env([
+                ("ZERG_OPERATOR_METRICS_SERVICE_HOST".to_string(), "10.96.0.12".to_string()),
+                ("ZERG_OPERATOR_METRICS_PORT".to_string(), "tcp://10.96.0.12:8080".to_string()),
+                ("ZERG_OPERATOR_WEBHOOKS_PORT_80_TCP".to_string(), "tcp://10.96.0.13:80".to_string()),
+                ("ZERG_OPERATOR_WEBHOOKS_SERVICE_PORT_WEBHOOKS".to_string(), "80".to_string()),
+                ("ZERG_OPERATOR__WEBHOOK_PORT".to_string(), "9000".to_string()),
+            ])
+            .unwrap();
+        
+        let config = layers.config(true).unwrap();
+        assert_eq!(config.operator.webhook_port, 9000);
+        assert_eq!(config.operator.metrics_port, Config::default().operator.metrics_port);
+    }
+}
@@ -1,9 +1,17 @@
-use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use tracing::info;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
-use crate::error::Error;
+use crate::cicd::execution;
+use crate::crd::{CiCdProvider, DependencyType, ExecutionSettings, GitOpsProvider};
+
+mod layers;
+
+pub use layers::{Layers, COMMAND_LINE};
+
+/// Configuration shared with the controller and webhook receiver, replaced
+/// when its source changes
+pub type SharedConfig = tokio::sync::watch::Receiver<Arc<Config>>;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -18,13 +26,21 @@ pub struct Config {
     
     /// CI/CD configuration templates
     pub cicd_templates: HashMap<String, CiCdTemplate>,
+    
+    /// Multi-tenancy restrictions on what DependencyManagers may install
+    #[serde(default)]
+    pub tenancy: TenancyConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OperatorConfig {
-    /// Default namespace for operator operations
+    /// Namespace the operator runs in, which holds its configuration ConfigMap
     pub default_namespace: String,
     
+    /// Log filter, such as `info` or `zerg_operator=debug,kube=info`
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+    
     /// Reconciliation interval in seconds
     pub reconciliation_interval: u64,
     
@@ -36,6 +52,55 @@ pub struct OperatorConfig {
     
     /// Metrics port
     pub metrics_port: u16,
+    
+    /// Namespaces watched for DependencyManagers; empty watches all namespaces
+    #[serde(default)]
+    pub watch_namespaces: Vec<String>,
+    
+    /// Directory holding vendored install manifests as `<provider>/<version>/install.yaml`
+    #[serde(default = "default_manifests_dir")]
+    pub manifests_dir: String,
+    
+    /// Port receiving git webhooks for Argo Workflows pipelines
+    #[serde(default = "default_webhook_port")]
+    pub webhook_port: u16,
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_manifests_dir() -> String {
+    "/opt/zerg/manifests".to_string()
+}
+
+fn default_webhook_port() -> u16 {
+    8081
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TenancyConfig {
+    /// Enforce tenant policies before installing anything
+    pub enabled: bool,
+    
+    /// Policy for source namespaces without an entry in `policies`
+    pub default_policy: Option<TenantPolicy>,
+    
+    /// Policies keyed by the namespace the DependencyManager lives in
+    #[serde(default)]
+    pub policies: HashMap<String, TenantPolicy>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TenantPolicy {
+    /// Namespace patterns dependencies may be installed into; unset allows any
+    pub allowed_target_namespaces: Option<Vec<String>>,
+    
+    /// Chart or manifest repository patterns that may be used; unset allows any
+    pub allowed_repositories: Option<Vec<String>>,
+    
+    /// Dependency types that may be used; unset allows any
+    pub allowed_dependency_types: Option<Vec<DependencyType>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -90,6 +155,48 @@ pub struct CiCdTemplate {
     
     /// Default pipeline configuration
     pub config: HashMap<String, serde_json::Value>,
+    
+    /// Step templates pipelines of this provider can use, on top of the built-in ones
+    #[serde(default)]
+    pub steps: HashMap<String, StepTemplate>,
+    
+    /// Resources, retries, timeout and scheduling for pipelines that leave them unset
+    #[serde(default)]
+    pub defaults: ExecutionSettings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepTemplate {
+    /// Template description
+    #[serde(default)]
+    pub description: String,
+    
+    /// Container image, which steps may override
+    pub image: String,
+    
+    /// Commands to run, reading params from environment variables
+    pub commands: Vec<String>,
+    
+    /// Params steps pass to the template
+    #[serde(default)]
+    pub params: Vec<TemplateParam>,
+    
+    /// Environment variables
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateParam {
+    /// Param name, exposed to the commands as an upper-case environment variable
+    pub name: String,
+    
+    /// Param description
+    #[serde(default)]
+    pub description: String,
+    
+    /// Default value; params without one are required
+    pub default: Option<String>,
 }
 
 impl Default for Config {
@@ -227,6 +334,8 @@ impl Default for Config {
                 name: "tekton".to_string(),
                 provider: "tekton".to_string(),
                 config: HashMap::new(),
+                steps: HashMap::new(),
+                defaults: ExecutionSettings::default(),
             },
         );
         
@@ -237,36 +346,111 @@ impl Default for Config {
                 name: "argo-workflows".to_string(),
                 provider: "argo-workflows".to_string(),
                 config: HashMap::new(),
+                steps: HashMap::new(),
+                defaults: ExecutionSettings::default(),
             },
         );
         
         Self {
             operator: OperatorConfig {
                 default_namespace: "zerg-system".to_string(),
+                log_level: default_log_level(),
                 reconciliation_interval: 300,
                 max_concurrent_reconciles: 5,
                 metrics_enabled: true,
                 metrics_port: 8080,
+                watch_namespaces: Vec::new(),
+                manifests_dir: default_manifests_dir(),
+                webhook_port: default_webhook_port(),
             },
             dependency_templates,
             gitops_templates,
             cicd_templates,
+            tenancy: TenancyConfig::default(),
+        }
+    }
+}
+
+/// Settings given on the command line, which take precedence over every
+/// other configuration source
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    /// Namespace the operator runs in
+    pub namespace: Option<String>,
+    
+    /// Log filter
+    pub log_level: Option<String>,
+    
+    /// Namespaces to watch, `*` standing for all of them
+    pub watch_namespaces: Option<Vec<String>>,
+}
+
+impl Overrides {
+    /// Layer of the settings given
+    pub fn layer(&self) -> serde_json::Value {
+        let mut operator = serde_json::Map::new();
+        if let Some(namespace) = &self.namespace {
+            operator.insert("default_namespace".to_string(), namespace.clone().into());
         }
+        if let Some(log_level) = &self.log_level {
+            operator.insert("log_level".to_string(), log_level.clone().into());
+        }
+        if let Some(namespaces) = &self.watch_namespaces {
+            let namespaces: Vec<String> = namespaces.iter().filter(|ns| *ns != "*").cloned().collect();
+            operator.insert("watch_namespaces".to_string(), namespaces.into());
+        }
+        serde_json::json!({ "operator": operator })
     }
 }
 
-pub async fn load_config(path: &str) -> Result<Config, Error> {
-    info!("Loading configuration from: {}", path);
-    
-    match std::fs::read_to_string(path) {
-        Ok(content) => {
-            let config: Config = serde_yaml::from_str(&content)
-                .map_err(|e| Error::ConfigError(format!("Failed to parse config file: {}", e)))?;
-            Ok(config)
+impl Config {
+    /// Settings that would only fail once they are used, as paths and
+    /// problems, sorted by path
+    pub fn problems(&self) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+        let mut problem = |path: String, message: String| problems.push((path, message));
+        
+        let operator = &self.operator;
+        if operator.reconciliation_interval == 0 {
+            problem("operator.reconciliation_interval".to_string(), "must be positive".to_string());
+        }
+        if operator.max_concurrent_reconciles == 0 {
+            problem("operator.max_concurrent_reconciles".to_string(), "must be positive".to_string());
         }
-        Err(_) => {
-            info!("Config file not found, using default configuration");
-            Ok(Config::default())
+        if operator.metrics_enabled && operator.metrics_port == operator.webhook_port {
+            problem("operator.webhook_port".to_string(), format!("{} is also the metrics port", operator.webhook_port));
         }
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&operator.log_level) {
+            problem("operator.log_level".to_string(), format!("is not a log filter: {}", e));
+        }
+        
+        for (name, template) in &self.gitops_templates {
+            if serde_json::from_value::<GitOpsProvider>(serde_json::Value::String(template.provider.clone())).is_err() {
+                problem(format!("gitops_templates.{}.provider", name), format!("unknown provider {}", template.provider));
+            }
+        }
+        
+        for (name, template) in &self.cicd_templates {
+            let path = format!("cicd_templates.{}", name);
+            if serde_json::from_value::<CiCdProvider>(serde_json::Value::String(template.provider.clone())).is_err() {
+                problem(format!("{}.provider", path), format!("unknown provider {}", template.provider));
+            }
+            if let Err(message) = execution::validate_defaults(&template.defaults) {
+                problem(format!("{}.defaults", path), message);
+            }
+            
+            for (step, step_template) in &template.steps {
+                if step_template.image.is_empty() || step_template.commands.is_empty() {
+                    problem(format!("{}.steps.{}", path, step), "needs an image and commands".to_string());
+                }
+                let mut params = HashSet::new();
+                if let Some(param) = step_template.params.iter().find(|param| !params.insert(&param.name)) {
+                    problem(format!("{}.steps.{}.params", path, step), format!("declares param {} twice", param.name));
+                }
+            }
+        }
+        
+        problems.sort();
+        problems
     }
-}
\ No newline at end of file
+}
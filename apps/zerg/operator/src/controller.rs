@@ -2,51 +2,166 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
-use futures_util::StreamExt;
+use futures_util::{stream, StreamExt};
+use k8s_openapi::api::core::v1::{ConfigMap, Secret};
 use kube::{
-    api::{Api, Patch, PatchParams, ResourceExt},
+    api::{Api, DynamicObject, Patch, PatchParams, ResourceExt},
     client::Client,
     runtime::{
         controller::{Action, Controller},
         finalizer::{finalizer, Event as Finalizer},
+        watcher,
     },
 };
-use tracing::{error, info, instrument};
+use tracing::{error, info, instrument, warn};
 
 use crate::{
-    config::Config,
-    crd::{DependencyManager, DependencyManagerStatus, Phase},
+    config::{Config, SharedConfig},
+    crd::{
+        DependencyInstallStatus, DependencyManager, DependencyManagerStatus, DependencyStatus, GitOpsConfig, GitOpsMode,
+        GitOpsStatus, Phase,
+    },
     dependencies::DependencyInstaller,
     error::Error,
-    gitops::GitOpsManager,
+    gitops::{self, GitOpsManager},
+    impersonation::{self, Impersonation},
     cicd::CiCdManager,
+    reload,
+    resources::{self, CLUSTER_LABELLED_KINDS, LABELLED_KINDS, MANAGED_BY_LABEL, OWNED_KINDS, OWNER_NAME_LABEL},
+    updates::{Indexes, Pins},
 };
 
 pub struct DependencyController {
     client: Client,
-    config: Arc<Config>,
+    kube_config: kube::Config,
+    config: SharedConfig,
 }
 
 impl DependencyController {
-    pub fn new(client: Client, config: Arc<Config>) -> Self {
-        Self { client, config }
+    pub fn new(client: Client, kube_config: kube::Config, config: SharedConfig) -> Self {
+        Self { client, kube_config, config }
+    }
+    
+    /// Current configuration
+    fn config(&self) -> Arc<Config> {
+        self.config.borrow().clone()
     }
     
+    /// Client and identity used for installs of `dm`, impersonating its
+    /// `service_account_name` when one is set.
+    fn install_client(&self, dm: &DependencyManager) -> Result<(Client, Option<Impersonation>), Error> {
+        impersonation::owner_client(&self.client, &self.kube_config, dm)
+    }
+    
+    /// Reconciles DependencyManagers in `operator.watch_namespaces`, starting
+    /// over with the new namespaces whenever the setting changes
     #[instrument(skip(self))]
     pub async fn run(self) -> Result<()> {
-        let api: Api<DependencyManager> = Api::all(self.client.clone());
+        let mut config = self.config.clone();
+        let ctx = Arc::new(self);
         
-        Controller::new(api, Default::default())
-            .run(reconcile, error_policy, Arc::new(self))
-            .for_each(|result| async move {
+        loop {
+            let namespaces = config.borrow_and_update().operator.watch_namespaces.clone();
+            let mut controllers = Vec::new();
+            
+            if namespaces.is_empty() {
+                info!("Watching DependencyManagers in all namespaces");
+                controllers.push(ctx.controller(None).await.run(reconcile, error_policy, ctx.clone()).boxed());
+            } else {
+                for namespace in &namespaces {
+                    info!("Watching DependencyManagers in namespace {}", namespace);
+                    controllers.push(ctx.controller(Some(namespace)).await.run(reconcile, error_policy, ctx.clone()).boxed());
+                }
+            }
+            
+            let reconciles = stream::select_all(controllers).for_each(|result| async move {
                 match result {
                     Ok(_) => info!("Reconciliation successful"),
                     Err(e) => error!("Reconciliation error: {}", e),
                 }
-            })
-            .await;
+            });
             
-        Ok(())
+            tokio::select! {
+                _ = reconciles => return Ok(()),
+                _ = reload::namespaces_changed(&mut config, &namespaces) => {
+                    info!("Watched namespaces changed, restarting the controllers");
+                }
+            }
+        }
+    }
+    
+    /// Builds a controller for DependencyManagers in `namespace`, or in all
+    /// namespaces when `None`, with watches on related objects in the same scope.
+    async fn controller(&self, namespace: Option<&str>) -> Controller<DependencyManager> {
+        let api: Api<DependencyManager> = match namespace {
+            Some(ns) => Api::namespaced(self.client.clone(), ns),
+            None => Api::all(self.client.clone()),
+        };
+        
+        let controller = self.watch_related(Controller::new(api, watcher::Config::default()), namespace).await;
+        
+        // DependencyManagers using changed templates or tenant policies are reconciled again on reload
+        let store = controller.store();
+        controller.reconcile_on(reload::affected(self.config.clone(), store))
+    }
+    
+    /// Registers watches on objects created or referenced by DependencyManagers.
+    ///
+    /// Kinds whose CRDs are not installed (e.g. no Flux in the cluster) are skipped.
+    async fn watch_related(
+        &self,
+        mut controller: Controller<DependencyManager>,
+        namespace: Option<&str>,
+    ) -> Controller<DependencyManager> {
+        let managed = watcher::Config::default().labels(&format!("{}={}", MANAGED_BY_LABEL, resources::FIELD_MANAGER));
+        let referenced = watcher::Config::default().labels(OWNER_NAME_LABEL);
+        
+        for kind in OWNED_KINDS {
+            match resources::resolve_served_kind(&self.client, *kind).await {
+                Ok((resource, _)) => {
+                    info!("Watching owned {}", resource.kind);
+                    let api: Api<DynamicObject> = match namespace {
+                        Some(ns) => Api::namespaced_with(self.client.clone(), ns, &resource),
+                        None => Api::all_with(self.client.clone(), &resource),
+                    };
+                    controller = controller.owns_with(api, resource, managed.clone());
+                }
+                Err(e) => warn!("Not watching {}: {}", kind.2, e),
+            }
+        }
+        
+        // Labelled kinds live in whichever namespace the provider runs in, which
+        // owners may change at any time, so they are watched in all namespaces
+        // and only owners in scope are mapped
+        let scope = namespace.map(str::to_string);
+        for kind in LABELLED_KINDS {
+            match resources::resolve_kind(&self.client, *kind).await {
+                Ok((resource, _)) => {
+                    info!("Watching labelled {}", resource.kind);
+                    let api: Api<DynamicObject> = Api::all_with(self.client.clone(), &resource);
+                    let scope = scope.clone();
+                    controller = controller.watches_with(api, resource, managed.clone(), move |obj| {
+                        resources::owner_from_labels(&obj.metadata)
+                            .filter(|owner| scope.is_none() || owner.namespace == scope)
+                    });
+                }
+                Err(e) => warn!("Not watching {}: {}", kind.2, e),
+            }
+        }
+        
+        let (config_maps, secrets): (Api<ConfigMap>, Api<Secret>) = match namespace {
+            Some(ns) => (Api::namespaced(self.client.clone(), ns), Api::namespaced(self.client.clone(), ns)),
+            None => (Api::all(self.client.clone()), Api::all(self.client.clone())),
+        };
+        
+        // ConfigMaps and Secrets referenced through `values_from` are labelled by the user
+        controller
+            .watches(config_maps, referenced.clone(), |cm| {
+                resources::owner_from_labels(&cm.metadata)
+            })
+            .watches(secrets, referenced, |secret| {
+                resources::owner_from_labels(&secret.metadata)
+            })
     }
 }
 
@@ -82,94 +197,309 @@ async fn reconcile_dependency_manager(
     
     info!("Applying DependencyManager {}", name);
     
+    // One configuration throughout, even if it is reloaded meanwhile
+    let config = ctx.config();
+    
+    // Enforce tenant policy before anything is installed
+    if let Err(e) = config.tenancy.enforce(&namespace, &dm.spec) {
+        error!("Rejected DependencyManager {}: {}", name, e);
+        update_status(&ctx.client, &dm, Phase::Failed, Some(e.to_string()), None).await?;
+        return Ok(Action::requeue(Duration::from_secs(300)));
+    }
+    
     // Update status to Installing
-    update_status(&ctx.client, &dm, Phase::Installing, None).await?;
+    update_status(&ctx.client, &dm, Phase::Installing, None, None).await?;
+    
+    // Installs run as the DependencyManager's ServiceAccount when one is set
+    let (client, impersonation) = match ctx.install_client(&dm) {
+        Ok(install_client) => install_client,
+        Err(e) => {
+            error!("Failed to create impersonating client: {}", e);
+            update_status(&ctx.client, &dm, Phase::Failed, Some(format!("Impersonation failed: {}", e)), None).await?;
+            return Ok(Action::requeue(Duration::from_secs(300)));
+        }
+    };
     
     // Install dependencies
-    let installer = DependencyInstaller::new(ctx.client.clone());
+    let installer = DependencyInstaller::new(client.clone(), impersonation.clone());
     let mut dependency_statuses = Vec::new();
     
-    for dep in &dm.spec.dependencies {
-        if !dep.enabled {
+    // GitOps controllers come from vendored manifests and are reported like any other dependency
+    if let Some(gitops_config) = &dm.spec.gitops {
+        let template = config.gitops_templates.get(gitops_config.provider.template_name());
+        match gitops::install_bundle(gitops_config, template, &config.operator.manifests_dir) {
+            Ok(Some(bundle)) => dependency_statuses.push(installer.install_bundle(&bundle).await),
+            Ok(None) => {}
+            Err(e) => {
+                error!("Failed to resolve GitOps install: {}", e);
+                update_status(&ctx.client, &dm, Phase::Failed, Some(format!("GitOps setup failed: {}", e)), None).await?;
+                return Ok(Action::requeue(Duration::from_secs(300)));
+            }
+        }
+    }
+    
+    // In git-write mode Flux/Argo apply the committed dependency manifests instead
+    let git_write = dm.spec.gitops
+        .as_ref()
+        .is_some_and(|gitops| gitops.mode == Some(GitOpsMode::GitWrite));
+    
+    // Chart version constraints resolve to versions pinned in status; updates
+    // found in git-write mode are proposed on branches instead
+    let mut pins = match Pins::resolve(&dm, git_write, &mut Indexes::new(ctx.client.clone(), &namespace)).await {
+        Ok(pins) => pins,
+        Err(e) => {
+            error!("Failed to resolve chart versions: {}", e);
+            update_status(&ctx.client, &dm, Phase::Failed, Some(format!("Version resolution failed: {}", e)), Some(dependency_statuses)).await?;
+            return Ok(Action::requeue(Duration::from_secs(300)));
+        }
+    };
+    let pinned = pins.apply(&dm);
+    
+    for dep in &pinned.spec.dependencies {
+        if !dep.enabled || git_write {
             continue;
         }
         
         info!("Installing dependency: {}", dep.name);
         
         match installer.install_dependency(dep, &namespace).await {
-            Ok(status) => {
-                info!("Successfully installed dependency: {}", dep.name);
+            Ok(mut status) => {
+                match status.status {
+                    DependencyInstallStatus::Failed => error!("Failed to install dependency {}: {}", dep.name, status.error.as_deref().unwrap_or_default()),
+                    _ => info!("Successfully installed dependency: {}", dep.name),
+                }
+                pins.annotate(&mut status);
                 dependency_statuses.push(status);
             }
             Err(e) => {
                 error!("Failed to install dependency {}: {}", dep.name, e);
-                update_status(&ctx.client, &dm, Phase::Failed, Some(format!("Failed to install {}: {}", dep.name, e))).await?;
+                update_status(&ctx.client, &dm, Phase::Failed, Some(format!("Failed to install {}: {}", dep.name, e)), Some(dependency_statuses)).await?;
                 return Ok(Action::requeue(Duration::from_secs(300)));
             }
         }
     }
     
+    // Failed dependencies (e.g. denied by the impersonated account's RBAC) are reported individually
+    let failed: Vec<&str> = dependency_statuses
+        .iter()
+        .filter(|status| matches!(status.status, DependencyInstallStatus::Failed))
+        .map(|status| status.name.as_str())
+        .collect();
+    
+    if !failed.is_empty() {
+        let message = format!("Failed to install {}", failed.join(", "));
+        update_status(&ctx.client, &dm, Phase::Failed, Some(message), Some(dependency_statuses)).await?;
+        return Ok(Action::requeue(Duration::from_secs(300)));
+    }
+    
     // Setup GitOps if configured
+    let mut last_commit = None;
     if let Some(gitops_config) = &dm.spec.gitops {
         info!("Setting up GitOps with provider: {:?}", gitops_config.provider);
         
-        let gitops_manager = GitOpsManager::new(ctx.client.clone());
-        if let Err(e) = gitops_manager.setup_gitops(gitops_config, &namespace).await {
+        let template = config.gitops_templates.get(gitops_config.provider.template_name()).cloned();
+        let gitops_manager = GitOpsManager::new(client.clone(), impersonation.clone(), template);
+        if let Err(e) = gitops_manager.setup_gitops(gitops_config, &dm).await {
             error!("Failed to setup GitOps: {}", e);
-            update_status(&ctx.client, &dm, Phase::Failed, Some(format!("GitOps setup failed: {}", e))).await?;
+            update_status(&ctx.client, &dm, Phase::Failed, Some(format!("GitOps setup failed: {}", e)), Some(dependency_statuses)).await?;
             return Ok(Action::requeue(Duration::from_secs(300)));
         }
+        
+        if git_write {
+            match write_pinned(&gitops_manager, gitops_config, &dm, &mut pins).await {
+                Ok(commit) => {
+                    info!("Committed dependencies of {} at {}", name, commit);
+                    let now = chrono::Utc::now().to_rfc3339();
+                    dependency_statuses.extend(pins.apply(&dm).spec.dependencies.iter().filter(|d| d.enabled).map(|dep| {
+                        let mut status = DependencyStatus {
+                            name: dep.name.clone(),
+                            status: DependencyInstallStatus::Committed,
+                            version: dep.version.clone(),
+                            constraint: None,
+                            latest_version: None,
+                            proposed_version: None,
+                            update_branch: None,
+                            last_checked: None,
+                            digest: None,
+                            last_updated: Some(now.clone()),
+                            error: None,
+                        };
+                        pins.annotate(&mut status);
+                        status
+                    }));
+                    last_commit = Some(commit);
+                }
+                Err(e) => {
+                    error!("Failed to commit dependencies: {}", e);
+                    update_status(&ctx.client, &dm, Phase::Failed, Some(format!("Git write failed: {}", e)), Some(dependency_statuses)).await?;
+                    return Ok(Action::requeue(Duration::from_secs(300)));
+                }
+            }
+        }
     }
     
     // Setup CI/CD if configured
     if let Some(cicd_config) = &dm.spec.cicd {
         info!("Setting up CI/CD with provider: {:?}", cicd_config.provider);
         
-        let cicd_manager = CiCdManager::new(ctx.client.clone());
-        if let Err(e) = cicd_manager.setup_cicd(cicd_config, &namespace).await {
+        let template = config.cicd_templates.get(cicd_config.provider.template_name());
+        let cicd_manager = CiCdManager::new(client.clone(), impersonation.clone());
+        if let Err(e) = cicd_manager.setup_cicd(&ctx.client, cicd_config, template, &dm).await {
             error!("Failed to setup CI/CD: {}", e);
-            update_status(&ctx.client, &dm, Phase::Failed, Some(format!("CI/CD setup failed: {}", e))).await?;
+            update_status(&ctx.client, &dm, Phase::Failed, Some(format!("CI/CD setup failed: {}", e)), Some(dependency_statuses)).await?;
             return Ok(Action::requeue(Duration::from_secs(300)));
         }
+        
+        // Start the run requested through the run-pipeline annotation, once per nonce
+        match cicd_manager.run_requested(&ctx.client, template, &dm).await {
+            Ok(Some(run)) => info!("Started requested run {} for {}", run, name),
+            Ok(None) => {}
+            Err(e) => {
+                error!("Failed to start requested pipeline run: {}", e);
+                update_status(&ctx.client, &dm, Phase::Failed, Some(format!("Pipeline run failed: {}", e)), Some(dependency_statuses)).await?;
+                return Ok(Action::requeue(Duration::from_secs(300)));
+            }
+        }
     }
     
     // Update status to Ready
-    update_status(&ctx.client, &dm, Phase::Ready, None).await?;
+    update_status(&ctx.client, &dm, Phase::Ready, None, Some(dependency_statuses)).await?;
+    
+    // Publish the sync state of the generated GitOps objects; changes to them
+    // trigger another reconcile, which refreshes it
+    if let Some(gitops_config) = &dm.spec.gitops {
+        let gitops_manager = GitOpsManager::new(client.clone(), impersonation.clone(), None);
+        match gitops_manager.gitops_status(gitops_config, &dm).await {
+            Ok(gitops_status) => {
+                let gitops_status = GitOpsStatus { last_commit, ..gitops_status };
+                update_gitops_status(&ctx.client, &dm, gitops_status).await?
+            }
+            Err(e) => warn!("Failed to read GitOps status of {}: {}", name, e),
+        }
+    }
     
     info!("Successfully reconciled DependencyManager {}", name);
     Ok(Action::requeue(Duration::from_secs(3600))) // Requeue every hour
 }
 
-#[instrument(skip(_ctx))]
+/// Commits the dependencies of `dm` at their pinned versions, after pinning
+/// proposed updates that were merged, and pushes newly proposed updates to
+/// their branches
+async fn write_pinned(
+    gitops_manager: &GitOpsManager,
+    config: &GitOpsConfig,
+    dm: &DependencyManager,
+    pins: &mut Pins,
+) -> Result<String, Error> {
+    let proposals: Vec<(String, String, String, bool)> = pins
+        .proposed()
+        .filter_map(|(name, pin)| {
+            Some((name.to_string(), pin.proposed.clone()?, pin.branch.clone()?, pins.is_unpublished(name)))
+        })
+        .collect();
+    
+    // Committing the old version would revert a merged update
+    for (dependency, ..) in proposals.iter().filter(|(.., unpublished)| !unpublished) {
+        match gitops_manager.is_committed(config, &pins.proposal(dm, dependency), dependency).await {
+            Ok(true) => pins.accept(dependency),
+            Ok(false) => {}
+            Err(e) => warn!("Failed to check whether the update of {} was merged: {}", dependency, e),
+        }
+    }
+    
+    let commit = gitops_manager.write_dependencies(config, &pins.apply(dm)).await?;
+    
+    for (dependency, version, branch, _) in proposals.iter().filter(|(.., unpublished)| *unpublished) {
+        match gitops_manager.propose_update(config, &pins.proposal(dm, dependency), dependency, version, branch).await {
+            Ok(_) => info!("Proposed {} {} on branch {}", dependency, version, branch),
+            Err(e) => {
+                warn!("Failed to propose {} {}: {}", dependency, version, e);
+                pins.withdraw(dependency);
+            }
+        }
+    }
+    
+    Ok(commit)
+}
+
+#[instrument(skip(ctx))]
 async fn cleanup_dependency_manager(
     dm: Arc<DependencyManager>,
-    _ctx: Arc<DependencyController>,
+    ctx: Arc<DependencyController>,
 ) -> Result<Action, Error> {
     let name = dm.name_any();
     info!("Cleaning up DependencyManager {}", name);
     
-    // TODO: Implement cleanup logic
+    // Objects in the DependencyManager namespace are garbage-collected through
+    // their ownerReferences; Argo and cluster-scoped objects are found through their labels
+    if let Some(gitops_config) = &dm.spec.gitops {
+        for kind in LABELLED_KINDS {
+            let namespace = gitops::controller_namespace(gitops_config);
+            match resources::prune_labelled(&ctx.client, *kind, namespace, &dm, &[]).await {
+                Ok(()) => {}
+                Err(Error::KubeError(kube::Error::Discovery(_))) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    
+    for kind in CLUSTER_LABELLED_KINDS {
+        match resources::prune_labelled(&ctx.client, *kind, "", &dm, &[]).await {
+            Ok(()) => {}
+            Err(Error::KubeError(kube::Error::Discovery(_))) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    
+    // TODO: Implement remaining cleanup logic
     // - Uninstall dependencies if configured
-    // - Clean up GitOps resources
-    // - Clean up CI/CD pipelines
     
     Ok(Action::await_change())
 }
 
+/// `dependencies` completed with the previous statuses of those it leaves
+/// out, so the versions pinned there survive a reconcile that stopped early
+fn with_previous(dm: &DependencyManager, dependencies: Option<Vec<DependencyStatus>>) -> Option<Vec<DependencyStatus>> {
+    let previous = dm.status.as_ref().and_then(|status| status.dependencies.clone());
+    let Some(mut dependencies) = dependencies else {
+        return previous;
+    };
+    
+    for status in previous.into_iter().flatten() {
+        if !dependencies.iter().any(|dependency| dependency.name == status.name) {
+            dependencies.push(status);
+        }
+    }
+    
+    Some(dependencies)
+}
+
+/// Records `phase` and `dependencies` in the status of `dm`. Only a `Ready`
+/// reconcile replaces the dependency statuses; any other phase keeps the
+/// previous statuses of dependencies it did not get to.
 async fn update_status(
     client: &Client,
     dm: &DependencyManager,
     phase: Phase,
     error_message: Option<String>,
+    dependencies: Option<Vec<DependencyStatus>>,
 ) -> Result<(), Error> {
     let name = dm.name_any();
     let namespace = dm.namespace().unwrap_or_default();
     let api: Api<DependencyManager> = Api::namespaced(client.clone(), &namespace);
     
+    let dependencies = match phase {
+        Phase::Ready => dependencies,
+        _ => with_previous(dm, dependencies),
+    };
     let mut status = DependencyManagerStatus {
         phase,
-        dependencies: None,
-        gitops_status: None,
+        dependencies,
+        // Kept until the next read of the GitOps objects replaces it
+        gitops_status: dm.spec.gitops
+            .as_ref()
+            .and(dm.status.as_ref())
+            .and_then(|status| status.gitops_status.clone()),
         cicd_status: None,
         last_reconciled: Some(chrono::Utc::now().to_rfc3339()),
         conditions: None,
@@ -186,12 +516,30 @@ async fn update_status(
         }]);
     }
     
-    let patch = serde_json::json!({
+    let mut patch = serde_json::json!({
         "status": status
     });
     
+    // Pipeline runs are recorded as they are submitted; leave them in place
+    if let Some(status) = patch["status"].as_object_mut().filter(|_| dm.spec.cicd.is_some()) {
+        status.remove("cicd_status");
+    }
+    
     api.patch_status(&name, &PatchParams::default(), &Patch::Merge(&patch)).await
-        .map_err(|e| Error::KubeError(e))?;
+        .map_err(Error::KubeError)?;
+    
+    Ok(())
+}
+
+async fn update_gitops_status(client: &Client, dm: &DependencyManager, gitops_status: GitOpsStatus) -> Result<(), Error> {
+    let api: Api<DependencyManager> = Api::namespaced(client.clone(), &dm.namespace().unwrap_or_default());
+    
+    let patch = serde_json::json!({
+        "status": { "gitops_status": gitops_status }
+    });
+    
+    api.patch_status(&dm.name_any(), &PatchParams::default(), &Patch::Merge(&patch)).await
+        .map_err(Error::KubeError)?;
     
     Ok(())
 }
@@ -12,6 +12,10 @@ use std::collections::HashMap;
     namespaced
 )]
 #[kube(status = "DependencyManagerStatus")]
+#[kube(printcolumn = r#"{"name":"Phase","type":"string","jsonPath":".status.phase"}"#)]
+#[kube(printcolumn = r#"{"name":"Sync","type":"string","jsonPath":".status.gitops_status.sync_status"}"#)]
+#[kube(printcolumn = r#"{"name":"Revision","type":"string","jsonPath":".status.gitops_status.revision"}"#)]
+#[kube(printcolumn = r#"{"name":"Age","type":"date","jsonPath":".metadata.creationTimestamp"}"#)]
 pub struct DependencyManagerSpec {
     /// Dependencies to install and manage
     pub dependencies: Vec<Dependency>,
@@ -21,6 +25,13 @@ pub struct DependencyManagerSpec {
     
     /// CI/CD pipeline configuration
     pub cicd: Option<CiCdConfig>,
+    
+    /// ServiceAccount in this namespace to impersonate for every install,
+    /// limiting the DependencyManager to what that account's RBAC allows
+    pub service_account_name: Option<String>,
+    
+    /// Periodic checks for newer chart versions; disabled when unset
+    pub updates: Option<UpdatePolicy>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
@@ -35,7 +46,8 @@ pub struct Dependency {
     /// Repository or source information
     pub source: DependencySource,
     
-    /// Version or chart version
+    /// Version or chart version; charts also accept a semver constraint such
+    /// as `~1.14` or `>=0.9 <1.0`, resolved against the repository index
     pub version: Option<String>,
     
     /// Target namespace
@@ -44,6 +56,9 @@ pub struct Dependency {
     /// Values for Helm charts
     pub values: Option<HashMap<String, serde_json::Value>>,
     
+    /// ConfigMaps or Secrets whose values are merged underneath `values`
+    pub values_from: Option<Vec<ValuesReference>>,
+    
     /// Dependencies that must be installed before this one
     pub depends_on: Option<Vec<String>>,
     
@@ -51,7 +66,34 @@ pub struct Dependency {
     pub enabled: bool,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct UpdatePolicy {
+    /// How often chart repositories are checked, e.g. `12h`; defaults to `24h`
+    pub interval: Option<String>,
+    
+    /// Prefix of the branches proposing updates in `git-write` mode, defaults to `zerg/update`
+    pub branch_prefix: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct ValuesReference {
+    /// Kind of the referenced object (ConfigMap, Secret)
+    pub kind: ValuesReferenceKind,
+    
+    /// Name of the object in the DependencyManager namespace
+    pub name: String,
+    
+    /// Data key holding the values YAML, defaults to `values.yaml`
+    pub values_key: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, JsonSchema)]
+pub enum ValuesReferenceKind {
+    ConfigMap,
+    Secret,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum DependencyType {
     Helm,
@@ -62,7 +104,7 @@ pub enum DependencyType {
 
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct DependencySource {
-    /// Repository URL
+    /// Repository URL; `oci://` URLs are OCI registries holding the chart
     pub repo: String,
     
     /// Chart name (for Helm)
@@ -74,6 +116,13 @@ pub struct DependencySource {
     /// Git reference (branch, tag, commit)
     #[serde(rename = "ref")]
     pub ref_: Option<String>,
+    
+    /// `kubernetes.io/dockerconfigjson` Secret with credentials for an `oci://` registry
+    pub secret_ref: Option<String>,
+    
+    /// Manifest digest an `oci://` chart is pinned to; resolved from `version` and
+    /// recorded in status when unset
+    pub digest: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
@@ -92,6 +141,413 @@ pub struct GitOpsConfig {
     
     /// Sync policy
     pub sync_policy: Option<SyncPolicy>,
+    
+    /// Additional repositories synced alongside the primary one
+    pub sources: Option<Vec<GitOpsSource>>,
+    
+    /// Flux-specific settings
+    pub flux: Option<FluxConfig>,
+    
+    /// ArgoCD-specific settings
+    pub argocd: Option<ArgoCdConfig>,
+    
+    /// Installation of the GitOps controllers themselves
+    pub install: Option<GitOpsInstall>,
+    
+    /// How dependencies reach the cluster, defaults to `apply`
+    pub mode: Option<GitOpsMode>,
+    
+    /// Commit settings for `git-write` mode
+    pub git_write: Option<GitWriteConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum GitOpsMode {
+    /// The operator installs dependencies itself
+    Apply,
+    /// The operator commits rendered dependency manifests to `path` and leaves applying them to Flux/Argo
+    GitWrite,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct GitWriteConfig {
+    /// Commit author name
+    pub author_name: Option<String>,
+    
+    /// Commit author email
+    pub author_email: Option<String>,
+    
+    /// Push to this branch, based on `branch`, for review instead of committing to `branch` directly
+    pub pull_request_branch: Option<String>,
+    
+    /// Secret with `username` and `password` keys for HTTPS pushes
+    pub secret_ref: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct GitOpsInstall {
+    /// Install the controllers, defaults to true; disable when they are managed elsewhere
+    pub enabled: Option<bool>,
+    
+    /// Version of the vendored manifests, defaults to the provider's GitOps template
+    pub version: Option<String>,
+    
+    /// Install the high-availability variant (ArgoCD only)
+    pub ha: Option<bool>,
+    
+    /// Namespace of the controllers, defaults to `argocd` or `flux-system`
+    pub namespace: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct GitOpsSource {
+    /// Source name, unique within the DependencyManager and used in generated object names
+    pub name: String,
+    
+    /// Git repository
+    pub repository: String,
+    
+    /// Branch to use
+    pub branch: String,
+    
+    /// Path within repository
+    pub path: String,
+    
+    /// Sync policy, defaults to the GitOpsConfig sync policy
+    pub sync_policy: Option<SyncPolicy>,
+    
+    /// Flux settings for this source; interval, timeout, secret_ref and
+    /// decryption default to those of `flux.sync`
+    pub flux: Option<FluxSyncOptions>,
+    
+    /// ArgoCD settings for this source
+    pub argocd: Option<ArgoCdSourceOptions>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct ArgoCdConfig {
+    /// Existing AppProject to use, defaults to `default` (or the generated `app_project`)
+    pub project: Option<String>,
+    
+    /// AppProject generated for this DependencyManager and used by its Applications
+    pub app_project: Option<ArgoCdAppProject>,
+    
+    /// Destination cluster, defaults to the in-cluster API server
+    pub destination: Option<ArgoCdDestination>,
+    
+    /// Combine the primary repository and all `sources` into one multi-source Application
+    pub multi_source: Option<bool>,
+    
+    /// Settings for the primary repository's source
+    pub source: Option<ArgoCdSourceOptions>,
+    
+    /// Sync options, e.g. `CreateNamespace=true`, `ServerSideApply=true`
+    pub sync_options: Option<Vec<String>>,
+    
+    /// Retry policy for failed syncs
+    pub retry: Option<ArgoCdRetry>,
+    
+    /// ApplicationSets generating Applications from lists, git directories or clusters
+    pub application_sets: Option<Vec<ArgoCdApplicationSet>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct ArgoCdAppProject {
+    /// Project description
+    pub description: Option<String>,
+    
+    /// Repositories Applications may pull from, defaults to the configured repositories
+    pub source_repos: Option<Vec<String>>,
+    
+    /// Destinations Applications may deploy to, defaults to the configured destination
+    pub destinations: Option<Vec<ArgoCdDestination>>,
+    
+    /// Cluster-scoped kinds Applications may manage
+    pub cluster_resource_whitelist: Option<Vec<ArgoCdGroupKind>>,
+    
+    /// Namespaced kinds Applications may not manage
+    pub namespace_resource_blacklist: Option<Vec<ArgoCdGroupKind>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct ArgoCdGroupKind {
+    /// API group, empty for the core group
+    pub group: String,
+    
+    /// Kind
+    pub kind: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct ArgoCdDestination {
+    /// API server URL of the destination cluster
+    pub server: Option<String>,
+    
+    /// Name of a cluster registered in ArgoCD, alternative to `server`
+    pub name: Option<String>,
+    
+    /// Target namespace, defaults to the DependencyManager namespace
+    pub namespace: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct ArgoCdSourceOptions {
+    /// Helm chart name, when the repository is a Helm repository rather than git
+    pub chart: Option<String>,
+    
+    /// Name other sources of a multi-source Application use to reference this one (`$name/...`)
+    pub ref_name: Option<String>,
+    
+    /// Sync wave of the generated Application
+    pub sync_wave: Option<i32>,
+    
+    /// Target namespace, overriding the destination namespace
+    pub namespace: Option<String>,
+    
+    /// Helm rendering options
+    pub helm: Option<ArgoCdHelmOptions>,
+    
+    /// Kustomize rendering options
+    pub kustomize: Option<ArgoCdKustomizeOptions>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct ArgoCdHelmOptions {
+    /// Helm release name
+    pub release_name: Option<String>,
+    
+    /// Values files, relative to the source or `$ref/...` paths
+    pub value_files: Option<Vec<String>>,
+    
+    /// Inline values
+    pub values: Option<HashMap<String, serde_json::Value>>,
+    
+    /// Individual `--set` parameters
+    pub parameters: Option<HashMap<String, String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct ArgoCdKustomizeOptions {
+    /// Prefix added to resource names
+    pub name_prefix: Option<String>,
+    
+    /// Suffix added to resource names
+    pub name_suffix: Option<String>,
+    
+    /// Image overrides, e.g. `nginx=nginx:1.25`
+    pub images: Option<Vec<String>>,
+    
+    /// Labels added to all resources
+    pub common_labels: Option<HashMap<String, String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct ArgoCdRetry {
+    /// Maximum number of attempts, negative for unlimited
+    pub limit: Option<i64>,
+    
+    /// Initial backoff duration, e.g. `5s`
+    pub backoff_duration: Option<String>,
+    
+    /// Backoff multiplier
+    pub backoff_factor: Option<i64>,
+    
+    /// Maximum backoff duration, e.g. `3m`
+    pub backoff_max_duration: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct ArgoCdApplicationSet {
+    /// ApplicationSet name, unique within the DependencyManager
+    pub name: String,
+    
+    /// Generators whose parameters fill the template
+    pub generators: Vec<ArgoCdGenerator>,
+    
+    /// Application template; `{{param}}` placeholders are filled by the generators
+    pub template: ArgoCdApplicationTemplate,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct ArgoCdGenerator {
+    /// Fixed list of parameter sets
+    pub list: Option<Vec<HashMap<String, String>>>,
+    
+    /// One parameter set per matching directory of a git repository
+    pub git: Option<ArgoCdGitGenerator>,
+    
+    /// One parameter set per cluster registered in ArgoCD
+    pub clusters: Option<ArgoCdClusterGenerator>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct ArgoCdGitGenerator {
+    /// Git repository
+    pub repository: String,
+    
+    /// Revision to scan
+    pub revision: String,
+    
+    /// Directory globs to include
+    pub directories: Vec<String>,
+    
+    /// Directory globs to exclude
+    pub exclude: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct ArgoCdClusterGenerator {
+    /// Only clusters whose secrets carry these labels
+    pub match_labels: Option<HashMap<String, String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct ArgoCdApplicationTemplate {
+    /// Application name, e.g. `{{path.basename}}`
+    pub name: String,
+    
+    /// Git repository
+    pub repository: String,
+    
+    /// Revision
+    pub revision: String,
+    
+    /// Path within the repository
+    pub path: String,
+    
+    /// Destination, e.g. `server: '{{server}}'`
+    pub destination: ArgoCdDestination,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct FluxConfig {
+    /// Flux API versions to generate objects for, defaults to v1
+    pub api_version: Option<FluxApiVersion>,
+    
+    /// Run `flux bootstrap` against the primary repository, defaults to true.
+    /// When false Flux is only installed and the objects below are created.
+    pub bootstrap: Option<bool>,
+    
+    /// Settings for the primary repository, and defaults for `sources`
+    pub sync: Option<FluxSyncOptions>,
+    
+    /// Helm charts delivered through HelmRepository/HelmRelease objects
+    pub helm_releases: Option<Vec<FluxHelmRelease>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FluxApiVersion {
+    /// GitRepository/Kustomization v1, HelmRelease v2 (Flux 2.3+)
+    V1,
+    /// GitRepository/Kustomization v1beta2, HelmRelease v2beta1
+    V1beta2,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct FluxSyncOptions {
+    /// Reconcile interval, e.g. `5m`
+    pub interval: Option<String>,
+    
+    /// Timeout for fetching the source and applying the Kustomization
+    pub timeout: Option<String>,
+    
+    /// Secret holding git credentials for the GitRepository
+    pub secret_ref: Option<String>,
+    
+    /// Names of sources whose Kustomizations must be ready first (`primary` for the primary repository)
+    pub depends_on: Option<Vec<String>>,
+    
+    /// Wait for all applied resources to become ready
+    pub wait: Option<bool>,
+    
+    /// Resources whose readiness gates the Kustomization
+    pub health_checks: Option<Vec<FluxHealthCheck>>,
+    
+    /// Variable substitution applied after kustomize build
+    pub post_build: Option<FluxPostBuild>,
+    
+    /// Decryption of SOPS-encrypted manifests
+    pub decryption: Option<FluxDecryption>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct FluxHealthCheck {
+    /// API version of the resource, e.g. `apps/v1`
+    pub api_version: String,
+    
+    /// Kind of the resource
+    pub kind: String,
+    
+    /// Name of the resource
+    pub name: String,
+    
+    /// Namespace of the resource
+    pub namespace: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct FluxPostBuild {
+    /// Variables substituted into the manifests
+    pub substitute: Option<HashMap<String, String>>,
+    
+    /// ConfigMaps or Secrets holding variables to substitute
+    pub substitute_from: Option<Vec<FluxSubstituteReference>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct FluxSubstituteReference {
+    /// Kind of the referenced object (ConfigMap, Secret)
+    pub kind: ValuesReferenceKind,
+    
+    /// Name of the object
+    pub name: String,
+    
+    /// Do not fail if the object is missing
+    pub optional: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct FluxDecryption {
+    /// Decryption provider, only `sops` is supported by Flux
+    pub provider: String,
+    
+    /// Secret holding the decryption keys
+    pub secret_ref: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct FluxHelmRelease {
+    /// Release name, unique within the DependencyManager
+    pub name: String,
+    
+    /// Helm repository URL (`oci://` URLs create an OCI HelmRepository)
+    pub repository: String,
+    
+    /// Chart name
+    pub chart: String,
+    
+    /// Chart version or semver range
+    pub version: Option<String>,
+    
+    /// Namespace to install the release into
+    pub target_namespace: Option<String>,
+    
+    /// Values for the chart
+    pub values: Option<HashMap<String, serde_json::Value>>,
+    
+    /// Reconcile interval, defaults to that of `flux.sync`
+    pub interval: Option<String>,
+    
+    /// Timeout for Helm operations
+    pub timeout: Option<String>,
+    
+    /// Secret holding repository credentials
+    pub secret_ref: Option<String>,
+    
+    /// Names of releases that must be ready first
+    pub depends_on: Option<Vec<String>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
@@ -115,18 +571,47 @@ pub struct SyncPolicy {
 
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct CiCdConfig {
-    /// CI/CD provider (tekton, argo-workflows)
+    /// CI/CD provider (tekton, argo-workflows, github-actions, gitlab-ci)
     pub provider: CiCdProvider,
     
     /// Pipeline definitions
     pub pipelines: Vec<Pipeline>,
+    
+    /// How many runs are kept, in status and in the cluster
+    pub retention: Option<RunRetention>,
+    
+    /// Commit the rendered workflows to the trigger repositories (GitHub Actions and GitLab CI only)
+    pub git_write: Option<CiCdGitWrite>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct CiCdGitWrite {
+    /// Branch to commit to, defaults to the first trigger branch without wildcards
+    pub branch: Option<String>,
+    
+    /// Author, pull-request branch and credentials of the commits
+    #[serde(flatten)]
+    pub commit: GitWriteConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
+pub struct RunRetention {
+    /// Runs kept per pipeline, defaults to 5
+    pub history_limit: Option<u32>,
+    
+    /// Delete finished PipelineRuns/Workflows beyond `history_limit`, defaults to true
+    pub prune: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 #[serde(rename_all = "kebab-case")]
 pub enum CiCdProvider {
     Tekton,
     ArgoWorkflows,
+    /// Workflows under `.github/workflows` of the trigger repository
+    GithubActions,
+    /// `.gitlab-ci.yml` of the trigger repository
+    GitlabCi,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
@@ -139,6 +624,81 @@ pub struct Pipeline {
     
     /// Steps to execute
     pub steps: Vec<PipelineStep>,
+    
+    /// Steps run once `steps` finish, whether they succeeded or not; they run in parallel
+    pub finally: Option<Vec<PipelineStep>>,
+    
+    /// Volumes steps mount, defaults to a `shared-data` volume claim mounted by every step
+    pub workspaces: Option<Vec<PipelineWorkspace>>,
+    
+    /// Params on top of `git-repo-url`, `git-revision` and `git-branch`, referenced as `$(params.<name>)`
+    pub params: Option<Vec<PipelineParam>>,
+    
+    /// Settings for every step, except `timeout`, which bounds the whole run
+    #[serde(flatten)]
+    pub execution: ExecutionSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct PipelineParam {
+    /// Param name
+    pub name: String,
+    
+    /// Type values are checked against
+    #[serde(rename = "type", default)]
+    pub type_: ParamType,
+    
+    /// Param description
+    pub description: Option<String>,
+    
+    /// Default value; params without one must be set on every run, so only manual pipelines may have them
+    pub default: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamType {
+    #[default]
+    String,
+    Integer,
+    Boolean,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct PipelineWorkspace {
+    /// Workspace name, referenced from step `mounts`
+    pub name: String,
+    
+    /// Kind of volume backing the workspace
+    #[serde(rename = "type")]
+    pub type_: WorkspaceType,
+    
+    /// Requested storage for `volume-claim` and `cache`, defaults to 1Gi
+    pub size: Option<String>,
+    
+    /// Storage class for `volume-claim` and `cache`
+    pub storage_class: Option<String>,
+    
+    /// Name of the ConfigMap or Secret for `config-map` and `secret`
+    pub source: Option<String>,
+    
+    /// Cache key for `cache`; changing it starts from an empty cache
+    pub key: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum WorkspaceType {
+    /// Volume claimed for each run and shared by its steps
+    VolumeClaim,
+    /// Scratch directory private to each step
+    EmptyDir,
+    /// ConfigMap mounted read-only
+    ConfigMap,
+    /// Secret mounted read-only
+    Secret,
+    /// Volume claim kept across runs, named after a hash of `key`
+    Cache,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
@@ -149,7 +709,7 @@ pub struct PipelineTrigger {
     /// Schedule trigger
     pub schedule: Option<String>,
     
-    /// Manual trigger
+    /// Allow runs requested through the `zerg.io/run-pipeline` annotation or the operator's HTTP API
     pub manual: bool,
 }
 
@@ -163,6 +723,22 @@ pub struct GitTrigger {
     
     /// Event types (push, pull_request)
     pub events: Vec<String>,
+    
+    /// Git host sending the webhooks, inferred from `repository` when unset
+    pub provider: Option<GitProvider>,
+    
+    /// Secret in this namespace holding the webhook secret under `token`
+    pub secret_ref: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GitProvider {
+    GitHub,
+    GitLab,
+    Gitea,
+    /// Any host posting `ref` and `head_commit` like GitHub push events, without signature validation
+    Generic,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
@@ -170,17 +746,171 @@ pub struct PipelineStep {
     /// Step name
     pub name: String,
     
-    /// Docker image to use
+    /// Docker image to use, defaults to the template's image
+    #[serde(default)]
     pub image: String,
     
-    /// Commands to run
+    /// Commands to run; not allowed with `template`
+    #[serde(default)]
     pub commands: Vec<String>,
     
+    /// Step template from `cicd_templates` or the built-in library providing the image and commands
+    pub template: Option<String>,
+    
+    /// Params passed to `template`
+    pub params: Option<HashMap<String, String>>,
+    
     /// Environment variables
     pub env: Option<HashMap<String, String>>,
     
     /// Working directory
     pub working_dir: Option<String>,
+    
+    /// Steps this step runs after, defaults to the previous step; empty to start with the pipeline
+    pub depends_on: Option<Vec<String>>,
+    
+    /// Conditions that must all hold for the step to run
+    pub when: Option<Vec<StepCondition>>,
+    
+    /// Workspaces mounted into the step, defaults to every workspace
+    pub mounts: Option<Vec<StepMount>>,
+    
+    /// Environment variables taken from Secret keys
+    pub secret_env: Option<Vec<SecretEnvVar>>,
+    
+    /// Results the step writes to `$RESULTS_DIR/<name>`, referenced by later steps as `$(steps.<step>.results.<name>)`
+    pub results: Option<Vec<StepResult>>,
+    
+    /// Files or directories the step passes on to later steps
+    pub artifacts: Option<Vec<StepArtifact>>,
+    
+    /// Artifacts of earlier steps placed into this step
+    pub input_artifacts: Option<Vec<InputArtifact>>,
+    
+    /// Resources, retries and scheduling, overriding those of the pipeline
+    #[serde(flatten)]
+    pub execution: ExecutionSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct StepResult {
+    /// Result name
+    pub name: String,
+    
+    /// Result description
+    pub description: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct StepArtifact {
+    /// Artifact name
+    pub name: String,
+    
+    /// Path the step leaves the artifact at
+    pub path: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct InputArtifact {
+    /// Step producing the artifact
+    pub step: String,
+    
+    /// Name of the artifact in that step
+    pub name: String,
+    
+    /// Path the artifact is placed at
+    pub path: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, JsonSchema)]
+pub struct ExecutionSettings {
+    /// CPU and memory requests and limits
+    pub resources: Option<StepResources>,
+    
+    /// Times a failed step is retried
+    pub retries: Option<u32>,
+    
+    /// Time allowed, e.g. `30m` or `1h30m`
+    pub timeout: Option<String>,
+    
+    /// Labels of the nodes the step's pod may run on
+    pub node_selector: Option<HashMap<String, String>>,
+    
+    /// Node taints the step's pod tolerates
+    pub tolerations: Option<Vec<StepToleration>>,
+    
+    /// ServiceAccount the step's pod runs as
+    pub service_account: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, JsonSchema)]
+pub struct StepResources {
+    /// Requested resources, e.g. `cpu: 500m`
+    pub requests: Option<HashMap<String, String>>,
+    
+    /// Resource limits, e.g. `memory: 2Gi`
+    pub limits: Option<HashMap<String, String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub struct StepToleration {
+    /// Taint key, empty with `Exists` to tolerate every taint
+    pub key: Option<String>,
+    
+    /// `Equal` (the default) or `Exists`
+    pub operator: Option<String>,
+    
+    /// Taint value for `Equal`
+    pub value: Option<String>,
+    
+    /// `NoSchedule`, `PreferNoSchedule` or `NoExecute`; empty for every effect
+    pub effect: Option<String>,
+    
+    /// Seconds a `NoExecute` taint is tolerated for
+    pub toleration_seconds: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct StepMount {
+    /// Workspace to mount
+    pub workspace: String,
+    
+    /// Mount path, defaults to `/workspace/<workspace>`
+    pub path: Option<String>,
+    
+    /// Mount read-only
+    pub read_only: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct SecretEnvVar {
+    /// Environment variable name
+    pub name: String,
+    
+    /// Secret in the pipeline's namespace
+    pub secret: String,
+    
+    /// Key within the Secret
+    pub key: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct StepCondition {
+    /// Pipeline parameter to test
+    pub param: String,
+    
+    /// How `param` is compared with `values`
+    pub operator: ConditionOperator,
+    
+    /// Values to compare against
+    pub values: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ConditionOperator {
+    In,
+    NotIn,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
@@ -225,6 +955,24 @@ pub struct DependencyStatus {
     /// Installed version
     pub version: Option<String>,
     
+    /// Constraint `version` was resolved from and stays pinned to until an update
+    pub constraint: Option<String>,
+    
+    /// Newest version in the chart repository, when newer than `version`
+    pub latest_version: Option<String>,
+    
+    /// Newer version within `constraint` proposed on `update_branch` in `git-write` mode
+    pub proposed_version: Option<String>,
+    
+    /// Branch bumping the dependency to `proposed_version`
+    pub update_branch: Option<String>,
+    
+    /// Last check of the chart repository for newer versions
+    pub last_checked: Option<String>,
+    
+    /// Manifest digest `version` was pulled by, for charts from OCI registries
+    pub digest: Option<String>,
+    
     /// Last update time
     pub last_updated: Option<String>,
     
@@ -238,6 +986,8 @@ pub enum DependencyInstallStatus {
     Pending,
     Installing,
     Installed,
+    /// Rendered and committed in `git-write` mode
+    Committed,
     Failed,
     Updating,
     Uninstalling,
@@ -248,11 +998,41 @@ pub struct GitOpsStatus {
     /// Provider status
     pub provider: GitOpsProvider,
     
-    /// Sync status
+    /// Sync status across all sources (Synced, OutOfSync, Progressing, Failed)
     pub sync_status: String,
     
     /// Last sync time
     pub last_sync: Option<String>,
+    
+    /// Revision last applied from the primary repository
+    pub revision: Option<String>,
+    
+    /// Commit last pushed in `git-write` mode
+    pub last_commit: Option<String>,
+    
+    /// Status of each generated Flux Kustomization or Argo Application
+    pub sources: Option<Vec<GitOpsSourceStatus>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+pub struct GitOpsSourceStatus {
+    /// Name of the Kustomization or Application
+    pub name: String,
+    
+    /// Sync status (Synced, OutOfSync, Progressing, Failed, Unknown)
+    pub sync_status: String,
+    
+    /// Argo health status
+    pub health: Option<String>,
+    
+    /// Last applied revision
+    pub revision: Option<String>,
+    
+    /// Last sync time
+    pub last_sync: Option<String>,
+    
+    /// Message explaining a failed or pending sync
+    pub message: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
@@ -262,6 +1042,9 @@ pub struct CiCdStatus {
     
     /// Pipeline statuses
     pub pipelines: Vec<PipelineStatus>,
+    
+    /// Last `zerg.io/run-pipeline` annotation value a run was started for
+    pub run_request: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
@@ -274,6 +1057,58 @@ pub struct PipelineStatus {
     
     /// Last run time
     pub last_run: Option<String>,
+    
+    /// Name of the PipelineRun or Workflow last started
+    pub last_run_name: Option<String>,
+    
+    /// Commit the last run was started for
+    pub last_revision: Option<String>,
+    
+    /// Most recent runs, newest first
+    pub runs: Option<Vec<PipelineRunRecord>>,
+    
+    /// Features of the pipeline the provider cannot express, and how they were rendered instead
+    pub compatibility: Option<Vec<String>>,
+    
+    /// Commit of the trigger repository holding the rendered workflow, in git-write mode
+    pub last_commit: Option<String>,
+    
+    /// Why the last run request for this pipeline was rejected
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
+pub struct PipelineRunRecord {
+    /// Name of the PipelineRun or Workflow
+    pub name: String,
+    
+    /// Run phase
+    pub phase: RunPhase,
+    
+    /// Start time
+    pub started_at: Option<String>,
+    
+    /// Completion time
+    pub finished_at: Option<String>,
+    
+    /// Seconds from start to completion
+    pub duration_seconds: Option<i64>,
+    
+    /// Commit the run was started for
+    pub revision: Option<String>,
+    
+    /// Step that failed the run
+    pub failed_step: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, JsonSchema)]
+#[serde(rename_all = "PascalCase")]
+pub enum RunPhase {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
@@ -293,4 +1128,21 @@ pub struct Condition {
     
     /// Human readable message
     pub message: Option<String>,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    
+    /// DependencyManager `name` in `namespace` with an empty spec
+    pub fn owner(namespace: &str, name: &str) -> DependencyManager {
+        let mut dm = DependencyManager::new(name, DependencyManagerSpec {
+            dependencies: Vec::new(),
+            gitops: None,
+            cicd: None,
+            service_account_name: None,
+            updates: None,
+        });
+        dm.metadata.namespace = Some(namespace.to_string());
+        dm
+    }
+}
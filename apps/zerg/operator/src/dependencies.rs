@@ -1,18 +1,173 @@
 use anyhow::Result;
-use kube::Client;
+use k8s_openapi::api::core::v1::{ConfigMap, Secret};
+use kube::{Api, Client};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::{info, instrument, warn};
 
-use crate::crd::{Dependency, DependencyStatus, DependencyInstallStatus, DependencyType};
+use crate::crd::{Dependency, DependencyStatus, DependencyInstallStatus, DependencyType, ValuesReferenceKind};
 use crate::error::Error;
+use crate::impersonation::{self, Impersonation};
+use crate::oci::{self, ChartReference, Registry};
+use crate::resources;
+
+/// Data key read from `values_from` references when none is given
+const DEFAULT_VALUES_KEY: &str = "values.yaml";
+
+/// Built-in operators as `(name, repository, chart, namespace)`, installed
+/// from fixed charts regardless of `source` and `namespace`
+pub const BUILTIN_OPERATORS: &[(&str, &str, &str, &str)] = &[
+    ("external-secrets", "https://charts.external-secrets.io", "external-secrets", "external-secrets-system"),
+    ("crossplane", "https://charts.crossplane.io/stable", "crossplane", "crossplane-system"),
+    ("loki", "https://grafana.github.io/helm-charts", "loki-stack", "loki-system"),
+];
+
+/// Fixed chart of a built-in operator dependency, `None` for anything else
+pub fn builtin_operator(dependency: &Dependency) -> Option<(&'static str, &'static str, &'static str)> {
+    if dependency.type_ != DependencyType::Operator {
+        return None;
+    }
+    
+    BUILTIN_OPERATORS
+        .iter()
+        .find(|(name, ..)| *name == dependency.name)
+        .map(|(_, repo, chart, namespace)| (*repo, *chart, *namespace))
+}
+
+/// Repository and chart of a dependency installed from a chart of its
+/// choosing, `None` for built-in operators and anything that is not a chart
+pub fn chart_source(dependency: &Dependency) -> Option<(&str, &str)> {
+    if builtin_operator(dependency).is_some() {
+        return None;
+    }
+    
+    match (&dependency.type_, &dependency.source.chart) {
+        (DependencyType::Helm | DependencyType::Operator, Some(chart)) => Some((&dependency.source.repo, chart)),
+        _ => None,
+    }
+}
+
+/// Repository and namespace a dependency is actually installed from and into.
+///
+/// Built-in operators ignore `source` and `namespace` and use fixed charts, so
+/// they are resolved here the same way `install_operator` does.
+pub fn install_target(dependency: &Dependency, namespace: &str) -> (String, String) {
+    if let Some((repo, _, target_namespace)) = builtin_operator(dependency) {
+        return (repo.to_string(), target_namespace.to_string());
+    }
+    
+    let target_namespace = dependency.namespace.as_deref().unwrap_or(namespace);
+    (dependency.source.repo.clone(), target_namespace.to_string())
+}
+
+/// Version-pinned manifests vendored into the operator image, installed
+/// without reaching out to the internet
+#[derive(Debug, Clone)]
+pub struct Bundle {
+    /// Name reported in `status.dependencies`
+    pub name: String,
+    
+    pub version: String,
+    
+    /// Vendored manifest file
+    pub path: PathBuf,
+    
+    /// Namespace to install into
+    pub namespace: String,
+    
+    /// Namespace the upstream manifests are written for
+    pub source_namespace: &'static str,
+}
+
+/// Moves `manifests` written for namespace `from` into `to`: the Namespace
+/// object itself, namespaced objects, RoleBinding subjects referring to it, and
+/// in-cluster DNS names such as `notification-controller.flux-system.svc` in
+/// arguments, environment variables and config data.
+/// A Namespace object is added when the manifests do not contain one.
+pub fn relocate(manifests: Vec<serde_json::Value>, from: &str, to: &str) -> Vec<serde_json::Value> {
+    let has_namespace = manifests
+        .iter()
+        .any(|m| m["kind"] == "Namespace" && m["metadata"]["name"] == from);
+    
+    let mut relocated = Vec::with_capacity(manifests.len() + 1);
+    if !has_namespace {
+        relocated.push(serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "Namespace",
+            "metadata": { "name": to },
+        }));
+    }
+    
+    for mut manifest in manifests {
+        if manifest["kind"] == "Namespace" && manifest["metadata"]["name"] == from {
+            manifest["metadata"]["name"] = serde_json::json!(to);
+        }
+        if manifest["metadata"]["namespace"] == from {
+            manifest["metadata"]["namespace"] = serde_json::json!(to);
+        }
+        if let Some(subjects) = manifest["subjects"].as_array_mut() {
+            for subject in subjects.iter_mut().filter(|s| s["namespace"] == from) {
+                subject["namespace"] = serde_json::json!(to);
+            }
+        }
+        if from != to {
+            rewrite_service_names(&mut manifest, &format!(".{}.svc", from), &format!(".{}.svc", to));
+        }
+        relocated.push(manifest);
+    }
+    
+    relocated
+}
+
+/// Replaces `from` with `to` in every string of `value`
+fn rewrite_service_names(value: &mut serde_json::Value, from: &str, to: &str) {
+    match value {
+        serde_json::Value::String(s) if s.contains(from) => *s = s.replace(from, to),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(|item| rewrite_service_names(item, from, to)),
+        serde_json::Value::Object(fields) => fields.values_mut().for_each(|field| rewrite_service_names(field, from, to)),
+        _ => {}
+    }
+}
+
+/// Per-call scratch directory for files handed to CLIs, removed when dropped
+struct Workdir(PathBuf);
+
+/// Tells apart work directories created within the same nanosecond
+static WORKDIRS: AtomicU64 = AtomicU64::new(0);
+
+impl Workdir {
+    fn new(purpose: &str) -> Result<Self, Error> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or_default();
+        let sequence = WORKDIRS.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("zerg-{}-{}-{}-{}", purpose, std::process::id(), nanos, sequence));
+        std::fs::create_dir(&dir)
+            .map_err(|e| Error::IoError(format!("Failed to create {}: {}", dir.display(), e)))?;
+        Ok(Self(dir))
+    }
+}
+
+impl Drop for Workdir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
 
 pub struct DependencyInstaller {
     client: Client,
+    impersonation: Option<Impersonation>,
 }
 
 impl DependencyInstaller {
-    pub fn new(client: Client) -> Self {
-        Self { client }
+    pub fn new(client: Client, impersonation: Option<Impersonation>) -> Self {
+        Self { client, impersonation }
+    }
+    
+    /// Cluster CLI command run as the DependencyManager's ServiceAccount, if any
+    fn command(&self, program: &str) -> Command {
+        impersonation::command(program, self.impersonation.as_ref())
     }
     
     #[instrument(skip(self))]
@@ -30,22 +185,91 @@ impl DependencyInstaller {
             DependencyType::Operator => self.install_operator(dependency, namespace).await,
         };
         
+        Ok(self.install_status(&dependency.name, result))
+    }
+    
+    /// Status reported for an install of `name` that finished with `result`
+    fn install_status(&self, name: &str, result: Result<String, Error>) -> DependencyStatus {
         match result {
-            Ok(version) => Ok(DependencyStatus {
-                name: dependency.name.clone(),
+            Ok(version) => DependencyStatus {
+                name: name.to_string(),
                 status: DependencyInstallStatus::Installed,
                 version: Some(version),
+                constraint: None,
+                latest_version: None,
+                proposed_version: None,
+                update_branch: None,
+                last_checked: None,
+                digest: None,
                 last_updated: Some(chrono::Utc::now().to_rfc3339()),
                 error: None,
-            }),
-            Err(e) => Ok(DependencyStatus {
-                name: dependency.name.clone(),
+            },
+            Err(e) => DependencyStatus {
+                name: name.to_string(),
                 status: DependencyInstallStatus::Failed,
                 version: None,
+                constraint: None,
+                latest_version: None,
+                proposed_version: None,
+                update_branch: None,
+                last_checked: None,
+                digest: None,
                 last_updated: Some(chrono::Utc::now().to_rfc3339()),
-                error: Some(e.to_string()),
-            }),
+                error: Some(match &self.impersonation {
+                    Some(identity) if e.is_forbidden() => {
+                        format!("Permission denied for {}: {}", identity.user, e)
+                    }
+                    _ => e.to_string(),
+                }),
+            },
+        }
+    }
+    
+    /// Installs a vendored bundle, reported like any other dependency
+    #[instrument(skip(self))]
+    pub async fn install_bundle(&self, bundle: &Bundle) -> DependencyStatus {
+        info!("Installing {} {} into {}", bundle.name, bundle.version, bundle.namespace);
+        
+        let result = self.apply_bundle(bundle).await.map(|()| bundle.version.clone());
+        self.install_status(&bundle.name, result)
+    }
+    
+    async fn apply_bundle(&self, bundle: &Bundle) -> Result<(), Error> {
+        let content = std::fs::read_to_string(&bundle.path).map_err(|e| Error::DependencyError(format!(
+            "{} {} is not vendored at {}: {}", bundle.name, bundle.version, bundle.path.display(), e
+        )))?;
+        
+        let manifests = relocate(resources::parse_yaml(&content)?, bundle.source_namespace, &bundle.namespace);
+        let documents = manifests
+            .iter()
+            .map(serde_yaml::to_string)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::SerializationError(format!("Failed to serialize {}: {}", bundle.name, e)))?;
+        
+        let workdir = Workdir::new("bundle")?;
+        let manifest_file = workdir.0.join(format!("{}-{}.yaml", bundle.name, bundle.version));
+        std::fs::write(&manifest_file, documents.join("---\n"))
+            .map_err(|e| Error::IoError(format!("Failed to write manifest file: {}", e)))?;
+        
+        // Server-side apply, since the CRDs of both controllers exceed the
+        // client-side last-applied annotation limit
+        let output = self.command("kubectl")
+            .args([
+                "apply", "--server-side", "--force-conflicts",
+                "--field-manager", resources::FIELD_MANAGER,
+                "--namespace", &bundle.namespace,
+            ])
+            .arg("-f")
+            .arg(&manifest_file)
+            .output()
+            .map_err(|e| Error::CommandError(format!("Failed to execute kubectl apply: {}", e)))?;
+        
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::CommandError(format!("{} install failed: {}", bundle.name, stderr)));
         }
+        
+        Ok(())
     }
     
     #[instrument(skip(self))]
@@ -62,47 +286,59 @@ impl DependencyInstaller {
         
         let target_namespace = dependency.namespace.as_deref().unwrap_or(namespace);
         
-        // Add Helm repository
-        let mut add_repo_cmd = Command::new("helm");
-        add_repo_cmd
-            .args(&["repo", "add", &dependency.name, &dependency.source.repo])
-            .output()
-            .map_err(|e| Error::CommandError(format!("Failed to add Helm repo: {}", e)))?;
-        
-        // Update repositories
-        let mut update_cmd = Command::new("helm");
-        update_cmd
-            .args(&["repo", "update"])
-            .output()
-            .map_err(|e| Error::CommandError(format!("Failed to update Helm repos: {}", e)))?;
+        // Chart archives and values files of concurrent installs never meet
+        let workdir = Workdir::new("chart")?;
+        
+        let (chart, archive) = match ChartReference::new(&dependency.source.repo, chart_name) {
+            // OCI charts are pulled by digest and installed from the archive
+            Some(reference) => (self.pull_oci_chart(dependency, &reference, namespace, &workdir.0).await?, true),
+            None => {
+                // Add Helm repository
+                let mut add_repo_cmd = self.command("helm");
+                add_repo_cmd
+                    .args(["repo", "add", &dependency.name, &dependency.source.repo])
+                    .output()
+                    .map_err(|e| Error::CommandError(format!("Failed to add Helm repo: {}", e)))?;
+                
+                // Update repositories
+                let mut update_cmd = self.command("helm");
+                update_cmd
+                    .args(["repo", "update"])
+                    .output()
+                    .map_err(|e| Error::CommandError(format!("Failed to update Helm repos: {}", e)))?;
+                
+                (format!("{}/{}", dependency.name, chart_name), false)
+            }
+        };
         
-        // Build install command
-        let mut install_cmd = Command::new("helm");
-        install_cmd.args(&[
-            "install",
+        // Build install command; upgrade so changed values are rolled out on re-reconcile
+        let mut install_cmd = self.command("helm");
+        install_cmd.args([
+            "upgrade",
+            "--install",
             &dependency.name,
-            &format!("{}/{}", dependency.name, chart_name),
+            &chart,
             "--namespace",
             target_namespace,
             "--create-namespace",
         ]);
         
-        // Add version if specified
-        if let Some(version) = &dependency.version {
-            install_cmd.args(&["--version", version]);
+        // Add version if specified; archives carry their own
+        if let Some(version) = dependency.version.as_ref().filter(|_| !archive) {
+            install_cmd.args(["--version", version]);
         }
         
         // Add values if specified
-        if let Some(values) = &dependency.values {
-            let values_yaml = serde_yaml::to_string(values)
+        if let Some(values) = self.resolve_values(dependency, namespace).await? {
+            let values_yaml = serde_yaml::to_string(&values)
                 .map_err(|e| Error::SerializationError(format!("Failed to serialize values: {}", e)))?;
             
             // Write values to temporary file
-            let values_file = format!("/tmp/values-{}.yaml", dependency.name);
+            let values_file = workdir.0.join("values.yaml");
             std::fs::write(&values_file, values_yaml)
                 .map_err(|e| Error::IoError(format!("Failed to write values file: {}", e)))?;
             
-            install_cmd.args(&["--values", &values_file]);
+            install_cmd.arg("--values").arg(&values_file);
         }
         
         // Execute install command
@@ -118,6 +354,79 @@ impl DependencyInstaller {
         Ok(dependency.version.clone().unwrap_or_else(|| "latest".to_string()))
     }
     
+    /// Pulls an OCI chart at `source.digest`, or at the digest `version` is
+    /// tagged with, into an archive in `dir` and returns its path
+    async fn pull_oci_chart(
+        &self,
+        dependency: &Dependency,
+        chart: &ChartReference,
+        namespace: &str,
+        dir: &Path,
+    ) -> Result<String, Error> {
+        let credentials = oci::registry_credentials(&self.client, namespace, dependency.source.secret_ref.as_deref(), &chart.registry).await?;
+        let registry = Registry::new(credentials);
+        
+        let digest = match (&dependency.source.digest, &dependency.version) {
+            (Some(digest), _) => digest.clone(),
+            (None, Some(version)) => registry.digest(chart, version).await?,
+            (None, None) => {
+                return Err(Error::ConfigError(format!("Version or digest required for OCI chart {}", chart)));
+            }
+        };
+        
+        info!("Pulling {}@{}", chart, digest);
+        let archive = registry.pull(chart, &digest).await?;
+        
+        let path = dir.join(format!("{}.tgz", dependency.name));
+        std::fs::write(&path, archive)
+            .map_err(|e| Error::IoError(format!("Failed to write chart archive: {}", e)))?;
+        
+        Ok(path.to_string_lossy().into_owned())
+    }
+    
+    /// Merges `values_from` references in order, with inline `values` applied last
+    async fn resolve_values(
+        &self,
+        dependency: &Dependency,
+        namespace: &str,
+    ) -> Result<Option<HashMap<String, serde_json::Value>>, Error> {
+        let references = dependency.values_from.as_deref().unwrap_or_default();
+        
+        if references.is_empty() {
+            return Ok(dependency.values.clone());
+        }
+        
+        let mut documents = Vec::new();
+        
+        for reference in references {
+            let key = reference.values_key.as_deref().unwrap_or(DEFAULT_VALUES_KEY);
+            
+            let content = match reference.kind {
+                ValuesReferenceKind::ConfigMap => {
+                    let api: Api<ConfigMap> = Api::namespaced(self.client.clone(), namespace);
+                    api.get(&reference.name).await?
+                        .data
+                        .and_then(|mut data| data.remove(key))
+                }
+                ValuesReferenceKind::Secret => {
+                    let api: Api<Secret> = Api::namespaced(self.client.clone(), namespace);
+                    api.get(&reference.name).await?
+                        .data
+                        .and_then(|mut data| data.remove(key))
+                        .map(|bytes| String::from_utf8_lossy(&bytes.0).into_owned())
+                }
+            };
+            
+            let content = content.ok_or_else(|| Error::ConfigError(format!(
+                "{:?} {} has no key {}", reference.kind, reference.name, key
+            )))?;
+            
+            documents.push((reference.name.as_str(), content));
+        }
+        
+        layer_values(&documents, dependency.values.as_ref())
+    }
+    
     #[instrument(skip(self))]
     async fn install_kustomize(
         &self,
@@ -130,13 +439,13 @@ impl DependencyInstaller {
             .as_ref()
             .unwrap_or(&dependency.source.repo);
         
-        let mut cmd = Command::new("kubectl");
-        cmd.args(&["apply", "-k", path]);
+        let mut cmd = self.command("kubectl");
+        cmd.args(["apply", "-k", path]);
         
         if let Some(ns) = &dependency.namespace {
-            cmd.args(&["--namespace", ns]);
+            cmd.args(["--namespace", ns]);
         } else {
-            cmd.args(&["--namespace", namespace]);
+            cmd.args(["--namespace", namespace]);
         }
         
         let output = cmd
@@ -159,13 +468,13 @@ impl DependencyInstaller {
     ) -> Result<String, Error> {
         info!("Installing YAML manifests: {}", dependency.name);
         
-        let mut cmd = Command::new("kubectl");
-        cmd.args(&["apply", "-f", &dependency.source.repo]);
+        let mut cmd = self.command("kubectl");
+        cmd.args(["apply", "-f", &dependency.source.repo]);
         
         if let Some(ns) = &dependency.namespace {
-            cmd.args(&["--namespace", ns]);
+            cmd.args(["--namespace", ns]);
         } else {
-            cmd.args(&["--namespace", namespace]);
+            cmd.args(["--namespace", namespace]);
         }
         
         let output = cmd
@@ -206,14 +515,14 @@ impl DependencyInstaller {
     async fn install_external_secrets(&self, namespace: &str) -> Result<String, Error> {
         info!("Installing External Secrets Operator");
         
-        let mut cmd = Command::new("helm");
-        cmd.args(&[
+        let mut cmd = self.command("helm");
+        cmd.args([
             "repo", "add", "external-secrets", "https://charts.external-secrets.io"
         ]);
         cmd.output().map_err(|e| Error::CommandError(format!("Failed to add external-secrets repo: {}", e)))?;
         
-        let mut install_cmd = Command::new("helm");
-        install_cmd.args(&[
+        let mut install_cmd = self.command("helm");
+        install_cmd.args([
             "install", "external-secrets", "external-secrets/external-secrets",
             "--namespace", "external-secrets-system",
             "--create-namespace"
@@ -235,14 +544,14 @@ impl DependencyInstaller {
     async fn install_crossplane(&self, namespace: &str) -> Result<String, Error> {
         info!("Installing Crossplane");
         
-        let mut cmd = Command::new("helm");
-        cmd.args(&[
+        let mut cmd = self.command("helm");
+        cmd.args([
             "repo", "add", "crossplane-stable", "https://charts.crossplane.io/stable"
         ]);
         cmd.output().map_err(|e| Error::CommandError(format!("Failed to add crossplane repo: {}", e)))?;
         
-        let mut install_cmd = Command::new("helm");
-        install_cmd.args(&[
+        let mut install_cmd = self.command("helm");
+        install_cmd.args([
             "install", "crossplane", "crossplane-stable/crossplane",
             "--namespace", "crossplane-system",
             "--create-namespace"
@@ -264,14 +573,14 @@ impl DependencyInstaller {
     async fn install_loki_operator(&self, namespace: &str) -> Result<String, Error> {
         info!("Installing Loki Operator");
         
-        let mut cmd = Command::new("helm");
-        cmd.args(&[
+        let mut cmd = self.command("helm");
+        cmd.args([
             "repo", "add", "grafana", "https://grafana.github.io/helm-charts"
         ]);
         cmd.output().map_err(|e| Error::CommandError(format!("Failed to add grafana repo: {}", e)))?;
         
-        let mut install_cmd = Command::new("helm");
-        install_cmd.args(&[
+        let mut install_cmd = self.command("helm");
+        install_cmd.args([
             "install", "loki", "grafana/loki-stack",
             "--namespace", "loki-system",
             "--create-namespace"
@@ -303,4 +612,114 @@ impl DependencyInstaller {
             self.install_yaml_manifests(dependency, namespace).await
         }
     }
-}
\ No newline at end of file
+}
+
+/// Merges `(name, content)` values documents in order, with `inline` values applied last
+fn layer_values(
+    documents: &[(&str, String)],
+    inline: Option<&HashMap<String, serde_json::Value>>,
+) -> Result<Option<HashMap<String, serde_json::Value>>, Error> {
+    let mut merged = serde_json::Value::Object(Default::default());
+    
+    for (name, content) in documents {
+        let values: serde_json::Value = serde_yaml::from_str(content)
+            .map_err(|e| Error::SerializationError(format!("Failed to parse values from {}: {}", name, e)))?;
+        
+        merge_values(&mut merged, values);
+    }
+    
+    if let Some(values) = inline {
+        merge_values(&mut merged, serde_json::to_value(values)
+            .map_err(|e| Error::SerializationError(format!("Failed to serialize values: {}", e)))?);
+    }
+    
+    serde_json::from_value(merged)
+        .map(Some)
+        .map_err(|e| Error::SerializationError(format!("Values must be a map: {}", e)))
+}
+
+/// Deep-merges `overlay` into `base`, with maps merged key by key and anything else replaced
+fn merge_values(base: &mut serde_json::Value, overlay: serde_json::Value) {
+    match (base, overlay) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge_values(base.entry(key).or_insert(serde_json::Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    #[test]
+    fn values_from_merge_in_order_with_inline_values_last() {
+        let documents = [
+            ("defaults", "replicas: 1\nimage:\n  repository: loki\n  tag: \"2.9\"\n".to_string()),
+            ("overrides", "replicas: 3\nimage:\n  tag: \"2.10\"\n".to_string()),
+        ];
+        let inline = HashMap::from([("replicas".to_string(), json!(5))]);
+        
+        let values = layer_values(&documents, Some(&inline)).unwrap().unwrap();
+        assert_eq!(values["replicas"], json!(5));
+        assert_eq!(values["image"], json!({ "repository": "loki", "tag": "2.10" }));
+        
+        let values = layer_values(&documents, None).unwrap().unwrap();
+        assert_eq!(values["replicas"], json!(3));
+        
+        assert!(layer_values(&[("list", "- a\n".to_string())], None).is_err());
+    }
+    
+    #[test]
+    fn relocate_moves_namespaced_objects_and_subjects() {
+        let manifests = vec![
+            json!({ "apiVersion": "v1", "kind": "Namespace", "metadata": { "name": "flux-system" } }),
+            json!({
+                "apiVersion": "apps/v1",
+                "kind": "Deployment",
+                "metadata": { "name": "source-controller", "namespace": "flux-system" },
+            }),
+            json!({
+                "apiVersion": "rbac.authorization.k8s.io/v1",
+                "kind": "ClusterRoleBinding",
+                "metadata": { "name": "cluster-reconciler" },
+                "subjects": [{ "kind": "ServiceAccount", "name": "kustomize-controller", "namespace": "flux-system" }],
+            }),
+            json!({
+                "apiVersion": "apps/v1",
+                "kind": "Deployment",
+                "metadata": { "name": "kustomize-controller", "namespace": "flux-system" },
+                "spec": { "template": { "spec": { "containers": [{
+                    "name": "manager",
+                    "args": ["--events-addr=http://notification-controller.flux-system.svc.cluster.local./"],
+                }] } } },
+            }),
+        ];
+        
+        let relocated = relocate(manifests, "flux-system", "gitops");
+        
+        assert_eq!(relocated.len(), 4);
+        assert_eq!(relocated[0]["metadata"]["name"], "gitops");
+        assert_eq!(relocated[1]["metadata"]["namespace"], "gitops");
+        assert_eq!(relocated[2]["subjects"][0]["namespace"], "gitops");
+        assert!(relocated[2]["metadata"].get("namespace").is_none());
+        assert_eq!(
+            relocated[3]["spec"]["template"]["spec"]["containers"][0]["args"][0],
+            "--events-addr=http://notification-controller.gitops.svc.cluster.local./"
+        );
+    }
+    
+    #[test]
+    fn relocate_adds_missing_namespace() {
+        let manifests = vec![json!({ "apiVersion": "v1", "kind": "ServiceAccount", "metadata": { "name": "argocd-server" } })];
+        
+        let relocated = relocate(manifests, "argocd", "argocd");
+        
+        assert_eq!(relocated[0]["kind"], "Namespace");
+        assert_eq!(relocated[0]["metadata"]["name"], "argocd");
+        assert_eq!(relocated.len(), 2);
+    }
+}
@@ -1,6 +1,7 @@
 use thiserror::Error;
 
 #[derive(Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     #[error("Kubernetes API error: {0}")]
     KubeError(#[from] kube::Error),
@@ -28,4 +29,48 @@ pub enum Error {
     
     #[error("CI/CD error: {0}")]
     CiCdError(String),
-}
\ No newline at end of file
+    
+    #[error("Policy violation: {0}")]
+    PolicyError(String),
+}
+
+impl Error {
+    /// Whether the API server or a cluster CLI rejected the request for lack of permissions
+    pub fn is_forbidden(&self) -> bool {
+        match self {
+            Error::KubeError(kube::Error::Api(response)) => response.code == 403,
+            Error::CommandError(message) => {
+                // kubectl reports the status reason, helm only the Forbidden status message
+                message.contains("Error from server (Forbidden)") || message.contains(" is forbidden: User \"")
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn api_error(code: u16, reason: &str) -> Error {
+        Error::KubeError(kube::Error::Api(kube::core::ErrorResponse {
+            status: "Failure".to_string(),
+            message: format!("{} error", reason),
+            reason: reason.to_string(),
+            code,
+        }))
+    }
+    
+    #[test]
+    fn forbidden_is_read_from_the_api_status() {
+        assert!(api_error(403, "Forbidden").is_forbidden());
+        assert!(!api_error(404, "NotFound").is_forbidden());
+        
+        let kubectl = r#"Error from server (Forbidden): deployments.apps "loki" is forbidden: User "system:serviceaccount:team-a:installer" cannot get resource "deployments""#;
+        let helm = r#"Helm install failed: Error: INSTALLATION FAILED: namespaces "kube-system" is forbidden: User "system:serviceaccount:team-a:installer" cannot get resource "namespaces""#;
+        assert!(Error::CommandError(kubectl.to_string()).is_forbidden());
+        assert!(Error::CommandError(helm.to_string()).is_forbidden());
+        assert!(!Error::CommandError("Helm install failed: chart \"forbidden-fruit\" not found".to_string()).is_forbidden());
+        assert!(!Error::DependencyError("forbidden".to_string()).is_forbidden());
+    }
+}
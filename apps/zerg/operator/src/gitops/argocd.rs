@@ -0,0 +1,291 @@
+use serde_json::{json, Value};
+
+use crate::crd::{
+    ArgoCdAppProject, ArgoCdApplicationSet, ArgoCdConfig, ArgoCdDestination, ArgoCdGenerator, SyncPolicy,
+};
+use crate::error::Error;
+
+use super::SyncTarget;
+
+/// API version of the ArgoCD kinds the operator generates
+pub const API_VERSION: &str = "argoproj.io/v1alpha1";
+
+/// Project Applications belong to unless the CR names or generates one
+pub const DEFAULT_PROJECT: &str = "default";
+
+/// API server of the cluster ArgoCD runs in
+pub const IN_CLUSTER_SERVER: &str = "https://kubernetes.default.svc";
+
+/// Annotation ordering Applications within a sync
+const SYNC_WAVE_ANNOTATION: &str = "argocd.argoproj.io/sync-wave";
+
+/// Destination with the cluster defaulted to the in-cluster API server and the
+/// namespace to `namespace`
+pub fn destination(destination: Option<&ArgoCdDestination>, namespace: &str) -> Value {
+    let mut value = json!({
+        "namespace": destination
+            .and_then(|d| d.namespace.as_deref())
+            .unwrap_or(namespace),
+    });
+    
+    match destination.and_then(|d| d.name.as_deref()) {
+        Some(name) => value["name"] = json!(name),
+        None => {
+            value["server"] = json!(destination
+                .and_then(|d| d.server.as_deref())
+                .unwrap_or(IN_CLUSTER_SERVER))
+        }
+    }
+    
+    value
+}
+
+/// Application source for `target`
+pub fn source(target: &SyncTarget<'_>) -> Value {
+    let options = target.argocd;
+    
+    let mut source = json!({
+        "repoURL": target.repository,
+        "targetRevision": target.branch,
+    });
+    
+    match options.and_then(|o| o.chart.as_deref()) {
+        Some(chart) => source["chart"] = json!(chart),
+        None => source["path"] = json!(target.path),
+    }
+    
+    if let Some(ref_name) = options.and_then(|o| o.ref_name.as_deref()) {
+        source["ref"] = json!(ref_name);
+    }
+    if let Some(helm) = options.and_then(|o| o.helm.as_ref()) {
+        let mut value = json!({});
+        if let Some(release_name) = &helm.release_name {
+            value["releaseName"] = json!(release_name);
+        }
+        if let Some(files) = &helm.value_files {
+            value["valueFiles"] = json!(files);
+        }
+        if let Some(values) = &helm.values {
+            value["valuesObject"] = json!(values);
+        }
+        if let Some(parameters) = &helm.parameters {
+            let mut parameters: Vec<_> = parameters.iter().collect();
+            parameters.sort();
+            value["parameters"] = parameters
+                .into_iter()
+                .map(|(name, value)| json!({ "name": name, "value": value }))
+                .collect();
+        }
+        source["helm"] = value;
+    }
+    if let Some(kustomize) = options.and_then(|o| o.kustomize.as_ref()) {
+        let mut value = json!({});
+        if let Some(prefix) = &kustomize.name_prefix {
+            value["namePrefix"] = json!(prefix);
+        }
+        if let Some(suffix) = &kustomize.name_suffix {
+            value["nameSuffix"] = json!(suffix);
+        }
+        if let Some(images) = &kustomize.images {
+            value["images"] = json!(images);
+        }
+        if let Some(labels) = &kustomize.common_labels {
+            value["commonLabels"] = json!(labels);
+        }
+        source["kustomize"] = value;
+    }
+    
+    source
+}
+
+/// Sync policy combining the CR's automation settings with ArgoCD sync options
+/// and retry policy, `None` when nothing is configured
+pub fn sync_policy(policy: Option<&SyncPolicy>, config: Option<&ArgoCdConfig>) -> Option<Value> {
+    let mut value = json!({});
+    
+    if let Some(policy) = policy.filter(|p| p.automated) {
+        value["automated"] = json!({ "prune": policy.prune, "selfHeal": policy.self_heal });
+    }
+    if let Some(options) = config.and_then(|c| c.sync_options.as_ref()) {
+        value["syncOptions"] = json!(options);
+    }
+    if let Some(retry) = config.and_then(|c| c.retry.as_ref()) {
+        let mut backoff = json!({});
+        if let Some(duration) = &retry.backoff_duration {
+            backoff["duration"] = json!(duration);
+        }
+        if let Some(factor) = retry.backoff_factor {
+            backoff["factor"] = json!(factor);
+        }
+        if let Some(max_duration) = &retry.backoff_max_duration {
+            backoff["maxDuration"] = json!(max_duration);
+        }
+        
+        value["retry"] = json!({ "limit": retry.limit.unwrap_or(5), "backoff": backoff });
+    }
+    
+    value.as_object().is_some_and(|v| !v.is_empty()).then_some(value)
+}
+
+/// Application syncing `sources`; a single source is rendered as `source`,
+/// several as a multi-source Application
+pub fn application(
+    name: &str,
+    argocd_namespace: &str,
+    project: &str,
+    sources: Vec<Value>,
+    destination: Value,
+    sync_policy: Option<Value>,
+    sync_wave: Option<i32>,
+) -> Value {
+    let mut spec = json!({
+        "project": project,
+        "destination": destination,
+    });
+    
+    match <[Value; 1]>::try_from(sources) {
+        Ok([source]) => spec["source"] = source,
+        Err(sources) => spec["sources"] = json!(sources),
+    }
+    if let Some(policy) = sync_policy {
+        spec["syncPolicy"] = policy;
+    }
+    
+    let mut metadata = json!({ "name": name, "namespace": argocd_namespace });
+    if let Some(wave) = sync_wave {
+        metadata["annotations"] = json!({ SYNC_WAVE_ANNOTATION: wave.to_string() });
+    }
+    
+    json!({
+        "apiVersion": API_VERSION,
+        "kind": "Application",
+        "metadata": metadata,
+        "spec": spec,
+    })
+}
+
+/// AppProject restricting the DependencyManager's Applications to
+/// `source_repos` and `default_destination` unless the project lists its own
+pub fn app_project(
+    name: &str,
+    argocd_namespace: &str,
+    project: &ArgoCdAppProject,
+    source_repos: Vec<String>,
+    default_destination: Value,
+) -> Value {
+    let mut spec = json!({
+        "sourceRepos": project.source_repos.clone().unwrap_or(source_repos),
+        "destinations": match &project.destinations {
+            Some(destinations) => destinations
+                .iter()
+                .map(|d| destination(Some(d), "*"))
+                .collect(),
+            None => vec![default_destination],
+        },
+    });
+    
+    if let Some(description) = &project.description {
+        spec["description"] = json!(description);
+    }
+    if let Some(kinds) = &project.cluster_resource_whitelist {
+        spec["clusterResourceWhitelist"] = kinds
+            .iter()
+            .map(|k| json!({ "group": k.group, "kind": k.kind }))
+            .collect();
+    }
+    if let Some(kinds) = &project.namespace_resource_blacklist {
+        spec["namespaceResourceBlacklist"] = kinds
+            .iter()
+            .map(|k| json!({ "group": k.group, "kind": k.kind }))
+            .collect();
+    }
+    
+    json!({
+        "apiVersion": API_VERSION,
+        "kind": "AppProject",
+        "metadata": { "name": name, "namespace": argocd_namespace },
+        "spec": spec,
+    })
+}
+
+/// ApplicationSet generator in ArgoCD's format
+fn generator(set: &str, generator: &ArgoCdGenerator) -> Result<Value, Error> {
+    match (&generator.list, &generator.git, &generator.clusters) {
+        (Some(elements), None, None) => Ok(json!({ "list": { "elements": elements } })),
+        (None, Some(git), None) => {
+            let include = git.directories.iter().map(|path| json!({ "path": path }));
+            let exclude = git
+                .exclude
+                .iter()
+                .flatten()
+                .map(|path| json!({ "path": path, "exclude": true }));
+            
+            Ok(json!({
+                "git": {
+                    "repoURL": git.repository,
+                    "revision": git.revision,
+                    "directories": include.chain(exclude).collect::<Vec<_>>(),
+                },
+            }))
+        }
+        (None, None, Some(clusters)) => {
+            let mut value = json!({});
+            if let Some(labels) = &clusters.match_labels {
+                value["selector"] = json!({ "matchLabels": labels });
+            }
+            Ok(json!({ "clusters": value }))
+        }
+        _ => Err(Error::GitOpsError(format!(
+            "Generator of ApplicationSet {} must set exactly one of list, git or clusters",
+            set
+        ))),
+    }
+}
+
+/// ApplicationSet rendering `set.template` once per generated parameter set
+pub fn application_set(
+    name: &str,
+    argocd_namespace: &str,
+    set: &ArgoCdApplicationSet,
+    project: &str,
+    namespace: &str,
+    sync_policy: Option<Value>,
+) -> Result<Value, Error> {
+    if set.generators.is_empty() {
+        return Err(Error::GitOpsError(format!("ApplicationSet {} has no generators", set.name)));
+    }
+    
+    let generators = set
+        .generators
+        .iter()
+        .map(|g| generator(&set.name, g))
+        .collect::<Result<Vec<_>, _>>()?;
+    
+    let template = &set.template;
+    
+    let mut spec = json!({
+        "project": project,
+        "source": {
+            "repoURL": template.repository,
+            "targetRevision": template.revision,
+            "path": template.path,
+        },
+        "destination": destination(Some(&template.destination), namespace),
+    });
+    if let Some(policy) = sync_policy {
+        spec["syncPolicy"] = policy;
+    }
+    
+    Ok(json!({
+        "apiVersion": API_VERSION,
+        "kind": "ApplicationSet",
+        "metadata": { "name": name, "namespace": argocd_namespace },
+        "spec": {
+            "generators": generators,
+            "template": {
+                "metadata": { "name": template.name },
+                "spec": spec,
+            },
+        },
+    }))
+}
@@ -0,0 +1,294 @@
+use serde_json::{json, Value};
+
+use crate::crd::{FluxApiVersion, FluxHelmRelease, FluxSyncOptions};
+use crate::oci;
+
+use super::SyncTarget;
+
+/// Interval used when neither the CR nor the `flux` GitOps template set one
+pub const DEFAULT_INTERVAL: &str = "5m";
+
+/// Source name under which `depends_on` refers to the primary repository
+pub const PRIMARY_SOURCE: &str = "primary";
+
+/// API versions of the Flux kinds the operator generates
+#[derive(Debug, Clone, Copy)]
+pub struct FluxApis {
+    pub source: &'static str,
+    pub kustomize: &'static str,
+    pub helm: &'static str,
+}
+
+impl From<FluxApiVersion> for FluxApis {
+    fn from(version: FluxApiVersion) -> Self {
+        match version {
+            FluxApiVersion::V1 => Self {
+                source: "source.toolkit.fluxcd.io/v1",
+                kustomize: "kustomize.toolkit.fluxcd.io/v1",
+                helm: "helm.toolkit.fluxcd.io/v2",
+            },
+            FluxApiVersion::V1beta2 => Self {
+                source: "source.toolkit.fluxcd.io/v1beta2",
+                kustomize: "kustomize.toolkit.fluxcd.io/v1beta2",
+                helm: "helm.toolkit.fluxcd.io/v2beta1",
+            },
+        }
+    }
+}
+
+impl FluxApis {
+    /// Group, version and kind of a GitRepository
+    pub fn git_repository(&self) -> (&'static str, &'static str, &'static str) {
+        kind(self.source, "GitRepository")
+    }
+    
+    /// Group, version and kind of a Kustomization
+    pub fn kustomization(&self) -> (&'static str, &'static str, &'static str) {
+        kind(self.kustomize, "Kustomization")
+    }
+    
+    /// Group, version and kind of a HelmRepository
+    pub fn helm_repository(&self) -> (&'static str, &'static str, &'static str) {
+        kind(self.source, "HelmRepository")
+    }
+    
+    /// Group, version and kind of a HelmRelease
+    pub fn helm_release(&self) -> (&'static str, &'static str, &'static str) {
+        kind(self.helm, "HelmRelease")
+    }
+}
+
+fn kind(api_version: &'static str, kind: &'static str) -> (&'static str, &'static str, &'static str) {
+    let (group, version) = api_version.split_once('/').unwrap_or(("", api_version));
+    (group, version, kind)
+}
+
+/// Options for a source, with interval, timeout, secret_ref and decryption
+/// inherited from `defaults` when the source does not set them
+pub fn effective_options(own: Option<&FluxSyncOptions>, defaults: Option<&FluxSyncOptions>) -> FluxSyncOptions {
+    let mut options = own.cloned().unwrap_or_default();
+    
+    if let Some(defaults) = defaults {
+        options.interval = options.interval.or_else(|| defaults.interval.clone());
+        options.timeout = options.timeout.or_else(|| defaults.timeout.clone());
+        options.secret_ref = options.secret_ref.or_else(|| defaults.secret_ref.clone());
+        options.decryption = options.decryption.or_else(|| defaults.decryption.clone());
+    }
+    
+    options
+}
+
+/// GitRepository for `target`
+pub fn git_repository(
+    apis: FluxApis,
+    target: &SyncTarget<'_>,
+    namespace: &str,
+    options: &FluxSyncOptions,
+    interval: &str,
+) -> Value {
+    let mut spec = json!({
+        "interval": options.interval.as_deref().unwrap_or(interval),
+        "url": target.repository,
+        "ref": { "branch": target.branch },
+    });
+    
+    if let Some(timeout) = &options.timeout {
+        spec["timeout"] = json!(timeout);
+    }
+    if let Some(secret) = &options.secret_ref {
+        spec["secretRef"] = json!({ "name": secret });
+    }
+    
+    json!({
+        "apiVersion": apis.source,
+        "kind": "GitRepository",
+        "metadata": { "name": target.name, "namespace": namespace },
+        "spec": spec,
+    })
+}
+
+/// Kustomization applying `target.path` from the GitRepository of the same name.
+///
+/// `depends_on` holds the resolved Kustomization names.
+pub fn kustomization(
+    apis: FluxApis,
+    target: &SyncTarget<'_>,
+    namespace: &str,
+    options: &FluxSyncOptions,
+    interval: &str,
+    prune: bool,
+    depends_on: &[String],
+) -> Value {
+    let mut spec = json!({
+        "interval": options.interval.as_deref().unwrap_or(interval),
+        "sourceRef": { "kind": "GitRepository", "name": target.name },
+        "path": target.path,
+        "prune": prune,
+        "targetNamespace": namespace,
+    });
+    
+    if let Some(timeout) = &options.timeout {
+        spec["timeout"] = json!(timeout);
+    }
+    if !depends_on.is_empty() {
+        spec["dependsOn"] = depends_on.iter().map(|name| json!({ "name": name })).collect();
+    }
+    if let Some(wait) = options.wait {
+        spec["wait"] = json!(wait);
+    }
+    if let Some(checks) = &options.health_checks {
+        spec["healthChecks"] = checks
+            .iter()
+            .map(|check| {
+                let mut reference = json!({
+                    "apiVersion": check.api_version,
+                    "kind": check.kind,
+                    "name": check.name,
+                });
+                if let Some(ns) = &check.namespace {
+                    reference["namespace"] = json!(ns);
+                }
+                reference
+            })
+            .collect();
+    }
+    if let Some(post_build) = &options.post_build {
+        let mut build = json!({});
+        if let Some(substitute) = &post_build.substitute {
+            build["substitute"] = json!(substitute);
+        }
+        if let Some(references) = &post_build.substitute_from {
+            build["substituteFrom"] = references
+                .iter()
+                .map(|reference| {
+                    let mut from = json!({ "kind": reference.kind, "name": reference.name });
+                    if let Some(optional) = reference.optional {
+                        from["optional"] = json!(optional);
+                    }
+                    from
+                })
+                .collect();
+        }
+        spec["postBuild"] = build;
+    }
+    if let Some(decryption) = &options.decryption {
+        spec["decryption"] = json!({ "provider": decryption.provider });
+        if let Some(secret) = &decryption.secret_ref {
+            spec["decryption"]["secretRef"] = json!({ "name": secret });
+        }
+    }
+    
+    json!({
+        "apiVersion": apis.kustomize,
+        "kind": "Kustomization",
+        "metadata": { "name": target.name, "namespace": namespace },
+        "spec": spec,
+    })
+}
+
+/// HelmRepository serving the chart of `release`
+pub fn helm_repository(
+    apis: FluxApis,
+    release: &FluxHelmRelease,
+    name: &str,
+    namespace: &str,
+    interval: &str,
+) -> Value {
+    let mut spec = json!({
+        "interval": release.interval.as_deref().unwrap_or(interval),
+        "url": release.repository,
+    });
+    
+    if release.repository.starts_with(oci::SCHEME) {
+        spec["type"] = json!("oci");
+    }
+    if let Some(secret) = &release.secret_ref {
+        spec["secretRef"] = json!({ "name": secret });
+    }
+    
+    json!({
+        "apiVersion": apis.source,
+        "kind": "HelmRepository",
+        "metadata": { "name": name, "namespace": namespace },
+        "spec": spec,
+    })
+}
+
+/// OCIRepository holding chart `release` of an `oci://` repository at
+/// manifest `digest`, with the chart archive as its artifact
+pub fn oci_repository(
+    apis: FluxApis,
+    release: &FluxHelmRelease,
+    name: &str,
+    namespace: &str,
+    interval: &str,
+    digest: &str,
+) -> Value {
+    let mut spec = json!({
+        "interval": release.interval.as_deref().unwrap_or(interval),
+        "url": format!("{}/{}", release.repository.trim_end_matches('/'), release.chart),
+        "ref": { "digest": digest },
+        "layerSelector": { "mediaType": oci::CHART_MEDIA_TYPE, "operation": "copy" },
+    });
+    
+    if let Some(secret) = &release.secret_ref {
+        spec["secretRef"] = json!({ "name": secret });
+    }
+    
+    json!({
+        "apiVersion": apis.source,
+        "kind": "OCIRepository",
+        "metadata": { "name": name, "namespace": namespace },
+        "spec": spec,
+    })
+}
+
+/// HelmRelease installing `release` from the HelmRepository of the same name.
+///
+/// `depends_on` holds the resolved HelmRelease names.
+pub fn helm_release(
+    apis: FluxApis,
+    release: &FluxHelmRelease,
+    name: &str,
+    namespace: &str,
+    interval: &str,
+    depends_on: &[String],
+) -> Value {
+    let interval = release.interval.as_deref().unwrap_or(interval);
+    
+    let mut chart = json!({
+        "chart": release.chart,
+        "sourceRef": { "kind": "HelmRepository", "name": name },
+        "interval": interval,
+    });
+    if let Some(version) = &release.version {
+        chart["version"] = json!(version);
+    }
+    
+    let mut spec = json!({
+        "interval": interval,
+        "releaseName": release.name,
+        "chart": { "spec": chart },
+        "install": { "createNamespace": true },
+    });
+    
+    if let Some(target) = &release.target_namespace {
+        spec["targetNamespace"] = json!(target);
+    }
+    if let Some(timeout) = &release.timeout {
+        spec["timeout"] = json!(timeout);
+    }
+    if let Some(values) = &release.values {
+        spec["values"] = json!(values);
+    }
+    if !depends_on.is_empty() {
+        spec["dependsOn"] = depends_on.iter().map(|name| json!({ "name": name })).collect();
+    }
+    
+    json!({
+        "apiVersion": apis.helm,
+        "kind": "HelmRelease",
+        "metadata": { "name": name, "namespace": namespace },
+        "spec": spec,
+    })
+}
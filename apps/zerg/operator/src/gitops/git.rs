@@ -0,0 +1,393 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::{debug, info};
+
+use crate::error::Error;
+
+/// Author used when `git_write` does not name one
+pub const DEFAULT_AUTHOR_NAME: &str = "zerg-operator";
+pub const DEFAULT_AUTHOR_EMAIL: &str = "zerg-operator@zerg.io";
+
+/// Credential helper answering git's prompts from the environment, so
+/// passwords never appear in command lines or remote URLs
+const CREDENTIAL_HELPER: &str = "!f() { echo \"username=$ZERG_GIT_USERNAME\"; echo \"password=$ZERG_GIT_PASSWORD\"; }; f";
+
+/// A commit of rendered files to `path` of `repository`
+#[derive(Debug)]
+pub struct GitWrite<'a> {
+    pub repository: &'a str,
+    /// Branch the commit is based on
+    pub branch: &'a str,
+    /// Branch pushed to; `branch` itself, or a pull-request branch
+    pub target_branch: &'a str,
+    /// Directory written to, or `.` for the repository root
+    pub path: &'a str,
+    /// Only files under `path` starting with it are replaced, leaving
+    /// hand-written files and those of other tools, such as Flux's
+    /// `flux-system`, alone
+    pub marker: &'a str,
+    pub message: &'a str,
+    pub author_name: &'a str,
+    pub author_email: &'a str,
+}
+
+/// Commits files through the `git` CLI in a throwaway clone
+pub struct GitWriter {
+    credentials: Option<(String, String)>,
+}
+
+/// Clone directory, removed when dropped
+struct Workdir(PathBuf);
+
+impl Workdir {
+    fn new() -> Self {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or_default();
+        Self(std::env::temp_dir().join(format!("zerg-git-{}-{}", std::process::id(), nanos)))
+    }
+}
+
+impl Drop for Workdir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+impl GitWriter {
+    pub fn new(credentials: Option<(String, String)>) -> Self {
+        Self { credentials }
+    }
+    
+    fn git(&self, dir: Option<&Path>, write: &GitWrite<'_>) -> Command {
+        let mut cmd = Command::new("git");
+        if let Some(dir) = dir {
+            cmd.current_dir(dir);
+        }
+        
+        cmd.env("GIT_TERMINAL_PROMPT", "0")
+            .env("GIT_AUTHOR_NAME", write.author_name)
+            .env("GIT_AUTHOR_EMAIL", write.author_email)
+            .env("GIT_COMMITTER_NAME", write.author_name)
+            .env("GIT_COMMITTER_EMAIL", write.author_email);
+        
+        if let Some((username, password)) = &self.credentials {
+            cmd.env("GIT_CONFIG_COUNT", "1")
+                .env("GIT_CONFIG_KEY_0", "credential.helper")
+                .env("GIT_CONFIG_VALUE_0", CREDENTIAL_HELPER)
+                .env("ZERG_GIT_USERNAME", username)
+                .env("ZERG_GIT_PASSWORD", password);
+        }
+        
+        cmd
+    }
+    
+    fn run(&self, dir: Option<&Path>, write: &GitWrite<'_>, args: &[&str]) -> Result<Output, Error> {
+        debug!("git {}", args.join(" "));
+        
+        self.git(dir, write)
+            .args(args)
+            .output()
+            .map_err(|e| Error::CommandError(format!("Failed to execute git: {}", e)))
+    }
+    
+    fn run_checked(&self, dir: Option<&Path>, write: &GitWrite<'_>, args: &[&str]) -> Result<String, Error> {
+        let output = self.run(dir, write, args)?;
+        
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::CommandError(format!("git {} failed: {}", args[0], stderr.trim())));
+        }
+        
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+    
+    /// Replaces the marked files under `write.path` with `files`, commits and
+    /// pushes, returning the commit the target branch ends up at.
+    ///
+    /// Nothing is pushed when the rendered files match what is already there,
+    /// and nothing at all when one of them would replace an unmarked file.
+    pub fn write(&self, write: &GitWrite<'_>, files: &BTreeMap<String, String>) -> Result<String, Error> {
+        let path = directory(write.path)?;
+        let workdir = Workdir::new();
+        let dir = workdir.0.as_path();
+        let clone_dir = dir.to_string_lossy();
+        
+        self.run_checked(None, write, &[
+            "clone", "--quiet", "--single-branch", "--branch", write.branch, "--", write.repository, &clone_dir,
+        ])?;
+        if write.target_branch != write.branch {
+            self.run_checked(Some(dir), write, &["checkout", "--quiet", "-B", write.target_branch])?;
+        }
+        
+        // The operator owns the files it marked, so dropped ones disappear;
+        // anything left in place of a rendered file was written by hand
+        let root = dir.join(&path);
+        remove_marked(&root, write.marker)?;
+        for (name, content) in files {
+            let file = root.join(safe_path(name)?);
+            if file.symlink_metadata().is_ok() {
+                return Err(Error::GitOpsError(format!(
+                    "Refusing to overwrite {} in {}, which was not written by the operator",
+                    path.join(name).display(),
+                    write.repository
+                )));
+            }
+            if let Some(parent) = file.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| Error::IoError(format!("Failed to create {}: {}", parent.display(), e)))?;
+            }
+            std::fs::write(&file, content)
+                .map_err(|e| Error::IoError(format!("Failed to write {}: {}", name, e)))?;
+        }
+        
+        self.run_checked(Some(dir), write, &["add", "--all", "--", &root.to_string_lossy()])?;
+        
+        let unchanged = self.run(Some(dir), write, &["diff", "--cached", "--quiet"])?.status.success();
+        if unchanged && write.target_branch == write.branch {
+            info!("{} is up to date", write.path);
+            return self.run_checked(Some(dir), write, &["rev-parse", "HEAD"]);
+        }
+        if !unchanged {
+            self.run_checked(Some(dir), write, &["commit", "--quiet", "-m", write.message])?;
+        }
+        
+        let commit = self.run_checked(Some(dir), write, &["rev-parse", "HEAD"])?;
+        
+        if write.target_branch == write.branch {
+            self.run_checked(Some(dir), write, &["push", "--quiet", "origin", &format!("HEAD:refs/heads/{}", write.branch)])?;
+        } else {
+            // Leave an open pull-request branch alone when it already proposes the same content
+            let fetched = self.run(Some(dir), write, &["fetch", "--quiet", "origin", write.target_branch])?;
+            if fetched.status.success() {
+                let current = self.run_checked(Some(dir), write, &["rev-parse", "FETCH_HEAD^{tree}"])?;
+                let proposed = self.run_checked(Some(dir), write, &["rev-parse", "HEAD^{tree}"])?;
+                if current == proposed {
+                    info!("Branch {} is up to date", write.target_branch);
+                    return self.run_checked(Some(dir), write, &["rev-parse", "FETCH_HEAD"]);
+                }
+            }
+            
+            self.run_checked(Some(dir), write, &[
+                "push", "--quiet", "--force", "origin", &format!("HEAD:refs/heads/{}", write.target_branch),
+            ])?;
+        }
+        
+        info!("Pushed {} to {}", commit, write.target_branch);
+        Ok(commit)
+    }
+    
+    /// Content of file `name` under `write.path` on `write.branch`, `None`
+    /// when there is no such file
+    pub fn read(&self, write: &GitWrite<'_>, name: &str) -> Result<Option<String>, Error> {
+        let file = directory(write.path)?.join(safe_path(name)?);
+        let workdir = Workdir::new();
+        let clone_dir = workdir.0.to_string_lossy();
+        
+        self.run_checked(None, write, &[
+            "clone", "--quiet", "--depth", "1", "--single-branch", "--branch", write.branch, "--", write.repository, &clone_dir,
+        ])?;
+        
+        match std::fs::read_to_string(workdir.0.join(&file)) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Error::IoError(format!("Failed to read {}: {}", file.display(), e))),
+        }
+    }
+}
+
+/// Removes the files under `dir` whose content starts with `marker`, outside
+/// of `.git` and without following symlinks
+fn remove_marked(dir: &Path, marker: &str) -> Result<(), Error> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(());
+    };
+    
+    for entry in entries.flatten() {
+        let file = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        
+        if file_type.is_dir() && entry.file_name() != ".git" {
+            remove_marked(&file, marker)?;
+        } else if file_type.is_file() && std::fs::read_to_string(&file).is_ok_and(|content| content.starts_with(marker)) {
+            std::fs::remove_file(&file)
+                .map_err(|e| Error::IoError(format!("Failed to remove {}: {}", file.display(), e)))?;
+        }
+    }
+    
+    Ok(())
+}
+
+/// Directory `path` of the clone; `.` is the repository root, which is safe to
+/// write to since only marked files are ever replaced
+fn directory(path: &str) -> Result<PathBuf, Error> {
+    match path.trim_matches('/') {
+        "." => Ok(PathBuf::new()),
+        path => safe_path(path),
+    }
+}
+
+/// Relative path below the clone root that cannot escape it
+fn safe_path(path: &str) -> Result<PathBuf, Error> {
+    let path = Path::new(path.trim_matches('/'));
+    
+    let components: Vec<_> = path.components().collect();
+    let escapes = components
+        .iter()
+        .any(|c| !matches!(c, std::path::Component::Normal(_) | std::path::Component::CurDir));
+    let normal = components.iter().any(|c| matches!(c, std::path::Component::Normal(_)));
+    if escapes || !normal {
+        return Err(Error::GitOpsError(format!("Invalid repository path: {}", path.display())));
+    }
+    
+    Ok(path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .current_dir(dir)
+            .args(args)
+            .env("GIT_AUTHOR_NAME", "test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+    
+    /// Bare repository with one commit on `main` holding `README.md`, the
+    /// generated `clusters/prod/stale.yaml` and Flux's `clusters/prod/flux-system`
+    fn bare_repository() -> Workdir {
+        let root = Workdir(std::env::temp_dir().join(format!("zerg-git-test-{:?}", std::thread::current().id())
+            .replace(['(', ')'], "")));
+        let _ = std::fs::remove_dir_all(&root.0);
+        let bare = root.0.join("remote.git");
+        let seed = root.0.join("seed");
+        std::fs::create_dir_all(&bare).unwrap();
+        std::fs::create_dir_all(seed.join("clusters/prod/flux-system")).unwrap();
+        
+        git(&bare, &["init", "--quiet", "--bare", "--initial-branch=main"]);
+        git(&seed, &["init", "--quiet", "--initial-branch=main"]);
+        std::fs::write(seed.join("README.md"), "platform\n").unwrap();
+        std::fs::write(seed.join("clusters/prod/stale.yaml"), "# generated\nstale\n").unwrap();
+        std::fs::write(seed.join("clusters/prod/flux-system/gotk-sync.yaml"), "kind: Kustomization\n").unwrap();
+        git(&seed, &["add", "--all"]);
+        git(&seed, &["commit", "--quiet", "-m", "Initial commit"]);
+        git(&seed, &["push", "--quiet", bare.to_str().unwrap(), "main"]);
+        
+        root
+    }
+    
+    fn write<'a>(repository: &'a str, target_branch: &'a str) -> GitWrite<'a> {
+        GitWrite {
+            repository,
+            branch: "main",
+            target_branch,
+            path: "clusters/prod",
+            marker: "# generated",
+            message: "Update dependencies",
+            author_name: DEFAULT_AUTHOR_NAME,
+            author_email: DEFAULT_AUTHOR_EMAIL,
+        }
+    }
+    
+    #[test]
+    fn writes_files_to_branch_and_pull_request_branch() {
+        let root = bare_repository();
+        let bare = root.0.join("remote.git");
+        let repository = bare.to_str().unwrap();
+        let files = BTreeMap::from([
+            ("kustomization.yaml".to_string(), "# generated\nresources: [loki.yaml]\n".to_string()),
+            ("loki.yaml".to_string(), "# generated\nkind: HelmRelease\n".to_string()),
+        ]);
+        let writer = GitWriter::new(None);
+        
+        let commit = writer.write(&write(repository, "main"), &files).unwrap();
+        assert_eq!(git(&bare, &["rev-parse", "main"]), commit);
+        assert_eq!(
+            git(&bare, &["ls-tree", "-r", "--name-only", "main"]),
+            "README.md\nclusters/prod/flux-system/gotk-sync.yaml\nclusters/prod/kustomization.yaml\nclusters/prod/loki.yaml"
+        );
+        assert_eq!(git(&bare, &["log", "-1", "--format=%an <%ae> %s", "main"]),
+            "zerg-operator <zerg-operator@zerg.io> Update dependencies");
+        
+        // Unchanged files do not create another commit
+        assert_eq!(writer.write(&write(repository, "main"), &files).unwrap(), commit);
+        
+        // Changes go to the pull-request branch, leaving `main` untouched
+        let files = BTreeMap::from([("kustomization.yaml".to_string(), "# generated\nresources: []\n".to_string())]);
+        let proposed = writer.write(&write(repository, "zerg/update"), &files).unwrap();
+        assert_eq!(git(&bare, &["rev-parse", "main"]), commit);
+        assert_eq!(git(&bare, &["rev-parse", "zerg/update"]), proposed);
+        assert_eq!(writer.write(&write(repository, "zerg/update"), &files).unwrap(), proposed);
+    }
+    
+    #[test]
+    fn reads_files_from_branch() {
+        let root = bare_repository();
+        let bare = root.0.join("remote.git");
+        let writer = GitWriter::new(None);
+        let write = write(bare.to_str().unwrap(), "main");
+        
+        assert_eq!(writer.read(&write, "stale.yaml").unwrap().as_deref(), Some("# generated\nstale\n"));
+        assert_eq!(writer.read(&write, "loki.yaml").unwrap(), None);
+        assert!(writer.read(&write, "../README.md").is_err());
+    }
+    
+    #[test]
+    fn replaces_only_marked_files() {
+        let root = bare_repository();
+        let bare = root.0.join("remote.git");
+        let write = GitWrite { path: ".", ..write(bare.to_str().unwrap(), "main") };
+        let writer = GitWriter::new(None);
+        
+        let files = BTreeMap::from([("old.yml".to_string(), "# generated\nold\n".to_string())]);
+        writer.write(&write, &files).unwrap();
+        let files = BTreeMap::from([("new.yml".to_string(), "# generated\nnew\n".to_string())]);
+        writer.write(&write, &files).unwrap();
+        
+        assert_eq!(
+            git(&bare, &["ls-tree", "-r", "--name-only", "main"]),
+            "README.md\nclusters/prod/flux-system/gotk-sync.yaml\nnew.yml"
+        );
+        
+        // Hand-written files are never replaced
+        let head = git(&bare, &["rev-parse", "main"]);
+        let files = BTreeMap::from([("README.md".to_string(), "# generated\nreadme\n".to_string())]);
+        let error = writer.write(&write, &files).unwrap_err().to_string();
+        assert!(error.contains("Refusing to overwrite README.md"), "{}", error);
+        assert_eq!(git(&bare, &["rev-parse", "main"]), head);
+    }
+    
+    #[test]
+    fn repositories_are_never_options() {
+        let root = bare_repository();
+        let pwned = root.0.join("pwned");
+        let upload_pack = format!("--upload-pack=touch {}", pwned.display());
+        let writer = GitWriter::new(None);
+        let files = BTreeMap::from([("loki.yaml".to_string(), "# generated\n".to_string())]);
+        
+        assert!(writer.write(&write(&upload_pack, "main"), &files).is_err());
+        assert!(writer.read(&write(&upload_pack, "main"), "loki.yaml").is_err());
+        assert!(!pwned.exists());
+    }
+    
+    #[test]
+    fn rejects_paths_outside_the_repository() {
+        assert!(safe_path("../etc").is_err());
+        assert!(safe_path("/").is_err());
+        assert!(safe_path(".").is_err());
+        assert!(safe_path("./").is_err());
+        assert!(directory(".").unwrap().as_os_str().is_empty());
+        assert!(safe_path("/clusters/prod/").is_ok());
+    }
+}
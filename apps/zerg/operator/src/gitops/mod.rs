@@ -0,0 +1,974 @@
+use anyhow::Result;
+use k8s_openapi::api::core::v1::Secret;
+use kube::{
+    api::{Api, DynamicObject},
+    Client, ResourceExt,
+};
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::process::Command;
+use sha2::{Digest, Sha256};
+use tracing::{info, instrument};
+
+use crate::config::GitOpsTemplate;
+use crate::crd::{
+    ArgoCdSourceOptions, DependencyManager, FluxApiVersion, FluxSyncOptions, GitOpsConfig, GitOpsProvider, GitOpsStatus,
+    GitWriteConfig, SyncPolicy,
+};
+use crate::dependencies::Bundle;
+use crate::error::Error;
+use crate::impersonation::{self, Impersonation};
+use crate::resources::{self, ARGOCD_APPLICATION, ARGOCD_APPLICATION_SET, ARGOCD_APP_PROJECT};
+
+pub mod argocd;
+mod flux;
+pub mod git;
+mod render;
+mod status;
+
+/// Namespace ArgoCD is installed into and Applications are created in, by default
+pub const ARGOCD_NAMESPACE: &str = "argocd";
+
+/// Namespace Flux is installed into by default
+pub const FLUX_NAMESPACE: &str = "flux-system";
+
+/// `<PROVIDER>_VERSION=<version>` lines naming the versions vendored into the image
+const VENDORED_VERSIONS: &str = include_str!("../../versions.env");
+
+/// Version installed when neither the CR nor the provider's GitOps template set one
+fn default_version(provider: &str) -> Option<&'static str> {
+    let key = format!("{}_VERSION", provider.to_uppercase());
+    VENDORED_VERSIONS
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(name, _)| name.trim() == key)
+        .map(|(_, version)| version.trim())
+}
+
+/// Username and password for HTTPS pushes from the `username` and `password`
+/// keys of Secret `secret_ref`, if set
+pub async fn git_credentials(
+    client: &Client,
+    namespace: &str,
+    secret_ref: Option<&str>,
+) -> Result<Option<(String, String)>, Error> {
+    let Some(name) = secret_ref else {
+        return Ok(None);
+    };
+    
+    let api: Api<Secret> = Api::namespaced(client.clone(), namespace);
+    let data = api.get(name).await?.data.unwrap_or_default();
+    let field = |key: &str| {
+        data.get(key)
+            .map(|value| String::from_utf8_lossy(&value.0).into_owned())
+            .ok_or_else(|| Error::GitOpsError(format!("Secret {} has no key {}", name, key)))
+    };
+    
+    Ok(Some((field("username")?, field("password")?)))
+}
+
+/// Commit of the rendered dependencies to `config.path`, based on `config.branch`
+fn git_write_settings<'a>(
+    config: &'a GitOpsConfig,
+    git_write: &'a GitWriteConfig,
+    target_branch: &'a str,
+    message: &'a str,
+) -> git::GitWrite<'a> {
+    git::GitWrite {
+        repository: &config.repository,
+        branch: &config.branch,
+        target_branch,
+        path: &config.path,
+        marker: render::HEADER,
+        message,
+        author_name: git_write.author_name.as_deref().unwrap_or(git::DEFAULT_AUTHOR_NAME),
+        author_email: git_write.author_email.as_deref().unwrap_or(git::DEFAULT_AUTHOR_EMAIL),
+    }
+}
+
+/// Namespace the provider's controllers run in; Argo objects are created there too
+pub fn controller_namespace(config: &GitOpsConfig) -> &str {
+    match config.install.as_ref().and_then(|i| i.namespace.as_deref()) {
+        Some(namespace) => namespace,
+        None => match config.provider {
+            GitOpsProvider::Flux => FLUX_NAMESPACE,
+            GitOpsProvider::ArgoCD => ARGOCD_NAMESPACE,
+        },
+    }
+}
+
+/// Controller version: `install.version`, then the template's `version`
+fn install_version(config: &GitOpsConfig, template: Option<&GitOpsTemplate>) -> Result<String, Error> {
+    let provider = config.provider.template_name();
+    let version = config
+        .install
+        .as_ref()
+        .and_then(|i| i.version.as_deref())
+        .or(template.and_then(|t| t.config.get("version")).and_then(|v| v.as_str()))
+        .or(default_version(provider))
+        .unwrap_or_default();
+    
+    // Versions become part of a path under the manifests directory
+    if version.is_empty() || version.contains(['/', '\\']) || version.starts_with('.') {
+        return Err(Error::GitOpsError(format!("Invalid {} version: {:?}", provider, version)));
+    }
+    
+    Ok(version.to_string())
+}
+
+/// Vendored manifests installing the provider's controllers, `None` when
+/// `install.enabled` is false
+pub fn install_bundle(
+    config: &GitOpsConfig,
+    template: Option<&GitOpsTemplate>,
+    manifests_dir: &str,
+) -> Result<Option<Bundle>, Error> {
+    let install = config.install.clone().unwrap_or_default();
+    if !install.enabled.unwrap_or(true) {
+        return Ok(None);
+    }
+    
+    let provider = config.provider.template_name();
+    let version = install_version(config, template)?;
+    let ha = install.ha.unwrap_or(false);
+    
+    let (variant, source_namespace) = match config.provider {
+        GitOpsProvider::ArgoCD => (if ha { "ha/install.yaml" } else { "install.yaml" }, ARGOCD_NAMESPACE),
+        GitOpsProvider::Flux if ha => {
+            return Err(Error::GitOpsError("Flux has no high-availability install variant".to_string()));
+        }
+        GitOpsProvider::Flux => ("install.yaml", FLUX_NAMESPACE),
+    };
+    
+    Ok(Some(Bundle {
+        name: provider.to_string(),
+        path: Path::new(manifests_dir).join(provider).join(&version).join(variant),
+        version,
+        namespace: controller_namespace(config).to_string(),
+        source_namespace,
+    }))
+}
+
+impl GitOpsProvider {
+    /// Key of the provider's entry in `Config::gitops_templates`
+    pub fn template_name(&self) -> &'static str {
+        match self {
+            GitOpsProvider::Flux => "flux",
+            GitOpsProvider::ArgoCD => "argocd",
+        }
+    }
+}
+
+/// A git source resolved to the name of the objects that sync it
+#[derive(Debug)]
+pub struct SyncTarget<'a> {
+    /// Name of the generated Flux/Argo objects
+    pub name: String,
+    /// Source name, `None` for the primary repository
+    pub source: Option<&'a str>,
+    pub repository: &'a str,
+    pub branch: &'a str,
+    pub path: &'a str,
+    pub sync_policy: Option<&'a SyncPolicy>,
+    pub flux: Option<&'a FluxSyncOptions>,
+    pub argocd: Option<&'a ArgoCdSourceOptions>,
+}
+
+/// Longest generated object name, that of a DNS label so the name also fits label values
+const MAX_NAME_LENGTH: usize = 63;
+
+/// Name of the objects generated for `source` of `owner`, or for its primary
+/// repository when `source` is `None`.
+///
+/// Argo Applications of every DependencyManager share one namespace, so their
+/// names are additionally qualified with the owner's namespace. The parts are
+/// joined with `-`, which they may contain themselves, so a hash of the parts
+/// keeps e.g. `team-a`/`platform` and `team`/`a-platform` apart; the readable
+/// prefix is truncated to keep the name within `MAX_NAME_LENGTH`.
+pub fn object_name(owner: &DependencyManager, source: Option<&str>, qualify_namespace: bool) -> String {
+    let namespace = owner.namespace().unwrap_or_default();
+    let mut parts = Vec::new();
+    
+    if qualify_namespace {
+        parts.push(namespace.clone());
+    }
+    parts.push(owner.name_any());
+    parts.extend(source.map(str::to_string));
+    
+    let identity = format!("{}/{}/{}", namespace, owner.name_any(), source.map(|s| format!("/{}", s)).unwrap_or_default());
+    let hash: String = Sha256::digest(identity.as_bytes()).iter().take(4).map(|b| format!("{:02x}", b)).collect();
+    
+    let readable = parts.join("-");
+    let prefix = readable[..readable.len().min(MAX_NAME_LENGTH - hash.len() - 1)].trim_end_matches(['-', '.']);
+    format!("{}-{}", prefix, hash)
+}
+
+/// Primary repository followed by any additional `sources`
+fn sync_targets<'a>(
+    config: &'a GitOpsConfig,
+    owner: &DependencyManager,
+    qualify_namespace: bool,
+) -> Vec<SyncTarget<'a>> {
+    let primary = SyncTarget {
+        name: object_name(owner, None, qualify_namespace),
+        source: None,
+        repository: &config.repository,
+        branch: &config.branch,
+        path: &config.path,
+        sync_policy: config.sync_policy.as_ref(),
+        flux: config.flux.as_ref().and_then(|flux| flux.sync.as_ref()),
+        argocd: config.argocd.as_ref().and_then(|argocd| argocd.source.as_ref()),
+    };
+    
+    let additional = config.sources.iter().flatten().map(|source| SyncTarget {
+        name: object_name(owner, Some(&source.name), qualify_namespace),
+        source: Some(&source.name),
+        repository: &source.repository,
+        branch: &source.branch,
+        path: &source.path,
+        sync_policy: source.sync_policy.as_ref().or(config.sync_policy.as_ref()),
+        flux: source.flux.as_ref(),
+        argocd: source.argocd.as_ref(),
+    });
+    
+    std::iter::once(primary).chain(additional).collect()
+}
+
+pub struct GitOpsManager {
+    client: Client,
+    impersonation: Option<Impersonation>,
+    template: Option<GitOpsTemplate>,
+}
+
+impl GitOpsManager {
+    pub fn new(client: Client, impersonation: Option<Impersonation>, template: Option<GitOpsTemplate>) -> Self {
+        Self { client, impersonation, template }
+    }
+    
+    /// Default from the provider's GitOps template in the operator config
+    fn template_value(&self, key: &str) -> Option<&serde_json::Value> {
+        self.template.as_ref().and_then(|template| template.config.get(key))
+    }
+    
+    /// Cluster CLI command run as the DependencyManager's ServiceAccount, if any
+    fn command(&self, program: &str) -> Command {
+        impersonation::command(program, self.impersonation.as_ref())
+    }
+    
+    #[instrument(skip(self, owner))]
+    pub async fn setup_gitops(
+        &self,
+        config: &GitOpsConfig,
+        owner: &DependencyManager,
+    ) -> Result<(), Error> {
+        let namespace = owner.namespace().unwrap_or_default();
+        
+        // Source names become part of object names, so they must be unique
+        let mut seen = HashSet::new();
+        for source in config.sources.iter().flatten() {
+            if source.name == flux::PRIMARY_SOURCE || !seen.insert(source.name.as_str()) {
+                return Err(Error::GitOpsError(format!("Duplicate or reserved GitOps source name: {}", source.name)));
+            }
+        }
+        
+        match config.provider {
+            GitOpsProvider::Flux => self.setup_flux(config, &namespace, owner).await,
+            GitOpsProvider::ArgoCD => self.setup_argocd(config, &namespace, owner).await,
+        }
+    }
+    
+    /// Renders the dependencies of `owner` and commits them to `config.path`
+    /// (git-write mode), returning the commit the target branch is at
+    #[instrument(skip(self, owner))]
+    pub async fn write_dependencies(
+        &self,
+        config: &GitOpsConfig,
+        owner: &DependencyManager,
+    ) -> Result<String, Error> {
+        let mut message = format!(
+            "Update dependencies of {}/{}\n\n",
+            owner.namespace().unwrap_or_default(),
+            owner.name_any()
+        );
+        for dependency in owner.spec.dependencies.iter().filter(|d| d.enabled) {
+            let version = dependency.version.as_deref().unwrap_or("latest");
+            message.push_str(&format!("- {} {}\n", dependency.name, version));
+        }
+        
+        let git_write = config.git_write.clone().unwrap_or_default();
+        let target_branch = git_write.pull_request_branch.as_deref().unwrap_or(&config.branch);
+        self.commit_dependencies(config, owner, target_branch, &message).await
+    }
+    
+    /// Commits the dependencies of `owner`, where `dependency` is at its newer
+    /// `version`, to `branch` for review, returning the commit `branch` is at
+    #[instrument(skip(self, owner))]
+    pub async fn propose_update(
+        &self,
+        config: &GitOpsConfig,
+        owner: &DependencyManager,
+        dependency: &str,
+        version: &str,
+        branch: &str,
+    ) -> Result<String, Error> {
+        let message = format!(
+            "Update {} to {}\n\nNewer chart version for {}/{}\n",
+            dependency,
+            version,
+            owner.namespace().unwrap_or_default(),
+            owner.name_any()
+        );
+        
+        self.commit_dependencies(config, owner, branch, &message).await
+    }
+    
+    /// Whether dependency `name` of `owner` is committed to `config.branch` as
+    /// it renders now, e.g. because a proposed update of it was merged
+    #[instrument(skip(self, owner))]
+    pub async fn is_committed(
+        &self,
+        config: &GitOpsConfig,
+        owner: &DependencyManager,
+        name: &str,
+    ) -> Result<bool, Error> {
+        let file = format!("{}.yaml", name);
+        let rendered = self.render_dependencies(config, owner)?.remove(&file);
+        
+        let git_write = config.git_write.clone().unwrap_or_default();
+        let credentials = self.git_credentials(owner, &git_write).await?;
+        let write = git_write_settings(config, &git_write, &config.branch, "");
+        let committed = git::GitWriter::new(credentials).read(&write, &file)?;
+        
+        Ok(rendered.is_some() && committed == rendered)
+    }
+    
+    fn render_dependencies(
+        &self,
+        config: &GitOpsConfig,
+        owner: &DependencyManager,
+    ) -> Result<BTreeMap<String, String>, Error> {
+        let interval = self.template_value("interval")
+            .and_then(|v| v.as_str())
+            .unwrap_or(flux::DEFAULT_INTERVAL);
+        
+        render::render_dependencies(config, owner, interval)
+    }
+    
+    async fn git_credentials(
+        &self,
+        owner: &DependencyManager,
+        git_write: &GitWriteConfig,
+    ) -> Result<Option<(String, String)>, Error> {
+        let namespace = owner.namespace().unwrap_or_default();
+        git_credentials(&self.client, &namespace, git_write.secret_ref.as_deref()).await
+    }
+    
+    /// Renders the dependencies of `owner` and pushes them to `target_branch`
+    async fn commit_dependencies(
+        &self,
+        config: &GitOpsConfig,
+        owner: &DependencyManager,
+        target_branch: &str,
+        message: &str,
+    ) -> Result<String, Error> {
+        let files = self.render_dependencies(config, owner)?;
+        
+        let git_write = config.git_write.clone().unwrap_or_default();
+        let credentials = self.git_credentials(owner, &git_write).await?;
+        let write = git_write_settings(config, &git_write, target_branch, message);
+        
+        git::GitWriter::new(credentials).write(&write, &files)
+    }
+    
+    /// Reads back the Kustomizations or Applications generated for `owner`
+    #[instrument(skip(self, owner))]
+    pub async fn gitops_status(
+        &self,
+        config: &GitOpsConfig,
+        owner: &DependencyManager,
+    ) -> Result<GitOpsStatus, Error> {
+        let namespace = owner.namespace().unwrap_or_default();
+        let mut sources = Vec::new();
+        
+        match config.provider {
+            GitOpsProvider::Flux => {
+                let apis = flux::FluxApis::from(
+                    config.flux
+                        .as_ref()
+                        .and_then(|f| f.api_version)
+                        .unwrap_or(FluxApiVersion::V1),
+                );
+                
+                for target in sync_targets(config, owner, false) {
+                    let kustomization = self
+                        .get_object(apis.kustomize, "Kustomization", &namespace, &target.name)
+                        .await?;
+                    let repository = self
+                        .get_object(apis.source, "GitRepository", &namespace, &target.name)
+                        .await?;
+                    sources.push(status::flux(&target.name, kustomization.as_ref(), repository.as_ref()));
+                }
+            }
+            GitOpsProvider::ArgoCD => {
+                let api_version = argocd::API_VERSION;
+                for name in argocd_application_names(config, owner) {
+                    let application = self
+                        .get_object(api_version, "Application", controller_namespace(config), &name)
+                        .await?;
+                    sources.push(status::argocd(&name, application.as_ref()));
+                }
+            }
+        }
+        
+        Ok(status::aggregate(config.provider.clone(), sources))
+    }
+    
+    /// Fetches an object by `apiVersion` and kind, `None` when it does not exist
+    async fn get_object(
+        &self,
+        api_version: &str,
+        kind: &str,
+        namespace: &str,
+        name: &str,
+    ) -> Result<Option<serde_json::Value>, Error> {
+        let (group, version) = api_version.split_once('/').unwrap_or(("", api_version));
+        let (resource, _) = resources::resolve_kind(&self.client, (group, version, kind)).await?;
+        let api: Api<DynamicObject> = Api::namespaced_with(self.client.clone(), namespace, &resource);
+        
+        let object = api.get_opt(name).await?;
+        object
+            .map(|o| serde_json::to_value(o).map_err(|e| Error::SerializationError(e.to_string())))
+            .transpose()
+    }
+    
+    #[instrument(skip(self, owner))]
+    async fn setup_flux(
+        &self,
+        config: &GitOpsConfig,
+        namespace: &str,
+        owner: &DependencyManager,
+    ) -> Result<(), Error> {
+        info!("Setting up Flux GitOps");
+        
+        let flux_config = config.flux.as_ref();
+        
+        // Bootstrap Flux with the git repository
+        if flux_config.and_then(|f| f.bootstrap).unwrap_or(true) {
+            let bootstrap_cmd = self.command("flux")
+                .args([
+                    "bootstrap", "git",
+                    "--url", &config.repository,
+                    "--branch", &config.branch,
+                    "--path", &config.path,
+                    "--namespace", controller_namespace(config),
+                    // Keep the components bootstrap commits in line with the vendored install
+                    "--version", &install_version(config, self.template.as_ref())?,
+                ])
+                .output()
+                .map_err(|e| Error::CommandError(format!("Failed to bootstrap Flux: {}", e)))?;
+            
+            if !bootstrap_cmd.status.success() {
+                let stderr = String::from_utf8_lossy(&bootstrap_cmd.stderr);
+                return Err(Error::CommandError(format!("Flux bootstrap failed: {}", stderr)));
+            }
+        }
+        
+        for manifest in self.flux_manifests(config, namespace, owner)? {
+            resources::apply_manifest(&self.client, manifest, owner).await?;
+        }
+        
+        // Remove objects of sources and releases that were renamed or removed
+        let targets = sync_targets(config, owner, false);
+        let names: Vec<String> = targets.into_iter().map(|t| t.name).collect();
+        let apis = flux::FluxApis::from(
+            flux_config
+                .and_then(|f| f.api_version)
+                .unwrap_or(FluxApiVersion::V1),
+        );
+        resources::prune_labelled(&self.client, apis.kustomization(), namespace, owner, &names).await?;
+        resources::prune_labelled(&self.client, apis.git_repository(), namespace, owner, &names).await?;
+        
+        let releases: Vec<String> = flux_config
+            .and_then(|f| f.helm_releases.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|release| object_name(owner, Some(&release.name), false))
+            .collect();
+        for kind in [apis.helm_release(), apis.helm_repository()] {
+            match resources::prune_labelled(&self.client, kind, namespace, owner, &releases).await {
+                // Helm controller CRDs are optional when no releases are configured
+                Err(Error::KubeError(kube::Error::Discovery(_))) if releases.is_empty() => {}
+                result => result?,
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// GitRepository and Kustomization per source, plus HelmRepository and
+    /// HelmRelease per configured release
+    fn flux_manifests(
+        &self,
+        config: &GitOpsConfig,
+        namespace: &str,
+        owner: &DependencyManager,
+    ) -> Result<Vec<serde_json::Value>, Error> {
+        let flux_config = config.flux.as_ref();
+        let apis = flux::FluxApis::from(
+            flux_config
+                .and_then(|f| f.api_version)
+                .unwrap_or(FluxApiVersion::V1),
+        );
+        let defaults = flux_config.and_then(|f| f.sync.as_ref());
+        let interval = defaults
+            .and_then(|d| d.interval.as_deref())
+            .or(self.template_value("interval").and_then(|v| v.as_str()))
+            .unwrap_or(flux::DEFAULT_INTERVAL)
+            .to_string();
+        let template_prune = self.template_value("prune").and_then(|v| v.as_bool());
+        
+        let targets = sync_targets(config, owner, false);
+        let mut manifests = Vec::new();
+        
+        for target in &targets {
+            let options = match target.source {
+                None => flux::effective_options(target.flux, None),
+                Some(_) => flux::effective_options(target.flux, defaults),
+            };
+            let prune = target.sync_policy
+                .map(|p| p.prune)
+                .or(template_prune)
+                .unwrap_or(false);
+            
+            // Resolve dependsOn source names to Kustomization names
+            let mut depends_on = Vec::new();
+            for dependency in options.depends_on.iter().flatten() {
+                let source = (dependency != flux::PRIMARY_SOURCE).then_some(dependency.as_str());
+                if source.is_some_and(|name| !config.sources.iter().flatten().any(|s| s.name == name)) {
+                    return Err(Error::GitOpsError(format!("{} depends on unknown source {}", target.name, dependency)));
+                }
+                depends_on.push(object_name(owner, source, false));
+            }
+            
+            manifests.push(flux::git_repository(apis, target, namespace, &options, &interval));
+            manifests.push(flux::kustomization(apis, target, namespace, &options, &interval, prune, &depends_on));
+        }
+        
+        let releases = flux_config.and_then(|f| f.helm_releases.as_ref()).into_iter().flatten();
+        let release_names: HashSet<&str> = releases.clone().map(|r| r.name.as_str()).collect();
+        
+        for release in releases {
+            let name = object_name(owner, Some(&release.name), false);
+            
+            let mut depends_on = Vec::new();
+            for dependency in release.depends_on.iter().flatten() {
+                if !release_names.contains(dependency.as_str()) {
+                    return Err(Error::GitOpsError(format!("{} depends on unknown release {}", release.name, dependency)));
+                }
+                depends_on.push(object_name(owner, Some(dependency), false));
+            }
+            
+            manifests.push(flux::helm_repository(apis, release, &name, namespace, &interval));
+            manifests.push(flux::helm_release(apis, release, &name, namespace, &interval, &depends_on));
+        }
+        
+        Ok(manifests)
+    }
+    
+    #[instrument(skip(self, owner))]
+    async fn setup_argocd(
+        &self,
+        config: &GitOpsConfig,
+        namespace: &str,
+        owner: &DependencyManager,
+    ) -> Result<(), Error> {
+        info!("Setting up ArgoCD GitOps");
+        
+        for manifest in argocd_manifests(config, namespace, owner)? {
+            resources::apply_manifest(&self.client, manifest, owner).await?;
+        }
+        
+        // Remove objects of sources and ApplicationSets that were renamed or removed
+        let argocd_config = config.argocd.as_ref();
+        let argocd_namespace = controller_namespace(config);
+        let applications = argocd_application_names(config, owner);
+        resources::prune_labelled(&self.client, ARGOCD_APPLICATION, argocd_namespace, owner, &applications).await?;
+        
+        let projects: Vec<String> = argocd_config
+            .and_then(|a| a.app_project.as_ref())
+            .map(|_| object_name(owner, None, true))
+            .into_iter()
+            .collect();
+        resources::prune_labelled(&self.client, ARGOCD_APP_PROJECT, argocd_namespace, owner, &projects).await?;
+        
+        let sets: Vec<String> = argocd_config
+            .and_then(|a| a.application_sets.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|set| object_name(owner, Some(&set.name), true))
+            .collect();
+        match resources::prune_labelled(&self.client, ARGOCD_APPLICATION_SET, argocd_namespace, owner, &sets).await {
+            // The ApplicationSet controller is optional when no sets are configured
+            Err(Error::KubeError(kube::Error::Discovery(_))) if sets.is_empty() => {}
+            result => result?,
+        }
+        
+        Ok(())
+    }
+}
+
+/// Names of the Applications synced for `config`: one per source, or a single
+/// multi-source Application
+fn argocd_application_names(config: &GitOpsConfig, owner: &DependencyManager) -> Vec<String> {
+    if config.argocd.as_ref().and_then(|a| a.multi_source).unwrap_or(false) {
+        vec![object_name(owner, None, true)]
+    } else {
+        sync_targets(config, owner, true).into_iter().map(|t| t.name).collect()
+    }
+}
+
+/// Project of the generated Applications: the generated AppProject, the named
+/// project, or ArgoCD's default
+fn argocd_project(config: &GitOpsConfig, owner: &DependencyManager) -> String {
+    let argocd_config = config.argocd.as_ref();
+    
+    if argocd_config.is_some_and(|a| a.app_project.is_some()) {
+        return object_name(owner, None, true);
+    }
+    argocd_config
+        .and_then(|a| a.project.clone())
+        .unwrap_or_else(|| argocd::DEFAULT_PROJECT.to_string())
+}
+
+/// Optional AppProject, Applications per source (or one multi-source
+/// Application) and ApplicationSets, all in the ArgoCD controller namespace
+fn argocd_manifests(
+    config: &GitOpsConfig,
+    namespace: &str,
+    owner: &DependencyManager,
+) -> Result<Vec<serde_json::Value>, Error> {
+    let argocd_config = config.argocd.as_ref();
+    let argocd_namespace = controller_namespace(config);
+    let targets = sync_targets(config, owner, true);
+    let mut manifests = Vec::new();
+    
+    let destination = argocd_config.and_then(|a| a.destination.as_ref());
+    let project = argocd_project(config, owner);
+    match argocd_config.and_then(|a| a.app_project.as_ref()) {
+        Some(_) if argocd_config.and_then(|a| a.project.as_ref()).is_some() => {
+            return Err(Error::GitOpsError("argocd.project and argocd.app_project are mutually exclusive".to_string()));
+        }
+        Some(app_project) => {
+            
+            let mut repositories: Vec<String> = targets.iter().map(|t| t.repository.to_string()).collect();
+            for set in argocd_config.and_then(|a| a.application_sets.as_ref()).into_iter().flatten() {
+                repositories.push(set.template.repository.clone());
+            }
+            repositories.sort();
+            repositories.dedup();
+            
+            manifests.push(argocd::app_project(
+                &project,
+                argocd_namespace,
+                app_project,
+                repositories,
+                argocd::destination(destination, namespace),
+            ));
+        }
+        None => {}
+    }
+    
+    if argocd_config.and_then(|a| a.multi_source).unwrap_or(false) {
+        let primary = &targets[0];
+        manifests.push(argocd::application(
+            &primary.name,
+            argocd_namespace,
+            &project,
+            targets.iter().map(argocd::source).collect(),
+            argocd::destination(destination, namespace),
+            argocd::sync_policy(primary.sync_policy, argocd_config),
+            primary.argocd.and_then(|o| o.sync_wave),
+        ));
+    } else {
+        for target in &targets {
+            let target_namespace = target.argocd.and_then(|o| o.namespace.as_deref());
+            let mut destination = argocd::destination(destination, namespace);
+            if let Some(target_namespace) = target_namespace {
+                destination["namespace"] = serde_json::json!(target_namespace);
+            }
+            
+            manifests.push(argocd::application(
+                &target.name,
+                argocd_namespace,
+                &project,
+                vec![argocd::source(target)],
+                destination,
+                argocd::sync_policy(target.sync_policy, argocd_config),
+                target.argocd.and_then(|o| o.sync_wave),
+            ));
+        }
+    }
+    
+    let mut seen = HashSet::new();
+    for set in argocd_config.and_then(|a| a.application_sets.as_ref()).into_iter().flatten() {
+        if !seen.insert(set.name.as_str()) {
+            return Err(Error::GitOpsError(format!("Duplicate ApplicationSet name: {}", set.name)));
+        }
+        
+        manifests.push(argocd::application_set(
+            &object_name(owner, Some(&set.name), true),
+            argocd_namespace,
+            set,
+            &project,
+            namespace,
+            argocd::sync_policy(config.sync_policy.as_ref(), argocd_config),
+        )?);
+    }
+    
+    Ok(manifests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::crd::{
+        ArgoCdAppProject, ArgoCdApplicationSet, ArgoCdApplicationTemplate, ArgoCdConfig, ArgoCdDestination,
+        ArgoCdGenerator, ArgoCdGitGenerator, ArgoCdRetry, FluxConfig, GitOpsInstall, GitOpsSource,
+    };
+    use crate::crd::tests::owner;
+    
+    fn config(provider: GitOpsProvider) -> GitOpsConfig {
+        GitOpsConfig {
+            provider,
+            repository: "https://example.com/platform".to_string(),
+            branch: "main".to_string(),
+            path: "clusters/prod".to_string(),
+            sync_policy: None,
+            sources: Some(vec![GitOpsSource {
+                name: "apps".to_string(),
+                repository: "https://example.com/apps".to_string(),
+                branch: "main".to_string(),
+                path: "overlays/prod".to_string(),
+                sync_policy: None,
+                flux: Some(FluxSyncOptions {
+                    depends_on: Some(vec![flux::PRIMARY_SOURCE.to_string()]),
+                    ..Default::default()
+                }),
+                argocd: None,
+            }]),
+            flux: None,
+            argocd: None,
+            install: None,
+            mode: None,
+            git_write: None,
+        }
+    }
+    
+    fn manager(template: Option<GitOpsTemplate>) -> GitOpsManager {
+        let kube_config = kube::Config::new("http://127.0.0.1:1".parse().unwrap());
+        GitOpsManager::new(Client::try_from(kube_config).unwrap(), None, template)
+    }
+    
+    #[test]
+    fn sync_targets_are_named_after_owner() {
+        let config = config(GitOpsProvider::ArgoCD);
+        let prefixes = |owner: &DependencyManager, qualify: bool| -> Vec<String> {
+            sync_targets(&config, owner, qualify)
+                .into_iter()
+                .map(|t| t.name.rsplit_once('-').unwrap().0.to_string())
+                .collect()
+        };
+        
+        assert_eq!(prefixes(&owner("team-a", "platform"), true), ["team-a-platform", "team-a-platform-apps"]);
+        assert_eq!(prefixes(&owner("team-b", "platform"), false), ["platform", "platform-apps"]);
+    }
+    
+    #[test]
+    fn object_names_are_unique_and_bounded() {
+        fn name(namespace: &str, name: &str, source: Option<&str>) -> String {
+            object_name(&owner(namespace, name), source, true)
+        }
+        
+        assert_ne!(name("team-a", "platform", None), name("team", "a-platform", None));
+        assert_ne!(name("team-a", "platform", Some("apps")), name("team-a", "platform-apps", None));
+        assert_eq!(name("team-a", "platform", Some("apps")), name("team-a", "platform", Some("apps")));
+        
+        let long = name("team-a", &"platform".repeat(10), Some("apps"));
+        assert_eq!(long.len(), MAX_NAME_LENGTH);
+        assert!(long.starts_with("team-a-platformplatform"));
+        assert_ne!(long, name("team-a", &"platform".repeat(10), Some("apps-2")));
+    }
+    
+    #[tokio::test]
+    async fn flux_manifests_use_v1_and_resolve_depends_on() {
+        let mut config = config(GitOpsProvider::Flux);
+        config.flux = Some(FluxConfig {
+            api_version: None,
+            bootstrap: Some(false),
+            sync: Some(FluxSyncOptions {
+                interval: Some("10m".to_string()),
+                secret_ref: Some("git-auth".to_string()),
+                ..Default::default()
+            }),
+            helm_releases: None,
+        });
+        
+        let manifests = manager(None)
+            .flux_manifests(&config, "team-a", &owner("team-a", "platform"))
+            .unwrap();
+        
+        assert_eq!(manifests.len(), 4);
+        assert_eq!(manifests[0]["apiVersion"], "source.toolkit.fluxcd.io/v1");
+        assert_eq!(manifests[0]["spec"]["interval"], "10m");
+        assert_eq!(manifests[0]["spec"]["secretRef"]["name"], "git-auth");
+        assert_eq!(manifests[1]["apiVersion"], "kustomize.toolkit.fluxcd.io/v1");
+        
+        // The additional source inherits credentials and waits for the primary Kustomization
+        assert_eq!(manifests[2]["spec"]["secretRef"]["name"], "git-auth");
+        let owner = owner("team-a", "platform");
+        assert_eq!(manifests[3]["metadata"]["name"], object_name(&owner, Some("apps"), false));
+        assert_eq!(manifests[3]["spec"]["dependsOn"][0]["name"], object_name(&owner, None, false));
+    }
+    
+    #[test]
+    fn flux_kinds_follow_the_configured_api_version() {
+        let apis = flux::FluxApis::from(FluxApiVersion::V1beta2);
+        assert_eq!(apis.git_repository(), ("source.toolkit.fluxcd.io", "v1beta2", "GitRepository"));
+        assert_eq!(apis.kustomization(), ("kustomize.toolkit.fluxcd.io", "v1beta2", "Kustomization"));
+        assert_eq!(apis.helm_repository(), ("source.toolkit.fluxcd.io", "v1beta2", "HelmRepository"));
+        assert_eq!(apis.helm_release(), ("helm.toolkit.fluxcd.io", "v2beta1", "HelmRelease"));
+        
+        let apis = flux::FluxApis::from(FluxApiVersion::V1);
+        assert_eq!(apis.kustomization(), ("kustomize.toolkit.fluxcd.io", "v1", "Kustomization"));
+        assert_eq!(apis.helm_release(), ("helm.toolkit.fluxcd.io", "v2", "HelmRelease"));
+    }
+    
+    #[tokio::test]
+    async fn flux_manifests_fall_back_to_template() {
+        let template = GitOpsTemplate {
+            name: "flux".to_string(),
+            provider: "flux".to_string(),
+            config: HashMap::from([
+                ("interval".to_string(), serde_json::json!("1m")),
+                ("prune".to_string(), serde_json::json!(true)),
+            ]),
+        };
+        
+        let manifests = manager(Some(template))
+            .flux_manifests(&config(GitOpsProvider::Flux), "team-a", &owner("team-a", "platform"))
+            .unwrap();
+        
+        assert_eq!(manifests[1]["spec"]["interval"], "1m");
+        assert_eq!(manifests[1]["spec"]["prune"], true);
+    }
+    
+    #[test]
+    fn argocd_manifests_generate_project_applications_and_sets() {
+        let mut config = config(GitOpsProvider::ArgoCD);
+        config.sync_policy = Some(SyncPolicy { automated: true, self_heal: true, prune: false });
+        config.argocd = Some(ArgoCdConfig {
+            app_project: Some(ArgoCdAppProject::default()),
+            destination: Some(ArgoCdDestination {
+                server: Some("https://prod.example.com".to_string()),
+                ..Default::default()
+            }),
+            sync_options: Some(vec!["CreateNamespace=true".to_string()]),
+            retry: Some(ArgoCdRetry {
+                limit: Some(3),
+                backoff_duration: Some("10s".to_string()),
+                backoff_factor: None,
+                backoff_max_duration: None,
+            }),
+            application_sets: Some(vec![ArgoCdApplicationSet {
+                name: "envs".to_string(),
+                generators: vec![ArgoCdGenerator {
+                    git: Some(ArgoCdGitGenerator {
+                        repository: "https://example.com/envs".to_string(),
+                        revision: "main".to_string(),
+                        directories: vec!["envs/*".to_string()],
+                        exclude: Some(vec!["envs/legacy".to_string()]),
+                    }),
+                    ..Default::default()
+                }],
+                template: ArgoCdApplicationTemplate {
+                    name: "{{path.basename}}".to_string(),
+                    repository: "https://example.com/envs".to_string(),
+                    revision: "main".to_string(),
+                    path: "{{path}}".to_string(),
+                    destination: ArgoCdDestination::default(),
+                },
+            }]),
+            ..Default::default()
+        });
+        
+        let manifests = argocd_manifests(&config, "team-a", &owner("team-a", "platform")).unwrap();
+        let kinds: Vec<&str> = manifests.iter().map(|m| m["kind"].as_str().unwrap()).collect();
+        assert_eq!(kinds, ["AppProject", "Application", "Application", "ApplicationSet"]);
+        
+        let project = object_name(&owner("team-a", "platform"), None, true);
+        assert_eq!(manifests[0]["metadata"]["name"], project);
+        assert_eq!(manifests[0]["spec"]["sourceRepos"].as_array().unwrap().len(), 3);
+        
+        let app = &manifests[1]["spec"];
+        assert_eq!(app["project"], project);
+        assert_eq!(app["destination"]["server"], "https://prod.example.com");
+        assert_eq!(app["destination"]["namespace"], "team-a");
+        assert_eq!(app["syncPolicy"]["automated"]["selfHeal"], true);
+        assert_eq!(app["syncPolicy"]["syncOptions"][0], "CreateNamespace=true");
+        assert_eq!(app["syncPolicy"]["retry"]["limit"], 3);
+        
+        let directories = &manifests[3]["spec"]["generators"][0]["git"]["directories"];
+        assert_eq!(directories[1]["exclude"], true);
+    }
+    
+    #[test]
+    fn argocd_multi_source_renders_one_application() {
+        let mut config = config(GitOpsProvider::ArgoCD);
+        config.argocd = Some(ArgoCdConfig {
+            multi_source: Some(true),
+            ..Default::default()
+        });
+        
+        let manifests = argocd_manifests(&config, "team-a", &owner("team-a", "platform")).unwrap();
+        assert_eq!(manifests.len(), 1);
+        assert_eq!(manifests[0]["spec"]["project"], "default");
+        assert_eq!(manifests[0]["spec"]["sources"].as_array().unwrap().len(), 2);
+        assert!(manifests[0]["spec"].get("syncPolicy").is_none());
+    }
+    
+    #[test]
+    fn install_bundle_resolves_vendored_manifests() {
+        let mut config = config(GitOpsProvider::ArgoCD);
+        
+        let bundle = install_bundle(&config, None, "/opt/zerg/manifests").unwrap().unwrap();
+        let version = default_version("argocd").unwrap();
+        assert!(version.starts_with('v'), "{}", version);
+        assert_eq!(bundle.path, Path::new("/opt/zerg/manifests/argocd").join(version).join("install.yaml"));
+        assert_eq!(bundle.namespace, ARGOCD_NAMESPACE);
+        
+        config.install = Some(GitOpsInstall {
+            version: Some("v2.10.0".to_string()),
+            ha: Some(true),
+            namespace: Some("gitops".to_string()),
+            ..Default::default()
+        });
+        let bundle = install_bundle(&config, None, "/manifests").unwrap().unwrap();
+        assert_eq!(bundle.path, Path::new("/manifests/argocd/v2.10.0/ha/install.yaml"));
+        assert_eq!(bundle.namespace, "gitops");
+        assert_eq!(controller_namespace(&config), "gitops");
+        
+        config.install.as_mut().unwrap().version = Some("../../etc".to_string());
+        assert!(install_bundle(&config, None, "/manifests").is_err());
+        
+        let mut config = GitOpsConfig {
+            provider: GitOpsProvider::Flux,
+            install: Some(GitOpsInstall { ha: Some(true), ..Default::default() }),
+            ..config
+        };
+        assert!(install_bundle(&config, None, "/manifests").is_err());
+        
+        config.install = Some(GitOpsInstall { enabled: Some(false), ..Default::default() });
+        assert!(install_bundle(&config, None, "/manifests").unwrap().is_none());
+    }
+}
@@ -0,0 +1,394 @@
+use std::collections::{BTreeMap, HashMap};
+
+use kube::ResourceExt;
+use serde_json::{json, Value};
+
+use crate::crd::{
+    Dependency, DependencyManager, DependencyType, FluxApiVersion, FluxHelmRelease, GitOpsConfig, GitOpsProvider,
+};
+use crate::dependencies::builtin_operator;
+use crate::error::Error;
+use crate::oci;
+
+use super::{argocd, controller_namespace, flux, object_name};
+
+/// Header of every generated file, marking it as replaceable in git-write mode
+pub const HEADER: &str = "# Generated by zerg-operator; changes are overwritten on the next reconcile\n";
+
+/// How a dependency is rendered into the repository
+enum Rendering {
+    /// HelmRelease (Flux) or Application (Argo) for a chart
+    Chart { repository: String, chart: String, namespace: String },
+    /// `kustomization.yaml` pulling in remote resources
+    Resources { resource: String, namespace: String },
+}
+
+fn rendering(dependency: &Dependency, namespace: &str) -> Rendering {
+    if let Some((repository, chart, target_namespace)) = builtin_operator(dependency) {
+        return Rendering::Chart {
+            repository: repository.to_string(),
+            chart: chart.to_string(),
+            namespace: target_namespace.to_string(),
+        };
+    }
+    
+    let source = &dependency.source;
+    let namespace = dependency.namespace.clone().unwrap_or_else(|| namespace.to_string());
+    
+    match (&dependency.type_, &source.chart) {
+        (DependencyType::Helm | DependencyType::Operator, Some(chart)) => Rendering::Chart {
+            repository: source.repo.clone(),
+            chart: chart.clone(),
+            namespace,
+        },
+        (DependencyType::Kustomize, _) => {
+            let mut resource = match &source.path {
+                Some(path) => format!("{}//{}", source.repo.trim_end_matches('/'), path.trim_start_matches('/')),
+                None => source.repo.clone(),
+            };
+            if let Some(reference) = source.ref_.as_ref().or(dependency.version.as_ref()) {
+                resource = format!("{}?ref={}", resource, reference);
+            }
+            Rendering::Resources { resource, namespace }
+        }
+        _ => Rendering::Resources { resource: source.repo.clone(), namespace },
+    }
+}
+
+/// Position of `name` in the install order: one more than the deepest of its `depends_on`
+fn install_wave(
+    name: &str,
+    dependencies: &HashMap<&str, &Dependency>,
+    waves: &mut HashMap<String, i32>,
+    visiting: &mut Vec<String>,
+) -> Result<i32, Error> {
+    if let Some(wave) = waves.get(name) {
+        return Ok(*wave);
+    }
+    if visiting.iter().any(|n| n == name) {
+        return Err(Error::DependencyError(format!("Dependency cycle through {}", name)));
+    }
+    
+    let dependency = dependencies
+        .get(name)
+        .ok_or_else(|| Error::DependencyError(format!("Unknown or disabled dependency {}", name)))?;
+    
+    visiting.push(name.to_string());
+    let mut wave = 0;
+    for parent in dependency.depends_on.iter().flatten() {
+        wave = wave.max(install_wave(parent, dependencies, waves, visiting)? + 1);
+    }
+    visiting.pop();
+    
+    waves.insert(name.to_string(), wave);
+    Ok(wave)
+}
+
+fn to_yaml(documents: &[Value]) -> Result<String, Error> {
+    let documents = documents
+        .iter()
+        .map(serde_yaml::to_string)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Error::SerializationError(format!("Failed to render manifests: {}", e)))?;
+    
+    Ok(format!("{}{}", HEADER, documents.join("---\n")))
+}
+
+/// Renders the enabled `dependencies` of `owner` into files relative to
+/// `config.path`, with a top-level `kustomization.yaml` tying them together.
+///
+/// Charts become Flux HelmRepository/HelmRelease pairs or Argo Applications
+/// (ordered through `dependsOn` or sync waves); kustomize and plain YAML
+/// dependencies become kustomizations referencing their remote sources.
+pub fn render_dependencies(
+    config: &GitOpsConfig,
+    owner: &DependencyManager,
+    interval: &str,
+) -> Result<BTreeMap<String, String>, Error> {
+    let namespace = owner.namespace().unwrap_or_default();
+    let enabled: Vec<&Dependency> = owner.spec.dependencies.iter().filter(|d| d.enabled).collect();
+    let by_name: HashMap<&str, &Dependency> = enabled.iter().map(|d| (d.name.as_str(), *d)).collect();
+    
+    // Flux can only wait for dependencies that become HelmReleases
+    let charts: Vec<&str> = enabled
+        .iter()
+        .filter(|d| matches!(rendering(d, &namespace), Rendering::Chart { .. }))
+        .map(|d| d.name.as_str())
+        .collect();
+    
+    let mut files = BTreeMap::new();
+    let mut resources = Vec::new();
+    let mut waves = HashMap::new();
+    
+    for dependency in &enabled {
+        let wave = install_wave(&dependency.name, &by_name, &mut waves, &mut Vec::new())?;
+        
+        match rendering(dependency, &namespace) {
+            Rendering::Chart { repository, chart, namespace: target_namespace } => {
+                let file = format!("{}.yaml", dependency.name);
+                let documents = match config.provider {
+                    GitOpsProvider::Flux => {
+                        flux_release(config, dependency, owner, repository, chart, target_namespace, interval, &charts)
+                    }
+                    GitOpsProvider::ArgoCD => vec![argocd_application(config, dependency, owner, repository, chart, &target_namespace, wave)?],
+                };
+                
+                files.insert(file.clone(), to_yaml(&documents)?);
+                resources.push(file);
+            }
+            Rendering::Resources { resource, namespace: target_namespace } => {
+                let kustomization = json!({
+                    "apiVersion": "kustomize.config.k8s.io/v1beta1",
+                    "kind": "Kustomization",
+                    "namespace": target_namespace,
+                    "resources": [resource],
+                });
+                
+                files.insert(format!("{}/kustomization.yaml", dependency.name), to_yaml(&[kustomization])?);
+                resources.push(dependency.name.clone());
+            }
+        }
+    }
+    
+    let kustomization = json!({
+        "apiVersion": "kustomize.config.k8s.io/v1beta1",
+        "kind": "Kustomization",
+        "resources": resources,
+    });
+    files.insert("kustomization.yaml".to_string(), to_yaml(&[kustomization])?);
+    
+    Ok(files)
+}
+
+/// HelmRepository and HelmRelease, with `values_from` referenced natively so
+/// that Secret values never reach the repository. OCI charts pinned to a
+/// digest come from an OCIRepository instead where the Flux APIs allow it.
+#[allow(clippy::too_many_arguments)]
+fn flux_release(
+    config: &GitOpsConfig,
+    dependency: &Dependency,
+    owner: &DependencyManager,
+    repository: String,
+    chart: String,
+    target_namespace: String,
+    interval: &str,
+    charts: &[&str],
+) -> Vec<Value> {
+    let namespace = owner.namespace().unwrap_or_default();
+    let name = object_name(owner, Some(&dependency.name), false);
+    let api_version = config.flux
+        .as_ref()
+        .and_then(|f| f.api_version)
+        .unwrap_or(FluxApiVersion::V1);
+    let apis = flux::FluxApis::from(api_version);
+    let repository_is_oci = repository.starts_with(oci::SCHEME);
+    
+    // HelmRelease `chartRef` needs the v2 API
+    let digest = dependency.source.digest
+        .as_deref()
+        .filter(|_| repository_is_oci && matches!(api_version, FluxApiVersion::V1));
+    
+    let release = FluxHelmRelease {
+        name: dependency.name.clone(),
+        repository,
+        chart,
+        version: dependency.version.clone(),
+        target_namespace: Some(target_namespace),
+        values: dependency.values.clone(),
+        interval: None,
+        timeout: None,
+        secret_ref: dependency.source.secret_ref.clone().filter(|_| repository_is_oci),
+        depends_on: None,
+    };
+    let depends_on: Vec<String> = dependency
+        .depends_on
+        .iter()
+        .flatten()
+        .filter(|parent| charts.contains(&parent.as_str()))
+        .map(|parent| object_name(owner, Some(parent), false))
+        .collect();
+    
+    let mut helm_release = flux::helm_release(apis, &release, &name, &namespace, interval, &depends_on);
+    if let Some(references) = &dependency.values_from {
+        helm_release["spec"]["valuesFrom"] = references
+            .iter()
+            .map(|reference| {
+                let mut from = json!({ "kind": reference.kind, "name": reference.name });
+                if let Some(key) = &reference.values_key {
+                    from["valuesKey"] = json!(key);
+                }
+                from
+            })
+            .collect();
+    }
+    
+    let source = match digest {
+        Some(digest) => {
+            if let Some(spec) = helm_release["spec"].as_object_mut() {
+                spec.remove("chart");
+                spec.insert("chartRef".to_string(), json!({ "kind": "OCIRepository", "name": name }));
+            }
+            flux::oci_repository(apis, &release, &name, &namespace, interval, digest)
+        }
+        None => flux::helm_repository(apis, &release, &name, &namespace, interval),
+    };
+    
+    vec![source, helm_release]
+}
+
+/// Application installing the chart, ordered by sync wave
+fn argocd_application(
+    config: &GitOpsConfig,
+    dependency: &Dependency,
+    owner: &DependencyManager,
+    repository: String,
+    chart: String,
+    target_namespace: &str,
+    wave: i32,
+) -> Result<Value, Error> {
+    if dependency.values_from.is_some() {
+        return Err(Error::DependencyError(format!(
+            "{}: values_from cannot be committed for ArgoCD; use inline values",
+            dependency.name
+        )));
+    }
+    
+    let argocd_config = config.argocd.as_ref();
+    let mut helm = json!({ "releaseName": dependency.name });
+    if let Some(values) = &dependency.values {
+        helm["valuesObject"] = json!(values);
+    }
+    
+    // Argo CD addresses OCI Helm repositories without the scheme
+    let source = json!({
+        "repoURL": repository.strip_prefix(oci::SCHEME).unwrap_or(&repository),
+        "chart": chart,
+        "targetRevision": dependency.version.as_deref().unwrap_or("*"),
+        "helm": helm,
+    });
+    
+    let mut destination = argocd::destination(argocd_config.and_then(|a| a.destination.as_ref()), target_namespace);
+    destination["namespace"] = json!(target_namespace);
+    
+    Ok(argocd::application(
+        &object_name(owner, Some(&dependency.name), true),
+        controller_namespace(config),
+        &super::argocd_project(config, owner),
+        vec![source],
+        destination,
+        argocd::sync_policy(config.sync_policy.as_ref(), argocd_config),
+        Some(wave),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crd::{DependencySource, ValuesReference, ValuesReferenceKind};
+    
+    fn dependency(name: &str, type_: DependencyType, repo: &str, chart: Option<&str>) -> Dependency {
+        Dependency {
+            name: name.to_string(),
+            type_,
+            source: DependencySource {
+                repo: repo.to_string(),
+                chart: chart.map(str::to_string),
+                path: None,
+                ref_: None,
+                secret_ref: None,
+                digest: None,
+            },
+            version: Some("1.2.3".to_string()),
+            namespace: None,
+            values: None,
+            values_from: None,
+            depends_on: None,
+            enabled: true,
+        }
+    }
+    
+    fn owner(dependencies: Vec<Dependency>) -> DependencyManager {
+        let mut dm = crate::crd::tests::owner("team-a", "platform");
+        dm.spec.dependencies = dependencies;
+        dm
+    }
+    
+    fn config(provider: GitOpsProvider) -> GitOpsConfig {
+        serde_json::from_value(json!({
+            "provider": provider,
+            "repository": "https://example.com/platform",
+            "branch": "main",
+            "path": "clusters/prod",
+        }))
+        .unwrap()
+    }
+    
+    #[test]
+    fn renders_flux_releases_and_kustomizations() {
+        let mut cert_manager = dependency("cert-manager", DependencyType::Helm, "https://charts.jetstack.io", Some("cert-manager"));
+        cert_manager.values_from = Some(vec![ValuesReference {
+            kind: ValuesReferenceKind::Secret,
+            name: "cert-manager-values".to_string(),
+            values_key: None,
+        }]);
+        let mut issuers = dependency("issuers", DependencyType::Kustomize, "https://github.com/org/issuers", None);
+        issuers.source.path = Some("overlays/prod".to_string());
+        issuers.depends_on = Some(vec!["cert-manager".to_string()]);
+        
+        let files = render_dependencies(&config(GitOpsProvider::Flux), &owner(vec![cert_manager, issuers]), "5m").unwrap();
+        
+        assert_eq!(
+            files.keys().collect::<Vec<_>>(),
+            ["cert-manager.yaml", "issuers/kustomization.yaml", "kustomization.yaml"]
+        );
+        assert!(files["kustomization.yaml"].contains("- cert-manager.yaml\n- issuers\n"));
+        assert!(files["cert-manager.yaml"].contains("kind: HelmRelease"));
+        assert!(files["cert-manager.yaml"].contains("name: cert-manager-values"));
+        assert!(files["issuers/kustomization.yaml"].contains("https://github.com/org/issuers//overlays/prod?ref=1.2.3"));
+    }
+    
+    #[test]
+    fn orders_argo_applications_by_sync_wave() {
+        let crossplane = dependency("crossplane", DependencyType::Operator, "", None);
+        let mut providers = dependency("providers", DependencyType::Helm, "https://charts.example.com", Some("providers"));
+        providers.depends_on = Some(vec!["crossplane".to_string()]);
+        
+        let files = render_dependencies(&config(GitOpsProvider::ArgoCD), &owner(vec![crossplane, providers]), "5m").unwrap();
+        
+        assert!(files["crossplane.yaml"].contains("argocd.argoproj.io/sync-wave: '0'"));
+        assert!(files["crossplane.yaml"].contains("namespace: crossplane-system"));
+        assert!(files["providers.yaml"].contains("argocd.argoproj.io/sync-wave: '1'"));
+    }
+    
+    #[test]
+    fn renders_digest_pinned_oci_charts() {
+        let mut loki = dependency("loki", DependencyType::Helm, "oci://ghcr.io/acme/charts", Some("loki"));
+        loki.source.secret_ref = Some("ghcr".to_string());
+        loki.source.digest = Some("sha256:0123".to_string());
+        let mut promtail = dependency("promtail", DependencyType::Helm, "oci://ghcr.io/acme/charts", Some("promtail"));
+        promtail.source.secret_ref = Some("ghcr".to_string());
+        
+        let files = render_dependencies(&config(GitOpsProvider::Flux), &owner(vec![loki, promtail]), "5m").unwrap();
+        
+        assert!(files["loki.yaml"].contains("kind: OCIRepository"));
+        assert!(files["loki.yaml"].contains("url: oci://ghcr.io/acme/charts/loki"));
+        assert!(files["loki.yaml"].contains("digest: sha256:0123"));
+        assert!(files["loki.yaml"].contains("chartRef:\n    kind: OCIRepository"));
+        assert!(files["loki.yaml"].contains("name: ghcr"));
+        assert!(files["promtail.yaml"].contains("type: oci"));
+        assert!(files["promtail.yaml"].contains("name: ghcr"));
+        
+        let files = render_dependencies(&config(GitOpsProvider::ArgoCD), &owner(vec![dependency("loki", DependencyType::Helm, "oci://ghcr.io/acme/charts", Some("loki"))]), "5m").unwrap();
+        assert!(files["loki.yaml"].contains("repoURL: ghcr.io/acme/charts"));
+    }
+    
+    #[test]
+    fn rejects_dependency_cycles() {
+        let mut a = dependency("a", DependencyType::Yaml, "https://example.com/a.yaml", None);
+        let mut b = dependency("b", DependencyType::Yaml, "https://example.com/b.yaml", None);
+        a.depends_on = Some(vec!["b".to_string()]);
+        b.depends_on = Some(vec!["a".to_string()]);
+        
+        assert!(render_dependencies(&config(GitOpsProvider::Flux), &owner(vec![a, b]), "5m").is_err());
+    }
+}
@@ -0,0 +1,202 @@
+use serde_json::Value;
+
+use crate::crd::{GitOpsProvider, GitOpsSourceStatus, GitOpsStatus};
+
+pub const SYNCED: &str = "Synced";
+pub const OUT_OF_SYNC: &str = "OutOfSync";
+pub const PROGRESSING: &str = "Progressing";
+pub const FAILED: &str = "Failed";
+pub const UNKNOWN: &str = "Unknown";
+
+/// Condition of the given type from `status.conditions`
+fn condition<'a>(object: &'a Value, type_: &str) -> Option<&'a Value> {
+    object["status"]["conditions"]
+        .as_array()?
+        .iter()
+        .find(|condition| condition["type"] == type_)
+}
+
+fn string(value: &Value) -> Option<String> {
+    value.as_str().map(str::to_string)
+}
+
+/// Status of a Flux Kustomization, with fetch failures of its GitRepository
+/// taking precedence since they keep the Kustomization from ever syncing
+pub fn flux(name: &str, kustomization: Option<&Value>, repository: Option<&Value>) -> GitOpsSourceStatus {
+    let mut status = GitOpsSourceStatus {
+        name: name.to_string(),
+        sync_status: UNKNOWN.to_string(),
+        health: None,
+        revision: None,
+        last_sync: None,
+        message: None,
+    };
+    
+    let Some(kustomization) = kustomization else {
+        status.message = Some("Kustomization not found".to_string());
+        return status;
+    };
+    
+    status.revision = string(&kustomization["status"]["lastAppliedRevision"]);
+    
+    if let Some(ready) = condition(kustomization, "Ready") {
+        status.sync_status = match (ready["status"].as_str(), ready["reason"].as_str()) {
+            (Some("True"), _) => SYNCED,
+            (Some("False"), Some("Progressing" | "DependencyNotReady")) | (Some("Unknown"), _) => PROGRESSING,
+            (Some("False"), _) => FAILED,
+            _ => UNKNOWN,
+        }
+        .to_string();
+        status.last_sync = string(&ready["lastTransitionTime"]);
+        if status.sync_status != SYNCED {
+            status.message = string(&ready["message"]);
+        }
+    }
+    
+    if let Some(ready) = repository.and_then(|r| condition(r, "Ready")) {
+        if ready["status"] == "False" {
+            status.sync_status = FAILED.to_string();
+            status.message = string(&ready["message"]).map(|m| format!("GitRepository: {}", m));
+        }
+    }
+    
+    status
+}
+
+/// Status of an Argo Application from its sync, health and last operation
+pub fn argocd(name: &str, application: Option<&Value>) -> GitOpsSourceStatus {
+    let mut status = GitOpsSourceStatus {
+        name: name.to_string(),
+        sync_status: UNKNOWN.to_string(),
+        health: None,
+        revision: None,
+        last_sync: None,
+        message: None,
+    };
+    
+    let Some(application) = application else {
+        status.message = Some("Application not found".to_string());
+        return status;
+    };
+    
+    let app_status = &application["status"];
+    let operation = &app_status["operationState"];
+    
+    status.health = string(&app_status["health"]["status"]);
+    status.revision = string(&app_status["sync"]["revision"])
+        .or_else(|| string(&app_status["sync"]["revisions"][0]));
+    status.last_sync = string(&operation["finishedAt"]).or_else(|| string(&app_status["reconciledAt"]));
+    
+    if let Some(sync) = app_status["sync"]["status"].as_str() {
+        status.sync_status = sync.to_string();
+    }
+    match operation["phase"].as_str() {
+        Some("Failed" | "Error") => {
+            status.sync_status = FAILED.to_string();
+            status.message = string(&operation["message"]);
+        }
+        Some("Running") => status.sync_status = PROGRESSING.to_string(),
+        _ => {}
+    }
+    if status.health.as_deref() == Some("Degraded") && status.message.is_none() {
+        status.message = string(&app_status["health"]["message"]);
+    }
+    
+    status
+}
+
+/// Overall status of `sources`, the first of which syncs the primary repository.
+///
+/// Any failure (or degraded Argo health) fails the whole, otherwise any
+/// out-of-sync source makes it OutOfSync, and anything short of all sources
+/// being Synced is Progressing.
+pub fn aggregate(provider: GitOpsProvider, sources: Vec<GitOpsSourceStatus>) -> GitOpsStatus {
+    let any = |state: &str| sources.iter().any(|s| s.sync_status == state);
+    
+    let sync_status = if any(FAILED) || sources.iter().any(|s| s.health.as_deref() == Some("Degraded")) {
+        FAILED
+    } else if any(OUT_OF_SYNC) {
+        OUT_OF_SYNC
+    } else if sources.iter().all(|s| s.sync_status == SYNCED) {
+        SYNCED
+    } else {
+        PROGRESSING
+    };
+    
+    GitOpsStatus {
+        provider,
+        sync_status: sync_status.to_string(),
+        last_sync: sources.iter().filter_map(|s| s.last_sync.clone()).max(),
+        revision: sources.first().and_then(|s| s.revision.clone()),
+        last_commit: None,
+        sources: Some(sources),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    #[test]
+    fn flux_ready_kustomization_is_synced() {
+        let kustomization = json!({
+            "status": {
+                "lastAppliedRevision": "main@sha1:abc123",
+                "conditions": [{
+                    "type": "Ready",
+                    "status": "True",
+                    "reason": "ReconciliationSucceeded",
+                    "lastTransitionTime": "2024-05-01T10:00:00Z",
+                }],
+            },
+        });
+        
+        let status = flux("platform", Some(&kustomization), None);
+        assert_eq!(status.sync_status, SYNCED);
+        assert_eq!(status.revision.as_deref(), Some("main@sha1:abc123"));
+        assert_eq!(status.last_sync.as_deref(), Some("2024-05-01T10:00:00Z"));
+    }
+    
+    #[test]
+    fn flux_repository_failure_fails_source() {
+        let kustomization = json!({
+            "status": { "conditions": [{ "type": "Ready", "status": "False", "reason": "Progressing" }] },
+        });
+        let repository = json!({
+            "status": { "conditions": [{ "type": "Ready", "status": "False", "message": "authentication required" }] },
+        });
+        
+        let status = flux("platform", Some(&kustomization), Some(&repository));
+        assert_eq!(status.sync_status, FAILED);
+        assert_eq!(status.message.as_deref(), Some("GitRepository: authentication required"));
+    }
+    
+    #[test]
+    fn argocd_application_status_is_aggregated() {
+        let synced = json!({
+            "status": {
+                "sync": { "status": "Synced", "revision": "abc123" },
+                "health": { "status": "Healthy" },
+                "operationState": { "phase": "Succeeded", "finishedAt": "2024-05-01T10:00:00Z" },
+            },
+        });
+        let out_of_sync = json!({
+            "status": {
+                "sync": { "status": "OutOfSync", "revision": "def456" },
+                "health": { "status": "Healthy" },
+                "reconciledAt": "2024-05-01T11:00:00Z",
+            },
+        });
+        
+        let sources = vec![argocd("a", Some(&synced)), argocd("b", Some(&out_of_sync))];
+        let status = aggregate(GitOpsProvider::ArgoCD, sources);
+        
+        assert_eq!(status.sync_status, OUT_OF_SYNC);
+        assert_eq!(status.revision.as_deref(), Some("abc123"));
+        assert_eq!(status.last_sync.as_deref(), Some("2024-05-01T11:00:00Z"));
+        
+        let status = aggregate(GitOpsProvider::ArgoCD, vec![argocd("a", None)]);
+        assert_eq!(status.sync_status, PROGRESSING);
+    }
+}
@@ -0,0 +1,54 @@
+/// Matches `value` against a shell-style pattern where `*` matches any run of
+/// characters (including `/`) and `?` matches exactly one character.
+pub fn matches(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    v = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Returns true when `value` matches any of `patterns`
+pub fn matches_any<S: AsRef<str>>(patterns: &[S], value: &str) -> bool {
+    patterns.iter().any(|pattern| matches(pattern.as_ref(), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("*", "anything"));
+        assert!(matches("team-*", "team-a"));
+        assert!(matches("release/*", "release/1.2"));
+        assert!(matches("v?.*", "v1.14"));
+        assert!(matches("*-system", "crossplane-system"));
+        assert!(!matches("team-*", "other"));
+        assert!(!matches("main", "main2"));
+        assert!(matches_any(&["main", "release/*"], "release/x"));
+    }
+}
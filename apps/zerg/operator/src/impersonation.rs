@@ -0,0 +1,118 @@
+use std::process::Command;
+
+use kube::{Client, ResourceExt};
+
+use crate::crd::DependencyManager;
+use crate::error::Error;
+
+/// Identity the operator acts as when installing on behalf of a DependencyManager
+#[derive(Debug, Clone)]
+pub struct Impersonation {
+    /// Impersonated user name
+    pub user: String,
+    
+    /// Impersonated groups
+    pub groups: Vec<String>,
+}
+
+impl Impersonation {
+    /// Impersonates the ServiceAccount `name` in `namespace`, including the
+    /// groups the API server would assign to its token.
+    pub fn service_account(namespace: &str, name: &str) -> Self {
+        Self {
+            user: format!("system:serviceaccount:{}:{}", namespace, name),
+            groups: vec![
+                "system:serviceaccounts".to_string(),
+                format!("system:serviceaccounts:{}", namespace),
+                "system:authenticated".to_string(),
+            ],
+        }
+    }
+    
+    /// Builds a client from the operator's own configuration that impersonates this identity
+    pub fn client(&self, base: &kube::Config) -> Result<Client, Error> {
+        let mut config = base.clone();
+        config.auth_info.impersonate = Some(self.user.clone());
+        config.auth_info.impersonate_groups = Some(self.groups.clone());
+        
+        Client::try_from(config).map_err(Error::KubeError)
+    }
+    
+    /// Adds the impersonation flags understood by `program` to `cmd`
+    pub fn apply_to(&self, program: &str, cmd: &mut Command) {
+        let (user_flag, group_flag) = match program {
+            "helm" => ("--kube-as-user", "--kube-as-group"),
+            _ => ("--as", "--as-group"),
+        };
+        
+        cmd.arg(format!("{}={}", user_flag, self.user));
+        for group in &self.groups {
+            cmd.arg(format!("{}={}", group_flag, group));
+        }
+    }
+}
+
+/// Client and identity used on behalf of `dm`, impersonating its
+/// `service_account_name` when one is set
+pub fn owner_client(
+    client: &Client,
+    base: &kube::Config,
+    dm: &DependencyManager,
+) -> Result<(Client, Option<Impersonation>), Error> {
+    let namespace = dm.namespace().unwrap_or_default();
+    
+    match dm.spec.service_account_name.as_deref() {
+        Some(service_account) => {
+            let identity = Impersonation::service_account(&namespace, service_account);
+            Ok((identity.client(base)?, Some(identity)))
+        }
+        None => Ok((client.clone(), None)),
+    }
+}
+
+/// Creates a `Command` for a cluster CLI, impersonating `impersonation` if set
+pub fn command(program: &str, impersonation: Option<&Impersonation>) -> Command {
+    let mut cmd = Command::new(program);
+    if let Some(impersonation) = impersonation {
+        impersonation.apply_to(program, &mut cmd);
+    }
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn args(cmd: &Command) -> Vec<String> {
+        cmd.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect()
+    }
+    
+    #[test]
+    fn service_accounts_carry_their_groups() {
+        let identity = Impersonation::service_account("team-a", "installer");
+        
+        assert_eq!(identity.user, "system:serviceaccount:team-a:installer");
+        assert_eq!(identity.groups, ["system:serviceaccounts", "system:serviceaccounts:team-a", "system:authenticated"]);
+    }
+    
+    #[test]
+    fn flags_match_each_cli() {
+        let identity = Impersonation::service_account("team-a", "installer");
+        
+        assert_eq!(args(&command("helm", Some(&identity))), [
+            "--kube-as-user=system:serviceaccount:team-a:installer",
+            "--kube-as-group=system:serviceaccounts",
+            "--kube-as-group=system:serviceaccounts:team-a",
+            "--kube-as-group=system:authenticated",
+        ]);
+        for program in ["kubectl", "flux"] {
+            assert_eq!(args(&command(program, Some(&identity))), [
+                "--as=system:serviceaccount:team-a:installer",
+                "--as-group=system:serviceaccounts",
+                "--as-group=system:serviceaccounts:team-a",
+                "--as-group=system:authenticated",
+            ]);
+        }
+        assert!(args(&command("kubectl", None)).is_empty());
+    }
+}
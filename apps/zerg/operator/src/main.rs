@@ -1,32 +1,237 @@
 use anyhow::Result;
-use clap::Parser;
-use kube::Client;
+use clap::{Parser, Subcommand};
+use kube::{Client, ResourceExt};
+use std::path::PathBuf;
 use std::sync::Arc;
-use tracing::{info, instrument};
-use tracing_subscriber::{prelude::*, EnvFilter};
+use tracing::{info, instrument, warn};
+use tracing_subscriber::{prelude::*, EnvFilter, Registry};
 
 mod crd;
 mod controller;
 mod dependencies;
 mod gitops;
 mod cicd;
+mod charts;
 mod config;
 mod error;
+mod glob;
+mod impersonation;
+mod oci;
+mod reload;
+mod resources;
+mod server;
+mod tenancy;
+mod updates;
 
+use cicd::hosted;
+use cicd::local::{self, Runtime};
+use cicd::RunTracker;
+use config::{Config, Overrides, SharedConfig};
 use controller::DependencyController;
+use reload::ConfigReloader;
+use server::WebhookServer;
 
 #[derive(Parser)]
 #[command(name = "zerg-operator")]
 #[command(about = "Kubernetes operator for managing dependencies and GitOps workflows")]
 struct Args {
-    #[arg(short, long, default_value = "info")]
-    log_level: String,
+    /// Log filter; overrides `operator.log_level`
+    #[arg(short, long)]
+    log_level: Option<String>,
     
-    #[arg(short, long, default_value = "zerg-system")]
-    namespace: String,
+    /// Namespace the operator runs in; overrides `operator.default_namespace`
+    #[arg(short, long)]
+    namespace: Option<String>,
     
     #[arg(short, long, default_value = "/etc/zerg/config.yaml")]
     config_path: String,
+    
+    /// ConfigMap in the operator's namespace whose `config.yaml` key overrides
+    /// the config file
+    #[arg(long)]
+    config_map: Option<String>,
+    
+    /// Fail when the configuration is missing or has unknown settings instead
+    /// of using the defaults
+    #[arg(long)]
+    strict_config: bool,
+    
+    /// Namespaces to watch for DependencyManagers (comma separated, `*` for all);
+    /// overrides `operator.watch_namespaces` from the config file
+    #[arg(long, value_delimiter = ',')]
+    watch_namespaces: Option<Vec<String>>,
+    
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Work with the pipelines of DependencyManager manifests
+    Pipeline {
+        #[command(subcommand)]
+        command: PipelineCommands,
+    },
+    
+    /// Inspect the operator configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print the effective configuration, with the source of every value
+    Dump,
+}
+
+#[derive(Subcommand)]
+enum PipelineCommands {
+    /// Run a pipeline on this machine, without a cluster
+    Run {
+        /// Manifests holding the DependencyManager
+        #[arg(short, long)]
+        file: PathBuf,
+        
+        /// Pipeline to run
+        #[arg(short, long)]
+        pipeline: String,
+        
+        /// DependencyManager to take the pipeline from, when the file holds several
+        #[arg(long)]
+        name: Option<String>,
+        
+        /// Param overrides as `name=value`; repeat or separate with commas
+        #[arg(long = "param")]
+        params: Vec<String>,
+        
+        /// Where steps run: containers of a runtime, or local processes
+        #[arg(long, value_enum, default_value = "auto")]
+        runtime: Runtime,
+        
+        /// Directory steps run in, mounted at /workspace in containers
+        #[arg(long, default_value = ".")]
+        workdir: PathBuf,
+    },
+    
+    /// Render the pipelines of a DependencyManager into GitHub Actions or GitLab CI files
+    Render {
+        /// Manifests holding the DependencyManager
+        #[arg(short, long)]
+        file: PathBuf,
+        
+        /// DependencyManager to render, when the file holds several
+        #[arg(long)]
+        name: Option<String>,
+        
+        /// Provider to render for (`github-actions` or `gitlab-ci`), defaults to
+        /// the DependencyManager's
+        #[arg(long)]
+        provider: Option<String>,
+        
+        /// Repository checkout to write the files to; they are printed when unset
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Runs pipeline `pipeline` from `file` locally and prints a summary, failing
+/// when a step failed
+fn run_pipeline(config: Arc<Config>, command: PipelineCommands) -> Result<()> {
+    let (file, pipeline, name, params, runtime, workdir) = match command {
+        PipelineCommands::Run { file, pipeline, name, params, runtime, workdir } => {
+            (file, pipeline, name, params, runtime, workdir)
+        }
+        PipelineCommands::Render { file, name, provider, output } => {
+            return render_pipelines(&config, file, name, provider, output);
+        }
+    };
+    
+    let manifests = std::fs::read_to_string(&file)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", file.display(), e))?;
+    let pipeline = local::load_pipeline(&manifests, name.as_deref(), &pipeline, &config)?;
+    let params = local::params(&pipeline, &workdir, local::parse_params(&params)?)?;
+    
+    let summary = local::run(&pipeline, &params, local::engine(runtime)?, &workdir)?;
+    println!("\n{}", summary);
+    
+    if !summary.succeeded() {
+        anyhow::bail!("Pipeline {} failed", pipeline.name);
+    }
+    Ok(())
+}
+
+/// Renders the pipelines of the DependencyManager in `file` for a hosted CI
+/// service, writing the files below `output` or printing them, and prints the
+/// compatibility reports to stderr
+fn render_pipelines(
+    config: &Config,
+    file: PathBuf,
+    name: Option<String>,
+    provider: Option<String>,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let manifests = std::fs::read_to_string(&file)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", file.display(), e))?;
+    let dm = local::load_manager(&manifests, name.as_deref())?;
+    let cicd = dm
+        .spec
+        .cicd
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("{} has no CI/CD configuration", dm.name_any()))?;
+    
+    let provider: crd::CiCdProvider = match provider {
+        Some(provider) => serde_json::from_value(serde_json::Value::String(provider.clone()))
+            .map_err(|_| anyhow::anyhow!("Unknown provider {}", provider))?,
+        None => cicd.provider.clone(),
+    };
+    if !hosted::is_hosted(&provider) {
+        anyhow::bail!("{} pipelines are not rendered into files", hosted::service(&provider));
+    }
+    
+    let template = config.cicd_templates.get(provider.template_name());
+    let cicd = cicd::prepare(cicd, template)?;
+    let owner = format!("{}/{}", dm.namespace().unwrap_or_else(|| "default".to_string()), dm.name_any());
+    
+    for rendering in hosted::render(&provider, &cicd.pipelines, template, &owner)? {
+        for (name, content) in &rendering.files {
+            let path = std::path::Path::new(rendering.path).join(name);
+            let path = path.strip_prefix(".").unwrap_or(&path);
+            match &output {
+                Some(output) => {
+                    let path = output.join(path);
+                    if let Some(parent) = path.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    std::fs::write(&path, content)
+                        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+                    println!("Wrote {}", path.display());
+                }
+                None => println!("# {}\n{}", path.display(), content),
+            }
+        }
+        for (pipeline, report) in &rendering.reports {
+            for line in report {
+                eprintln!("{}: {}", pipeline, line);
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// Applies `operator.log_level` now and whenever the configuration changes
+async fn follow_log_level(mut config: SharedConfig, filter: tracing_subscriber::reload::Handle<EnvFilter, Registry>) {
+    loop {
+        let log_level = config.borrow_and_update().operator.log_level.clone();
+        if let Err(e) = filter.reload(EnvFilter::new(&log_level)) {
+            warn!("Failed to apply log level {}: {}", log_level, e);
+        }
+        if config.changed().await.is_err() {
+            return;
+        }
+    }
 }
 
 #[tokio::main]
@@ -34,23 +239,55 @@ struct Args {
 async fn main() -> Result<()> {
     let args = Args::parse();
     
-    // Initialize tracing
+    // Initialize tracing; the filter follows `operator.log_level` once the configuration is loaded
+    let (filter, filter_handle) = tracing_subscriber::reload::Layer::new(EnvFilter::new(args.log_level.as_deref().unwrap_or("info")));
     tracing_subscriber::registry()
-        .with(EnvFilter::new(&args.log_level))
+        .with(filter)
         .with(tracing_subscriber::fmt::layer())
         .init();
-
+    
+    let overrides = Overrides {
+        namespace: args.namespace,
+        log_level: args.log_level,
+        watch_namespaces: args.watch_namespaces,
+    };
+    
+    match args.command {
+        Some(Commands::Pipeline { command }) => {
+            let (_, config) = ConfigReloader::load(args.config_path, None, overrides, args.strict_config).await?;
+            let config = config.borrow().clone();
+            return run_pipeline(config, command);
+        }
+        Some(Commands::Config { command: ConfigCommands::Dump }) => {
+            let config_map = match args.config_map {
+                Some(name) => Some((Client::try_default().await?, name)),
+                None => None,
+            };
+            let (reloader, _) = ConfigReloader::load(args.config_path, config_map, overrides, args.strict_config).await?;
+            for setting in reloader.layers().settings() {
+                println!("{}", setting);
+            }
+            return Ok(());
+        }
+        None => {}
+    }
+    
     info!("Starting Zerg Operator");
     
-    // Create Kubernetes client
-    let client = Client::try_default().await?;
+    // Create Kubernetes client; the inferred config is kept for impersonating clients
+    let kube_config = kube::Config::infer().await?;
+    let client = Client::try_from(kube_config.clone())?;
     
-    // Load configuration
-    let config = config::load_config(&args.config_path).await?;
+    // Load configuration, which is reloaded when its sources change
+    let config_map = args.config_map.map(|name| (client.clone(), name));
+    let (reloader, config) = ConfigReloader::load(args.config_path, config_map, overrides, args.strict_config).await?;
+    tokio::spawn(follow_log_level(config.clone(), filter_handle));
     
-    // Create and start the controller
-    let controller = DependencyController::new(client, Arc::new(config));
-    controller.run().await?;
+    // Create and start the controller, next to the webhook receiver and the run history tracker
+    let server = WebhookServer::new(client.clone(), kube_config.clone(), config.clone());
+    let tracker = RunTracker::new(client.clone(), config.clone());
+    let controller = DependencyController::new(client, kube_config, config);
+    tokio::try_join!(controller.run(), server.run(), tracker.run(), reloader.run())?;
     
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::json;
+
+use super::{digest, tag, CHART_MEDIA_TYPE, MANIFEST_MEDIA_TYPE, SCHEME};
+
+/// Token the local registry hands out and accepts
+const TOKEN: &str = "local-registry-token";
+
+#[derive(Default)]
+struct Contents {
+    /// Repository to tag to manifest digest
+    tags: HashMap<String, HashMap<String, String>>,
+    /// Digest to manifest or blob
+    blobs: HashMap<String, Vec<u8>>,
+}
+
+struct Shared {
+    address: String,
+    /// `Authorization` header the token endpoint requires, if any
+    basic: Option<String>,
+    contents: Mutex<Contents>,
+}
+
+/// In-process OCI registry serving Helm charts over the distribution API,
+/// optionally behind token authentication, for tests
+pub struct LocalRegistry {
+    shared: Arc<Shared>,
+}
+
+impl LocalRegistry {
+    /// Starts a registry on a free loopback port, requiring `credentials` if set
+    pub async fn start(credentials: Option<(&str, &str)>) -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let shared = Arc::new(Shared {
+            address: listener.local_addr().unwrap().to_string(),
+            basic: credentials.map(|(username, password)| format!("Basic {}", BASE64.encode(format!("{}:{}", username, password)))),
+            contents: Mutex::new(Contents::default()),
+        });
+        
+        let app = Router::new()
+            .route("/token", get(token))
+            .route("/v2/{*path}", get(serve))
+            .with_state(shared.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        
+        Self { shared }
+    }
+    
+    /// `oci://` URL of `namespace` in this registry
+    pub fn url(&self, namespace: &str) -> String {
+        format!("{}{}/{}", SCHEME, self.shared.address, namespace)
+    }
+    
+    /// Pushes chart `archive` as `version` of `repository`, returning the manifest digest
+    pub fn push(&self, repository: &str, version: &str, archive: &[u8]) -> String {
+        let layer = digest(archive);
+        let manifest = json!({
+            "schemaVersion": 2,
+            "mediaType": MANIFEST_MEDIA_TYPE,
+            "config": {
+                "mediaType": "application/vnd.cncf.helm.config.v1+json",
+                "digest": digest(b"{}"),
+                "size": 2,
+            },
+            "layers": [{ "mediaType": CHART_MEDIA_TYPE, "digest": layer, "size": archive.len() }],
+        });
+        let manifest = serde_json::to_vec(&manifest).unwrap();
+        let manifest_digest = digest(&manifest);
+        
+        let mut contents = self.shared.contents.lock().unwrap();
+        contents.blobs.insert(layer, archive.to_vec());
+        contents.blobs.insert(manifest_digest.clone(), manifest);
+        contents.tags.entry(repository.to_string()).or_default().insert(tag(version), manifest_digest.clone());
+        manifest_digest
+    }
+    
+    /// Replaces the content stored under `digest`, as a compromised registry might
+    pub fn tamper(&self, digest: &str, content: &[u8]) {
+        self.shared.contents.lock().unwrap().blobs.insert(digest.to_string(), content.to_vec());
+    }
+}
+
+async fn token(State(shared): State<Arc<Shared>>, headers: HeaderMap) -> Response {
+    let authorization = headers.get(header::AUTHORIZATION).and_then(|value| value.to_str().ok());
+    if shared.basic.is_some() && authorization != shared.basic.as_deref() {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    
+    Json(json!({ "token": TOKEN })).into_response()
+}
+
+async fn serve(State(shared): State<Arc<Shared>>, Path(path): Path<String>, headers: HeaderMap) -> Response {
+    let authorization = headers.get(header::AUTHORIZATION).and_then(|value| value.to_str().ok());
+    if shared.basic.is_some() && authorization != Some(format!("Bearer {}", TOKEN).as_str()) {
+        let challenge = format!(r#"Bearer realm="http://{}/token",service="{}""#, shared.address, shared.address);
+        return (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, challenge)]).into_response();
+    }
+    
+    let contents = shared.contents.lock().unwrap();
+    if let Some(repository) = path.strip_suffix("/tags/list") {
+        let tags: Vec<&String> = contents.tags.get(repository).map(|tags| tags.keys().collect()).unwrap_or_default();
+        return Json(json!({ "name": repository, "tags": tags })).into_response();
+    }
+    
+    let found = if let Some((repository, reference)) = path.split_once("/manifests/") {
+        let digest = match reference.starts_with("sha256:") {
+            true => Some(reference.to_string()),
+            false => contents.tags.get(repository).and_then(|tags| tags.get(reference)).cloned(),
+        };
+        digest.and_then(|digest| contents.blobs.get(&digest).map(|manifest| (digest, MANIFEST_MEDIA_TYPE, manifest)))
+    } else if let Some((_, digest)) = path.split_once("/blobs/") {
+        contents.blobs.get(digest).map(|blob| (digest.to_string(), "application/octet-stream", blob))
+    } else {
+        None
+    };
+    
+    match found {
+        Some((digest, media_type, content)) => (
+            [(header::CONTENT_TYPE, media_type.to_string()), (header::HeaderName::from_static("docker-content-digest"), digest)],
+            content.clone(),
+        ).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, Client};
+use reqwest::{header, StatusCode};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::Error;
+
+#[cfg(test)]
+pub mod local;
+
+/// Scheme of chart repositories that are OCI registries
+pub const SCHEME: &str = "oci://";
+
+/// Media type of the manifest layer holding the chart archive
+pub const CHART_MEDIA_TYPE: &str = "application/vnd.cncf.helm.chart.content.v1.tar+gzip";
+
+/// Media type of the manifests charts are pushed with
+pub const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// Data key of `kubernetes.io/dockerconfigjson` Secrets
+const DOCKER_CONFIG_KEY: &str = ".dockerconfigjson";
+
+/// A chart in an OCI registry: the `oci://` repository of a dependency and its chart name
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartReference {
+    /// Registry host, with the port if any
+    pub registry: String,
+    
+    /// Repository within the registry, ending in the chart name
+    pub repository: String,
+}
+
+impl ChartReference {
+    /// Reference to `chart` under `repository`, `None` unless that is an `oci://` URL
+    pub fn new(repository: &str, chart: &str) -> Option<Self> {
+        let path = repository.strip_prefix(SCHEME)?.trim_end_matches('/');
+        let (registry, namespace) = path.split_once('/').unwrap_or((path, ""));
+        let repository = match namespace {
+            "" => chart.to_string(),
+            namespace => format!("{}/{}", namespace, chart),
+        };
+        
+        Some(Self { registry: registry.to_string(), repository })
+    }
+    
+    /// URL of registry API `endpoint` (`manifests/<reference>`, `blobs/<digest>`,
+    /// `tags/list`) for this repository. Loopback registries are reached over
+    /// plain HTTP, as Docker does.
+    fn url(&self, endpoint: &str) -> String {
+        let host = self.registry.rsplit_once(':').map_or(self.registry.as_str(), |(host, _)| host);
+        let scheme = match host {
+            "localhost" | "127.0.0.1" | "[::1]" => "http",
+            _ => "https",
+        };
+        
+        format!("{}://{}/v2/{}/{}", scheme, self.registry, self.repository, endpoint)
+    }
+}
+
+impl fmt::Display for ChartReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}/{}", SCHEME, self.registry, self.repository)
+    }
+}
+
+/// Tag a chart version is pushed under, since tags cannot contain `+`
+pub fn tag(version: &str) -> String {
+    version.replace('+', "_")
+}
+
+/// `sha256:` digest of `content`
+pub fn digest(content: &[u8]) -> String {
+    let hash: String = Sha256::digest(content).iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256:{}", hash)
+}
+
+fn verify(content: &[u8], expected: &str) -> Result<(), Error> {
+    if !expected.starts_with("sha256:") {
+        return Err(Error::DependencyError(format!("Unsupported digest {}", expected)));
+    }
+    
+    let actual = digest(content);
+    if actual != expected {
+        return Err(Error::DependencyError(format!("Digest mismatch: expected {}, got {}", expected, actual)));
+    }
+    
+    Ok(())
+}
+
+/// Username and password for a registry
+#[derive(Debug, Clone, PartialEq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+struct DockerConfig {
+    #[serde(default)]
+    auths: HashMap<String, DockerAuth>,
+}
+
+#[derive(Deserialize)]
+struct DockerAuth {
+    username: Option<String>,
+    password: Option<String>,
+    /// Base64 of `username:password`
+    auth: Option<String>,
+}
+
+/// Credentials for `registry` in a `.dockerconfigjson` document, whose
+/// entries may be hosts or URLs such as `https://index.docker.io/v1/`
+pub fn docker_credentials(config: &[u8], registry: &str) -> Result<Option<Credentials>, Error> {
+    let config: DockerConfig = serde_json::from_slice(config)
+        .map_err(|e| Error::SerializationError(format!("Invalid {}: {}", DOCKER_CONFIG_KEY, e)))?;
+    
+    let host = |server: &str| {
+        let server = server.trim_start_matches("https://").trim_start_matches("http://");
+        server.split('/').next().unwrap_or_default().to_string()
+    };
+    let Some(auth) = config.auths.iter().find(|(server, _)| host(server) == registry).map(|(_, auth)| auth) else {
+        return Ok(None);
+    };
+    
+    if let (Some(username), Some(password)) = (&auth.username, &auth.password) {
+        return Ok(Some(Credentials { username: username.clone(), password: password.clone() }));
+    }
+    
+    let decoded = auth.auth
+        .as_deref()
+        .and_then(|auth| BASE64.decode(auth).ok())
+        .and_then(|auth| String::from_utf8(auth).ok());
+    let credentials = decoded.as_deref().and_then(|auth| auth.split_once(':')).map(|(username, password)| Credentials {
+        username: username.to_string(),
+        password: password.to_string(),
+    });
+    
+    credentials
+        .map(Some)
+        .ok_or_else(|| Error::SerializationError(format!("Invalid auth for {} in {}", registry, DOCKER_CONFIG_KEY)))
+}
+
+/// Credentials for `registry` from `kubernetes.io/dockerconfigjson` Secret `secret_ref`, if set
+pub async fn registry_credentials(
+    client: &Client,
+    namespace: &str,
+    secret_ref: Option<&str>,
+    registry: &str,
+) -> Result<Option<Credentials>, Error> {
+    let Some(name) = secret_ref else {
+        return Ok(None);
+    };
+    
+    let api: Api<Secret> = Api::namespaced(client.clone(), namespace);
+    let secret = api.get(name).await?;
+    let config = secret.data
+        .as_ref()
+        .and_then(|data| data.get(DOCKER_CONFIG_KEY))
+        .ok_or_else(|| Error::DependencyError(format!("Secret {} has no key {}", name, DOCKER_CONFIG_KEY)))?;
+    
+    docker_credentials(&config.0, registry)?
+        .map(Some)
+        .ok_or_else(|| Error::DependencyError(format!("Secret {} has no credentials for {}", name, registry)))
+}
+
+/// Parameters of a `WWW-Authenticate` challenge such as
+/// `Bearer realm="https://ghcr.io/token",service="ghcr.io"`, with the scheme lowercased
+fn challenge(header: &str) -> (String, HashMap<String, String>) {
+    let (scheme, mut rest) = header.trim().split_once(' ').unwrap_or((header.trim(), ""));
+    let mut params = HashMap::new();
+    
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_lowercase();
+        let value = value.trim_start();
+        let (value, remainder) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or_default())
+            }
+            None => {
+                let end = value.find(',').unwrap_or(value.len());
+                (&value[..end], &value[end..])
+            }
+        };
+        params.insert(key, value.to_string());
+        rest = remainder;
+    }
+    
+    (scheme.to_lowercase(), params)
+}
+
+#[derive(Deserialize)]
+struct Tags {
+    tags: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    layers: Vec<Descriptor>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Descriptor {
+    media_type: String,
+    digest: String,
+}
+
+#[derive(Deserialize)]
+struct Token {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+/// Pulls charts from OCI registries through the distribution API, answering
+/// `Basic` and `Bearer` token challenges with `credentials`
+pub struct Registry {
+    http: reqwest::Client,
+    credentials: Option<Credentials>,
+    /// `Authorization` header that answered the last challenge
+    authorization: Mutex<Option<String>>,
+}
+
+impl Registry {
+    pub fn new(credentials: Option<Credentials>) -> Self {
+        Self { http: reqwest::Client::new(), credentials, authorization: Mutex::new(None) }
+    }
+    
+    /// Chart versions pushed to `chart`
+    pub async fn versions(&self, chart: &ChartReference) -> Result<Vec<String>, Error> {
+        let tags: Tags = self.get(chart, &chart.url("tags/list"), None).await?
+            .json().await
+            .map_err(|e| Error::DependencyError(format!("Invalid tag list of {}: {}", chart, e)))?;
+        
+        Ok(tags.tags.unwrap_or_default().iter().map(|tag| tag.replace('_', "+")).collect())
+    }
+    
+    /// Digest of the manifest chart `version` is tagged with
+    pub async fn digest(&self, chart: &ChartReference, version: &str) -> Result<String, Error> {
+        let url = chart.url(&format!("manifests/{}", tag(version)));
+        let response = self.get(chart, &url, Some(MANIFEST_MEDIA_TYPE)).await?;
+        
+        let header = response.headers().get("docker-content-digest").and_then(|value| value.to_str().ok());
+        if let Some(digest) = header {
+            return Ok(digest.to_string());
+        }
+        
+        Ok(digest(&self.bytes(chart, response).await?))
+    }
+    
+    /// Chart archive of the manifest with `digest`, verified against it
+    pub async fn pull(&self, chart: &ChartReference, digest: &str) -> Result<Vec<u8>, Error> {
+        let url = chart.url(&format!("manifests/{}", digest));
+        let response = self.get(chart, &url, Some(MANIFEST_MEDIA_TYPE)).await?;
+        let manifest = self.bytes(chart, response).await?;
+        verify(&manifest, digest)?;
+        
+        let manifest: Manifest = serde_json::from_slice(&manifest)
+            .map_err(|e| Error::DependencyError(format!("Invalid manifest of {}@{}: {}", chart, digest, e)))?;
+        let layer = manifest.layers
+            .iter()
+            .find(|layer| layer.media_type == CHART_MEDIA_TYPE)
+            .ok_or_else(|| Error::DependencyError(format!("{}@{} is not a Helm chart", chart, digest)))?;
+        
+        let response = self.get(chart, &chart.url(&format!("blobs/{}", layer.digest)), None).await?;
+        let archive = self.bytes(chart, response).await?;
+        verify(&archive, &layer.digest)?;
+        
+        Ok(archive)
+    }
+    
+    async fn bytes(&self, chart: &ChartReference, response: reqwest::Response) -> Result<Vec<u8>, Error> {
+        response.bytes().await
+            .map(|bytes| bytes.to_vec())
+            .map_err(|e| Error::DependencyError(format!("Failed to read from {}: {}", chart, e)))
+    }
+    
+    async fn send(&self, url: &str, accept: Option<&str>, authorization: Option<&str>) -> Result<reqwest::Response, Error> {
+        let mut request = self.http.get(url);
+        if let Some(accept) = accept {
+            request = request.header(header::ACCEPT, accept);
+        }
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        
+        request.send().await
+            .map_err(|e| Error::DependencyError(format!("Failed to fetch {}: {}", url, e)))
+    }
+    
+    /// GET with the last authorization, answering a challenge once
+    async fn get(&self, chart: &ChartReference, url: &str, accept: Option<&str>) -> Result<reqwest::Response, Error> {
+        let authorization = self.authorization.lock().unwrap().clone();
+        let mut response = self.send(url, accept, authorization.as_deref()).await?;
+        
+        if response.status() == StatusCode::UNAUTHORIZED {
+            let challenge = response.headers()
+                .get(header::WWW_AUTHENTICATE)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            let authorization = self.authorize(chart, &challenge).await?;
+            response = self.send(url, accept, Some(&authorization)).await?;
+            *self.authorization.lock().unwrap() = Some(authorization);
+        }
+        
+        response.error_for_status()
+            .map_err(|e| Error::DependencyError(format!("Failed to fetch {}: {}", url, e)))
+    }
+    
+    /// `Authorization` header answering `challenge`
+    async fn authorize(&self, chart: &ChartReference, challenge: &str) -> Result<String, Error> {
+        let basic = self.credentials
+            .as_ref()
+            .map(|c| format!("Basic {}", BASE64.encode(format!("{}:{}", c.username, c.password))));
+        
+        let (scheme, params) = self::challenge(challenge);
+        match (scheme.as_str(), params.get("realm")) {
+            ("bearer", Some(realm)) => {
+                let default_scope = format!("repository:{}:pull", chart.repository);
+                let mut query = vec![("scope", params.get("scope").unwrap_or(&default_scope).as_str())];
+                if let Some(service) = params.get("service") {
+                    query.push(("service", service));
+                }
+                
+                let mut request = self.http.get(realm).query(&query);
+                if let Some(basic) = &basic {
+                    request = request.header(header::AUTHORIZATION, basic);
+                }
+                let token: Token = request.send().await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|e| Error::DependencyError(format!("Failed to authenticate to {}: {}", chart.registry, e)))?
+                    .json().await
+                    .map_err(|e| Error::DependencyError(format!("Invalid token from {}: {}", realm, e)))?;
+                
+                token.token
+                    .or(token.access_token)
+                    .map(|token| format!("Bearer {}", token))
+                    .ok_or_else(|| Error::DependencyError(format!("No token from {}", realm)))
+            }
+            ("basic", _) => basic.ok_or_else(|| Error::DependencyError(format!(
+                "{} requires credentials; set source.secret_ref", chart.registry
+            ))),
+            _ => Err(Error::DependencyError(format!("Unsupported authentication challenge from {}: {}", chart.registry, challenge))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use local::LocalRegistry;
+    
+    #[test]
+    fn parses_references_and_docker_credentials() {
+        let chart = ChartReference::new("oci://ghcr.io/acme/charts/", "loki").unwrap();
+        assert_eq!((chart.registry.as_str(), chart.repository.as_str()), ("ghcr.io", "acme/charts/loki"));
+        assert_eq!(chart.url("tags/list"), "https://ghcr.io/v2/acme/charts/loki/tags/list");
+        assert_eq!(ChartReference::new("oci://localhost:5000", "loki").unwrap().url("tags/list"), "http://localhost:5000/v2/loki/tags/list");
+        assert_eq!(ChartReference::new("https://grafana.github.io/helm-charts", "loki"), None);
+        
+        let config = br#"{"auths": {
+            "https://index.docker.io/v1/": {"auth": "cm9ib3Q6aHVudGVyMg=="},
+            "ghcr.io": {"username": "acme", "password": "token"}
+        }}"#;
+        let credentials = |registry| docker_credentials(config, registry).unwrap().map(|c| (c.username, c.password));
+        assert_eq!(credentials("index.docker.io"), Some(("robot".to_string(), "hunter2".to_string())));
+        assert_eq!(credentials("ghcr.io"), Some(("acme".to_string(), "token".to_string())));
+        assert_eq!(credentials("quay.io"), None);
+        
+        let (scheme, params) = challenge(r#"Bearer realm="https://ghcr.io/token",service="ghcr.io",scope="repository:acme/loki:pull,push""#);
+        assert_eq!(scheme, "bearer");
+        assert_eq!(params["realm"], "https://ghcr.io/token");
+        assert_eq!(params["scope"], "repository:acme/loki:pull,push");
+    }
+    
+    #[tokio::test]
+    async fn pulls_charts_by_verified_digest() {
+        let registry = LocalRegistry::start(Some(("robot", "hunter2"))).await;
+        let chart = ChartReference::new(&registry.url("charts"), "loki").unwrap();
+        let pushed = registry.push("charts/loki", "1.0.0", b"loki 1.0.0");
+        registry.push("charts/loki", "1.1.0+build.1", b"loki 1.1.0");
+        
+        let client = Registry::new(Some(Credentials { username: "robot".to_string(), password: "hunter2".to_string() }));
+        let mut versions = client.versions(&chart).await.unwrap();
+        versions.sort();
+        assert_eq!(versions, ["1.0.0", "1.1.0+build.1"]);
+        assert_eq!(client.digest(&chart, "1.0.0").await.unwrap(), pushed);
+        assert_eq!(client.pull(&chart, &pushed).await.unwrap(), b"loki 1.0.0");
+        
+        // Anonymous pulls are refused, and tampered archives are caught
+        assert!(Registry::new(None).versions(&chart).await.is_err());
+        registry.tamper(&digest(b"loki 1.0.0"), b"loki 6.6.6");
+        let error = client.pull(&chart, &pushed).await.unwrap_err();
+        assert!(error.to_string().contains("Digest mismatch"), "{}", error);
+    }
+}
@@ -0,0 +1,425 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::{stream, stream::BoxStream, Stream, StreamExt};
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{
+    api::Api,
+    runtime::{reflector::{ObjectRef, Store}, watcher, WatchStreamExt},
+    Client, ResourceExt,
+};
+use serde::Serialize;
+use tokio::sync::watch;
+use tracing::{error, info, instrument, warn};
+
+use crate::config::{Config, Layers, Overrides, SharedConfig, COMMAND_LINE};
+use crate::crd::DependencyManager;
+use crate::error::Error;
+
+/// Key of a configuration ConfigMap holding the configuration file
+pub const CONFIG_KEY: &str = "config.yaml";
+
+/// How often the configuration file is checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// ConfigMap holding the configuration under `config.yaml`
+#[derive(Clone)]
+pub struct ConfigMapSource {
+    client: Client,
+    namespace: String,
+    name: String,
+}
+
+impl ConfigMapSource {
+    fn label(&self) -> String {
+        format!("ConfigMap {}/{}", self.namespace, self.name)
+    }
+    
+    async fn read(&self) -> Result<Option<String>, Error> {
+        let api: Api<ConfigMap> = Api::namespaced(self.client.clone(), &self.namespace);
+        Ok(api.get_opt(&self.name).await?.and_then(content))
+    }
+    
+    /// Content of the ConfigMap after each change, `None` once it is deleted
+    fn changes(&self) -> BoxStream<'static, Option<String>> {
+        let api: Api<ConfigMap> = Api::namespaced(self.client.clone(), &self.namespace);
+        let label = self.label();
+        watcher(api, watcher::Config::default().fields(&format!("metadata.name={}", self.name)))
+            .default_backoff()
+            .filter_map(move |event| {
+                let change = match event {
+                    Ok(watcher::Event::Apply(cm) | watcher::Event::InitApply(cm)) => Some(content(cm)),
+                    Ok(watcher::Event::Delete(_)) => Some(None),
+                    Ok(_) => None,
+                    Err(e) => {
+                        warn!("Watching {} failed: {}", label, e);
+                        None
+                    }
+                };
+                async move { change }
+            })
+            .boxed()
+    }
+}
+
+fn content(cm: ConfigMap) -> Option<String> {
+    cm.data.and_then(|mut data| data.remove(CONFIG_KEY))
+}
+
+/// Content of the file at `path`, or `None` if it cannot be read
+fn read_file(path: &str) -> Option<String> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Some(content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            warn!("Config file {} not readable: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Assembles the configuration, lowest precedence first: the defaults, the
+/// file at `path`, the ConfigMap, `ZERG_*` environment variables and
+/// `overrides` from the command line
+fn assemble(
+    path: &str,
+    file: Option<&str>,
+    config_map: Option<(&ConfigMapSource, &str)>,
+    overrides: &Overrides,
+) -> Result<Layers, Error> {
+    let mut layers = Layers::new();
+    if let Some(file) = file {
+        layers.add_yaml(&format!("file {}", path), file)?;
+    }
+    if let Some((source, content)) = config_map {
+        layers.add_yaml(&source.label(), content)?;
+    }
+    layers.add_env(std::env::vars())?;
+    layers.add(COMMAND_LINE, overrides.layer());
+    Ok(layers)
+}
+
+/// Keeps the shared configuration in line with its sources, swapping in
+/// changes once they parse and validate
+pub struct ConfigReloader {
+    path: String,
+    config_map: Option<ConfigMapSource>,
+    overrides: Overrides,
+    strict: bool,
+    
+    /// Last content of the file and the ConfigMap
+    file: Option<String>,
+    map: Option<String>,
+    
+    /// Layers of the current configuration
+    layers: Layers,
+    sender: watch::Sender<Arc<Config>>,
+}
+
+impl ConfigReloader {
+    /// Loads the configuration from the file at `path` and, if given, the
+    /// ConfigMap named `config_map` in the operator's namespace. A missing
+    /// ConfigMap, or file when there is none, falls back to the defaults,
+    /// unless `strict` makes it an error.
+    pub async fn load(
+        path: String,
+        config_map: Option<(Client, String)>,
+        overrides: Overrides,
+        strict: bool,
+    ) -> Result<(Self, SharedConfig), Error> {
+        let file = read_file(&path);
+        
+        // The ConfigMap lives in the namespace the other sources name
+        let config_map = match config_map {
+            Some((client, name)) => {
+                let layers = assemble(&path, file.as_deref(), None, &overrides)?;
+                let namespace = layers.config(false)?.operator.default_namespace;
+                Some(ConfigMapSource { client, namespace, name })
+            }
+            None => None,
+        };
+        let map = match &config_map {
+            Some(source) => source.read().await?,
+            None => None,
+        };
+        
+        let mut reloader = Self {
+            path,
+            config_map,
+            overrides,
+            strict,
+            file,
+            map,
+            layers: Layers::new(),
+            sender: watch::Sender::new(Arc::new(Config::default())),
+        };
+        
+        let primary = reloader.primary();
+        if reloader.primary_missing() {
+            if strict {
+                return Err(Error::ConfigError(format!("Configuration {} not found", primary)));
+            }
+            warn!("Configuration {} not found, using default configuration", primary);
+        }
+        info!("Loading configuration from {}", primary);
+        
+        reloader.layers = reloader.assemble()?;
+        let config = reloader.layers.config(strict)?;
+        reloader.sender.send_replace(Arc::new(config));
+        
+        let receiver = reloader.sender.subscribe();
+        Ok((reloader, receiver))
+    }
+    
+    /// Layers of the current configuration
+    pub fn layers(&self) -> &Layers {
+        &self.layers
+    }
+    
+    /// Source the configuration is primarily read from: the ConfigMap if
+    /// there is one, the file otherwise
+    fn primary(&self) -> String {
+        match &self.config_map {
+            Some(source) => source.label(),
+            None => format!("file {}", self.path),
+        }
+    }
+    
+    fn primary_missing(&self) -> bool {
+        match &self.config_map {
+            Some(_) => self.map.is_none(),
+            None => self.file.is_none(),
+        }
+    }
+    
+    fn assemble(&self) -> Result<Layers, Error> {
+        let config_map = self.config_map.as_ref().zip(self.map.as_deref());
+        assemble(&self.path, self.file.as_deref(), config_map, &self.overrides)
+    }
+    
+    /// Follows the sources until the process exits. The file is checked
+    /// every `POLL_INTERVAL`, as mounted ConfigMaps are updated by swapping
+    /// a symlink, which file events would miss.
+    #[instrument(skip(self))]
+    pub async fn run(mut self) -> anyhow::Result<()> {
+        let mut changes = match &self.config_map {
+            Some(source) => source.changes(),
+            None => stream::pending().boxed(),
+        };
+        let mut poll = tokio::time::interval(POLL_INTERVAL);
+        poll.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        
+        loop {
+            tokio::select! {
+                _ = poll.tick() => {
+                    let file = read_file(&self.path);
+                    let map = self.map.clone();
+                    self.update(file, map);
+                }
+                Some(map) = changes.next() => {
+                    let file = self.file.clone();
+                    self.update(file, map);
+                }
+            }
+        }
+    }
+    
+    /// Swaps in the configuration assembled from the new `file` and `map`
+    /// content if it is valid and differs from the current one, returning
+    /// whether it did
+    fn update(&mut self, file: Option<String>, map: Option<String>) -> bool {
+        if file == self.file && map == self.map {
+            return false;
+        }
+        self.file = file;
+        self.map = map;
+        
+        if self.primary_missing() {
+            let reason = format!("Configuration {} unavailable, keeping the current one", self.primary());
+            if self.strict {
+                error!("{}", reason);
+            } else {
+                warn!("{}", reason);
+            }
+            return false;
+        }
+        
+        let (layers, config) = match self.assemble().and_then(|layers| {
+            let config = layers.config(self.strict)?;
+            Ok((layers, config))
+        }) {
+            Ok(assembled) => assembled,
+            Err(e) => {
+                error!("Rejected configuration from {}, keeping the current one: {}", self.primary(), e);
+                return false;
+            }
+        };
+        
+        let current = self.sender.borrow().clone();
+        self.layers = layers;
+        if !differ(&*current, &config) {
+            return false;
+        }
+        for setting in restart_required(&current, &config) {
+            warn!("operator.{} changed, which takes effect after a restart", setting);
+        }
+        
+        info!("Reloaded configuration from {}", self.primary());
+        self.sender.send_replace(Arc::new(config));
+        true
+    }
+}
+
+fn differ<T: Serialize>(old: T, new: T) -> bool {
+    serde_json::to_value(old).ok() != serde_json::to_value(new).ok()
+}
+
+/// Operator settings read once at startup that differ between `old` and `new`
+pub fn restart_required(old: &Config, new: &Config) -> Vec<&'static str> {
+    let (old, new) = (&old.operator, &new.operator);
+    [
+        ("default_namespace", old.default_namespace != new.default_namespace),
+        ("max_concurrent_reconciles", old.max_concurrent_reconciles != new.max_concurrent_reconciles),
+        ("metrics_enabled", old.metrics_enabled != new.metrics_enabled),
+        ("metrics_port", old.metrics_port != new.metrics_port),
+        ("webhook_port", old.webhook_port != new.webhook_port),
+    ]
+    .into_iter()
+    .filter_map(|(setting, changed)| changed.then_some(setting))
+    .collect()
+}
+
+/// Whether the settings `dm` is reconciled with differ between `old` and
+/// `new`: its tenant policy and the templates of its providers
+pub fn affects(old: &Config, new: &Config, dm: &DependencyManager) -> bool {
+    let namespace = dm.namespace().unwrap_or_default();
+    if differ(old.tenancy.policy_for(&namespace), new.tenancy.policy_for(&namespace)) {
+        return true;
+    }
+    
+    if let Some(gitops) = &dm.spec.gitops {
+        let name = gitops.provider.template_name();
+        if differ(old.gitops_templates.get(name), new.gitops_templates.get(name))
+            || old.operator.manifests_dir != new.operator.manifests_dir
+        {
+            return true;
+        }
+    }
+    
+    dm.spec.cicd.as_ref().is_some_and(|cicd| {
+        let name = cicd.provider.template_name();
+        differ(old.cicd_templates.get(name), new.cicd_templates.get(name))
+    })
+}
+
+/// Waits until `config` watches other namespaces than `namespaces`; never
+/// returns once the configuration can no longer change
+pub async fn namespaces_changed(config: &mut SharedConfig, namespaces: &[String]) {
+    while config.changed().await.is_ok() {
+        if config.borrow_and_update().operator.watch_namespaces != namespaces {
+            return;
+        }
+    }
+    std::future::pending().await
+}
+
+/// DependencyManagers in `store` affected by each change of `config`, for
+/// the controller to reconcile again
+pub fn affected(
+    mut config: SharedConfig,
+    store: Store<DependencyManager>,
+) -> impl Stream<Item = ObjectRef<DependencyManager>> + Send + 'static {
+    let current = config.borrow_and_update().clone();
+    
+    stream::unfold((config, current), move |(mut config, previous)| {
+        let store = store.clone();
+        async move {
+            config.changed().await.ok()?;
+            let current = config.borrow_and_update().clone();
+            let refs: Vec<_> = store
+                .state()
+                .iter()
+                .filter(|dm| affects(&previous, &current, dm))
+                .map(|dm| ObjectRef::from_obj(dm.as_ref()))
+                .collect();
+            if !refs.is_empty() {
+                info!("Reconciling {} DependencyManagers again after the configuration change", refs.len());
+            }
+            Some((stream::iter(refs), (config, current)))
+        }
+    })
+    .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TenantPolicy;
+    
+    fn reloader() -> (ConfigReloader, SharedConfig) {
+        let path = "/etc/zerg/config.yaml".to_string();
+        let sender = watch::Sender::new(Arc::new(Config::default()));
+        let receiver = sender.subscribe();
+        let reloader = ConfigReloader {
+            path,
+            config_map: None,
+            overrides: Overrides::default(),
+            strict: true,
+            file: None,
+            map: None,
+            layers: Layers::new(),
+            sender,
+        };
+        (reloader, receiver)
+    }
+    
+    fn manager(namespace: &str, spec: serde_json::Value) -> DependencyManager {
+        let mut dm = crate::crd::tests::owner(namespace, "app");
+        dm.spec = serde_json::from_value(spec).unwrap();
+        dm
+    }
+    
+    #[test]
+    fn swaps_in_valid_changes_only() {
+        let (mut reloader, config) = reloader();
+        let file = |content: &str| Some(content.to_string());
+        
+        assert!(!reloader.update(file("operator:\n  reconciliation_interval: 300\n"), None));
+        assert!(reloader.update(file("operator:\n  manifests_dir: /srv/manifests\n"), None));
+        assert_eq!(config.borrow().operator.manifests_dir, "/srv/manifests");
+        assert_eq!(reloader.layers().source("operator.manifests_dir"), "file /etc/zerg/config.yaml");
+        
+        assert!(!reloader.update(file("operator:\n  max_concurrent_reconciles: 0\n"), None));
+        assert!(!reloader.update(file("operator: ["), None));
+        assert!(!reloader.update(None, None));
+        assert_eq!(config.borrow().operator.manifests_dir, "/srv/manifests");
+    }
+    
+    #[test]
+    fn affects_managers_using_changed_settings() {
+        let old = Config::default();
+        let ci = manager("team-a", serde_json::json!({
+            "dependencies": [],
+            "cicd": { "provider": "tekton", "pipelines": [] }
+        }));
+        let plain = manager("team-b", serde_json::json!({ "dependencies": [] }));
+        
+        let mut new = old.clone();
+        new.cicd_templates.get_mut("tekton").unwrap().defaults.retries = Some(2);
+        new.cicd_templates.get_mut("argo-workflows").unwrap().defaults.retries = Some(2);
+        assert!(affects(&old, &new, &ci));
+        assert!(!affects(&old, &new, &plain));
+        
+        let mut new = old.clone();
+        new.tenancy.enabled = true;
+        new.tenancy.policies.insert("team-b".to_string(), TenantPolicy::default());
+        assert!(!affects(&old, &new, &ci));
+        assert!(affects(&old, &new, &plain));
+        
+        let mut new = old.clone();
+        new.operator.webhook_port = 9000;
+        new.operator.watch_namespaces = vec!["team-a".to_string()];
+        assert!(!affects(&old, &new, &ci));
+        assert_eq!(restart_required(&old, &new), ["webhook_port"]);
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+
+use kube::{
+    api::{Api, DeleteParams, DynamicObject, GroupVersionKind, ListParams, Patch, PatchParams, PostParams, ResourceExt},
+    client::Client,
+    discovery::{self, ApiResource, Scope},
+    runtime::reflector::ObjectRef,
+    Resource,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use serde::Deserialize;
+use tracing::{debug, info, instrument};
+
+use crate::crd::DependencyManager;
+use crate::error::Error;
+
+/// Field manager used for server-side apply
+pub const FIELD_MANAGER: &str = "zerg-operator";
+
+/// Label marking objects created by the operator
+pub const MANAGED_BY_LABEL: &str = "app.kubernetes.io/managed-by";
+
+/// Label naming the DependencyManager an object belongs to
+pub const OWNER_NAME_LABEL: &str = "zerg.io/dependency-manager";
+
+/// Label holding the namespace of the owning DependencyManager
+pub const OWNER_NAMESPACE_LABEL: &str = "zerg.io/dependency-manager-namespace";
+
+/// ArgoCD Application
+pub const ARGOCD_APPLICATION: (&str, &str, &str) = ("argoproj.io", "v1alpha1", "Application");
+
+/// ArgoCD AppProject
+pub const ARGOCD_APP_PROJECT: (&str, &str, &str) = ("argoproj.io", "v1alpha1", "AppProject");
+
+/// ArgoCD ApplicationSet
+pub const ARGOCD_APPLICATION_SET: (&str, &str, &str) = ("argoproj.io", "v1alpha1", "ApplicationSet");
+
+/// ClusterRoleBinding
+pub const CLUSTER_ROLE_BINDING: (&str, &str, &str) = ("rbac.authorization.k8s.io", "v1", "ClusterRoleBinding");
+
+/// Kinds created in the DependencyManager namespace, tracked through ownerReferences.
+///
+/// Flux kinds are generated in the API version each DependencyManager selects,
+/// so they are watched in whichever version the cluster prefers.
+pub const OWNED_KINDS: &[(&str, &str, &str)] = &[
+    ("source.toolkit.fluxcd.io", "v1", "GitRepository"),
+    ("kustomize.toolkit.fluxcd.io", "v1", "Kustomization"),
+    ("source.toolkit.fluxcd.io", "v1", "HelmRepository"),
+    ("helm.toolkit.fluxcd.io", "v2", "HelmRelease"),
+    ("tekton.dev", "v1", "Pipeline"),
+    ("tekton.dev", "v1", "Task"),
+    ("triggers.tekton.dev", "v1beta1", "TriggerBinding"),
+    ("triggers.tekton.dev", "v1beta1", "TriggerTemplate"),
+    ("triggers.tekton.dev", "v1beta1", "EventListener"),
+    ("argoproj.io", "v1alpha1", "WorkflowTemplate"),
+    ("argoproj.io", "v1alpha1", "CronWorkflow"),
+];
+
+/// PersistentVolumeClaim, created for pipeline caches
+pub const PERSISTENT_VOLUME_CLAIM: (&str, &str, &str) = ("", "v1", "PersistentVolumeClaim");
+
+/// Tekton run kinds, tracked for pipeline run history
+pub const TEKTON_PIPELINE_RUN: (&str, &str, &str) = ("tekton.dev", "v1", "PipelineRun");
+pub const TEKTON_TASK_RUN: (&str, &str, &str) = ("tekton.dev", "v1", "TaskRun");
+
+/// Argo Workflows run kind, tracked for pipeline run history
+pub const ARGO_WORKFLOW: (&str, &str, &str) = ("argoproj.io", "v1alpha1", "Workflow");
+
+/// Kinds that may live outside the DependencyManager namespace, tracked through labels
+pub const LABELLED_KINDS: &[(&str, &str, &str)] = &[ARGOCD_APPLICATION, ARGOCD_APP_PROJECT, ARGOCD_APPLICATION_SET];
+
+/// Cluster-scoped kinds, tracked through labels
+pub const CLUSTER_LABELLED_KINDS: &[(&str, &str, &str)] = &[CLUSTER_ROLE_BINDING];
+
+/// Labels tying an object back to its DependencyManager
+pub fn owner_labels(owner: &DependencyManager) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (MANAGED_BY_LABEL.to_string(), FIELD_MANAGER.to_string()),
+        (OWNER_NAME_LABEL.to_string(), owner.name_any()),
+        (OWNER_NAMESPACE_LABEL.to_string(), owner.namespace().unwrap_or_default()),
+    ])
+}
+
+/// Maps an object carrying owner labels to the DependencyManager it belongs to.
+///
+/// `OWNER_NAMESPACE_LABEL` may be omitted on user-labelled ConfigMaps and Secrets,
+/// in which case the object's own namespace is used.
+pub fn owner_from_labels(meta: &ObjectMeta) -> Option<ObjectRef<DependencyManager>> {
+    let labels = meta.labels.as_ref()?;
+    let name = labels.get(OWNER_NAME_LABEL)?;
+    let namespace = labels
+        .get(OWNER_NAMESPACE_LABEL)
+        .or(meta.namespace.as_ref())?;
+    
+    Some(ObjectRef::new(name).within(namespace))
+}
+
+/// Resolves a `(group, version, kind)` triple through API discovery
+pub async fn resolve_kind(
+    client: &Client,
+    (group, version, kind): (&str, &str, &str),
+) -> Result<(ApiResource, Scope), Error> {
+    let gvk = GroupVersionKind::gvk(group, version, kind);
+    let (resource, capabilities) = discovery::pinned_kind(client, &gvk).await?;
+    Ok((resource, capabilities.scope))
+}
+
+/// Resolves `kind` in whichever version its group prefers, ignoring the given one
+pub async fn resolve_served_kind(
+    client: &Client,
+    (group, _, kind): (&str, &str, &str),
+) -> Result<(ApiResource, Scope), Error> {
+    let api_group = discovery::group(client, group).await?;
+    let (resource, capabilities) = api_group
+        .recommended_kind(kind)
+        .ok_or_else(|| Error::KubeError(kube::Error::Discovery(kube::error::DiscoveryError::MissingKind(format!("{}/{}", group, kind)))))?;
+    Ok((resource, capabilities.scope))
+}
+
+/// Splits a multi-document YAML string into manifests, skipping empty documents
+pub fn parse_yaml(yaml: &str) -> Result<Vec<serde_json::Value>, Error> {
+    serde_yaml::Deserializer::from_str(yaml)
+        .map(|document| {
+            serde_json::Value::deserialize(document)
+                .map_err(|e| Error::SerializationError(format!("Failed to parse manifest: {}", e)))
+        })
+        .filter(|manifest| !matches!(manifest, Ok(serde_json::Value::Null)))
+        .collect()
+}
+
+/// Labels `manifest` with `owner` and resolves the API it is served from.
+///
+/// The object gets an ownerReference when it lives in the owner's namespace so
+/// that deleting the DependencyManager garbage-collects it and changes to it
+/// trigger a reconcile.
+async fn owned_object(
+    client: &Client,
+    manifest: serde_json::Value,
+    owner: &DependencyManager,
+) -> Result<(Api<DynamicObject>, DynamicObject, ApiResource), Error> {
+    let mut object: DynamicObject = serde_json::from_value(manifest)
+        .map_err(|e| Error::SerializationError(format!("Invalid manifest: {}", e)))?;
+    
+    let types = object
+        .types
+        .clone()
+        .ok_or_else(|| Error::SerializationError("Manifest is missing apiVersion/kind".to_string()))?;
+    let gvk = GroupVersionKind::try_from(&types)
+        .map_err(|e| Error::SerializationError(format!("Invalid apiVersion: {}", e)))?;
+    let (resource, capabilities) = discovery::pinned_kind(client, &gvk).await?;
+    
+    let owner_namespace = owner.namespace().unwrap_or_default();
+    
+    object.labels_mut().extend(owner_labels(owner));
+    
+    let api: Api<DynamicObject> = match capabilities.scope {
+        Scope::Namespaced => {
+            let namespace = object
+                .namespace()
+                .unwrap_or_else(|| owner_namespace.clone());
+            
+            if namespace == owner_namespace {
+                if let Some(owner_ref) = owner.controller_owner_ref(&()) {
+                    object.owner_references_mut().push(owner_ref);
+                }
+            }
+            
+            object.metadata.namespace = Some(namespace.clone());
+            Api::namespaced_with(client.clone(), &namespace, &resource)
+        }
+        Scope::Cluster => Api::all_with(client.clone(), &resource),
+    };
+    
+    Ok((api, object, resource))
+}
+
+/// Server-side applies a single manifest on behalf of `owner`, labelled and
+/// owned as described on `owned_object`
+#[instrument(skip(client, manifest, owner))]
+pub async fn apply_manifest(
+    client: &Client,
+    manifest: serde_json::Value,
+    owner: &DependencyManager,
+) -> Result<(), Error> {
+    let (api, object, resource) = owned_object(client, manifest, owner).await?;
+    let name = object.name_any();
+    
+    debug!("Applying {} {}", resource.kind, name);
+    
+    api.patch(&name, &PatchParams::apply(FIELD_MANAGER).force(), &Patch::Apply(&object))
+        .await?;
+    
+    Ok(())
+}
+
+/// Creates a manifest on behalf of `owner`, returning the name the API server
+/// gave it; used for runs, which are named through `generateName`
+#[instrument(skip(client, manifest, owner))]
+pub async fn create_manifest(
+    client: &Client,
+    manifest: serde_json::Value,
+    owner: &DependencyManager,
+) -> Result<String, Error> {
+    let (api, object, resource) = owned_object(client, manifest, owner).await?;
+    
+    let params = PostParams {
+        field_manager: Some(FIELD_MANAGER.to_string()),
+        ..Default::default()
+    };
+    let created = api.create(&params, &object).await?;
+    
+    debug!("Created {} {}", resource.kind, created.name_any());
+    
+    Ok(created.name_any())
+}
+
+/// Label selector matching every object created for `owner`
+pub fn owner_selector(owner: &DependencyManager) -> String {
+    format!(
+        "{}={},{}={}",
+        OWNER_NAME_LABEL,
+        owner.name_any(),
+        OWNER_NAMESPACE_LABEL,
+        owner.namespace().unwrap_or_default()
+    )
+}
+
+/// Deletes objects of `kind` in `namespace` created for `owner` whose names are not in `keep`.
+///
+/// Used to remove objects left behind when sources are renamed or removed, and
+/// with an empty `keep` to clean up objects ownerReferences cannot reach.
+/// `namespace` is ignored for cluster-scoped kinds.
+#[instrument(skip(client, owner))]
+pub async fn prune_labelled(
+    client: &Client,
+    kind: (&str, &str, &str),
+    namespace: &str,
+    owner: &DependencyManager,
+    keep: &[String],
+) -> Result<(), Error> {
+    let (resource, scope) = resolve_kind(client, kind).await?;
+    let api: Api<DynamicObject> = match scope {
+        Scope::Namespaced => Api::namespaced_with(client.clone(), namespace, &resource),
+        Scope::Cluster => Api::all_with(client.clone(), &resource),
+    };
+    
+    let objects = api.list(&ListParams::default().labels(&owner_selector(owner))).await?;
+    
+    for object in objects {
+        let name = object.name_any();
+        if !keep.contains(&name) {
+            info!("Deleting stale {} {}", resource.kind, name);
+            api.delete(&name, &DeleteParams::default()).await?;
+        }
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn meta(namespace: Option<&str>, labels: &[(&str, &str)]) -> ObjectMeta {
+        ObjectMeta {
+            namespace: namespace.map(str::to_string),
+            labels: Some(labels.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()),
+            ..Default::default()
+        }
+    }
+    
+    #[test]
+    fn owner_labels_map_to_dependency_managers() {
+        let owner = owner_from_labels(&meta(Some("flux-system"), &[(OWNER_NAME_LABEL, "platform"), (OWNER_NAMESPACE_LABEL, "team-a")])).unwrap();
+        assert_eq!((owner.name.as_str(), owner.namespace.as_deref()), ("platform", Some("team-a")));
+        
+        // User-labelled ConfigMaps and Secrets belong to an owner in their own namespace
+        let owner = owner_from_labels(&meta(Some("team-b"), &[(OWNER_NAME_LABEL, "platform")])).unwrap();
+        assert_eq!(owner.namespace.as_deref(), Some("team-b"));
+        
+        assert!(owner_from_labels(&meta(None, &[(OWNER_NAME_LABEL, "platform")])).is_none());
+        assert!(owner_from_labels(&meta(Some("team-a"), &[(OWNER_NAMESPACE_LABEL, "team-a")])).is_none());
+        assert!(owner_from_labels(&ObjectMeta::default()).is_none());
+    }
+}
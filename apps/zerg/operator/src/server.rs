@@ -0,0 +1,306 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Json, Router,
+};
+use k8s_openapi::api::authentication::v1::{TokenReview, TokenReviewSpec};
+use k8s_openapi::api::authorization::v1::{ResourceAttributes, SubjectAccessReview, SubjectAccessReviewSpec};
+use k8s_openapi::api::core::v1::Secret;
+use kube::{
+    api::{Api, PostParams},
+    Client,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{error, info, instrument, warn};
+
+use crate::cicd::{self, webhook, CiCdManager};
+use crate::config::{Config, SharedConfig};
+use crate::crd::{CiCdProvider, DependencyManager};
+use crate::error::Error;
+use crate::impersonation;
+
+/// Subresource callers need `create` access to in order to start runs
+const RUNS_SUBRESOURCE: &str = "runs";
+
+/// HTTP endpoint receiving git webhooks and manual run requests for pipelines
+/// of DependencyManagers
+pub struct WebhookServer {
+    client: Client,
+    kube_config: kube::Config,
+    config: SharedConfig,
+}
+
+type Reply = (StatusCode, Json<Value>);
+
+fn reply(status: StatusCode, message: impl Into<String>) -> Reply {
+    (status, Json(json!({ "message": message.into() })))
+}
+
+fn internal(error: Error) -> Reply {
+    error!("Webhook failed: {}", error);
+    reply(StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+}
+
+impl WebhookServer {
+    pub fn new(client: Client, kube_config: kube::Config, config: SharedConfig) -> Self {
+        Self { client, kube_config, config }
+    }
+    
+    /// Current configuration
+    fn config(&self) -> Arc<Config> {
+        self.config.borrow().clone()
+    }
+    
+    /// Serves `POST /webhooks/{namespace}/{name}` and
+    /// `POST /pipelines/{namespace}/{name}/{pipeline}/runs` until the process exits
+    #[instrument(skip(self))]
+    pub async fn run(self) -> anyhow::Result<()> {
+        let address = SocketAddr::from(([0, 0, 0, 0], self.config().operator.webhook_port));
+        let app = Router::new()
+            .route("/webhooks/{namespace}/{name}", post(receive))
+            .route("/pipelines/{namespace}/{name}/{pipeline}/runs", post(run))
+            .with_state(Arc::new(self));
+        
+        info!("Receiving webhooks on {}", address);
+        let listener = tokio::net::TcpListener::bind(address).await?;
+        axum::serve(listener, app).await?;
+        
+        Ok(())
+    }
+    
+    /// Whether DependencyManagers in `namespace` are reconciled by this operator
+    fn watches(&self, namespace: &str) -> bool {
+        let config = self.config();
+        let namespaces = &config.operator.watch_namespaces;
+        namespaces.is_empty() || namespaces.iter().any(|ns| ns == namespace)
+    }
+    
+    /// DependencyManager `namespace/name`, if this operator reconciles it
+    async fn dependency_manager(&self, namespace: &str, name: &str) -> Result<DependencyManager, Reply> {
+        let api: Api<DependencyManager> = Api::namespaced(self.client.clone(), namespace);
+        match api.get_opt(name).await {
+            Ok(Some(dm)) if self.watches(namespace) => Ok(dm),
+            Ok(_) => Err(reply(StatusCode::NOT_FOUND, format!("DependencyManager {}/{} not found", namespace, name))),
+            Err(e) => Err(internal(e.into())),
+        }
+    }
+    
+    /// Checks that the bearer token of a request belongs to someone allowed to
+    /// `create` the `runs` subresource of DependencyManager `namespace/name`
+    async fn authorize(&self, headers: &HeaderMap, namespace: &str, name: &str) -> Result<(), Reply> {
+        let token = headers
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| reply(StatusCode::UNAUTHORIZED, "Missing bearer token"))?;
+        
+        let review = TokenReview {
+            spec: TokenReviewSpec { token: Some(token.to_string()), ..Default::default() },
+            ..Default::default()
+        };
+        let reviewed = Api::<TokenReview>::all(self.client.clone())
+            .create(&PostParams::default(), &review)
+            .await
+            .map_err(|e| internal(e.into()))?;
+        let user = reviewed
+            .status
+            .filter(|status| status.authenticated == Some(true))
+            .and_then(|status| status.user)
+            .ok_or_else(|| reply(StatusCode::UNAUTHORIZED, "Invalid bearer token"))?;
+        
+        let access = SubjectAccessReview {
+            spec: SubjectAccessReviewSpec {
+                user: user.username.clone(),
+                groups: user.groups,
+                uid: user.uid,
+                extra: user.extra,
+                resource_attributes: Some(ResourceAttributes {
+                    group: Some("zerg.io".to_string()),
+                    resource: Some("dependencymanagers".to_string()),
+                    subresource: Some(RUNS_SUBRESOURCE.to_string()),
+                    verb: Some("create".to_string()),
+                    namespace: Some(namespace.to_string()),
+                    name: Some(name.to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let reviewed = Api::<SubjectAccessReview>::all(self.client.clone())
+            .create(&PostParams::default(), &access)
+            .await
+            .map_err(|e| internal(e.into()))?;
+        
+        if reviewed.status.is_some_and(|status| status.allowed) {
+            Ok(())
+        } else {
+            Err(reply(
+                StatusCode::FORBIDDEN,
+                format!("{} cannot create runs of {}/{}", user.username.unwrap_or_default(), namespace, name),
+            ))
+        }
+    }
+}
+
+/// Body of a manual run request
+#[derive(Deserialize, Default)]
+struct RunRequest {
+    /// Overrides of the pipeline params
+    #[serde(default)]
+    params: BTreeMap<String, String>,
+}
+
+/// Webhook secret stored under `WEBHOOK_SECRET_KEY` in the Secret `name`
+async fn webhook_secret(secrets: &Api<Secret>, name: &str) -> Result<Vec<u8>, Error> {
+    let secret = secrets.get(name).await?;
+    
+    secret
+        .data
+        .and_then(|mut data| data.remove(cicd::WEBHOOK_SECRET_KEY))
+        .map(|value| value.0)
+        .ok_or_else(|| Error::CiCdError(format!("Secret {} has no {} key", name, cicd::WEBHOOK_SECRET_KEY)))
+}
+
+/// Submits a Workflow for every Argo Workflows pipeline of the
+/// DependencyManager whose git trigger matches the delivery
+#[instrument(skip(server, headers, body))]
+async fn receive(
+    State(server): State<Arc<WebhookServer>>,
+    Path((namespace, name)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Reply {
+    let event = match webhook::parse(&headers, &body) {
+        Ok(event) => event,
+        Err(e) => return reply(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+    
+    let dm = match server.dependency_manager(&namespace, &name).await {
+        Ok(dm) => dm,
+        Err(reply) => return reply,
+    };
+    
+    // Tekton pipelines are triggered through their own EventListeners
+    let Some(cicd) = dm.spec.cicd.as_ref().filter(|cicd| matches!(cicd.provider, CiCdProvider::ArgoWorkflows)) else {
+        return reply(StatusCode::NOT_FOUND, format!("DependencyManager {}/{} has no Argo Workflows pipelines", namespace, name));
+    };
+    
+    // Secrets are read and runs submitted as the DependencyManager's ServiceAccount, if any
+    let (client, impersonation) = match impersonation::owner_client(&server.client, &server.kube_config, &dm) {
+        Ok(owner_client) => owner_client,
+        Err(e) => return internal(e),
+    };
+    let secrets: Api<Secret> = Api::namespaced(client.clone(), &namespace);
+    let manager = CiCdManager::new(client, impersonation);
+    let config = server.config();
+    let template = config.cicd_templates.get(cicd.provider.template_name());
+    
+    let mut runs = Vec::new();
+    let mut rejected = 0;
+    
+    for pipeline in &cicd.pipelines {
+        let Some(git) = pipeline.trigger.git.as_ref().filter(|git| webhook::matches(git, &event)) else {
+            continue;
+        };
+        
+        // Unsigned deliveries could start runs of any revision, so triggers without a secret never match
+        let Some(secret_ref) = &git.secret_ref else {
+            warn!("Rejected webhook for pipeline {} of {}/{}: trigger has no secret_ref", pipeline.name, namespace, name);
+            rejected += 1;
+            continue;
+        };
+        let secret = match webhook_secret(&secrets, secret_ref).await {
+            Ok(secret) => secret,
+            Err(e) => return internal(e),
+        };
+        if !webhook::verify(event.provider, &headers, &body, &secret) {
+            warn!("Rejected webhook for pipeline {} of {}/{}: invalid signature", pipeline.name, namespace, name);
+            rejected += 1;
+            continue;
+        }
+        
+        let params = BTreeMap::from([
+            ("git-repo-url".to_string(), event.repo_url.clone().unwrap_or_else(|| git.repository.clone())),
+            ("git-revision".to_string(), event.revision.clone().unwrap_or_default()),
+            ("git-branch".to_string(), event.branch.clone().unwrap_or_default()),
+        ]);
+        let run_name = match manager.submit_run(pipeline, &params, template, &dm).await {
+            Ok(run_name) => run_name,
+            Err(e) => return internal(e),
+        };
+        
+        if let Err(e) = cicd::record_run(&server.client, &dm, pipeline, &run_name, &params, None).await {
+            warn!("Failed to record run {}: {}", run_name, e);
+        }
+        
+        runs.push(run_name);
+    }
+    
+    match (runs.is_empty(), rejected) {
+        (true, 0) => (StatusCode::OK, Json(json!({ "runs": runs }))),
+        (true, _) => reply(StatusCode::UNAUTHORIZED, "Invalid webhook signature"),
+        (false, _) => (StatusCode::ACCEPTED, Json(json!({ "runs": runs }))),
+    }
+}
+
+/// Starts a run of a pipeline that allows manual runs, with the params in the
+/// body overriding the defaults
+#[instrument(skip(server, headers, body))]
+async fn run(
+    State(server): State<Arc<WebhookServer>>,
+    Path((namespace, name, pipeline)): Path<(String, String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Reply {
+    if let Err(reply) = server.authorize(&headers, &namespace, &name).await {
+        return reply;
+    }
+    
+    let request: RunRequest = if body.is_empty() {
+        RunRequest::default()
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(request) => request,
+            Err(e) => return reply(StatusCode::BAD_REQUEST, format!("Invalid run request: {}", e)),
+        }
+    };
+    
+    let dm = match server.dependency_manager(&namespace, &name).await {
+        Ok(dm) => dm,
+        Err(reply) => return reply,
+    };
+    let Some(cicd) = &dm.spec.cicd else {
+        return reply(StatusCode::NOT_FOUND, format!("DependencyManager {}/{} has no pipelines", namespace, name));
+    };
+    let (pipeline, params) = match cicd::manual_pipeline(cicd, &pipeline)
+        .and_then(|pipeline| Ok((pipeline, cicd::run_params(pipeline, request.params)?)))
+    {
+        Ok(run) => run,
+        Err(e) => return reply(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+    
+    let (client, impersonation) = match impersonation::owner_client(&server.client, &server.kube_config, &dm) {
+        Ok(owner_client) => owner_client,
+        Err(e) => return internal(e),
+    };
+    let config = server.config();
+    let template = config.cicd_templates.get(cicd.provider.template_name());
+    let run_name = match CiCdManager::new(client, impersonation).submit_run(pipeline, &params, template, &dm).await {
+        Ok(run_name) => run_name,
+        Err(e) => return internal(e),
+    };
+    
+    if let Err(e) = cicd::record_run(&server.client, &dm, pipeline, &run_name, &params, None).await {
+        warn!("Failed to record run {}: {}", run_name, e);
+    }
+    
+    (StatusCode::CREATED, Json(json!({ "run": run_name })))
+}
@@ -0,0 +1,400 @@
+use crate::config::{TenancyConfig, TenantPolicy};
+use std::collections::HashMap;
+
+use crate::crd::{
+    ArgoCdAppProject, ArgoCdApplicationSet, ArgoCdDestination, DependencyManagerSpec, DependencyType, GitOpsConfig,
+    GitOpsProvider,
+};
+use crate::dependencies::install_target;
+use crate::error::Error;
+use crate::gitops::{self, argocd};
+use crate::glob;
+
+/// Name of the ArgoCD cluster entry for the cluster it runs in
+const IN_CLUSTER_NAME: &str = "in-cluster";
+
+/// Subject AppProject violations are reported under
+const APP_PROJECT_SUBJECT: &str = "appProject";
+
+impl TenancyConfig {
+    /// Policy governing DependencyManagers in `source_namespace`, if any
+    pub fn policy_for(&self, source_namespace: &str) -> Option<&TenantPolicy> {
+        if !self.enabled {
+            return None;
+        }
+        
+        self.policies
+            .get(source_namespace)
+            .or(self.default_policy.as_ref())
+    }
+    
+    /// Checks every enabled dependency of `spec`, and every GitOps source and
+    /// destination, against the tenant policy of `source_namespace`, reporting
+    /// all violations at once.
+    pub fn enforce(&self, source_namespace: &str, spec: &DependencyManagerSpec) -> Result<(), Error> {
+        let Some(policy) = self.policy_for(source_namespace) else {
+            return Ok(());
+        };
+        
+        let mut violations = Vec::new();
+        
+        for dependency in spec.dependencies.iter().filter(|d| d.enabled) {
+            let (repository, target_namespace) = install_target(dependency, source_namespace);
+            
+            policy.check_namespace(&mut violations, &dependency.name, &target_namespace);
+            policy.check_repository(&mut violations, &dependency.name, &repository);
+            policy.check_type(&mut violations, &dependency.name, &dependency.type_);
+        }
+        
+        if let Some(gitops) = spec.gitops.as_ref() {
+            policy.check_gitops(&mut violations, gitops, source_namespace);
+        }
+        
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::PolicyError(format!(
+                "namespace {} may not install: {}",
+                source_namespace,
+                violations.join("; ")
+            )))
+        }
+    }
+}
+
+impl TenantPolicy {
+    fn check_namespace(&self, violations: &mut Vec<String>, subject: &str, namespace: &str) {
+        if let Some(allowed) = &self.allowed_target_namespaces {
+            if !glob::matches_any(allowed, namespace) {
+                violations.push(format!("{}: namespace {} is not allowed", subject, namespace));
+            }
+        }
+    }
+    
+    fn check_repository(&self, violations: &mut Vec<String>, subject: &str, repository: &str) {
+        if let Some(allowed) = &self.allowed_repositories {
+            if !glob::matches_any(allowed, repository) {
+                violations.push(format!("{}: repository {} is not allowed", subject, repository));
+            }
+        }
+    }
+    
+    fn check_type(&self, violations: &mut Vec<String>, subject: &str, type_: &DependencyType) {
+        if let Some(allowed) = &self.allowed_dependency_types {
+            if !allowed.contains(type_) {
+                violations.push(format!("{}: type {:?} is not allowed", subject, type_));
+            }
+        }
+    }
+    
+    /// Target namespaces only describe this cluster, so restricting them also
+    /// restricts ArgoCD destinations to the cluster ArgoCD runs in
+    fn check_destination(&self, violations: &mut Vec<String>, subject: &str, destination: Option<&ArgoCdDestination>, namespace: &str) {
+        self.check_namespace(violations, subject, namespace);
+        
+        if self.allowed_target_namespaces.is_none() {
+            return;
+        }
+        match destination.and_then(|d| d.name.as_deref()) {
+            Some(name) if name != IN_CLUSTER_NAME => {
+                violations.push(format!("{}: cluster {} is not allowed", subject, name));
+            }
+            Some(_) => {}
+            None => {
+                let server = destination.and_then(|d| d.server.as_deref()).unwrap_or(argocd::IN_CLUSTER_SERVER);
+                if server != argocd::IN_CLUSTER_SERVER {
+                    violations.push(format!("{}: cluster {} is not allowed", subject, server));
+                }
+            }
+        }
+    }
+    
+    /// GitOps controllers reconcile what they are pointed at with cluster-wide
+    /// privileges, so every repository they sync and every namespace they
+    /// apply to is subject to the policy like a dependency is
+    fn check_gitops(&self, violations: &mut Vec<String>, gitops: &GitOpsConfig, source_namespace: &str) {
+        // GitOps controllers are installed from vendored manifests into their own namespace
+        let provider = gitops.provider.template_name();
+        if gitops.install.as_ref().and_then(|i| i.enabled).unwrap_or(true) {
+            self.check_namespace(violations, provider, gitops::controller_namespace(gitops));
+        }
+        
+        let argocd_config = gitops.argocd.as_ref();
+        let destination = argocd_config.and_then(|a| a.destination.as_ref());
+        let primary = (provider, gitops.repository.as_str(), argocd_config.and_then(|a| a.source.as_ref()));
+        let sources = gitops.sources
+            .iter()
+            .flatten()
+            .map(|source| (source.name.as_str(), source.repository.as_str(), source.argocd.as_ref()));
+        
+        for (subject, repository, argocd_options) in std::iter::once(primary).chain(sources) {
+            self.check_repository(violations, subject, repository);
+            
+            match gitops.provider {
+                // Kustomizations apply their source into the DependencyManager namespace
+                GitOpsProvider::Flux => self.check_namespace(violations, subject, source_namespace),
+                GitOpsProvider::ArgoCD => {
+                    let namespace = argocd_options
+                        .and_then(|o| o.namespace.as_deref())
+                        .or(destination.and_then(|d| d.namespace.as_deref()))
+                        .unwrap_or(source_namespace);
+                    self.check_destination(violations, subject, destination, namespace);
+                }
+            }
+        }
+        
+        if let Some(project) = argocd_config.and_then(|a| a.app_project.as_ref()) {
+            self.check_app_project(violations, project);
+        }
+        for set in argocd_config.and_then(|a| a.application_sets.as_ref()).into_iter().flatten() {
+            self.check_application_set(violations, set, source_namespace);
+        }
+        
+        // Flux HelmReleases install charts just like Helm dependencies do
+        let releases = gitops.flux
+            .as_ref()
+            .and_then(|flux| flux.helm_releases.as_ref())
+            .into_iter()
+            .flatten();
+        
+        for release in releases {
+            let target_namespace = release.target_namespace.as_deref().unwrap_or(source_namespace);
+            
+            self.check_namespace(violations, &release.name, target_namespace);
+            self.check_repository(violations, &release.name, &release.repository);
+            self.check_type(violations, &release.name, &DependencyType::Helm);
+        }
+    }
+    
+    /// The generated AppProject bounds what its Applications may do, so it may
+    /// not grant more than the policy does. Unset lists default to the
+    /// repositories and destination checked above.
+    fn check_app_project(&self, violations: &mut Vec<String>, project: &ArgoCdAppProject) {
+        for repository in project.source_repos.iter().flatten() {
+            self.check_repository(violations, APP_PROJECT_SUBJECT, repository);
+        }
+        for destination in project.destinations.iter().flatten() {
+            let namespace = destination.namespace.as_deref().unwrap_or("*");
+            self.check_destination(violations, APP_PROJECT_SUBJECT, Some(destination), namespace);
+        }
+        
+        let cluster_kinds = project.cluster_resource_whitelist.iter().flatten();
+        if self.allowed_target_namespaces.is_some() {
+            for kind in cluster_kinds {
+                violations.push(format!("{}: cluster-scoped kind {} is not allowed", APP_PROJECT_SUBJECT, kind.kind));
+            }
+        }
+    }
+    
+    /// Checks the template of `set` once per list element. Values still
+    /// templated afterwards are filled by ArgoCD from git directories or
+    /// registered clusters, which the policy cannot vouch for.
+    fn check_application_set(&self, violations: &mut Vec<String>, set: &ArgoCdApplicationSet, source_namespace: &str) {
+        let template = &set.template;
+        let no_parameters = vec![HashMap::new()];
+        
+        for generator in &set.generators {
+            if let Some(git) = &generator.git {
+                self.check_repository(violations, &set.name, &git.repository);
+            }
+            
+            for parameters in generator.list.as_ref().unwrap_or(&no_parameters) {
+                let repository = substitute(&template.repository, parameters);
+                if !repository.contains("{{") {
+                    self.check_repository(violations, &set.name, &repository);
+                } else if self.allowed_repositories.is_some() {
+                    violations.push(format!("{}: templated repository {} is not allowed", set.name, repository));
+                }
+                
+                let destination = ArgoCdDestination {
+                    server: template.destination.server.as_deref().map(|v| substitute(v, parameters)),
+                    name: template.destination.name.as_deref().map(|v| substitute(v, parameters)),
+                    namespace: template.destination.namespace.as_deref().map(|v| substitute(v, parameters)),
+                };
+                let templated = [&destination.server, &destination.name, &destination.namespace]
+                    .into_iter()
+                    .flatten()
+                    .find(|value| value.contains("{{"));
+                match templated {
+                    None => {
+                        let namespace = destination.namespace.as_deref().unwrap_or(source_namespace);
+                        self.check_destination(violations, &set.name, Some(&destination), namespace);
+                    }
+                    Some(value) if self.allowed_target_namespaces.is_some() => {
+                        violations.push(format!("{}: templated destination {} is not allowed", set.name, value));
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+    }
+}
+
+/// Fills `{{key}}` placeholders of an ApplicationSet template from list generator `parameters`
+fn substitute(template: &str, parameters: &HashMap<String, String>) -> String {
+    parameters.iter().fold(template.to_string(), |value, (key, parameter)| {
+        value.replace(&format!("{{{{{}}}}}", key), parameter)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::crd::{Dependency, DependencySource};
+    
+    fn tenancy() -> TenancyConfig {
+        TenancyConfig {
+            enabled: true,
+            default_policy: Some(TenantPolicy {
+                allowed_dependency_types: Some(vec![DependencyType::Helm]),
+                ..TenantPolicy::default()
+            }),
+            policies: HashMap::from([(
+                "team-a".to_string(),
+                TenantPolicy {
+                    allowed_target_namespaces: Some(vec!["team-a".to_string(), "team-a-*".to_string()]),
+                    allowed_repositories: Some(vec!["https://charts.example.com/*".to_string(), "https://git.example.com/team-a/*".to_string()]),
+                    allowed_dependency_types: None,
+                },
+            )]),
+        }
+    }
+    
+    fn spec(dependencies: Vec<Dependency>, gitops: Option<serde_json::Value>) -> DependencyManagerSpec {
+        DependencyManagerSpec {
+            dependencies,
+            gitops: gitops.map(|gitops| serde_json::from_value(gitops).unwrap()),
+            cicd: None,
+            service_account_name: None,
+            updates: None,
+        }
+    }
+    
+    fn chart(name: &str, repo: &str, namespace: Option<&str>) -> Dependency {
+        Dependency {
+            name: name.to_string(),
+            type_: DependencyType::Helm,
+            source: DependencySource {
+                repo: repo.to_string(),
+                chart: Some(name.to_string()),
+                path: None,
+                ref_: None,
+                secret_ref: None,
+                digest: None,
+            },
+            version: None,
+            namespace: namespace.map(str::to_string),
+            values: None,
+            values_from: None,
+            depends_on: None,
+            enabled: true,
+        }
+    }
+    
+    fn violations(namespace: &str, spec: &DependencyManagerSpec) -> String {
+        tenancy().enforce(namespace, spec).unwrap_err().to_string()
+    }
+    
+    #[test]
+    fn dependencies_are_checked_against_the_namespace_policy() {
+        let allowed = spec(vec![chart("loki", "https://charts.example.com/grafana", Some("team-a-logs"))], None);
+        assert!(tenancy().enforce("team-a", &allowed).is_ok());
+        
+        let denied = spec(vec![chart("loki", "https://evil.example.com/grafana", Some("kube-system"))], None);
+        let error = violations("team-a", &denied);
+        assert!(error.contains("loki: namespace kube-system is not allowed"), "{}", error);
+        assert!(error.contains("loki: repository https://evil.example.com/grafana is not allowed"), "{}", error);
+        
+        // Other namespaces fall back to the default policy, and disabled tenancy allows anything
+        let mut kustomize = chart("issuers", "https://example.com/issuers", None);
+        kustomize.type_ = DependencyType::Kustomize;
+        assert!(violations("team-b", &spec(vec![kustomize.clone()], None)).contains("type Kustomize is not allowed"));
+        assert!(TenancyConfig { enabled: false, ..tenancy() }.enforce("team-b", &spec(vec![kustomize], None)).is_ok());
+    }
+    
+    #[test]
+    fn gitops_sources_and_destinations_are_checked() {
+        let flux = |repository: &str| json!({
+            "provider": "flux",
+            "repository": "https://git.example.com/team-a/platform",
+            "branch": "main",
+            "path": "clusters/prod",
+            "install": { "enabled": false },
+            "sources": [{ "name": "apps", "repository": repository, "branch": "main", "path": "apps" }],
+        });
+        assert!(tenancy().enforce("team-a", &spec(Vec::new(), Some(flux("https://git.example.com/team-a/apps")))).is_ok());
+        let error = violations("team-a", &spec(Vec::new(), Some(flux("https://git.example.com/team-b/apps"))));
+        assert!(error.contains("apps: repository https://git.example.com/team-b/apps is not allowed"), "{}", error);
+        
+        // Kustomizations apply into the DependencyManager namespace, so it must be an allowed target
+        let mut policy = tenancy();
+        policy.policies.insert("team-c".to_string(), policy.policies["team-a"].clone());
+        let error = policy.enforce("team-c", &spec(Vec::new(), Some(flux("https://git.example.com/team-a/apps")))).unwrap_err().to_string();
+        assert!(error.contains("flux: namespace team-c is not allowed"), "{}", error);
+        
+        let argocd = |destination: serde_json::Value| json!({
+            "provider": "argocd",
+            "repository": "https://git.example.com/team-a/platform",
+            "branch": "main",
+            "path": "apps",
+            "install": { "enabled": false },
+            "argocd": { "destination": destination },
+        });
+        assert!(tenancy().enforce("team-a", &spec(Vec::new(), Some(argocd(json!({ "namespace": "team-a-apps" }))))).is_ok());
+        let error = violations("team-a", &spec(Vec::new(), Some(argocd(json!({ "namespace": "kube-system" })))));
+        assert!(error.contains("argocd: namespace kube-system is not allowed"), "{}", error);
+        let error = violations("team-a", &spec(Vec::new(), Some(argocd(json!({ "server": "https://prod.example.com" })))));
+        assert!(error.contains("argocd: cluster https://prod.example.com is not allowed"), "{}", error);
+    }
+    
+    #[test]
+    fn app_projects_and_application_sets_are_checked() {
+        let argocd = |argocd: serde_json::Value| json!({
+            "provider": "argocd",
+            "repository": "https://git.example.com/team-a/platform",
+            "branch": "main",
+            "path": "apps",
+            "install": { "enabled": false },
+            "argocd": argocd,
+        });
+        
+        let error = violations("team-a", &spec(Vec::new(), Some(argocd(json!({
+            "app_project": {
+                "source_repos": ["*"],
+                "destinations": [{ "server": "https://kubernetes.default.svc" }],
+                "cluster_resource_whitelist": [{ "group": "", "kind": "Namespace" }],
+            },
+        })))));
+        assert!(error.contains("appProject: repository * is not allowed"), "{}", error);
+        assert!(error.contains("appProject: namespace * is not allowed"), "{}", error);
+        assert!(error.contains("appProject: cluster-scoped kind Namespace is not allowed"), "{}", error);
+        
+        let set = |generator: serde_json::Value, repository: &str, namespace: &str| argocd(json!({
+            "application_sets": [{
+                "name": "envs",
+                "generators": [generator],
+                "template": {
+                    "name": "{{env}}",
+                    "repository": repository,
+                    "revision": "main",
+                    "path": "envs/{{env}}",
+                    "destination": { "namespace": namespace },
+                },
+            }],
+        }));
+        
+        // List elements are substituted before checking
+        let list = json!({ "list": [{ "env": "dev" }, { "env": "prod" }] });
+        let allowed = set(list.clone(), "https://git.example.com/team-a/envs", "team-a-{{env}}");
+        assert!(tenancy().enforce("team-a", &spec(Vec::new(), Some(allowed))).is_ok());
+        let error = violations("team-a", &spec(Vec::new(), Some(set(list, "https://git.example.com/team-a/envs", "{{env}}"))));
+        assert!(error.contains("envs: namespace prod is not allowed"), "{}", error);
+        
+        // Git generator output is unknown, so placeholders left over are rejected
+        let git = json!({ "git": { "repository": "https://git.example.com/team-b/envs", "revision": "main", "directories": ["*"] } });
+        let error = violations("team-a", &spec(Vec::new(), Some(set(git, "https://git.example.com/{{path.basename}}", "team-a"))));
+        assert!(error.contains("envs: repository https://git.example.com/team-b/envs is not allowed"), "{}", error);
+        assert!(error.contains("envs: templated repository https://git.example.com/{{path.basename}} is not allowed"), "{}", error);
+    }
+}
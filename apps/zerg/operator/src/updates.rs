@@ -0,0 +1,509 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+use kube::Client;
+use tracing::{info, warn};
+
+use crate::charts::{self, ChartIndex, Constraint};
+use crate::cicd::execution;
+use crate::crd::{Dependency, DependencyManager, DependencyStatus};
+use crate::dependencies::chart_source;
+use crate::error::Error;
+use crate::oci::{self, ChartReference, Registry};
+
+/// How often chart repositories are checked when `updates.interval` is unset
+const DEFAULT_INTERVAL: &str = "24h";
+
+/// Prefix of update branches when `updates.branch_prefix` is unset
+const DEFAULT_BRANCH_PREFIX: &str = "zerg/update";
+
+/// Chart version a dependency is installed or rendered at
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pin {
+    /// Exact chart version
+    pub version: String,
+    
+    /// Constraint `version` was resolved from
+    pub constraint: Option<String>,
+    
+    /// Newest version in the chart repository, when newer than `version`
+    pub latest: Option<String>,
+    
+    /// Newer version within `constraint` awaiting review in git-write mode
+    pub proposed: Option<String>,
+    
+    /// Branch proposing `proposed`
+    pub branch: Option<String>,
+    
+    /// Last check for newer versions
+    pub checked: Option<String>,
+    
+    /// Manifest digest of `version`, for charts from OCI registries
+    pub digest: Option<String>,
+    
+    /// Manifest digest of `proposed`
+    proposed_digest: Option<String>,
+    
+    /// Whether `proposed` was found by this check and still needs pushing
+    unpublished: bool,
+}
+
+impl Pin {
+    /// Forgets the latest and proposed versions that are no longer newer than `version`
+    fn settle(&mut self) {
+        if !self.latest.as_deref().is_some_and(|latest| charts::is_newer(latest, &self.version)) {
+            self.latest = None;
+        }
+        if !self.proposed.as_deref().is_some_and(|proposed| charts::is_newer(proposed, &self.version)) {
+            self.proposed = None;
+        }
+        if self.proposed.is_none() {
+            self.branch = None;
+            self.proposed_digest = None;
+            self.unpublished = false;
+        }
+    }
+}
+
+/// Chart repository indexes, fetched once per repository, or per chart for
+/// OCI registries, whose tags stand in for the index
+#[derive(Default)]
+pub struct Indexes {
+    /// Client and namespace registry credentials are read from
+    secrets: Option<(Client, String)>,
+    indexes: HashMap<String, ChartIndex>,
+    registries: HashMap<String, Registry>,
+}
+
+impl Indexes {
+    /// Indexes reading registry credentials from Secrets in `namespace`
+    pub fn new(client: Client, namespace: &str) -> Self {
+        Self { secrets: Some((client, namespace.to_string())), ..Self::default() }
+    }
+    
+    async fn registry(&mut self, dependency: &Dependency, chart: &ChartReference) -> Result<&Registry, Error> {
+        let key = chart.to_string();
+        if !self.registries.contains_key(&key) {
+            let credentials = match &self.secrets {
+                Some((client, namespace)) => {
+                    oci::registry_credentials(client, namespace, dependency.source.secret_ref.as_deref(), &chart.registry).await?
+                }
+                None => None,
+            };
+            self.registries.insert(key.clone(), Registry::new(credentials));
+        }
+        
+        Ok(&self.registries[&key])
+    }
+    
+    async fn get(&mut self, dependency: &Dependency, repository: &str, chart: &str) -> Result<&ChartIndex, Error> {
+        let reference = ChartReference::new(repository, chart);
+        let key = reference.as_ref().map_or_else(|| repository.to_string(), ChartReference::to_string);
+        
+        if !self.indexes.contains_key(&key) {
+            let index = match &reference {
+                Some(reference) => {
+                    let versions = self.registry(dependency, reference).await?.versions(reference).await?;
+                    ChartIndex::from_versions(chart, versions)
+                }
+                None => ChartIndex::fetch(repository).await?,
+            };
+            self.indexes.insert(key.clone(), index);
+        }
+        
+        Ok(&self.indexes[&key])
+    }
+    
+    /// Manifest digest of `version` of an OCI chart
+    async fn digest(&mut self, dependency: &Dependency, chart: &ChartReference, version: &str) -> Result<String, Error> {
+        self.registry(dependency, chart).await?.digest(chart, version).await
+    }
+}
+
+/// Pinned chart versions of a DependencyManager's dependencies, by name
+#[derive(Debug, Default)]
+pub struct Pins(BTreeMap<String, Pin>);
+
+impl Pins {
+    /// Pins the chart versions of the enabled dependencies of `owner`.
+    ///
+    /// A constraint is resolved to the newest matching version and stays
+    /// pinned, as recorded in the previous status, until it is changed. When
+    /// `owner.spec.updates` is set and a check is due, newer versions within
+    /// the constraint replace the pin, or with `propose` (git-write mode) are
+    /// left for review on a branch; newer versions beyond it are reported.
+    ///
+    /// Charts from OCI registries are also pinned to the manifest digest of
+    /// their version, unless `source.digest` pins them explicitly, in which
+    /// case their version is never updated.
+    pub async fn resolve(owner: &DependencyManager, propose: bool, indexes: &mut Indexes) -> Result<Self, Error> {
+        let now = Utc::now();
+        let updates = owner.spec.updates.as_ref();
+        let interval = updates
+            .map(|updates| {
+                let interval = updates.interval.as_deref().unwrap_or(DEFAULT_INTERVAL);
+                execution::seconds(interval)
+                    .ok_or_else(|| Error::ConfigError(format!("Invalid update interval: {}", interval)))
+            })
+            .transpose()?;
+        let prefix = updates
+            .and_then(|updates| updates.branch_prefix.as_deref())
+            .unwrap_or(DEFAULT_BRANCH_PREFIX);
+        
+        let previous: HashMap<&str, &DependencyStatus> = owner.status
+            .iter()
+            .flat_map(|status| status.dependencies.iter().flatten())
+            .map(|status| (status.name.as_str(), status))
+            .collect();
+        
+        let mut pins = BTreeMap::new();
+        
+        for dependency in owner.spec.dependencies.iter().filter(|d| d.enabled) {
+            let Some((repository, chart)) = chart_source(dependency) else {
+                continue;
+            };
+            let reference = ChartReference::new(repository, chart);
+            let fixed = reference.is_some() && dependency.source.digest.is_some();
+            
+            // OCI charts are always pinned, to their newest version if none is requested
+            let Some(requested) = dependency.version.clone().or_else(|| reference.as_ref().map(|_| "*".to_string())) else {
+                continue;
+            };
+            let requested = &requested;
+            
+            let constraint = (!charts::is_exact(requested))
+                .then(|| Constraint::parse(requested))
+                .transpose()?;
+            
+            // A constraint's pin holds while the constraint is unchanged and still allows it
+            let pinned = match &constraint {
+                None => Some(requested.clone()),
+                Some(constraint) => previous
+                    .get(dependency.name.as_str())
+                    .filter(|status| status.constraint.as_ref() == Some(requested))
+                    .and_then(|status| status.version.clone())
+                    .filter(|version| charts::parse_version(version).is_some_and(|v| constraint.matches(&v))),
+            };
+            let carried = previous
+                .get(dependency.name.as_str())
+                .filter(|status| pinned.is_some() && status.version == pinned);
+            
+            let mut pin = Pin {
+                version: pinned.clone().unwrap_or_default(),
+                constraint: constraint.is_some().then(|| requested.clone()),
+                latest: carried.and_then(|status| status.latest_version.clone()),
+                proposed: carried.and_then(|status| status.proposed_version.clone()),
+                branch: carried.and_then(|status| status.update_branch.clone()),
+                checked: carried.and_then(|status| status.last_checked.clone()),
+                digest: carried.and_then(|status| status.digest.clone()),
+                proposed_digest: None,
+                unpublished: false,
+            };
+            
+            let due = interval.is_some_and(|interval| {
+                pin.checked
+                    .as_deref()
+                    .and_then(|checked| DateTime::parse_from_rfc3339(checked).ok())
+                    .is_none_or(|checked| (now - checked.with_timezone(&Utc)).num_seconds() >= interval)
+            });
+            
+            if pinned.is_none() || due {
+                let index = match indexes.get(dependency, repository, chart).await {
+                    Ok(index) => index,
+                    // A failed check leaves the pin alone and is retried on the next reconcile
+                    Err(e) if pinned.is_some() => {
+                        warn!("Failed to check {} for updates: {}", dependency.name, e);
+                        pins.insert(dependency.name.clone(), pin);
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                
+                if let Some(constraint) = &constraint {
+                    let newest = index.resolve(chart, constraint).ok_or_else(|| Error::DependencyError(format!(
+                        "No version of chart {} in {} matches {}", chart, repository, requested
+                    )))?;
+                    
+                    if pinned.is_none() {
+                        pin.version = newest.to_string();
+                    } else if fixed {
+                        // An explicit digest pins the chart regardless of newer versions
+                    } else if charts::is_newer(newest, &pin.version) && propose {
+                        if pin.proposed.as_deref() != Some(newest) {
+                            info!("Proposing {} {} on a branch", dependency.name, newest);
+                            pin.proposed = Some(newest.to_string());
+                            pin.branch = Some(format!("{}/{}-{}", prefix, dependency.name, newest));
+                            pin.unpublished = true;
+                        }
+                    } else if charts::is_newer(newest, &pin.version) {
+                        info!("Updating {} from {} to {}", dependency.name, pin.version, newest);
+                        pin.version = newest.to_string();
+                        pin.digest = None;
+                    }
+                }
+                
+                if due {
+                    pin.latest = index.latest(chart).map(str::to_string);
+                    pin.checked = Some(now.to_rfc3339());
+                }
+            }
+            
+            pin.settle();
+            
+            if let Some(reference) = &reference {
+                if let Some(digest) = &dependency.source.digest {
+                    pin.digest = Some(digest.clone());
+                } else if pin.digest.is_none() {
+                    pin.digest = Some(indexes.digest(dependency, reference, &pin.version).await?);
+                }
+                
+                if let Some(proposed) = pin.proposed.clone().filter(|_| pin.proposed_digest.is_none()) {
+                    match indexes.digest(dependency, reference, &proposed).await {
+                        Ok(digest) => pin.proposed_digest = Some(digest),
+                        Err(e) => {
+                            warn!("Failed to resolve the digest of {} {}: {}", dependency.name, proposed, e);
+                            pin.proposed = None;
+                            pin.settle();
+                        }
+                    }
+                }
+            }
+            
+            pins.insert(dependency.name.clone(), pin);
+        }
+        
+        Ok(Self(pins))
+    }
+    
+    /// `owner` with its dependencies at their pinned versions
+    pub fn apply(&self, owner: &DependencyManager) -> DependencyManager {
+        let mut pinned = owner.clone();
+        for dependency in &mut pinned.spec.dependencies {
+            if let Some(pin) = self.0.get(&dependency.name) {
+                dependency.version = Some(pin.version.clone());
+                if pin.digest.is_some() {
+                    dependency.source.digest = pin.digest.clone();
+                }
+            }
+        }
+        
+        pinned
+    }
+    
+    /// `owner` at its pinned versions, except for dependency `name` at its proposed version
+    pub fn proposal(&self, owner: &DependencyManager, name: &str) -> DependencyManager {
+        let mut proposal = self.apply(owner);
+        let Some(pin) = self.0.get(name) else {
+            return proposal;
+        };
+        for dependency in proposal.spec.dependencies.iter_mut().filter(|d| d.name == name) {
+            dependency.version = pin.proposed.clone();
+            if pin.proposed_digest.is_some() {
+                dependency.source.digest = pin.proposed_digest.clone();
+            }
+        }
+        
+        proposal
+    }
+    
+    /// Dependencies with an update proposed on a branch
+    pub fn proposed(&self) -> impl Iterator<Item = (&str, &Pin)> {
+        self.0
+            .iter()
+            .filter(|(_, pin)| pin.proposed.is_some())
+            .map(|(name, pin)| (name.as_str(), pin))
+    }
+    
+    /// Whether the proposal for `name` was found by this check and still needs pushing
+    pub fn is_unpublished(&self, name: &str) -> bool {
+        self.0.get(name).is_some_and(|pin| pin.unpublished)
+    }
+    
+    /// Pins `name` to its proposed version, once the proposal is merged
+    pub fn accept(&mut self, name: &str) {
+        if let Some(pin) = self.0.get_mut(name) {
+            if let Some(version) = pin.proposed.take() {
+                info!("Update of {} to {} was merged", name, version);
+                pin.version = version;
+                pin.digest = pin.proposed_digest.take();
+                pin.settle();
+            }
+        }
+    }
+    
+    /// Drops the proposal for `name`, e.g. when it could not be pushed
+    pub fn withdraw(&mut self, name: &str) {
+        if let Some(pin) = self.0.get_mut(name) {
+            pin.proposed = None;
+            pin.settle();
+        }
+    }
+    
+    /// Records the pin of the dependency `status` is for
+    pub fn annotate(&self, status: &mut DependencyStatus) {
+        let Some(pin) = self.0.get(&status.name) else {
+            return;
+        };
+        
+        status.constraint = pin.constraint.clone();
+        status.latest_version = pin.latest.clone();
+        status.proposed_version = pin.proposed.clone();
+        status.update_branch = pin.branch.clone();
+        status.last_checked = pin.checked.clone();
+        status.digest = pin.digest.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oci::local::LocalRegistry;
+    use crate::crd::{
+        Dependency, DependencyInstallStatus, DependencyManagerStatus, DependencySource,
+        DependencyType, Phase, UpdatePolicy,
+    };
+    
+    const REPOSITORY: &str = "https://grafana.github.io/helm-charts";
+    
+    fn indexes() -> Indexes {
+        let index = ChartIndex::parse(&std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/charts/index.yaml")).unwrap()).unwrap();
+        Indexes { indexes: HashMap::from([(REPOSITORY.to_string(), index)]), ..Indexes::default() }
+    }
+    
+    fn owner(version: &str, updates: Option<UpdatePolicy>, previous: Option<DependencyStatus>) -> DependencyManager {
+        let mut dm = crate::crd::tests::owner("team-a", "platform");
+        dm.spec.updates = updates;
+        dm.spec.dependencies = vec![Dependency {
+            name: "loki".to_string(),
+            type_: DependencyType::Helm,
+            source: DependencySource {
+                repo: REPOSITORY.to_string(),
+                chart: Some("loki-stack".to_string()),
+                path: None,
+                ref_: None,
+                secret_ref: None,
+                digest: None,
+            },
+            version: Some(version.to_string()),
+            namespace: None,
+            values: None,
+            values_from: None,
+            depends_on: None,
+            enabled: true,
+        }];
+        dm.status = previous.map(|status| DependencyManagerStatus {
+            phase: Phase::Ready,
+            dependencies: Some(vec![status]),
+            gitops_status: None,
+            cicd_status: None,
+            last_reconciled: None,
+            conditions: None,
+        });
+        dm
+    }
+    
+    fn status(version: &str, constraint: &str, checked: &str) -> DependencyStatus {
+        DependencyStatus {
+            name: "loki".to_string(),
+            status: DependencyInstallStatus::Installed,
+            version: Some(version.to_string()),
+            constraint: Some(constraint.to_string()),
+            latest_version: None,
+            proposed_version: None,
+            update_branch: None,
+            last_checked: Some(checked.to_string()),
+            digest: None,
+            last_updated: None,
+            error: None,
+        }
+    }
+    
+    #[tokio::test]
+    async fn constraints_resolve_once_and_stay_pinned() {
+        let pins = Pins::resolve(&owner("~2.9", None, None), false, &mut indexes()).await.unwrap();
+        assert_eq!(pins.0["loki"].version, "2.9.11");
+        assert_eq!(pins.apply(&owner("~2.9", None, None)).spec.dependencies[0].version.as_deref(), Some("2.9.11"));
+        
+        // The recorded pin holds, without fetching the index
+        let previous = status("2.9.10", "~2.9", "2024-05-01T00:00:00Z");
+        let pins = Pins::resolve(&owner("~2.9", None, Some(previous.clone())), false, &mut Indexes::default()).await.unwrap();
+        assert_eq!(pins.0["loki"].version, "2.9.10");
+        
+        // A changed constraint is resolved again
+        let pins = Pins::resolve(&owner(">=2.8 <2.9", None, Some(previous)), false, &mut indexes()).await.unwrap();
+        assert_eq!(pins.0["loki"].version, "v2.8.9");
+        
+        let error = Pins::resolve(&owner("~3.0", None, None), false, &mut indexes()).await.unwrap_err();
+        assert!(error.to_string().contains("No version of chart loki-stack"), "{}", error);
+    }
+    
+    #[tokio::test]
+    async fn due_checks_update_or_propose_within_the_constraint() {
+        let updates = Some(UpdatePolicy::default());
+        let previous = status("2.9.10", "~2.9", "2024-05-01T00:00:00Z");
+        
+        let pins = Pins::resolve(&owner("~2.9", updates.clone(), Some(previous.clone())), false, &mut indexes()).await.unwrap();
+        let pin = &pins.0["loki"];
+        assert_eq!((pin.version.as_str(), pin.latest.as_deref(), pin.proposed.as_deref()), ("2.9.11", Some("2.10.2"), None));
+        
+        let dm = owner("~2.9", updates.clone(), Some(previous));
+        let mut pins = Pins::resolve(&dm, true, &mut indexes()).await.unwrap();
+        let pin = &pins.0["loki"];
+        assert_eq!((pin.version.as_str(), pin.proposed.as_deref()), ("2.9.10", Some("2.9.11")));
+        assert_eq!(pin.branch.as_deref(), Some("zerg/update/loki-2.9.11"));
+        assert!(pins.is_unpublished("loki"));
+        assert_eq!(pins.proposal(&dm, "loki").spec.dependencies[0].version.as_deref(), Some("2.9.11"));
+        
+        pins.accept("loki");
+        let pin = &pins.0["loki"];
+        assert_eq!((pin.version.as_str(), pin.proposed.as_deref(), pin.branch.as_deref()), ("2.9.11", None, None));
+        
+        // Exact versions are only reported
+        let pins = Pins::resolve(&owner("2.9.10", updates.clone(), None), true, &mut indexes()).await.unwrap();
+        let pin = &pins.0["loki"];
+        assert_eq!((pin.version.as_str(), pin.latest.as_deref(), pin.proposed.as_deref()), ("2.9.10", Some("2.10.2"), None));
+        
+        // Checks wait for the interval
+        let checked = Utc::now().to_rfc3339();
+        let pins = Pins::resolve(&owner("~2.9", updates, Some(status("2.9.10", "~2.9", &checked))), true, &mut indexes()).await.unwrap();
+        let pin = &pins.0["loki"];
+        assert_eq!((pin.proposed.as_deref(), pin.checked.as_deref()), (None, Some(checked.as_str())));
+    }
+    
+    #[tokio::test]
+    async fn oci_charts_are_pinned_by_digest() {
+        let registry = LocalRegistry::start(None).await;
+        let older = registry.push("charts/loki-stack", "2.9.10", b"loki-stack 2.9.10");
+        let newer = registry.push("charts/loki-stack", "2.9.11", b"loki-stack 2.9.11");
+        let owner = |version: &str, updates, previous| {
+            let mut dm = owner(version, updates, previous);
+            dm.spec.dependencies[0].source.repo = registry.url("charts");
+            dm
+        };
+        
+        let dm = owner("~2.9", None, None);
+        let pins = Pins::resolve(&dm, false, &mut Indexes::default()).await.unwrap();
+        assert_eq!((pins.0["loki"].version.as_str(), pins.0["loki"].digest.as_ref()), ("2.9.11", Some(&newer)));
+        assert_eq!(pins.apply(&dm).spec.dependencies[0].source.digest.as_ref(), Some(&newer));
+        
+        // Proposals carry the digest of the proposed version, which becomes the pin once merged
+        let previous = DependencyStatus { digest: Some(older.clone()), ..status("2.9.10", "~2.9", "2024-05-01T00:00:00Z") };
+        let dm = owner("~2.9", Some(UpdatePolicy::default()), Some(previous.clone()));
+        let mut pins = Pins::resolve(&dm, true, &mut Indexes::default()).await.unwrap();
+        assert_eq!(pins.0["loki"].digest.as_ref(), Some(&older));
+        assert_eq!(pins.proposal(&dm, "loki").spec.dependencies[0].source.digest.as_ref(), Some(&newer));
+        pins.accept("loki");
+        assert_eq!((pins.0["loki"].version.as_str(), pins.0["loki"].digest.as_ref()), ("2.9.11", Some(&newer)));
+        
+        // A recorded digest holds when its tag is pushed again
+        registry.push("charts/loki-stack", "2.9.10", b"loki-stack 2.9.10, rebuilt");
+        let pins = Pins::resolve(&owner("~2.9", None, Some(previous)), false, &mut Indexes::default()).await.unwrap();
+        assert_eq!(pins.0["loki"].digest.as_ref(), Some(&older));
+        
+        // So does an explicit one
+        let mut dm = owner("2.9.11", Some(UpdatePolicy::default()), None);
+        dm.spec.dependencies[0].source.digest = Some(older.clone());
+        let pins = Pins::resolve(&dm, false, &mut Indexes::default()).await.unwrap();
+        assert_eq!(pins.0["loki"].digest.as_ref(), Some(&older));
+    }
+}